@@ -1,8 +1,13 @@
 //! Track the cursor of a text input.
+use std::ops::Range;
+
 use iced_graphics::Point;
 use iced_native::text;
 
-use crate::{hit_byte_index, offset_x_of_index, rope_ext::RopeExt, Rope};
+use crate::{
+	hit_byte_index_or_nearest, offset_x_of_index, rope_ext::RopeExt,
+	wrap::Rows, Rope,
+};
 
 /// The cursor of a text input.
 #[derive(Debug, Copy, Clone)]
@@ -65,6 +70,45 @@ impl Cursor {
 		}
 	}
 
+	/// Moves the cursor, and its selection, by `delta` bytes.
+	pub(crate) fn shift(&mut self, delta: isize) {
+		let shift = |index: usize| (index as isize + delta).max(0) as usize;
+
+		self.state = match self.state {
+			State::Index(index) => State::Index(shift(index)),
+			State::Selection { start, end } => State::Selection {
+				start: shift(start),
+				end: shift(end),
+			},
+		};
+	}
+
+	/// Keeps the cursor on the same text after the bytes in `range` were
+	/// replaced with `len` bytes.
+	///
+	/// Positions inside the replaced range move to the end of the
+	/// replacement.
+	pub(crate) fn replaced(&mut self, range: Range<usize>, len: usize) {
+		let map = |index: usize| {
+			if index <= range.start {
+				index
+			} else if index >= range.end {
+				index - range.len() + len
+			} else {
+				range.start + len
+			}
+		};
+
+		self.state = match self.state {
+			State::Index(index) => State::Index(map(index)),
+			State::Selection { start, end } => State::Selection {
+				start: map(start),
+				end: map(end),
+			},
+		};
+		self.offset_x_hint = None;
+	}
+
 	pub(crate) fn move_to_byte(&mut self, position: usize) {
 		self.move_to_impl(position);
 		self.offset_x_hint = None;
@@ -86,8 +130,8 @@ impl Cursor {
 		self.move_to_byte(value.next_grapheme(index));
 	}
 
-	pub(crate) fn move_right_by_words(&mut self, value: &Rope) {
-		self.move_to_impl(value.next_end_of_word(self.end(value)));
+	pub(crate) fn move_right_by_words(&mut self, value: &Rope, subwords: bool) {
+		self.move_to_impl(next_end_of_word(value, self.end(value), subwords));
 		self.offset_x_hint = None;
 	}
 
@@ -124,8 +168,12 @@ impl Cursor {
 		self.move_to_byte(value.previous_grapheme(index));
 	}
 
-	pub(crate) fn move_left_by_words(&mut self, value: &Rope) {
-		self.move_to_impl(value.previous_start_of_word(self.start(value)));
+	pub(crate) fn move_left_by_words(&mut self, value: &Rope, subwords: bool) {
+		self.move_to_impl(previous_start_of_word(
+			value,
+			self.start(value),
+			subwords,
+		));
 		self.offset_x_hint = None;
 	}
 
@@ -138,6 +186,7 @@ impl Cursor {
 	pub(crate) fn move_up<Renderer>(
 		&mut self,
 		value: &Rope,
+		rows: &Rows,
 		renderer: &Renderer,
 		font: Renderer::Font,
 		tab_width: u8,
@@ -150,6 +199,7 @@ impl Cursor {
 					index,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -162,6 +212,7 @@ impl Cursor {
 					start.min(end),
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -176,6 +227,7 @@ impl Cursor {
 	pub(crate) fn move_down<Renderer>(
 		&mut self,
 		value: &Rope,
+		rows: &Rows,
 		renderer: &Renderer,
 		font: Renderer::Font,
 		tab_width: u8,
@@ -188,6 +240,7 @@ impl Cursor {
 					index,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -200,6 +253,7 @@ impl Cursor {
 					end.max(start),
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -249,25 +303,38 @@ impl Cursor {
 		self.offset_x_hint = None;
 	}
 
-	pub(crate) fn select_left_by_words(&mut self, value: &Rope) {
+	pub(crate) fn select_left_by_words(
+		&mut self,
+		value: &Rope,
+		subwords: bool,
+	) {
 		match self.state(value) {
-			State::Index(index) => self
-				.select_range_impl(index, value.previous_start_of_word(index)),
-			State::Selection { start, end } => {
-				self.select_range_impl(start, value.previous_start_of_word(end))
-			}
+			State::Index(index) => self.select_range_impl(
+				index,
+				previous_start_of_word(value, index, subwords),
+			),
+			State::Selection { start, end } => self.select_range_impl(
+				start,
+				previous_start_of_word(value, end, subwords),
+			),
 		}
 		self.offset_x_hint = None;
 	}
 
-	pub(crate) fn select_right_by_words(&mut self, value: &Rope) {
+	pub(crate) fn select_right_by_words(
+		&mut self,
+		value: &Rope,
+		subwords: bool,
+	) {
 		match self.state(value) {
-			State::Index(index) => {
-				self.select_range_impl(index, value.next_end_of_word(index));
-			}
-			State::Selection { start, end } => {
-				self.select_range_impl(start, value.next_end_of_word(end));
-			}
+			State::Index(index) => self.select_range_impl(
+				index,
+				next_end_of_word(value, index, subwords),
+			),
+			State::Selection { start, end } => self.select_range_impl(
+				start,
+				next_end_of_word(value, end, subwords),
+			),
 		}
 		self.offset_x_hint = None;
 	}
@@ -301,6 +368,7 @@ impl Cursor {
 	pub(crate) fn select_up<Renderer>(
 		&mut self,
 		value: &Rope,
+		rows: &Rows,
 		renderer: &Renderer,
 		font: Renderer::Font,
 		tab_width: u8,
@@ -313,6 +381,7 @@ impl Cursor {
 					index,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -325,6 +394,7 @@ impl Cursor {
 					end,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -339,6 +409,7 @@ impl Cursor {
 	pub(crate) fn select_down<Renderer>(
 		&mut self,
 		value: &Rope,
+		rows: &Rows,
 		renderer: &Renderer,
 		font: Renderer::Font,
 		tab_width: u8,
@@ -351,6 +422,7 @@ impl Cursor {
 					index,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -363,6 +435,7 @@ impl Cursor {
 					end,
 					self.offset_x_hint,
 					value,
+					rows,
 					renderer,
 					font,
 					tab_width,
@@ -374,6 +447,53 @@ impl Cursor {
 		}
 	}
 
+	/// Expands the selection to the smallest enclosing bracket or quote
+	/// contents, brackets or quotes, line, paragraph, or document that
+	/// contains it. Returns `false` if the selection couldn't be expanded.
+	pub(crate) fn expand_selection(&mut self, value: &Rope) -> bool {
+		let start = self.start(value).min(self.end(value));
+		let end = self.start(value).max(self.end(value));
+
+		let mut candidates = vec![];
+
+		let pairs = [
+			value.enclosing_brackets(start, end),
+			find_enclosing_quotes(start, end, value),
+		];
+
+		for (open, close) in pairs.into_iter().flatten() {
+			candidates.push((open + 1, close));
+			candidates.push((open, close + 1));
+		}
+
+		let line_start = value.line_to_byte(value.byte_to_line(start));
+		let line_end = find_line_content_end(value.byte_to_line(end), value);
+		candidates.push((line_start, line_end));
+
+		candidates.push(find_paragraph(start, end, value));
+
+		candidates.push((0, value.len_bytes()));
+
+		let expanded = candidates
+			.into_iter()
+			.filter(|&(s, e)| s <= start && end <= e && (s, e) != (start, end))
+			.min_by_key(|&(s, e)| e - s);
+
+		match expanded {
+			Some((start, end)) => {
+				self.select_range(start, end);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Restores a selection that was previously expanded with
+	/// [`Cursor::expand_selection`].
+	pub(crate) fn shrink_selection(&mut self, previous: (usize, usize)) {
+		self.select_range(previous.0, previous.1);
+	}
+
 	pub(crate) fn select_all(&mut self, value: &Rope) {
 		self.select_range_impl(0, value.len_bytes());
 		self.offset_x_hint = None;
@@ -399,7 +519,14 @@ impl Cursor {
 }
 
 fn find_end_of_line(index: usize, value: &Rope) -> usize {
-	let next_line_start = value.line_to_byte(value.byte_to_line(index) + 1);
+	let line = value.byte_to_line(index);
+
+	// the last line never ends in a line break
+	if line + 1 == value.len_lines() {
+		return value.len_bytes();
+	}
+
+	let next_line_start = value.line_to_byte(line + 1);
 
 	let last_byte = next_line_start.checked_sub(1).map(|i| value.byte(i));
 	let second_last_byte =
@@ -412,10 +539,99 @@ fn find_end_of_line(index: usize, value: &Rope) -> usize {
 	}
 }
 
+/// Finds the end of the given line, excluding its line ending.
+pub(crate) fn find_line_content_end(line_index: usize, value: &Rope) -> usize {
+	let line_start = value.line_to_byte(line_index);
+	let line = value.line(line_index);
+
+	let mut len = line.len_bytes();
+	let mut bytes = line.bytes_at(len);
+	if bytes.prev() == Some(b'\n') {
+		len -= 1;
+		if bytes.prev() == Some(b'\r') {
+			len -= 1;
+		}
+	}
+
+	line_start + len
+}
+
+/// Finds the run of non-blank lines containing the given range.
+fn find_paragraph(start: usize, end: usize, value: &Rope) -> (usize, usize) {
+	let is_blank =
+		|line_index| value.line(line_index).chars().all(char::is_whitespace);
+
+	let mut first_line = value.byte_to_line(start);
+	while first_line > 0 && !is_blank(first_line - 1) {
+		first_line -= 1;
+	}
+
+	let mut last_line = value.byte_to_line(end);
+	while last_line + 1 < value.len_lines() && !is_blank(last_line + 1) {
+		last_line += 1;
+	}
+
+	(
+		value.line_to_byte(first_line),
+		find_line_content_end(last_line, value),
+	)
+}
+
+/// Finds the nearest pair of quotes on a single line around the given range,
+/// returning the byte indices of the opening and closing quote.
+fn find_enclosing_quotes(
+	start: usize,
+	end: usize,
+	value: &Rope,
+) -> Option<(usize, usize)> {
+	let line_index = value.byte_to_line(start);
+	if value.byte_to_line(end) != line_index {
+		return None;
+	}
+
+	let line_start = value.line_to_byte(line_index);
+	let line = value.line(line_index).to_string();
+	let (start, end) = (start - line_start, end - line_start);
+
+	['"', '\'', '`']
+		.into_iter()
+		.filter_map(|quote| {
+			let positions = line
+				.match_indices(quote)
+				.map(|(i, _)| i)
+				.filter(|&i| i == 0 || line.as_bytes()[i - 1] != b'\\')
+				.collect::<Vec<_>>();
+
+			positions
+				.chunks_exact(2)
+				.map(|pair| (pair[0], pair[1]))
+				.find(|&(open, close)| open < start && end <= close)
+		})
+		.min_by_key(|&(open, close)| close - open)
+		.map(|(open, close)| (line_start + open, line_start + close))
+}
+
+fn next_end_of_word(value: &Rope, index: usize, subwords: bool) -> usize {
+	if subwords {
+		value.next_end_of_subword(index)
+	} else {
+		value.next_end_of_word(index)
+	}
+}
+
+fn previous_start_of_word(value: &Rope, index: usize, subwords: bool) -> usize {
+	if subwords {
+		value.previous_start_of_subword(index)
+	} else {
+		value.previous_start_of_word(index)
+	}
+}
+
 fn find_index_above<Renderer>(
 	index: usize,
 	offset_x_hint: Option<f32>,
 	value: &Rope,
+	rows: &Rows,
 	renderer: &Renderer,
 	font: Renderer::Font,
 	tab_width: u8,
@@ -423,8 +639,8 @@ fn find_index_above<Renderer>(
 where
 	Renderer: text::Renderer,
 {
-	let line_index = value.byte_to_line(index);
-	if line_index == 0 {
+	let row = rows.row_of(value, index);
+	if row == 0 {
 		return (0, 0.0);
 	}
 
@@ -433,6 +649,7 @@ where
 		None => offset_x_of_index(
 			index,
 			value,
+			rows,
 			renderer,
 			font.clone(),
 			None,
@@ -440,8 +657,9 @@ where
 		),
 	};
 
-	let previous_line_start = value.line_to_byte(line_index - 1);
-	let previous_line = value.line(line_index - 1);
+	let previous_line_start = rows.start(value, row - 1);
+	let previous_line =
+		value.byte_slice(previous_line_start..rows.end(value, row - 1));
 
 	{
 		let mut bytes = previous_line.bytes();
@@ -457,32 +675,25 @@ where
 
 	let size = renderer.default_size();
 
-	let index_above = hit_byte_index(
-		renderer,
-		previous_line,
-		None,
-		size,
-		font,
-		tab_width,
-		Point::new(offset_x, f32::from(size) / 2.0),
-	)
-	.map_or_else(
-		|| {
-			panic!(
-				"Failed to hit test for point ({offset_x}, {})",
-				f32::from(size) / 2.0
-			)
-		},
-		|offset| previous_line_start + offset,
-	);
-
-	(index_above, offset_x)
+	let index_above = previous_line_start
+		+ hit_byte_index_or_nearest(
+			renderer,
+			previous_line,
+			None,
+			size,
+			font,
+			tab_width,
+			Point::new(offset_x, f32::from(size) / 2.0),
+		);
+
+	(rows.clamp_to_row(value, row - 1, index_above), offset_x)
 }
 
 fn find_index_below<Renderer>(
 	index: usize,
 	offset_x_hint: Option<f32>,
 	value: &Rope,
+	rows: &Rows,
 	renderer: &Renderer,
 	font: Renderer::Font,
 	tab_width: u8,
@@ -490,13 +701,14 @@ fn find_index_below<Renderer>(
 where
 	Renderer: text::Renderer,
 {
-	let line_index = value.byte_to_line(index);
-	if line_index + 1 == value.len_lines() {
+	let row = rows.row_of(value, index);
+	if row + 1 == rows.len(value) {
 		return (
 			value.len_bytes(),
 			offset_x_of_index(
 				value.len_bytes(),
 				value,
+				rows,
 				renderer,
 				font,
 				None,
@@ -510,6 +722,7 @@ where
 		None => offset_x_of_index(
 			index,
 			value,
+			rows,
 			renderer,
 			font.clone(),
 			None,
@@ -517,8 +730,8 @@ where
 		),
 	};
 
-	let next_line_start = value.line_to_byte(line_index + 1);
-	let next_line = value.line(line_index + 1);
+	let next_line_start = rows.start(value, row + 1);
+	let next_line = value.byte_slice(next_line_start..rows.end(value, row + 1));
 
 	{
 		let mut bytes = next_line.bytes();
@@ -532,24 +745,16 @@ where
 
 	let size = renderer.default_size();
 
-	let index_below = hit_byte_index(
-		renderer,
-		next_line,
-		None,
-		size,
-		font,
-		tab_width,
-		Point::new(offset_x, f32::from(size) / 2.0),
-	)
-	.map_or_else(
-		|| {
-			panic!(
-				"Failed to hit test for point ({offset_x}, {})",
-				f32::from(size) / 2.0
-			)
-		},
-		|offset| next_line_start + offset,
-	);
-
-	(index_below, offset_x)
+	let index_below = next_line_start
+		+ hit_byte_index_or_nearest(
+			renderer,
+			next_line,
+			None,
+			size,
+			font,
+			tab_width,
+			Point::new(offset_x, f32::from(size) / 2.0),
+		);
+
+	(rows.clamp_to_row(value, row + 1, index_below), offset_x)
 }
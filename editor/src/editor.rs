@@ -1,4 +1,6 @@
-use crate::{Cursor, Rope};
+use std::{cmp::Reverse, iter, ops::Range};
+
+use crate::{cursor, display_column, rope_ext::RopeExt, Cursor, Rope};
 
 pub struct Editor<'a> {
 	value: &'a mut Rope,
@@ -10,10 +12,6 @@ impl<'a> Editor<'a> {
 		Editor { value, cursor }
 	}
 
-	pub fn contents(&self) -> String {
-		self.value.to_string()
-	}
-
 	pub fn insert(&mut self, character: char) {
 		if let Some((left, right)) = self.cursor.selection(self.value) {
 			self.cursor.move_left(self.value);
@@ -29,6 +27,127 @@ impl<'a> Editor<'a> {
 		self.cursor.move_right(self.value);
 	}
 
+	/// Types `character` over the grapheme after the caret, as in overwrite
+	/// mode.
+	///
+	/// A selection is replaced as with [`insert`](Editor::insert), and at the
+	/// end of a line, the character is inserted before the line break.
+	pub fn overwrite(&mut self, character: char) {
+		if self.cursor.selection(self.value).is_none() {
+			let caret = self.cursor.end(self.value);
+			let next = self.value.next_grapheme(caret);
+			let first = self.value.byte_slice(caret..next).chars().next();
+
+			if !matches!(first, None | Some('\n' | '\r')) {
+				let start = self.value.byte_to_char(caret);
+				let end = self.value.byte_to_char(next);
+				self.value.remove(start..end);
+			}
+		}
+
+		self.insert(character);
+	}
+
+	/// Removes spaces and tabs from the end of every line, keeping the cursor
+	/// on the same text, and returns the byte ranges removed, last first.
+	///
+	/// A cursor in removed whitespace ends up at the end of its line.
+	pub fn trim_trailing_whitespace(&mut self) -> Vec<Range<usize>> {
+		let mut removed = vec![];
+
+		for line in (0..self.value.len_lines()).rev() {
+			let start = self.value.line_to_byte(line);
+			let text = self.value.line(line).to_string();
+			let content = text.trim_end_matches(&['\n', '\r'][..]);
+			let trimmed = content.trim_end_matches(&[' ', '\t'][..]);
+
+			if trimmed.len() < content.len() {
+				let range = start + trimmed.len()..start + content.len();
+				self.value.remove(
+					self.value.byte_to_char(range.start)
+						..self.value.byte_to_char(range.end),
+				);
+				self.cursor.replaced(range.clone(), 0);
+				removed.push(range);
+			}
+		}
+
+		removed
+	}
+
+	/// Inserts spaces up to the next tab stop, replacing the selection, as a
+	/// tab typed with soft tabs.
+	pub fn insert_soft_tab(&mut self, tab_width: u8) {
+		if self.cursor.selection(self.value).is_some() {
+			self.backspace();
+		}
+
+		let column =
+			display_column(self.value, self.cursor.end(self.value), tab_width);
+		let tab_width = usize::from(tab_width.max(1));
+
+		self.paste(&" ".repeat(tab_width - column % tab_width));
+	}
+
+	/// Inserts the indentation of the line before the caret's, after a line
+	/// break was typed, so the new line starts at the same level.
+	///
+	/// With `soft_tabs`, tabs in the indentation are inserted as `tab_width`
+	/// spaces each.
+	pub fn copy_indent(&mut self, soft_tabs: bool, tab_width: u8) {
+		let line = self.value.byte_to_line(self.cursor.end(self.value));
+		if line == 0 {
+			return;
+		}
+
+		let indent = self
+			.value
+			.line(line - 1)
+			.chars()
+			.take_while(|&c| c == ' ' || c == '\t')
+			.collect::<String>();
+		let indent = if soft_tabs {
+			indent.replace('\t', &" ".repeat(usize::from(tab_width)))
+		} else {
+			indent
+		};
+
+		if !indent.is_empty() {
+			self.paste(&indent);
+		}
+	}
+
+	/// Replaces the character before the cursor with its canonical composition
+	/// with `character`, such as `e` and a combining acute accent into `é`.
+	///
+	/// Returns `false`, changing nothing, if there is a selection or the two
+	/// don't compose.
+	pub fn compose(&mut self, character: char) -> bool {
+		if self.cursor.selection(self.value).is_some() {
+			return false;
+		}
+
+		let end = self.cursor.end(self.value);
+		let index = self.value.byte_to_char(end);
+		if index == 0 {
+			return false;
+		}
+
+		let previous = self.value.char(index - 1);
+		let composed =
+			match unicode_normalization::char::compose(previous, character) {
+				Some(composed) => composed,
+				None => return false,
+			};
+
+		self.value.remove(index - 1..index);
+		self.value.insert_char(index - 1, composed);
+		self.cursor
+			.move_to_byte(end - previous.len_utf8() + composed.len_utf8());
+
+		true
+	}
+
 	pub fn paste(&mut self, content: &str) {
 		let length = content.len();
 
@@ -80,4 +199,360 @@ impl<'a> Editor<'a> {
 			}
 		}
 	}
+
+	/// Deletes the selection, or the word before the cursor if there is none.
+	///
+	/// At the start of a line, this deletes the preceding line break instead.
+	pub fn delete_word_left(&mut self, subwords: bool) {
+		if self.cursor.selection(self.value).is_some() {
+			self.backspace();
+			return;
+		}
+
+		let end = self.cursor.start(self.value);
+		let start = line_break_before(self.value, end).unwrap_or_else(|| {
+			if subwords {
+				self.value.previous_start_of_subword(end)
+			} else {
+				self.value.previous_start_of_word(end)
+			}
+		});
+
+		self.remove_bytes(start, end);
+		self.cursor.move_to_byte(start);
+	}
+
+	/// Deletes the selection, or the word after the cursor if there is none.
+	///
+	/// At the end of a line, this deletes the following line break instead.
+	pub fn delete_word_right(&mut self, subwords: bool) {
+		if self.cursor.selection(self.value).is_some() {
+			self.backspace();
+			return;
+		}
+
+		let start = self.cursor.end(self.value);
+		let end = line_break_after(self.value, start).unwrap_or_else(|| {
+			if subwords {
+				self.value.next_end_of_subword(start)
+			} else {
+				self.value.next_end_of_word(start)
+			}
+		});
+
+		self.remove_bytes(start, end);
+		self.cursor.move_to_byte(start);
+	}
+
+	/// Inserts `indent` at the start of each line touched by the cursor.
+	///
+	/// A selection keeps covering the same text, extended over the new
+	/// indentation of its first line. A selection ending at the start of a
+	/// line doesn't touch that line.
+	pub fn indent_lines(&mut self, indent: &str) {
+		let before = self.cursor.state(self.value);
+		let mut inserted = vec![];
+
+		for line in self.touched_lines().rev() {
+			let start = self.value.line_to_byte(line);
+			self.value.insert(self.value.line_to_char(line), indent);
+			inserted.push(start);
+		}
+
+		// Indentation inserted right at the start of a selection goes inside
+		// it, so the selection grows over it
+		self.map_cursor(before, |index, is_end| {
+			index
+				+ inserted
+					.iter()
+					.filter(|&&start| {
+						start < index || (is_end && start == index)
+					})
+					.count() * indent.len()
+		});
+	}
+
+	/// Removes up to `tab_width` columns of leading whitespace from each line
+	/// touched by the cursor: either that many spaces or a single tab.
+	///
+	/// See [`indent_lines`](Editor::indent_lines) for how the selection is
+	/// kept.
+	pub fn dedent_lines(&mut self, tab_width: u8) {
+		let before = self.cursor.state(self.value);
+		let mut removed = vec![];
+
+		for line in self.touched_lines().rev() {
+			let start = self.value.line_to_byte(line);
+			let len = match self.value.get_byte(start) {
+				Some(b'\t') => 1,
+				_ => (start..)
+					.take(usize::from(tab_width))
+					.take_while(|&i| self.value.get_byte(i) == Some(b' '))
+					.count(),
+			};
+
+			self.remove_bytes(start, start + len);
+			removed.push((start, len));
+		}
+
+		self.map_cursor(before, |index, _| {
+			index
+				- removed
+					.iter()
+					.map(|&(start, len)| index.saturating_sub(start).min(len))
+					.sum::<usize>()
+		});
+	}
+
+	/// Comments out the lines touched by the cursor by inserting `prefix` and
+	/// a space after the indentation of each, or, if every one of them
+	/// already starts with `prefix` after its indentation, uncomments them by
+	/// removing it and a space after it. Blank lines are left alone.
+	///
+	/// See [`indent_lines`](Editor::indent_lines) for how the selection is
+	/// kept.
+	pub fn toggle_line_comment(&mut self, prefix: &str) {
+		if prefix.is_empty() {
+			return;
+		}
+
+		let before = self.cursor.state(self.value);
+
+		// the start of the text after the indentation of each line that isn't
+		// blank, and that text
+		let lines = self
+			.touched_lines()
+			.filter_map(|line| {
+				let text = self.value.line(line).to_string();
+				let content = text.trim_start_matches(&[' ', '\t'][..]);
+
+				(!content.trim().is_empty()).then(|| {
+					let start = self.value.line_to_byte(line) + text.len()
+						- content.len();
+					(start, content.to_owned())
+				})
+			})
+			.collect::<Vec<_>>();
+
+		if lines.iter().all(|(_, content)| content.starts_with(prefix)) {
+			let mut removed = vec![];
+
+			for (start, content) in lines.iter().rev() {
+				let after = &content[prefix.len()..];
+				let len = prefix.len() + usize::from(after.starts_with(' '));
+
+				self.remove_bytes(*start, start + len);
+				removed.push((*start, len));
+			}
+
+			self.map_cursor(before, |index, _| {
+				index
+					- removed
+						.iter()
+						.map(|&(start, len)| {
+							index.saturating_sub(start).min(len)
+						})
+						.sum::<usize>()
+			});
+		} else {
+			let comment = format!("{} ", prefix);
+
+			for (start, _) in lines.iter().rev() {
+				self.value.insert(self.value.byte_to_char(*start), &comment);
+			}
+
+			self.map_cursor(before, |index, is_end| {
+				index
+					+ lines
+						.iter()
+						.filter(|&&(start, _)| {
+							start < index || (is_end && start == index)
+						})
+						.count() * comment.len()
+			});
+		}
+	}
+
+	/// Inserts a copy of the lines touched by the cursor below them, moving
+	/// the cursor onto the copy.
+	pub fn duplicate_lines(&mut self) {
+		let lines = self.touched_lines();
+		let start = self.value.line_to_byte(*lines.start());
+		let end = self.value.line_to_byte(lines.end() + 1);
+
+		let block = self.value.byte_slice(start..end).to_string();
+		// The last line has no line break to copy
+		let copy = if block.ends_with('\n') {
+			block
+		} else {
+			format!("\n{}", block)
+		};
+
+		self.value.insert(self.value.byte_to_char(end), &copy);
+		self.cursor.shift(copy.len() as isize);
+	}
+
+	/// Swaps the lines touched by the cursor with the line above them, or
+	/// below them if `up` isn't set, keeping the cursor on the same text.
+	///
+	/// Returns `false`, changing nothing, if there's no line to swap with.
+	pub fn move_lines(&mut self, up: bool) -> bool {
+		let lines = self.touched_lines();
+		let (first, last) = (*lines.start(), *lines.end());
+
+		let (above, below) = if up {
+			match first.checked_sub(1) {
+				Some(above) => (above..first, first..last + 1),
+				None => return false,
+			}
+		} else {
+			if last + 1 >= self.value.len_lines() {
+				return false;
+			}
+			(first..last + 1, last + 1..last + 2)
+		};
+
+		let slice = |lines: Range<usize>| {
+			self.value
+				.byte_slice(
+					self.value.line_to_byte(lines.start)
+						..self.value.line_to_byte(lines.end),
+				)
+				.to_string()
+		};
+		let (above_text, below_text) = (slice(above.clone()), slice(below));
+		let (above_content, above_break) = split_line_break(&above_text);
+		let (below_content, below_break) = split_line_break(&below_text);
+
+		// Line breaks stay where they are, so a last line without one stays
+		// last
+		let start = self.value.line_to_byte(above.start);
+		self.remove_bytes(start, start + above_text.len() + below_text.len());
+		self.value.insert(
+			self.value.byte_to_char(start),
+			&[below_content, above_break, above_content, below_break].concat(),
+		);
+
+		self.cursor.shift(if up {
+			-((above_content.len() + above_break.len()) as isize)
+		} else {
+			(below_content.len() + above_break.len()) as isize
+		});
+
+		true
+	}
+
+	/// Returns the lines touched by the cursor, leaving out the line a
+	/// selection ends at the start of.
+	fn touched_lines(&self) -> std::ops::RangeInclusive<usize> {
+		let (start, end) =
+			self.cursor.selection(self.value).unwrap_or_else(|| {
+				let caret = self.cursor.end(self.value);
+				(caret, caret)
+			});
+
+		let first = self.value.byte_to_line(start);
+		let mut last = self.value.byte_to_line(end);
+		if last > first && self.value.line_to_byte(last) == end {
+			last -= 1;
+		}
+
+		first..=last
+	}
+
+	/// Moves the cursor from `before`, its state before an edit, with `map`.
+	///
+	/// `map` is given an index from before the edit, and whether it's the
+	/// end of a selection or a caret without one rather than the start of a
+	/// selection, and returns where that index is after the edit.
+	fn map_cursor(
+		&mut self,
+		before: cursor::State,
+		map: impl Fn(usize, bool) -> usize,
+	) {
+		match before {
+			cursor::State::Index(index) => {
+				self.cursor.move_to_byte(map(index, true));
+			}
+			cursor::State::Selection { start, end } => {
+				self.cursor.select_range(
+					map(start, start > end),
+					map(end, end > start),
+				);
+			}
+		}
+	}
+
+	fn remove_bytes(&mut self, start: usize, end: usize) {
+		let start = self.value.byte_to_char(start);
+		let end = self.value.byte_to_char(end);
+		self.value.remove(start..end);
+	}
+}
+
+/// Splits the line break off the end of `text`, if it has one.
+fn split_line_break(text: &str) -> (&str, &str) {
+	let content = text.trim_end_matches(&['\r', '\n'][..]);
+	text.split_at(content.len())
+}
+
+/// Returns the start of the line break ending just before `index`, if any.
+fn line_break_before(value: &Rope, index: usize) -> Option<usize> {
+	if index == 0 || value.byte(index - 1) != b'\n' {
+		return None;
+	}
+
+	if index >= 2 && value.byte(index - 2) == b'\r' {
+		Some(index - 2)
+	} else {
+		Some(index - 1)
+	}
+}
+
+/// Returns the end of the line break starting at `index`, if any.
+fn line_break_after(value: &Rope, index: usize) -> Option<usize> {
+	match value.get_byte(index)? {
+		b'\n' => Some(index + 1),
+		b'\r' if value.get_byte(index + 1) == Some(b'\n') => Some(index + 2),
+		_ => None,
+	}
+}
+
+/// Applies `edit` at every cursor, from last to first.
+///
+/// Editing from the end keeps the offsets of the cursors that are yet to be
+/// edited valid; the ones that were already edited are shifted by however
+/// much each later edit changed the length of the value.
+pub fn edit_all(
+	value: &mut Rope,
+	primary: &mut Cursor,
+	others: &mut [Cursor],
+	mut edit: impl FnMut(&mut Editor<'_>),
+) {
+	let mut cursors = iter::once(primary)
+		.chain(others.iter_mut())
+		.collect::<Vec<_>>();
+
+	cursors.sort_by_key(|cursor| {
+		Reverse(
+			cursor
+				.selection(value)
+				.map_or_else(|| cursor.end(value), |(start, _)| start),
+		)
+	});
+
+	let mut edited: Vec<&mut Cursor> = Vec::with_capacity(cursors.len());
+
+	for cursor in cursors {
+		let len_before = value.len_bytes();
+
+		edit(&mut Editor::new(value, &mut *cursor));
+
+		let delta = value.len_bytes() as isize - len_before as isize;
+		for edited_cursor in &mut edited {
+			edited_cursor.shift(delta);
+		}
+
+		edited.push(cursor);
+	}
 }
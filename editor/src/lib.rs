@@ -6,11 +6,22 @@ pub mod cursor;
 mod editor;
 mod rope_ext;
 pub mod style;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
+mod wrap;
+
+use std::{
+	borrow::Cow,
+	cell::Cell,
+	collections::VecDeque,
+	iter,
+	ops::{ControlFlow, Range},
+	time::{Duration, Instant},
+};
 
-use std::{borrow::Cow, ops::ControlFlow};
-
+use cursor::find_line_content_end;
 pub use cursor::Cursor;
-use editor::Editor;
+use editor::{edit_all, Editor};
 use iced_graphics::{alignment, Color, Vector};
 use iced_native::{
 	event::{self, Event},
@@ -22,11 +33,13 @@ use iced_native::{
 	Shell, Size, Widget,
 };
 use ordered_float::NotNan;
-use rope_ext::RopeExt;
+use rope_ext::{replace_tab, RopeExt};
 pub use ropey::Rope;
 use ropey::RopeSlice;
 use style::StyleSheet;
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
+use wrap::{LineLengthCache, Rows, RowsCache};
 
 /// A field that can be filled with text.
 ///
@@ -60,11 +73,26 @@ pub struct TextInput<'a, Message, Renderer: text::Renderer> {
 	font: Renderer::Font,
 	width: Length,
 	height: Length,
+	max_height: Option<u32>,
 	padding: Padding,
 	size: Option<u16>,
+	phantom_line: bool,
 	tab_width: u8,
+	render_whitespace: RenderWhitespace,
+	indent_guides: bool,
+	line_numbers: bool,
+	word_wrap: bool,
+	cursor_blink_rate: Option<Duration>,
+	paste_line_ending: LineEnding,
+	normalization: Normalization,
+	word_chars: &'a str,
+	marks: &'a [(usize, MarkKind)],
+	trim_trailing_whitespace: bool,
+	line_comment: Option<String>,
 	on_change: Box<dyn Fn(String) -> Message + 'a>,
 	on_submit: Option<Message>,
+	on_focus: Option<Message>,
+	on_blur: Option<Message>,
 	style_sheet: Box<dyn StyleSheet + 'a>,
 }
 
@@ -90,11 +118,26 @@ where
 			font: Default::default(),
 			width: Length::Fill,
 			height: Length::Fill,
+			max_height: None,
 			padding: Padding::ZERO,
 			size: None,
+			phantom_line: true,
 			tab_width: 4,
+			render_whitespace: RenderWhitespace::None,
+			indent_guides: false,
+			line_numbers: false,
+			word_wrap: false,
+			cursor_blink_rate: None,
+			paste_line_ending: LineEnding::Lf,
+			normalization: Normalization::None,
+			word_chars: "",
+			marks: &[],
+			trim_trailing_whitespace: false,
+			line_comment: None,
 			on_change: Box::new(on_change),
 			on_submit: None,
+			on_focus: None,
+			on_blur: None,
 			style_sheet: Default::default(),
 		}
 	}
@@ -112,12 +155,21 @@ where
 		self.width = width;
 		self
 	}
-	/// Sets the width of the [`TextInput`].
+	/// Sets the height of the [`TextInput`].
+	///
+	/// With [`Length::Shrink`], the [`TextInput`] is as tall as its contents.
 	pub fn height(mut self, height: Length) -> Self {
 		self.height = height;
 		self
 	}
 
+	/// Sets the maximum height of the [`TextInput`], past which its contents
+	/// scroll.
+	pub fn max_height(mut self, max_height: u32) -> Self {
+		self.max_height = Some(max_height);
+		self
+	}
+
 	/// Sets the [`Padding`] of the [`TextInput`].
 	pub fn padding<P: Into<Padding>>(mut self, padding: P) -> Self {
 		self.padding = padding.into();
@@ -125,17 +177,190 @@ where
 	}
 
 	/// Sets the text size of the [`TextInput`].
+	///
+	/// The scroll offset is rescaled immediately, so that changing the size
+	/// between frames keeps the cursor roughly where it was on screen.
 	pub fn size(mut self, size: u16) -> Self {
 		self.size = Some(size);
+		self.state.new_size(size);
+		self
+	}
+
+	/// Sets whether an extra line of height is reserved below the last line,
+	/// which matters with [`Length::Shrink`]. Defaults to `true`.
+	pub fn phantom_line(mut self, enabled: bool) -> Self {
+		self.phantom_line = enabled;
+		self
+	}
+
+	/// Sets whether copying or cutting with nothing selected acts on the
+	/// whole line at the caret, including its line break. Defaults to `true`.
+	pub fn copy_line_when_empty(self, enabled: bool) -> Self {
+		self.state.copy_line_when_empty = enabled;
+		self
+	}
+
+	/// Sets whether triple clicking selects all of the text instead of the
+	/// line clicked. Defaults to `false`.
+	pub fn triple_click_selects_all(self, enabled: bool) -> Self {
+		self.state.triple_click_selects_all = enabled;
+		self
+	}
+
+	/// Sets whether typing a line break inserts one. Without this, Enter only
+	/// submits the [`TextInput`]. Defaults to `true`.
+	pub fn multiline(self, enabled: bool) -> Self {
+		self.state.multiline = enabled;
+		self
+	}
+
+	/// Sets how line breaks in text pasted into a [`TextInput`] that isn't
+	/// [`multiline`](TextInput::multiline) are removed. Defaults to
+	/// [`SingleLinePaste::Flatten`].
+	pub fn single_line_paste(self, mode: SingleLinePaste) -> Self {
+		self.state.single_line_paste = mode;
+		self
+	}
+
+	/// Sets the distance between lines, as a multiple of the text size.
+	/// Defaults to `1.0`.
+	pub fn line_height(self, multiplier: f32) -> Self {
+		self.state.line_height = multiplier;
+		self
+	}
+
+	/// Sets whether the [`TextInput`] keeps scrolled to the bottom as its
+	/// contents grow, like a terminal. Scrolling up stops following the
+	/// bottom until the user scrolls back down to it.
+	pub fn sticky_scroll(self, enabled: bool) -> Self {
+		self.state.sticky_scroll = enabled;
+		self
+	}
+
+	/// Sets whether moving by words also stops within `snake_case` and
+	/// `camelCase` identifiers.
+	pub fn subword_motion(self, enabled: bool) -> Self {
+		self.state.subword_motion = enabled;
+		self
+	}
+
+	/// Sets whether typing a tab inserts spaces up to the next tab stop
+	/// instead of a tab character.
+	pub fn soft_tabs(self, enabled: bool) -> Self {
+		self.state.soft_tabs = enabled;
 		self
 	}
 
 	/// Set the tab width of the [`TextInput`].
+	///
+	/// Shift+Tab always inserts a literal tab character, even with
+	/// [`soft_tabs`](TextInput::soft_tabs), unless the selection spans
+	/// multiple lines. Over multiple lines, Tab and Shift+Tab indent and
+	/// dedent each line by a tab width instead.
 	pub fn tab_width(mut self, tab_width: u8) -> Self {
 		self.tab_width = tab_width;
 		self
 	}
 
+	/// Sets which whitespace the [`TextInput`] draws markers for.
+	pub fn render_whitespace(mut self, mode: RenderWhitespace) -> Self {
+		self.render_whitespace = mode;
+		self
+	}
+
+	/// Sets whether the [`TextInput`] draws markers for all whitespace, a
+	/// shorthand for [`render_whitespace`](TextInput::render_whitespace) with
+	/// [`RenderWhitespace::All`] or [`RenderWhitespace::None`].
+	pub fn show_whitespace(self, enabled: bool) -> Self {
+		self.render_whitespace(if enabled {
+			RenderWhitespace::All
+		} else {
+			RenderWhitespace::None
+		})
+	}
+
+	/// Sets whether the [`TextInput`] draws a vertical guide at each level of
+	/// indentation.
+	pub fn indent_guides(mut self, enabled: bool) -> Self {
+		self.indent_guides = enabled;
+		self
+	}
+
+	/// Sets whether line numbers are drawn in a gutter to the left of the
+	/// text.
+	pub fn line_numbers(mut self, enabled: bool) -> Self {
+		self.line_numbers = enabled;
+		self
+	}
+
+	/// Sets whether lines wider than the [`TextInput`] wrap at word
+	/// boundaries onto more rows, instead of scrolling sideways. Defaults to
+	/// `false`.
+	///
+	/// Indent guides aren't drawn while wrapping.
+	pub fn word_wrap(mut self, enabled: bool) -> Self {
+		self.word_wrap = enabled;
+		self
+	}
+
+	/// Sets how long each blink of the caret takes, shown for the first half
+	/// and hidden for the second, or `None` to keep it shown. Defaults to
+	/// `None`.
+	///
+	/// Blinking starts over whenever the caret is moved or text is typed, so
+	/// the caret stays shown while typing. A [`TextInput`] can't redraw
+	/// itself, so to blink, something else has to redraw it while it's
+	/// focused, every half a blink after
+	/// [`State::last_cursor_move`].
+	pub fn cursor_blink_rate(mut self, rate: Option<Duration>) -> Self {
+		self.cursor_blink_rate = rate;
+		self
+	}
+
+	/// Sets the line ending that pasted text is normalized to.
+	pub fn paste_line_ending(mut self, line_ending: LineEnding) -> Self {
+		self.paste_line_ending = line_ending;
+		self
+	}
+
+	/// Sets the Unicode normalization applied to typed and pasted text.
+	pub fn normalize(mut self, normalization: Normalization) -> Self {
+		self.normalization = normalization;
+		self
+	}
+
+	/// Sets extra characters that double-clicking treats as part of a word,
+	/// such as `.` to select `foo.bar` at once.
+	///
+	/// By default, words are split at unicode word boundaries.
+	pub fn word_chars(mut self, word_chars: &'a str) -> Self {
+		self.word_chars = word_chars;
+		self
+	}
+
+	/// Sets the byte indices of notable places in the text, such as errors,
+	/// which are marked with ticks in a ruler along the right edge. Clicking
+	/// a tick scrolls to its mark.
+	pub fn marks(mut self, marks: &'a [(usize, MarkKind)]) -> Self {
+		self.marks = marks;
+		self
+	}
+
+	/// Sets whether trailing spaces and tabs are removed from every line
+	/// before the [`TextInput`] is submitted. Defaults to `false`.
+	pub fn trim_trailing_whitespace_on_submit(mut self, enabled: bool) -> Self {
+		self.trim_trailing_whitespace = enabled;
+		self
+	}
+
+	/// Sets the prefix that starts a comment running to the end of the line,
+	/// such as `"#"` or `"//"`, with which Ctrl+/ comments or uncomments the
+	/// selected lines. Without one, Ctrl+/ does nothing. Defaults to `None`.
+	pub fn line_comment(mut self, prefix: Option<String>) -> Self {
+		self.line_comment = prefix;
+		self
+	}
+
 	/// Sets the message that should be produced when the [`TextInput`] is
 	/// focused and the enter key is pressed.
 	pub fn on_submit(mut self, message: Message) -> Self {
@@ -143,6 +368,20 @@ where
 		self
 	}
 
+	/// Sets the message that should be produced when the [`TextInput`] gains
+	/// focus by being clicked.
+	pub fn on_focus(mut self, message: Message) -> Self {
+		self.on_focus = Some(message);
+		self
+	}
+
+	/// Sets the message that should be produced when the [`TextInput`] loses
+	/// focus, by clicking elsewhere or pressing Escape with nothing selected.
+	pub fn on_blur(mut self, message: Message) -> Self {
+		self.on_blur = Some(message);
+		self
+	}
+
 	/// Sets the style of the [`TextInput`].
 	pub fn style(
 		mut self,
@@ -173,38 +412,228 @@ where
 			&self.placeholder,
 			self.size,
 			self.tab_width,
+			self.render_whitespace,
+			self.indent_guides,
+			self.line_numbers,
+			self.word_wrap,
+			self.cursor_blink_rate,
+			self.marks,
 			&self.font,
 			self.style_sheet.as_ref(),
 		)
 	}
 }
 
+/// Which whitespace a [`TextInput`] draws visible markers for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderWhitespace {
+	/// Whitespace is invisible.
+	None,
+	/// Only leading and trailing whitespace on each line is marked.
+	Boundary,
+	/// All whitespace is marked.
+	All,
+}
+
+/// What a mark in the ruler of a [`TextInput`] stands for, which decides its
+/// color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkKind {
+	/// A match of a search.
+	Match,
+	/// An error reported about the text.
+	Error,
+}
+
+/// How a [`TextInput`] that isn't multiline removes line breaks from pasted
+/// text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SingleLinePaste {
+	/// Each line break is replaced with a space.
+	Flatten,
+	/// Everything from the first line break on is left out.
+	FirstLine,
+}
+
+impl SingleLinePaste {
+	/// Removes the line breaks from `text`.
+	pub fn apply(self, mut text: String) -> String {
+		match self {
+			SingleLinePaste::Flatten if text.contains(&['\r', '\n'][..]) => {
+				LineEnding::Lf.normalize(&text).replace('\n', " ")
+			}
+			SingleLinePaste::Flatten => text,
+			SingleLinePaste::FirstLine => {
+				if let Some(end) = text.find(&['\r', '\n'][..]) {
+					text.truncate(end);
+				}
+				text
+			}
+		}
+	}
+}
+
+/// The line ending that a [`TextInput`] converts pasted text to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+	/// Pasted text is inserted unchanged.
+	AsIs,
+	/// Line endings are converted to `\n`.
+	Lf,
+	/// Line endings are converted to `\r\n`.
+	Crlf,
+}
+
+impl LineEnding {
+	/// Converts every `\r\n`, `\r` and `\n` in `text` to this line ending.
+	pub fn normalize(self, text: &str) -> Cow<'_, str> {
+		let ending = match self {
+			LineEnding::AsIs => return Cow::Borrowed(text),
+			LineEnding::Lf if !text.contains('\r') => {
+				return Cow::Borrowed(text);
+			}
+			LineEnding::Lf => "\n",
+			LineEnding::Crlf => "\r\n",
+		};
+
+		let mut normalized = String::with_capacity(text.len());
+		let mut chars = text.chars().peekable();
+
+		while let Some(c) = chars.next() {
+			match c {
+				'\r' => {
+					chars.next_if_eq(&'\n');
+					normalized.push_str(ending);
+				}
+				'\n' => normalized.push_str(ending),
+				c => normalized.push(c),
+			}
+		}
+
+		Cow::Owned(normalized)
+	}
+}
+
+/// The Unicode normalization form that a [`TextInput`] converts typed and
+/// pasted text to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+	/// Text is inserted unchanged.
+	None,
+	/// Text is converted to Normalization Form C, so that characters are
+	/// composed where possible. A typed combining character is composed with
+	/// the character before the cursor.
+	Nfc,
+}
+
+impl Normalization {
+	/// Converts `text` to this normalization form.
+	pub fn normalize(self, text: &str) -> Cow<'_, str> {
+		match self {
+			Normalization::Nfc if !unicode_normalization::is_nfc(text) => {
+				Cow::Owned(text.nfc().collect())
+			}
+			_ => Cow::Borrowed(text),
+		}
+	}
+}
+
 /// Computes the layout of a [`TextInput`].
+///
+/// With `phantom_line`, an extra line of height is reserved below the last
+/// line. Lines are `line_height` times the text size apart. With
+/// `line_numbers`, the text is moved right to make room for a gutter, and
+/// with `ruler`, room is kept right of the text for the ruler of marks.
+#[allow(clippy::too_many_arguments)]
 pub fn layout<Renderer>(
 	renderer: &Renderer,
 	limits: &layout::Limits,
 	width: Length,
 	height: Length,
-	value: &Rope,
+	max_height: Option<u32>,
+	state: &State,
 	padding: Padding,
 	size: Option<u16>,
+	phantom_line: bool,
+	line_height: f32,
+	line_numbers: bool,
+	tab_width: u8,
+	word_wrap: bool,
+	ruler: bool,
+	font: &Renderer::Font,
 ) -> layout::Node
 where
 	Renderer: text::Renderer,
 {
 	let text_size = size.unwrap_or_else(|| renderer.default_size());
 
-	let line_count = value.len_lines() + 1;
+	let value = &state.value;
+	let gutter_width = if line_numbers {
+		gutter_width(value, renderer, font.clone(), text_size)
+	} else {
+		0.0
+	};
 
-	let text_height = text_size as usize * line_count;
+	let limits = match max_height {
+		Some(max_height) => limits.max_height(max_height),
+		None => *limits,
+	};
+	let ruler_width = if ruler { RULER_WIDTH } else { 0.0 };
+
+	let limits = limits
+		.pad(padding)
+		.shrink(Size::new(gutter_width + ruler_width, 0.0))
+		.width(width)
+		.height(height);
+
+	// wrapped lines take up as many rows as they're wrapped into
+	let wrap_width = word_wrap
+		.then(|| limits.resolve(Size::new(limits.max().width, 0.0)).width);
+	let rows = state.rows_cache.get(
+		value,
+		renderer,
+		font.clone(),
+		text_size,
+		tab_width,
+		wrap_width,
+	);
+	let line_count = rows.len(value) + usize::from(phantom_line);
 
-	let limits = limits.pad(padding).width(width).height(height);
+	let text_height = f32::from(text_size) * line_height * line_count as f32;
 
 	let mut text =
-		layout::Node::new(limits.resolve(Size::new(0.0, text_height as f32)));
-	text.move_to(Point::new(padding.left.into(), padding.top.into()));
+		layout::Node::new(limits.resolve(Size::new(0.0, text_height)));
+	text.move_to(Point::new(
+		f32::from(padding.left) + gutter_width,
+		padding.top.into(),
+	));
+
+	let size = Size::new(
+		text.size().width + gutter_width + ruler_width,
+		text.size().height,
+	);
+
+	layout::Node::with_children(size.pad(padding), vec![text])
+}
+
+/// The space on each side of the line numbers in the gutter.
+const GUTTER_PADDING: f32 = 8.0;
+
+/// Computes the width of the gutter of line numbers, which fits the number of
+/// the last line of `value`.
+fn gutter_width<Renderer>(
+	value: &Rope,
+	renderer: &Renderer,
+	font: Renderer::Font,
+	size: u16,
+) -> f32
+where
+	Renderer: text::Renderer,
+{
+	let digits = value.len_lines().to_string().len();
 
-	layout::Node::with_children(text.size().pad(padding), vec![text])
+	renderer.measure_width(&"0".repeat(digits), size, font)
+		+ GUTTER_PADDING * 2.0
 }
 
 /// Processes an [`Event`] and updates the [`State`] of a [`TextInput`]
@@ -219,9 +648,18 @@ pub fn update<'a, Message, Renderer>(
 	shell: &mut Shell<'_, Message>,
 	size: Option<u16>,
 	tab_width: u8,
+	paste_line_ending: LineEnding,
+	normalization: Normalization,
+	word_chars: &str,
+	marks: &[(usize, MarkKind)],
+	trim_trailing_whitespace: bool,
+	word_wrap: bool,
+	line_comment: Option<&str>,
 	font: &Renderer::Font,
 	on_change: &dyn Fn(String) -> Message,
 	on_submit: &Option<Message>,
+	on_focus: &Option<Message>,
+	on_blur: &Option<Message>,
 	state: impl FnOnce() -> &'a mut State,
 ) -> event::Status
 where
@@ -233,14 +671,130 @@ where
 	let text_bounds = layout.children().next().unwrap().bounds();
 
 	state.new_size(size);
+	state.wrap_width = word_wrap.then_some(text_bounds.width);
+
+	// restart the caret's blink whenever it might move
+	if matches!(
+		event,
+		Event::Keyboard(keyboard::Event::KeyPressed { .. })
+			| Event::Keyboard(keyboard::Event::CharacterReceived(_))
+			| Event::Mouse(mouse::Event::ButtonPressed(_))
+			| Event::Touch(touch::Event::FingerPressed { .. })
+	) || (state.is_dragging
+		&& matches!(event, Event::Mouse(mouse::Event::CursorMoved { .. })))
+	{
+		state.last_cursor_move = Instant::now();
+	}
+	state.refresh_rows(renderer, font.clone(), size, tab_width);
+
+	if state.rows.is_wrapping() {
+		state.scroll.x = 0.0;
+	} else if std::mem::take(&mut state.clamp_scroll) {
+		let max_x =
+			state.max_line_length(renderer, font.clone(), size, tab_width)
+				- text_bounds.width;
+		state.scroll.x = state.scroll.x.min(max_x.max(0.0));
+	}
+
+	if std::mem::take(&mut state.scroll_to_cursor) {
+		state.recalculate_scroll_offset(
+			renderer,
+			text_bounds.size(),
+			font.clone(),
+			size,
+			tab_width,
+		);
+	}
+
+	let line_count = state.rows.len(&state.value);
+	if state.sticky_scroll
+		&& state.follow_bottom
+		&& line_count > state.last_line_count
+	{
+		state.scroll.y = state.bottom_scroll_offset(size, text_bounds.height);
+	}
+	state.last_line_count = line_count;
 
 	match event {
 		Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
 		| Event::Touch(touch::Event::FingerPressed { .. }) => {
 			let is_clicked = layout.bounds().contains(cursor_position);
 
+			if is_clicked && !state.is_focused {
+				if let Some(on_focus) = on_focus.clone() {
+					shell.publish(on_focus);
+				}
+			} else if !is_clicked && state.is_focused {
+				if let Some(on_blur) = on_blur.clone() {
+					shell.publish(on_blur);
+				}
+			}
+
 			state.is_focused = is_clicked;
 
+			let mark = if is_clicked {
+				mark_at_point(marks, &state.value, text_bounds, cursor_position)
+			} else {
+				None
+			};
+			if let Some(index) = mark {
+				// center the mark's row in the view
+				let line = state.rows.row_of(&state.value, index);
+				let pitch = state.line_pitch(size);
+				let bottom =
+					state.bottom_scroll_offset(size, text_bounds.height);
+				state.scroll.y = (line as f32 * pitch
+					- (text_bounds.height - pitch) / 2.0)
+					.max(0.0)
+					.min(bottom);
+
+				if state.sticky_scroll {
+					state.follow_bottom = state.scroll.y >= bottom;
+				}
+
+				return event::Status::Captured;
+			}
+
+			for scrollbar in [Scrollbar::Vertical, Scrollbar::Horizontal] {
+				let geometry = scrollbar_geometry(
+					scrollbar,
+					state,
+					&state.rows,
+					renderer,
+					&TextLayout {
+						font,
+						size,
+						tab_width,
+						bounds: text_bounds,
+					},
+				);
+
+				if let Some(geometry) = geometry
+					.filter(|g| is_clicked && g.track.contains(cursor_position))
+				{
+					let (thumb_start, thumb_length) =
+						scrollbar.extent(geometry.thumb);
+
+					// clicking the track outside the thumb centers the thumb
+					// on the click
+					let grab = if geometry.thumb.contains(cursor_position) {
+						scrollbar.along(cursor_position) - thumb_start
+					} else {
+						thumb_length / 2.0
+					};
+
+					state.drag_scrollbar(
+						scrollbar,
+						&geometry,
+						grab,
+						cursor_position,
+					);
+					state.scrollbar_drag = Some((scrollbar, grab));
+
+					return event::Status::Captured;
+				}
+			}
+
 			if is_clicked {
 				let offset = cursor_position - text_bounds.position();
 				let click =
@@ -258,10 +812,24 @@ where
 								Point::ORIGIN + offset,
 							)
 						} else {
-							None
+							0
 						};
 
-						state.cursor.move_to_byte(position.unwrap_or(0));
+						if state.keyboard_modifiers.shift() {
+							// extend the selection from its anchor
+							let anchor = state.cursor.start(&state.value);
+							state.extra_cursors.clear();
+							state.cursor.select_range(anchor, position);
+						} else {
+							if state.keyboard_modifiers.alt() {
+								state.extra_cursors.push(state.cursor);
+							} else {
+								state.extra_cursors.clear();
+							}
+
+							state.cursor.move_to_byte(position);
+						}
+						state.merge_cursors();
 						state.is_dragging = true;
 					}
 					click::Kind::Double => {
@@ -272,22 +840,46 @@ where
 							tab_width,
 							state,
 							Point::ORIGIN + offset,
-						)
-						.unwrap_or(0);
-
-						state.cursor.select_range(
-							state.value.previous_start_of_word(position),
-							state.value.next_end_of_word(position),
 						);
 
-						state.is_dragging = false;
+						let (start, end) =
+							word_bounds_at(&state.value, position, word_chars);
+
+						state.extra_cursors.clear();
+						state.cursor.select_range(start, end);
+
+						state.drag_anchor = (start, end);
+						state.drag_word_chars = word_chars.to_owned();
+						state.is_dragging = true;
 					}
-					click::Kind::Triple => {
+					click::Kind::Triple if state.triple_click_selects_all => {
+						state.extra_cursors.clear();
 						state.cursor.select_all(&state.value);
 						state.is_dragging = false;
 					}
+					click::Kind::Triple => {
+						let position = index_at_point(
+							renderer,
+							font.clone(),
+							size,
+							tab_width,
+							state,
+							Point::ORIGIN + offset,
+						);
+
+						let (start, end) =
+							line_bounds_at(&state.value, position);
+
+						state.extra_cursors.clear();
+						state.cursor.select_range(start, end);
+
+						state.drag_anchor = (start, end);
+						state.is_dragging = true;
+					}
 				}
 
+				state.drag_kind = click.kind();
+
 				state.last_click = Some(click);
 
 				return event::Status::Captured;
@@ -297,11 +889,75 @@ where
 		| Event::Touch(touch::Event::FingerLifted { .. })
 		| Event::Touch(touch::Event::FingerLost { .. }) => {
 			state.is_dragging = false;
+			state.auto_scroll = None;
+			state.scrollbar_drag = None;
 		}
 		Event::Mouse(mouse::Event::CursorMoved { position })
 		| Event::Touch(touch::Event::FingerMoved { position, .. }) => {
+			if let Some((scrollbar, grab)) = state.scrollbar_drag {
+				if let Some(geometry) = scrollbar_geometry(
+					scrollbar,
+					state,
+					&state.rows,
+					renderer,
+					&TextLayout {
+						font,
+						size,
+						tab_width,
+						bounds: text_bounds,
+					},
+				) {
+					state.drag_scrollbar(scrollbar, &geometry, grab, position);
+				}
+
+				return event::Status::Captured;
+			}
+
 			if state.is_dragging {
-				let offset = position - text_bounds.position();
+				// past the edge of the text, select up to the edge and keep
+				// scrolling towards the pointer
+				let edge = Point::new(
+					position
+						.x
+						.max(text_bounds.x)
+						.min(text_bounds.x + text_bounds.width),
+					position
+						.y
+						.max(text_bounds.y)
+						.min(text_bounds.y + text_bounds.height),
+				);
+				let offset = edge - text_bounds.position();
+
+				state.auto_scroll = if edge != position {
+					let max_x = state.max_line_length(
+						renderer,
+						font.clone(),
+						size,
+						tab_width,
+					) - text_bounds.width;
+
+					let mut velocity = (position - edge) * AUTO_SCROLL_SPEED;
+					if state.rows.is_wrapping() {
+						velocity.x = 0.0;
+					}
+
+					Some(AutoScroll {
+						point: Point::ORIGIN + offset,
+						velocity,
+						max_scroll: Vector::new(
+							max_x.max(0.0),
+							state
+								.bottom_scroll_offset(size, text_bounds.height),
+						),
+					})
+				} else {
+					None
+				};
+				state.auto_scroll_target.set(None);
+
+				if let Some(auto_scroll) = state.auto_scroll {
+					state.step_auto_scroll(&auto_scroll);
+				}
 
 				let position = index_at_point(
 					renderer,
@@ -310,12 +966,9 @@ where
 					tab_width,
 					state,
 					Point::ORIGIN + offset,
-				)
-				.unwrap_or(0);
+				);
 
-				state
-					.cursor
-					.select_range(state.cursor.start(&state.value), position);
+				state.drag_to(position);
 
 				return event::Status::Captured;
 			}
@@ -323,21 +976,26 @@ where
 		Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
 			let delta = match delta {
 				mouse::ScrollDelta::Lines { x, y } => {
-					let size = f32::from(size);
-					Vector::new(x * size, y * size * -1.0)
+					let pitch = state.line_pitch(size);
+					Vector::new(x * pitch, y * pitch * -1.0)
 				}
 				mouse::ScrollDelta::Pixels { x, y } => Vector::new(x, y),
 			};
 
 			if delta.y.abs() > 0.1 {
-				state.scroll.y = (state.scroll.y + delta.y)
-					.max(0.0)
-					.min(state.value.len_lines() as f32 * f32::from(size));
+				state.scroll.y = (state.scroll.y + delta.y).max(0.0).min(
+					state.rows.len(&state.value) as f32
+						* state.line_pitch(size),
+				);
+
+				if state.sticky_scroll {
+					state.follow_bottom = state.scroll.y
+						>= state.bottom_scroll_offset(size, text_bounds.height);
+				}
 			}
 
-			if delta.x.abs() > 0.1 {
-				let max = (max_line_length(
-					&state.value,
+			if delta.x.abs() > 0.1 && !state.rows.is_wrapping() {
+				let max = (state.max_line_length(
 					renderer,
 					font.clone(),
 					size,
@@ -353,16 +1011,53 @@ where
 				&& !state.keyboard_modifiers.command()
 				&& (!c.is_control() || c == '\n' || c == '\r' || c == '\t')
 			{
-				let mut editor =
-					Editor::new(&mut state.value, &mut state.cursor);
-
-				editor.insert(c);
+				// Shift+Tab, and Tab over multiple lines, are handled when the
+				// key is pressed, and may or may not also send a tab character
+				// depending on the platform
+				if c == '\t'
+					&& (state.keyboard_modifiers.shift()
+						|| state.has_multiline_selection())
+				{
+					return event::Status::Captured;
+				}
 
-				if c == '\r' {
-					editor.insert('\n');
+				// Enter submits instead
+				if (c == '\n' || c == '\r') && !state.multiline {
+					return event::Status::Captured;
 				}
 
-				let message = (on_change)(editor.contents());
+				let before = state.snapshot();
+				let soft_tabs = state.soft_tabs;
+				let is_overwrite = state.is_overwrite;
+				state.edit_all(|editor| {
+					if c == '\t' && soft_tabs {
+						editor.insert_soft_tab(tab_width);
+						return;
+					}
+
+					if normalization == Normalization::Nfc && editor.compose(c)
+					{
+						return;
+					}
+
+					// line breaks are never typed over
+					if is_overwrite && c != '\n' && c != '\r' {
+						editor.overwrite(c);
+					} else {
+						editor.insert(c);
+					}
+
+					if c == '\r' {
+						editor.insert('\n');
+					}
+
+					if c == '\n' || c == '\r' {
+						editor.copy_indent(soft_tabs, tab_width);
+					}
+				});
+				state.push_undo_typing(before, c);
+
+				let message = (on_change)(state.contents());
 				shell.publish(message);
 
 				state.recalculate_scroll_offset(
@@ -380,27 +1075,60 @@ where
 			if state.is_focused {
 				let modifiers = state.keyboard_modifiers;
 
+				let is_navigation = matches!(
+					key_code,
+					keyboard::KeyCode::Left
+						| keyboard::KeyCode::Right
+						| keyboard::KeyCode::Up
+						| keyboard::KeyCode::Down
+						| keyboard::KeyCode::Home
+						| keyboard::KeyCode::End
+						| keyboard::KeyCode::Escape
+				);
+				let is_single_cursor_command = modifiers.command()
+					&& matches!(
+						key_code,
+						keyboard::KeyCode::A
+							| keyboard::KeyCode::X
+							| keyboard::KeyCode::V
+					);
+
+				if is_navigation || is_single_cursor_command {
+					state.extra_cursors.clear();
+				}
+
 				match key_code {
 					keyboard::KeyCode::Enter
 					| keyboard::KeyCode::NumpadEnter
 						if !state.keyboard_modifiers.control() =>
 					{
 						if let Some(on_submit) = on_submit.clone() {
-							shell.publish(on_submit);
-						}
+							if trim_trailing_whitespace
+								&& state.trim_trailing_whitespace()
+							{
+								let message = (on_change)(state.contents());
+								shell.publish(message);
+							}
+
+							shell.publish(on_submit);
+						}
 					}
 					keyboard::KeyCode::Backspace => {
-						if platform::is_jump_modifier_pressed(modifiers)
-							&& state.cursor.selection(&state.value).is_none()
+						let before = state.snapshot();
+
+						if platform::is_delete_word_modifier_pressed(modifiers)
 						{
-							state.cursor.select_left_by_words(&state.value);
+							let subwords = state.subword_motion;
+							state.edit_all(|editor| {
+								editor.delete_word_left(subwords)
+							});
+						} else {
+							state.edit_all(|editor| editor.backspace());
 						}
 
-						let mut editor =
-							Editor::new(&mut state.value, &mut state.cursor);
-						editor.backspace();
+						state.push_undo(before);
 
-						let message = (on_change)(editor.contents());
+						let message = (on_change)(state.contents());
 						shell.publish(message);
 
 						state.recalculate_scroll_offset(
@@ -412,17 +1140,21 @@ where
 						);
 					}
 					keyboard::KeyCode::Delete => {
-						if platform::is_jump_modifier_pressed(modifiers)
-							&& state.cursor.selection(&state.value).is_none()
+						let before = state.snapshot();
+
+						if platform::is_delete_word_modifier_pressed(modifiers)
 						{
-							state.cursor.select_right_by_words(&state.value);
+							let subwords = state.subword_motion;
+							state.edit_all(|editor| {
+								editor.delete_word_right(subwords)
+							});
+						} else {
+							state.edit_all(|editor| editor.delete());
 						}
 
-						let mut editor =
-							Editor::new(&mut state.value, &mut state.cursor);
-						editor.delete();
+						state.push_undo(before);
 
-						let message = (on_change)(editor.contents());
+						let message = (on_change)(state.contents());
 						shell.publish(message);
 
 						state.recalculate_scroll_offset(
@@ -436,9 +1168,15 @@ where
 					keyboard::KeyCode::Left => {
 						if platform::is_jump_modifier_pressed(modifiers) {
 							if modifiers.shift() {
-								state.cursor.select_left_by_words(&state.value);
+								state.cursor.select_left_by_words(
+									&state.value,
+									state.subword_motion,
+								);
 							} else {
-								state.cursor.move_left_by_words(&state.value);
+								state.cursor.move_left_by_words(
+									&state.value,
+									state.subword_motion,
+								);
 							}
 						} else if modifiers.shift() {
 							state.cursor.select_left(&state.value)
@@ -457,11 +1195,15 @@ where
 					keyboard::KeyCode::Right => {
 						if platform::is_jump_modifier_pressed(modifiers) {
 							if modifiers.shift() {
-								state
-									.cursor
-									.select_right_by_words(&state.value);
+								state.cursor.select_right_by_words(
+									&state.value,
+									state.subword_motion,
+								);
 							} else {
-								state.cursor.move_right_by_words(&state.value);
+								state.cursor.move_right_by_words(
+									&state.value,
+									state.subword_motion,
+								);
 							}
 						} else if modifiers.shift() {
 							state.cursor.select_right(&state.value)
@@ -477,10 +1219,63 @@ where
 							tab_width,
 						);
 					}
+					keyboard::KeyCode::Up
+						if modifiers.alt() && modifiers.shift() =>
+					{
+						state.expand_selection();
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::Down
+						if modifiers.alt() && modifiers.shift() =>
+					{
+						state.shrink_selection();
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::Up | keyboard::KeyCode::Down
+						if modifiers.alt() =>
+					{
+						let before = state.snapshot();
+						let up = key_code == keyboard::KeyCode::Up;
+						// cursors are only shifted by changes in length, so
+						// lines are only moved at the primary cursor
+						state.extra_cursors.clear();
+						let mut moved = false;
+						state.edit_all(|editor| moved = editor.move_lines(up));
+
+						if moved {
+							state.push_undo(before);
+
+							let message = (on_change)(state.contents());
+							shell.publish(message);
+
+							state.recalculate_scroll_offset(
+								renderer,
+								text_bounds.size(),
+								font.clone(),
+								size,
+								tab_width,
+							);
+						}
+					}
 					keyboard::KeyCode::Up => {
 						if modifiers.shift() {
 							state.cursor.select_up(
 								&state.value,
+								&state.rows,
 								renderer,
 								font.clone(),
 								tab_width,
@@ -488,6 +1283,7 @@ where
 						} else {
 							state.cursor.move_up(
 								&state.value,
+								&state.rows,
 								renderer,
 								font.clone(),
 								tab_width,
@@ -506,6 +1302,7 @@ where
 						if modifiers.shift() {
 							state.cursor.select_down(
 								&state.value,
+								&state.rows,
 								renderer,
 								font.clone(),
 								tab_width,
@@ -513,6 +1310,7 @@ where
 						} else {
 							state.cursor.move_down(
 								&state.value,
+								&state.rows,
 								renderer,
 								font.clone(),
 								tab_width,
@@ -584,48 +1382,38 @@ where
 					keyboard::KeyCode::C
 						if state.keyboard_modifiers.command() =>
 					{
-						match state.cursor.selection(&state.value) {
-							Some((start, end)) => {
-								clipboard.write(
-									state
-										.value
-										.byte_slice(start..end)
-										.to_string(),
-								);
-							}
-							None => {}
+						if let Some((start, end)) = state.copied_range() {
+							clipboard.write(
+								state.value.byte_slice(start..end).to_string(),
+							);
 						}
 					}
 					keyboard::KeyCode::X
 						if state.keyboard_modifiers.command() =>
 					{
-						match state.cursor.selection(&state.value) {
-							Some((start, end)) => {
-								clipboard.write(
-									state
-										.value
-										.byte_slice(start..end)
-										.to_string(),
-								);
+						if let Some((start, end)) = state.copied_range() {
+							clipboard.write(
+								state.value.byte_slice(start..end).to_string(),
+							);
 
-								let mut editor = Editor::new(
-									&mut state.value,
-									&mut state.cursor,
-								);
-								editor.delete();
+							let before = state.snapshot();
+							state.cursor.select_range(start, end);
 
-								let message = (on_change)(editor.contents());
-								shell.publish(message);
+							Editor::new(&mut state.value, &mut state.cursor)
+								.delete();
+							state.refresh_search();
+							state.push_undo(before);
 
-								state.recalculate_scroll_offset(
-									renderer,
-									text_bounds.size(),
-									font.clone(),
-									size,
-									tab_width,
-								);
-							}
-							None => {}
+							let message = (on_change)(state.contents());
+							shell.publish(message);
+
+							state.recalculate_scroll_offset(
+								renderer,
+								text_bounds.size(),
+								font.clone(),
+								size,
+								tab_width,
+							);
 						}
 					}
 					keyboard::KeyCode::V => {
@@ -643,18 +1431,24 @@ where
 									})
 									.collect(),
 							};
+							let content = if state.multiline {
+								content
+							} else {
+								state.single_line_paste.apply(content)
+							};
+							let content = paste_line_ending.normalize(&content);
+							let content = normalization.normalize(&content);
 
-							let mut editor = Editor::new(
-								&mut state.value,
-								&mut state.cursor,
-							);
-
-							editor.paste(&content);
+							let before = state.snapshot();
+							Editor::new(&mut state.value, &mut state.cursor)
+								.paste(&content);
+							state.refresh_search();
+							state.push_undo(before);
 
-							let message = (on_change)(editor.contents());
+							let message = (on_change)(state.contents());
 							shell.publish(message);
 
-							state.is_pasting = Some(content);
+							state.is_pasting = Some(content.into_owned());
 
 							state.recalculate_scroll_offset(
 								renderer,
@@ -667,6 +1461,96 @@ where
 							state.is_pasting = None;
 						}
 					}
+					keyboard::KeyCode::Slash
+						if state.keyboard_modifiers.command() =>
+					{
+						if let Some(prefix) = line_comment {
+							let before = state.snapshot();
+							state.edit_all(|editor| {
+								editor.toggle_line_comment(prefix)
+							});
+							state.push_undo(before);
+
+							let message = (on_change)(state.contents());
+							shell.publish(message);
+
+							state.recalculate_scroll_offset(
+								renderer,
+								text_bounds.size(),
+								font.clone(),
+								size,
+								tab_width,
+							);
+						}
+					}
+					keyboard::KeyCode::D
+						if state.keyboard_modifiers.command()
+							&& state.keyboard_modifiers.shift() =>
+					{
+						let before = state.snapshot();
+						state.extra_cursors.clear();
+						state.edit_all(|editor| editor.duplicate_lines());
+						state.push_undo(before);
+
+						let message = (on_change)(state.contents());
+						shell.publish(message);
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::D
+						if state.keyboard_modifiers.command() =>
+					{
+						state.add_next_occurrence();
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::L
+						if state.keyboard_modifiers.command()
+							&& state.keyboard_modifiers.shift() =>
+					{
+						state.select_all_occurrences();
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::Z | keyboard::KeyCode::Y
+						if state.keyboard_modifiers.command() =>
+					{
+						let redo = key_code == keyboard::KeyCode::Y
+							|| state.keyboard_modifiers.shift();
+						let changed =
+							if redo { state.redo() } else { state.undo() };
+
+						if changed {
+							let message = (on_change)(state.contents());
+							shell.publish(message);
+
+							state.recalculate_scroll_offset(
+								renderer,
+								text_bounds.size(),
+								font.clone(),
+								size,
+								tab_width,
+							);
+						}
+					}
 					keyboard::KeyCode::A
 						if state.keyboard_modifiers.command() =>
 					{
@@ -680,6 +1564,11 @@ where
 							tab_width,
 						);
 					}
+					keyboard::KeyCode::Insert
+						if state.keyboard_modifiers.is_empty() =>
+					{
+						state.is_overwrite = !state.is_overwrite;
+					}
 					keyboard::KeyCode::Escape => {
 						state.is_dragging = false;
 						state.is_pasting = None;
@@ -687,6 +1576,65 @@ where
 						state.keyboard_modifiers =
 							keyboard::Modifiers::default();
 
+						// the first Escape deselects, and the next one blurs
+						if state.cursor.selection(&state.value).is_some() {
+							let end = state.cursor.end(&state.value);
+							state.cursor.move_to_byte(end);
+						} else {
+							state.is_focused = false;
+
+							if let Some(on_blur) = on_blur.clone() {
+								shell.publish(on_blur);
+							}
+						}
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::Tab
+						if state.has_multiline_selection() =>
+					{
+						let before = state.snapshot();
+						if modifiers.shift() {
+							state.edit_all(|editor| {
+								editor.dedent_lines(tab_width)
+							});
+						} else {
+							let indent = if state.soft_tabs {
+								" ".repeat(usize::from(tab_width))
+							} else {
+								"\t".to_owned()
+							};
+							state.edit_all(|editor| {
+								editor.indent_lines(&indent)
+							});
+						}
+						state.push_undo(before);
+
+						let message = (on_change)(state.contents());
+						shell.publish(message);
+
+						state.recalculate_scroll_offset(
+							renderer,
+							text_bounds.size(),
+							font.clone(),
+							size,
+							tab_width,
+						);
+					}
+					keyboard::KeyCode::Tab if modifiers.shift() => {
+						let before = state.snapshot();
+						state.edit_all(|editor| editor.insert('\t'));
+						state.push_undo(before);
+
+						let message = (on_change)(state.contents());
+						shell.publish(message);
+
 						state.recalculate_scroll_offset(
 							renderer,
 							text_bounds.size(),
@@ -743,6 +1691,12 @@ pub fn draw<Renderer>(
 	placeholder: &str,
 	size: Option<u16>,
 	tab_width: u8,
+	render_whitespace: RenderWhitespace,
+	indent_guides: bool,
+	line_numbers: bool,
+	word_wrap: bool,
+	cursor_blink_rate: Option<Duration>,
+	marks: &[(usize, MarkKind)],
 	font: &Renderer::Font,
 	style_sheet: &dyn StyleSheet,
 ) where
@@ -751,6 +1705,17 @@ pub fn draw<Renderer>(
 	let bounds = layout.bounds();
 	let text_bounds = layout.children().next().unwrap().bounds();
 	let value = &state.value;
+	let size = size.unwrap_or_else(|| renderer.default_size());
+
+	// the contents may have changed since the rows were last updated
+	let rows = state.rows_cache.get(
+		value,
+		renderer,
+		font.clone(),
+		size,
+		tab_width,
+		word_wrap.then_some(text_bounds.width),
+	);
 
 	let is_mouse_over = bounds.contains(pointer_position);
 
@@ -772,171 +1737,168 @@ pub fn draw<Renderer>(
 		style.background,
 	);
 
-	let size = size.unwrap_or_else(|| renderer.default_size());
-
-	let (selections, cursor) = if state.is_focused() {
-		match state.cursor.state(value) {
-			cursor::State::Index(position) => {
-				let point = offset_of_index(
-					position,
-					value,
-					renderer,
-					font.clone(),
-					size,
-					tab_width,
-				);
-
-				(vec![], Some(point))
-			}
-			cursor::State::Selection { start, end } => {
-				let left = start.min(end);
-				let right = end.max(start);
-
-				let (left_point, right_point) = {
-					let left_y =
-						value.byte_to_line(left) as f32 * f32::from(size);
-					let right_y = left_y
-						+ (value.byte_slice(left..right).len_lines() - 1)
-							as f32 * f32::from(size);
-
-					let left_x = offset_x_of_index(
-						left,
-						value,
-						renderer,
-						font.clone(),
-						Some(size),
-						tab_width,
-					);
-					let right_x = offset_x_of_index(
-						right,
-						value,
-						renderer,
-						font.clone(),
-						Some(size),
-						tab_width,
-					);
-
-					(Point::new(left_x, left_y), Point::new(right_x, right_y))
-				};
-
-				let selection_quads = if left_point.y == right_point.y {
-					vec![(
-						renderer::Quad {
-							bounds: Rectangle {
-								x: text_bounds.x + left_point.x,
-								y: text_bounds.y + left_point.y,
-								width: right_point.x - left_point.x,
-								height: f32::from(size),
-							},
-							border_radius: 0.0,
-							border_width: 0.0,
-							border_color: Color::TRANSPARENT,
-						},
-						style_sheet.selection_color(),
-					)]
-				} else {
-					let mut selections = vec![];
-
-					let color = style_sheet.selection_color();
+	let line_height = state.line_pitch(size);
 
-					let quad = |start_point: Point, width| renderer::Quad {
-						bounds: Rectangle {
-							x: text_bounds.x + start_point.x,
-							y: text_bounds.y + start_point.y,
-							width,
-							height: f32::from(size),
-						},
-						border_radius: 0.0,
-						border_width: 0.0,
-						border_color: Color::TRANSPARENT,
-					};
+	let caret = offset_of_index(
+		state.cursor.end(value),
+		value,
+		&rows,
+		renderer,
+		font.clone(),
+		size,
+		line_height,
+		tab_width,
+	);
+	state.caret_point.set(
+		caret + (text_bounds.position() - bounds.position()) - state.scroll
+			+ Vector::new(0.0, line_height),
+	);
 
-					let mut line_start = left;
+	// hit test where the next step of auto scrolling will select to, so the
+	// step can select it without a renderer
+	if let Some(auto_scroll) = state.auto_scroll.filter(|_| state.is_dragging) {
+		let scroll = state.next_auto_scroll(&auto_scroll);
+		let index = index_at_point(
+			renderer,
+			font.clone(),
+			size,
+			tab_width,
+			state,
+			auto_scroll.point + (scroll - state.scroll),
+		);
+		state.auto_scroll_target.set(Some((scroll, index)));
+	}
 
-					let mut line_index = value.byte_to_line(line_start);
+	let is_caret_shown = state.is_caret_shown(cursor_blink_rate);
 
-					let mut start_point = left_point;
+	let (selections, cursors) = if state.is_focused() {
+		let mut selections = vec![];
+		let mut cursors = vec![];
 
-					loop {
-						let line_end = value.line_to_byte(line_index + 1);
+		for cursor in state.cursors() {
+			// in overwrite mode, a caret covers the grapheme it types over,
+			// or a space at the end of a line
+			let block_width = match cursor.state(value) {
+				cursor::State::Index(index) if state.is_overwrite => {
+					let next = value.next_grapheme(index);
+					let first = value.byte_slice(index..next).chars().next();
 
-						let mut width = width_of_range(
-							line_start,
-							line_end.min(right),
+					Some(if matches!(first, None | Some('\n' | '\r')) {
+						renderer.measure_width(" ", size, font.clone())
+					} else {
+						width_of_range(
+							index,
+							next,
 							value,
 							renderer,
 							font.clone(),
 							Some(size),
 							tab_width,
-						);
-
-						if value.byte(line_end.min(right) - 1) == b'\n' {
-							width += f32::from(size) / 2.0;
-						}
-
-						selections.push((quad(start_point, width), color));
-
-						if line_end >= right {
-							break;
-						}
-
-						line_start = line_end;
-						start_point =
-							Point::new(0.0, start_point.y + f32::from(size));
-
-						line_index += 1;
-					}
+						)
+					})
+				}
+				_ => None,
+			};
 
-					selections
-				};
+			let (quads, point) = cursor_geometry(
+				cursor,
+				value,
+				&rows,
+				renderer,
+				font,
+				size,
+				line_height,
+				tab_width,
+				text_bounds,
+				style_sheet.selection_color(),
+			);
 
-				(
-					selection_quads,
-					if end < start {
-						Some(left_point)
-					} else {
-						Some(right_point)
-					},
-				)
+			selections.extend(quads);
+			if is_caret_shown {
+				cursors.push((point, block_width));
 			}
 		}
+
+		(selections, cursors)
 	} else {
-		(vec![], None)
+		(vec![], vec![])
 	};
 
-	let cursor = cursor
-		.map(|point| {
-			point + (text_bounds.position() - Point::ORIGIN) - state.scroll
+	let first_row = (state.scroll.y / line_height).floor() as usize;
+	let row_count = (text_bounds.height / line_height).ceil() as usize;
+	let last_row = (first_row + row_count + 1).min(rows.len(value));
+
+	// only matches in the rows scrolled into view are highlighted
+	let visible = rows.start(value, first_row)
+		..rows.end(value, last_row.saturating_sub(1));
+	let matches = state.search_matches();
+	let first_visible =
+		matches.partition_point(|found| found.end <= visible.start);
+
+	let highlights = matches[first_visible..]
+		.iter()
+		.take_while(|found| found.start < visible.end)
+		.flat_map(|found| {
+			let mut cursor = Cursor::default();
+			cursor.select_range(found.start, found.end);
+
+			let is_active =
+				state.cursor.selection(value) == Some((found.start, found.end));
+			let color = if is_active {
+				style_sheet.active_search_highlight_color()
+			} else {
+				style_sheet.search_highlight_color()
+			};
+
+			let (quads, _) = cursor_geometry(
+				&cursor,
+				value,
+				&rows,
+				renderer,
+				font,
+				size,
+				line_height,
+				tab_width,
+				text_bounds,
+				color,
+			);
+			quads
 		})
-		.filter(|&point| {
-			let bottom = point + Vector::new(0.0, f32::from(size));
-			text_bounds.contains(point) || text_bounds.contains(bottom)
+		.collect::<Vec<_>>();
+
+	let cursors = cursors
+		.into_iter()
+		.filter_map(|(point, block_width)| {
+			let point =
+				point + (text_bounds.position() - Point::ORIGIN) - state.scroll;
+			let quad = caret_quad(point, text_bounds, line_height)?;
+
+			Some(match block_width {
+				// blocks are drawn over the text, so they're translucent to
+				// keep it legible
+				Some(width) => (
+					renderer::Quad {
+						bounds: Rectangle {
+							x: point.x,
+							width,
+							..quad.bounds
+						},
+						..quad
+					},
+					Color {
+						a: style_sheet.cursor_color().a * 0.5,
+						..style_sheet.cursor_color()
+					},
+				),
+				None => (quad, style_sheet.cursor_color()),
+			})
 		})
-		.map(|point| {
-			let y = f32::max(point.y - 1.0, text_bounds.y);
-
-			let height = f32::min(
-				f32::from(size) + 2.0,
-				text_bounds.y + text_bounds.height - y,
-			);
-
-			(
-				renderer::Quad {
-					bounds: Rectangle {
-						x: point.x - 1.0,
-						y,
-						width: 2.0,
-						height,
-					},
-					border_radius: 0.0,
-					border_width: 0.0,
-					border_color: Color::TRANSPARENT,
-				},
-				style_sheet.cursor_color(),
-			)
-		});
+		.collect::<Vec<_>>();
 
 	let render = |renderer: &mut Renderer| {
+		for (highlight, color) in highlights {
+			renderer.fill_quad(highlight, color);
+		}
 		for (selection, color) in selections {
 			renderer.fill_quad(selection, color);
 		}
@@ -946,6 +1908,7 @@ pub fn draw<Renderer>(
 			style_sheet.value_color()
 		};
 
+		let text_size = size;
 		let size = f32::from(size);
 
 		if value.len_bytes() == 0 {
@@ -965,32 +1928,52 @@ pub fn draw<Renderer>(
 			return;
 		}
 
-		let first_line = (state.scroll.y / size).floor() as usize;
-
-		let line_count = (text_bounds.height / size).ceil() as usize;
+		if indent_guides && !rows.is_wrapping() {
+			let (first_line, line_count) = (first_row, row_count);
+			let lines = value.byte_slice(
+				value.line_to_byte(first_line)
+					..=value
+						.line_to_byte(
+							(first_line + line_count).min(value.len_lines()),
+						)
+						.min(value.len_bytes() - 1),
+			);
 
-		let lines = value.byte_slice(
-			value.line_to_byte(first_line)
-				..=value
-					.line_to_byte(
-						(first_line + line_count).min(value.len_lines()),
-					)
-					.min(value.len_bytes() - 1),
-		);
+			draw_indent_guides(
+				renderer,
+				lines,
+				Point::new(
+					text_bounds.x,
+					text_bounds.y + first_line as f32 * line_height,
+				),
+				text_size,
+				line_height,
+				font,
+				tab_width,
+				style_sheet.indent_guide_color(),
+			);
+		}
 
-		let text = lines.display(tab_width);
+		for row in first_row..last_row {
+			let raw_line =
+				value.byte_slice(rows.start(value, row)..rows.end(value, row));
+			let line = raw_line.display(tab_width).next().unwrap_or_default();
+
+			// text is centered in the space between lines
+			let position = Point::new(
+				text_bounds.x,
+				text_bounds.y
+					+ row as f32 * line_height
+					+ (line_height - size) / 2.0,
+			);
 
-		for (i, mut line) in text.enumerate() {
-			if i == line_count && line == "" {
-				line = " ".into();
-			}
 			renderer.fill_text(Text {
 				content: &line,
 				color,
 				font: font.clone(),
 				bounds: Rectangle {
-					x: text_bounds.x,
-					y: text_bounds.y + (i + first_line) as f32 * size,
+					x: position.x,
+					y: position.y,
 					width: f32::INFINITY,
 					height: size,
 				},
@@ -998,6 +1981,19 @@ pub fn draw<Renderer>(
 				horizontal_alignment: alignment::Horizontal::Left,
 				vertical_alignment: alignment::Vertical::Top,
 			});
+
+			if render_whitespace != RenderWhitespace::None {
+				draw_whitespace(
+					renderer,
+					raw_line,
+					render_whitespace,
+					position,
+					text_size,
+					font,
+					tab_width,
+					style_sheet.whitespace_color(),
+				);
+			}
 		}
 	};
 
@@ -1005,245 +2001,2003 @@ pub fn draw<Renderer>(
 		renderer.with_translation(state.scroll * -1.0, render);
 	});
 
-	if let Some((cursor, color)) = cursor {
-		renderer.fill_quad(cursor, color);
-	}
-}
-
-/// Computes the current [`mouse::Interaction`] of the [`TextInput`].
-pub fn mouse_interaction(
-	layout: Layout<'_>,
-	cursor_position: Point,
-) -> mouse::Interaction {
-	if layout.bounds().contains(cursor_position) {
-		mouse::Interaction::Text
-	} else {
-		mouse::Interaction::default()
-	}
-}
+	if line_numbers {
+		let gutter_width = gutter_width(value, renderer, font.clone(), size);
+		let gutter_bounds = Rectangle {
+			x: text_bounds.x - gutter_width,
+			width: gutter_width,
+			..text_bounds
+		};
 
-impl<'a, Message, Renderer> Widget<Message, Renderer>
-	for TextInput<'a, Message, Renderer>
-where
-	Message: Clone,
-	Renderer: text::Renderer,
-{
-	fn width(&self) -> Length {
-		self.width
+		draw_line_numbers(
+			renderer,
+			value,
+			&rows,
+			gutter_bounds,
+			state.scroll.y,
+			size,
+			line_height,
+			font,
+			style_sheet.gutter_color(),
+		);
 	}
 
-	fn height(&self) -> Length {
-		self.height
+	for (cursor, color) in cursors {
+		renderer.fill_quad(cursor, color);
 	}
 
-	fn layout(
-		&self,
-		renderer: &Renderer,
-		limits: &layout::Limits,
-	) -> layout::Node {
-		layout(
+	for scrollbar in [Scrollbar::Vertical, Scrollbar::Horizontal] {
+		let geometry = match scrollbar_geometry(
+			scrollbar,
+			state,
+			&rows,
 			renderer,
-			limits,
-			self.width,
-			self.height,
-			&self.state.value,
-			self.padding,
-			self.size,
-		)
-	}
+			&TextLayout {
+				font,
+				size,
+				tab_width,
+				bounds: text_bounds,
+			},
+		) {
+			Some(geometry) => geometry,
+			None => continue,
+		};
 
-	fn on_event(
-		&mut self,
-		event: Event,
-		layout: Layout<'_>,
-		cursor_position: Point,
-		renderer: &Renderer,
-		clipboard: &mut dyn Clipboard,
-		shell: &mut Shell<'_, Message>,
-	) -> event::Status {
-		update(
-			event,
-			layout,
-			cursor_position,
-			renderer,
-			clipboard,
-			shell,
-			self.size,
-			self.tab_width,
-			&self.font,
-			self.on_change.as_ref(),
-			&self.on_submit,
-			|| &mut self.state,
-		)
-	}
+		let is_dragged = matches!(
+			state.scrollbar_drag,
+			Some((dragged, _)) if dragged == scrollbar
+		);
+		let color = if is_dragged || geometry.thumb.contains(pointer_position) {
+			style_sheet.scrollbar_hovered_color()
+		} else {
+			style_sheet.scrollbar_color()
+		};
 
-	fn mouse_interaction(
-		&self,
-		layout: Layout<'_>,
-		cursor_position: Point,
-		_viewport: &Rectangle,
-		_renderer: &Renderer,
-	) -> mouse::Interaction {
-		mouse_interaction(layout, cursor_position)
+		renderer.fill_quad(
+			renderer::Quad {
+				bounds: geometry.thumb,
+				border_radius: SCROLLBAR_WIDTH / 2.0,
+				border_width: 0.0,
+				border_color: Color::TRANSPARENT,
+			},
+			color,
+		);
 	}
 
-	fn draw(
-		&self,
-		renderer: &mut Renderer,
-		_style: &renderer::Style,
-		layout: Layout<'_>,
-		cursor_position: Point,
-		_viewport: &Rectangle,
-	) {
-		self.draw(renderer, layout, cursor_position)
+	for &(index, kind) in marks {
+		renderer.fill_quad(
+			renderer::Quad {
+				bounds: ruler_tick(index, value, text_bounds),
+				border_radius: 0.0,
+				border_width: 0.0,
+				border_color: Color::TRANSPARENT,
+			},
+			style_sheet.mark_color(kind),
+		);
 	}
 }
 
-impl<'a, Message, Renderer> From<TextInput<'a, Message, Renderer>>
-	for Element<'a, Message, Renderer>
-where
-	Message: 'a + Clone,
-	Renderer: 'a + text::Renderer,
-{
-	fn from(
-		text_input: TextInput<'a, Message, Renderer>,
-	) -> Element<'a, Message, Renderer> {
-		Element::new(text_input)
-	}
-}
+/// The width of the ruler along the right edge of a [`TextInput`] where
+/// marks are drawn.
+const RULER_WIDTH: f32 = 6.0;
 
-/// The state of a [`TextInput`].
-#[derive(Debug, Clone)]
-pub struct State {
-	value: Rope,
-	is_focused: bool,
-	is_dragging: bool,
-	is_pasting: Option<String>,
-	last_click: Option<mouse::Click>,
-	cursor: Cursor,
-	keyboard_modifiers: keyboard::Modifiers,
-	scroll: Vector,
-	last_size: u16,
-}
+/// The height of the tick of a mark in the ruler.
+const RULER_TICK_HEIGHT: f32 = 3.0;
 
-impl Default for State {
-	fn default() -> Self {
-		Self {
-			value: Rope::new(),
-			is_focused: false,
-			is_dragging: false,
-			is_pasting: None,
-			last_click: None,
-			cursor: Cursor::default(),
-			keyboard_modifiers: keyboard::Modifiers::default(),
-			scroll: Vector::new(0.0, 0.0),
-			last_size: 1,
-		}
+/// Computes the tick of the mark at byte `index` in the ruler just right of
+/// `text_bounds`, where [`layout`] keeps room for it.
+///
+/// The ruler stands for the whole of `value`, not just the visible part, so
+/// ticks don't move when scrolling.
+fn ruler_tick(index: usize, value: &Rope, text_bounds: Rectangle) -> Rectangle {
+	let line = value.byte_to_line(index.min(value.len_bytes()));
+	let fraction = line as f32 / value.len_lines() as f32;
+	let y = text_bounds.y
+		+ (text_bounds.height * fraction)
+			.min(text_bounds.height - RULER_TICK_HEIGHT)
+			.max(0.0);
+
+	Rectangle {
+		x: text_bounds.x + text_bounds.width,
+		y,
+		width: RULER_WIDTH,
+		height: RULER_TICK_HEIGHT,
 	}
 }
 
-impl State {
-	/// Creates a new [`State`], representing a focused [`TextInput`].
-	pub fn focused() -> Self {
-		Self {
-			is_focused: true,
-			..Default::default()
-		}
-	}
+/// Returns the column of byte `index` in its line, as displayed: counted in
+/// graphemes, with each tab counting as `tab_width` columns.
+pub(crate) fn display_column(
+	value: &Rope,
+	index: usize,
+	tab_width: u8,
+) -> usize {
+	let line_start = value.line_to_byte(value.byte_to_line(index));
 
-	pub fn contents(&self) -> String {
-		self.value.to_string()
-	}
+	// An index inside a CRLF line break counts as being before it
+	value
+		.byte_slice(line_start..index)
+		.to_string()
+		.trim_end_matches('\r')
+		.graphemes(true)
+		.map(|g| if g == "\t" { usize::from(tab_width) } else { 1 })
+		.sum()
+}
 
-	/// Returns whether the [`TextInput`] is currently focused or not.
-	fn is_focused(&self) -> bool {
-		self.is_focused
-	}
+/// Finds the byte ranges of the occurrences of `query`, lowercased into
+/// characters, in `text`, ignoring case. Occurrences don't overlap.
+fn find_ignoring_case(text: &Rope, query: &[char]) -> Vec<Range<usize>> {
+	// Returns the length of the match of `query` at the start of `chars`
+	let match_len = |chars: ropey::iter::Chars<'_>| {
+		let mut query = query.iter();
+		let mut len = 0;
+
+		for c in chars {
+			if query.as_slice().is_empty() {
+				break;
+			}
+			for lower in c.to_lowercase() {
+				if query.next() != Some(&lower) {
+					return None;
+				}
+			}
+			len += c.len_utf8();
+		}
 
-	fn recalculate_scroll_offset<Renderer: text::Renderer>(
-		&mut self,
-		renderer: &Renderer,
-		bounds_size: Size<f32>,
-		font: Renderer::Font,
-		size: u16,
-		tab_width: u8,
-	) {
-		let cursor_index = self.cursor.end(&self.value);
-		let cursor = offset_of_index(
-			cursor_index,
-			&self.value,
-			renderer,
-			font,
-			size,
-			tab_width,
-		);
+		Some(len).filter(|_| query.as_slice().is_empty())
+	};
 
-		let x = if cursor.x < self.scroll.x {
-			cursor.x
-		} else if cursor.x > self.scroll.x + bounds_size.width {
-			cursor.x - bounds_size.width
-		} else {
-			self.scroll.x
-		};
+	let mut matches = vec![];
+	let mut next_start = 0;
+	let mut start = 0;
+	let mut chars = text.chars();
 
-		let y = if cursor.y < self.scroll.y {
-			cursor.y
-		} else if cursor.y + f32::from(size)
-			> self.scroll.y + bounds_size.height
-		{
-			cursor.y + f32::from(size) - bounds_size.height
-		} else {
-			self.scroll.y
+	loop {
+		let rest = chars.clone();
+		let c = match chars.next() {
+			Some(c) => c,
+			None => break,
 		};
 
-		self.scroll = Vector::new(x, y);
+		if start >= next_start {
+			if let Some(len) = match_len(rest) {
+				matches.push(start..start + len);
+				next_start = start + len;
+			}
+		}
+		start += c.len_utf8();
 	}
 
-	fn new_size(&mut self, size: u16) {
-		if size != self.last_size {
-			let factor = f32::from(size) / f32::from(self.last_size);
-			self.last_size = size;
+	matches
+}
 
-			self.scroll = self.scroll * factor;
+/// Returns the byte index of the mark whose tick is at `point`, if any.
+///
+/// Ticks are hard to hit exactly, so points a couple of pixels above or below
+/// one count too.
+fn mark_at_point(
+	marks: &[(usize, MarkKind)],
+	value: &Rope,
+	text_bounds: Rectangle,
+	point: Point,
+) -> Option<usize> {
+	marks.iter().map(|&(index, _)| index).find(|&index| {
+		let tick = ruler_tick(index, value, text_bounds);
+		Rectangle {
+			y: tick.y - 2.0,
+			height: tick.height + 4.0,
+			..tick
 		}
-	}
+		.contains(point)
+	})
 }
 
-mod platform {
-	use crate::keyboard;
+/// The thickness of the scrollbars of a [`TextInput`].
+const SCROLLBAR_WIDTH: f32 = 6.0;
 
-	pub fn is_jump_modifier_pressed(modifiers: keyboard::Modifiers) -> bool {
-		if cfg!(target_os = "macos") {
-			modifiers.alt()
-		} else {
-			modifiers.control()
+/// The shortest a scrollbar thumb gets, so that it stays easy to grab in
+/// long text.
+const MIN_THUMB_LENGTH: f32 = 20.0;
+
+/// One of the scrollbars of a [`TextInput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scrollbar {
+	/// Along the bottom edge of the text, scrolling it sideways.
+	Horizontal,
+	/// Along the right edge of the text, scrolling it up and down.
+	Vertical,
+}
+
+impl Scrollbar {
+	/// Returns the coordinate of `point` along the scrollbar.
+	fn along(self, point: Point) -> f32 {
+		match self {
+			Scrollbar::Horizontal => point.x,
+			Scrollbar::Vertical => point.y,
+		}
+	}
+
+	/// Returns where `rectangle` starts along the scrollbar, and how long it
+	/// is.
+	fn extent(self, rectangle: Rectangle) -> (f32, f32) {
+		match self {
+			Scrollbar::Horizontal => (rectangle.x, rectangle.width),
+			Scrollbar::Vertical => (rectangle.y, rectangle.height),
 		}
 	}
 }
 
-/// Computes the position of the text cursor at the given point of a
-/// [`TextInput`].
-fn index_at_point<Renderer>(
-	renderer: &Renderer,
-	font: Renderer::Font,
+/// Where a scrollbar is drawn, and how far it scrolls.
+#[derive(Debug, Clone, Copy)]
+struct ScrollbarGeometry {
+	track: Rectangle,
+	thumb: Rectangle,
+	/// The scroll offset with the thumb at the end of the track.
+	max_scroll: f32,
+}
+
+/// How the text of a [`TextInput`] is laid out.
+struct TextLayout<'a, Font> {
+	font: &'a Font,
 	size: u16,
 	tab_width: u8,
+	/// The bounds of the text, inside the padding and beside the gutter.
+	bounds: Rectangle,
+}
+
+/// Computes the geometry of `scrollbar`, or `None` if the text fits without
+/// scrolling along it.
+///
+/// The thumb is as long, relative to the track, as the visible part of the
+/// text is relative to all of it.
+fn scrollbar_geometry<Renderer>(
+	scrollbar: Scrollbar,
 	state: &State,
-	mut point: Point,
-) -> Option<usize>
+	rows: &Rows,
+	renderer: &Renderer,
+	text_layout: &TextLayout<'_, Renderer::Font>,
+) -> Option<ScrollbarGeometry>
+where
+	Renderer: text::Renderer,
+{
+	let TextLayout {
+		font,
+		size,
+		tab_width,
+		bounds: text_bounds,
+	} = *text_layout;
+
+	// wrapped text never scrolls sideways
+	if scrollbar == Scrollbar::Horizontal && rows.is_wrapping() {
+		return None;
+	}
+
+	// the scrollbars stay clear of each other
+	let right = text_bounds.x + text_bounds.width;
+
+	let (content, viewport, scroll, track) = match scrollbar {
+		Scrollbar::Horizontal => (
+			state.max_line_length(renderer, font.clone(), size, tab_width),
+			text_bounds.width,
+			state.scroll.x,
+			Rectangle {
+				x: text_bounds.x,
+				y: text_bounds.y + text_bounds.height - SCROLLBAR_WIDTH,
+				width: right - SCROLLBAR_WIDTH - text_bounds.x,
+				height: SCROLLBAR_WIDTH,
+			},
+		),
+		Scrollbar::Vertical => (
+			rows.len(&state.value) as f32 * state.line_pitch(size),
+			text_bounds.height,
+			state.scroll.y,
+			Rectangle {
+				x: right - SCROLLBAR_WIDTH,
+				y: text_bounds.y,
+				width: SCROLLBAR_WIDTH,
+				height: text_bounds.height,
+			},
+		),
+	};
+
+	if content <= viewport {
+		return None;
+	}
+
+	let max_scroll = content - viewport;
+	let (track_start, track_length) = scrollbar.extent(track);
+	let thumb_length = (track_length * viewport / content)
+		.max(MIN_THUMB_LENGTH)
+		.min(track_length);
+	let thumb_start = track_start
+		+ (track_length - thumb_length) * (scroll / max_scroll).min(1.0);
+
+	let thumb = match scrollbar {
+		Scrollbar::Horizontal => Rectangle {
+			x: thumb_start,
+			width: thumb_length,
+			..track
+		},
+		Scrollbar::Vertical => Rectangle {
+			y: thumb_start,
+			height: thumb_length,
+			..track
+		},
+	};
+
+	Some(ScrollbarGeometry {
+		track,
+		thumb,
+		max_scroll,
+	})
+}
+
+/// Computes the quad of a caret at `point`, `line_height` tall and clipped to
+/// `text_bounds`, or `None` if none of it is visible.
+///
+/// Any overlap counts as visible, so that a caret at the very edge of the
+/// text, such as at the start of a trailing empty line, is still drawn.
+fn caret_quad(
+	point: Point,
+	text_bounds: Rectangle,
+	line_height: f32,
+) -> Option<renderer::Quad> {
+	let (left, right) = (point.x - 1.0, point.x + 1.0);
+	let (top, bottom) = (point.y - 1.0, point.y + line_height + 1.0);
+
+	let y = f32::max(top, text_bounds.y);
+	let height = f32::min(bottom, text_bounds.y + text_bounds.height) - y;
+
+	if right < text_bounds.x
+		|| left > text_bounds.x + text_bounds.width
+		|| height <= 0.0
+	{
+		return None;
+	}
+
+	Some(renderer::Quad {
+		bounds: Rectangle {
+			x: left,
+			y,
+			width: 2.0,
+			height,
+		},
+		border_radius: 0.0,
+		border_width: 0.0,
+		border_color: Color::TRANSPARENT,
+	})
+}
+
+/// Computes the selection quads of a [`Cursor`], and the position of its
+/// caret relative to the text.
+#[allow(clippy::too_many_arguments)]
+fn cursor_geometry<Renderer>(
+	cursor: &Cursor,
+	value: &Rope,
+	rows: &Rows,
+	renderer: &Renderer,
+	font: &Renderer::Font,
+	size: u16,
+	line_height: f32,
+	tab_width: u8,
+	text_bounds: Rectangle,
+	selection_color: Color,
+) -> (Vec<(renderer::Quad, Color)>, Point)
+where
+	Renderer: text::Renderer,
+{
+	match cursor.state(value) {
+		cursor::State::Index(position) => {
+			let point = offset_of_index(
+				position,
+				value,
+				rows,
+				renderer,
+				font.clone(),
+				size,
+				line_height,
+				tab_width,
+			);
+
+			(vec![], point)
+		}
+		cursor::State::Selection { start, end } => {
+			let left = start.min(end);
+			let right = end.max(start);
+
+			let (left_point, right_point) = {
+				let left_y = rows.row_of(value, left) as f32 * line_height;
+				let right_y = rows.row_of(value, right) as f32 * line_height;
+
+				let left_x = offset_x_of_index(
+					left,
+					value,
+					rows,
+					renderer,
+					font.clone(),
+					Some(size),
+					tab_width,
+				);
+				let right_x = offset_x_of_index(
+					right,
+					value,
+					rows,
+					renderer,
+					font.clone(),
+					Some(size),
+					tab_width,
+				);
+
+				(Point::new(left_x, left_y), Point::new(right_x, right_y))
+			};
+
+			let selection_quads = if left_point.y == right_point.y {
+				vec![(
+					renderer::Quad {
+						bounds: Rectangle {
+							x: text_bounds.x + left_point.x,
+							y: text_bounds.y + left_point.y,
+							width: (right_point.x - left_point.x).max(0.0),
+							height: line_height,
+						},
+						border_radius: 0.0,
+						border_width: 0.0,
+						border_color: Color::TRANSPARENT,
+					},
+					selection_color,
+				)]
+			} else {
+				let mut selections = vec![];
+
+				let quad = |start_point: Point, width| renderer::Quad {
+					bounds: Rectangle {
+						x: text_bounds.x + start_point.x,
+						y: text_bounds.y + start_point.y,
+						width,
+						height: line_height,
+					},
+					border_radius: 0.0,
+					border_width: 0.0,
+					border_color: Color::TRANSPARENT,
+				};
+
+				let mut line_start = left;
+
+				let mut row = rows.row_of(value, line_start);
+
+				let mut start_point = left_point;
+
+				loop {
+					let line_end = rows.end(value, row);
+					let selection_end = line_end.min(right);
+
+					// The line break itself is shown as half a space, rather
+					// than however wide the renderer thinks it is.
+					let mut content_end = selection_end;
+					if value.byte(content_end - 1) == b'\n' {
+						content_end -= 1;
+					}
+					if content_end > line_start
+						&& value.byte(content_end - 1) == b'\r'
+					{
+						content_end -= 1;
+					}
+
+					let mut width = width_of_range(
+						line_start,
+						content_end,
+						value,
+						renderer,
+						font.clone(),
+						Some(size),
+						tab_width,
+					);
+
+					if content_end < selection_end {
+						width += f32::from(size) / 2.0;
+					}
+
+					selections.push((
+						quad(start_point, width.max(0.0)),
+						selection_color,
+					));
+
+					if line_end >= right {
+						break;
+					}
+
+					line_start = line_end;
+					start_point = Point::new(0.0, start_point.y + line_height);
+
+					row += 1;
+				}
+
+				selections
+			};
+
+			(
+				selection_quads,
+				if end < start { left_point } else { right_point },
+			)
+		}
+	}
+}
+
+/// Draws markers over the whitespace of a single line, as selected by `mode`.
+///
+/// Only the start of each run of whitespace is measured; the rest of the run
+/// is advanced by the width of a space, as that's what tabs are displayed as.
+#[allow(clippy::too_many_arguments)]
+fn draw_whitespace<Renderer>(
+	renderer: &mut Renderer,
+	line: RopeSlice<'_>,
+	mode: RenderWhitespace,
+	position: Point,
+	size: u16,
+	font: &Renderer::Font,
+	tab_width: u8,
+	color: Color,
+) where
+	Renderer: text::Renderer,
+{
+	const SPACE_MARKER: &str = "·";
+	const TAB_MARKER: &str = "→";
+
+	let line: Cow<'_, str> = match line.as_str() {
+		Some(s) => s.into(),
+		None => line.to_string().into(),
+	};
+	let line = line.trim_end_matches(['\n', '\r']);
+
+	let is_whitespace = |c: char| c == ' ' || c == '\t';
+
+	if !line.contains(is_whitespace) {
+		return;
+	}
+
+	let (leading_end, trailing_start) = match mode {
+		RenderWhitespace::None => return,
+		RenderWhitespace::Boundary => (
+			line.len() - line.trim_start_matches(is_whitespace).len(),
+			line.trim_end_matches(is_whitespace).len(),
+		),
+		RenderWhitespace::All => (line.len(), 0),
+	};
+
+	let space_width = renderer.measure_width(" ", size, font.clone());
+
+	let mut x = 0.0;
+	let mut in_run = false;
+
+	for (i, c) in line.char_indices() {
+		if !is_whitespace(c) {
+			in_run = false;
+			continue;
+		}
+
+		if !in_run {
+			x = renderer.measure_width(
+				&replace_tab(&line[..i], tab_width),
+				size,
+				font.clone(),
+			);
+			in_run = true;
+		}
+
+		if i < leading_end || i >= trailing_start {
+			renderer.fill_text(Text {
+				content: if c == '\t' { TAB_MARKER } else { SPACE_MARKER },
+				color,
+				font: font.clone(),
+				bounds: Rectangle {
+					x: position.x + x,
+					y: position.y,
+					width: f32::INFINITY,
+					height: f32::from(size),
+				},
+				size: f32::from(size),
+				horizontal_alignment: alignment::Horizontal::Left,
+				vertical_alignment: alignment::Vertical::Top,
+			});
+		}
+
+		x += if c == '\t' {
+			space_width * f32::from(tab_width)
+		} else {
+			space_width
+		};
+	}
+}
+
+/// Draws the number of each visible line, right-aligned in `gutter_bounds`
+/// next to the first of its `rows` and scrolled along with the text by
+/// `scroll_y`.
+#[allow(clippy::too_many_arguments)]
+fn draw_line_numbers<Renderer>(
+	renderer: &mut Renderer,
+	value: &Rope,
+	rows: &Rows,
+	gutter_bounds: Rectangle,
+	scroll_y: f32,
+	size: u16,
+	line_height: f32,
+	font: &Renderer::Font,
+	color: Color,
+) where
+	Renderer: text::Renderer,
+{
+	let first_row = (scroll_y / line_height).floor() as usize;
+	let row_count = (gutter_bounds.height / line_height).ceil() as usize + 1;
+	let last_row = (first_row + row_count).min(rows.len(value));
+
+	renderer.with_layer(gutter_bounds, |renderer| {
+		for row in first_row..last_row {
+			// rows continuing a wrapped line aren't numbered
+			if row > 0 && rows.is_broken(value, row - 1) {
+				continue;
+			}
+
+			let line = value.byte_to_line(rows.start(value, row));
+			let size = f32::from(size);
+			let number = (line + 1).to_string();
+
+			renderer.fill_text(Text {
+				content: &number,
+				color,
+				font: font.clone(),
+				bounds: Rectangle {
+					x: gutter_bounds.x + gutter_bounds.width - GUTTER_PADDING,
+					y: gutter_bounds.y + row as f32 * line_height - scroll_y
+						+ (line_height - size) / 2.0,
+					width: f32::INFINITY,
+					height: size,
+				},
+				size,
+				horizontal_alignment: alignment::Horizontal::Right,
+				vertical_alignment: alignment::Vertical::Top,
+			});
+		}
+	});
+}
+
+/// Draws a thin vertical line at each level of indentation of `lines`, the
+/// first of which is drawn at `position`.
+#[allow(clippy::too_many_arguments)]
+fn draw_indent_guides<Renderer>(
+	renderer: &mut Renderer,
+	lines: RopeSlice<'_>,
+	position: Point,
+	size: u16,
+	line_height: f32,
+	font: &Renderer::Font,
+	tab_width: u8,
+	color: Color,
+) where
+	Renderer: text::Renderer,
+{
+	let indents = lines
+		.lines()
+		.map(|line| indent_columns(line, tab_width))
+		.collect::<Vec<_>>();
+
+	let space_width = renderer.measure_width(" ", size, font.clone());
+
+	for (i, levels) in indent_guide_levels(&indents, tab_width)
+		.into_iter()
+		.enumerate()
+	{
+		for level in 0..levels {
+			let column = level * usize::from(tab_width);
+
+			renderer.fill_quad(
+				renderer::Quad {
+					bounds: Rectangle {
+						x: position.x + space_width * column as f32,
+						y: position.y + i as f32 * line_height,
+						width: 1.0,
+						height: line_height,
+					},
+					border_radius: 0.0,
+					border_width: 0.0,
+					border_color: Color::TRANSPARENT,
+				},
+				color,
+			);
+		}
+	}
+}
+
+/// Returns the width of the leading whitespace of `line` in columns, or
+/// `None` if the line is blank.
+fn indent_columns(line: RopeSlice<'_>, tab_width: u8) -> Option<usize> {
+	let mut columns = 0;
+
+	for c in line.chars() {
+		match c {
+			' ' => columns += 1,
+			'\t' => columns += usize::from(tab_width),
+			'\n' | '\r' => return None,
+			_ => return Some(columns),
+		}
+	}
+
+	None
+}
+
+/// Computes how many indent guides to draw on each line, given their
+/// indentation from [`indent_columns`].
+///
+/// Blank lines get the guides shared by the lines around them, so blocks
+/// containing empty lines have unbroken guides.
+fn indent_guide_levels(indents: &[Option<usize>], tab_width: u8) -> Vec<usize> {
+	let tab_width = usize::from(tab_width.max(1));
+	let level = |columns: usize| columns / tab_width;
+
+	let mut previous = 0;
+
+	indents
+		.iter()
+		.enumerate()
+		.map(|(i, indent)| match *indent {
+			Some(columns) => {
+				previous = level(columns);
+				previous
+			}
+			None => {
+				let next = indents[i + 1..]
+					.iter()
+					.find_map(|&indent| indent)
+					.map_or(0, level);
+				previous.min(next)
+			}
+		})
+		.collect()
+}
+
+/// Computes the current [`mouse::Interaction`] of the [`TextInput`].
+pub fn mouse_interaction(
+	layout: Layout<'_>,
+	cursor_position: Point,
+) -> mouse::Interaction {
+	if layout.bounds().contains(cursor_position) {
+		mouse::Interaction::Text
+	} else {
+		mouse::Interaction::default()
+	}
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer>
+	for TextInput<'a, Message, Renderer>
+where
+	Message: Clone,
+	Renderer: text::Renderer,
+{
+	fn width(&self) -> Length {
+		self.width
+	}
+
+	fn height(&self) -> Length {
+		self.height
+	}
+
+	fn layout(
+		&self,
+		renderer: &Renderer,
+		limits: &layout::Limits,
+	) -> layout::Node {
+		layout(
+			renderer,
+			limits,
+			self.width,
+			self.height,
+			self.max_height,
+			self.state,
+			self.padding,
+			self.size,
+			self.phantom_line,
+			self.state.line_height,
+			self.line_numbers,
+			self.tab_width,
+			self.word_wrap,
+			!self.marks.is_empty(),
+			&self.font,
+		)
+	}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		renderer: &Renderer,
+		clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> event::Status {
+		update(
+			event,
+			layout,
+			cursor_position,
+			renderer,
+			clipboard,
+			shell,
+			self.size,
+			self.tab_width,
+			self.paste_line_ending,
+			self.normalization,
+			self.word_chars,
+			self.marks,
+			self.trim_trailing_whitespace,
+			self.word_wrap,
+			self.line_comment.as_deref(),
+			&self.font,
+			self.on_change.as_ref(),
+			&self.on_submit,
+			&self.on_focus,
+			&self.on_blur,
+			|| &mut self.state,
+		)
+	}
+
+	fn mouse_interaction(
+		&self,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_viewport: &Rectangle,
+		_renderer: &Renderer,
+	) -> mouse::Interaction {
+		mouse_interaction(layout, cursor_position)
+	}
+
+	fn draw(
+		&self,
+		renderer: &mut Renderer,
+		_style: &renderer::Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_viewport: &Rectangle,
+	) {
+		self.draw(renderer, layout, cursor_position)
+	}
+}
+
+impl<'a, Message, Renderer> From<TextInput<'a, Message, Renderer>>
+	for Element<'a, Message, Renderer>
+where
+	Message: 'a + Clone,
+	Renderer: 'a + text::Renderer,
+{
+	fn from(
+		text_input: TextInput<'a, Message, Renderer>,
+	) -> Element<'a, Message, Renderer> {
+		Element::new(text_input)
+	}
+}
+
+/// The state of a [`TextInput`].
+#[derive(Debug, Clone)]
+pub struct State {
+	value: Rope,
+	is_focused: bool,
+	is_dragging: bool,
+	/// The kind of click that began the last drag.
+	drag_kind: click::Kind,
+	/// What the click that began the last drag selected, which stays
+	/// selected while it's dragged.
+	drag_anchor: (usize, usize),
+	/// The extra word characters in effect when the last drag began, for
+	/// extending it by words.
+	drag_word_chars: String,
+	/// The scrollbar whose thumb is being dragged, and where along the thumb
+	/// it was grabbed.
+	scrollbar_drag: Option<(Scrollbar, f32)>,
+	auto_scroll: Option<AutoScroll>,
+	/// Where the next step of auto scrolling scrolls to, and the byte index
+	/// at the edge of the text there, hit tested when last drawn since
+	/// stepping doesn't have a renderer.
+	auto_scroll_target: Cell<Option<(Vector, usize)>>,
+	/// The width lines are wrapped to, if they're wrapped.
+	wrap_width: Option<f32>,
+	/// The rows the text was shown in when it was last updated.
+	rows: Rows,
+	rows_cache: RowsCache,
+	line_length_cache: LineLengthCache,
+	is_pasting: Option<String>,
+	last_click: Option<mouse::Click>,
+	cursor: Cursor,
+	extra_cursors: Vec<Cursor>,
+	keyboard_modifiers: keyboard::Modifiers,
+	scroll: Vector,
+	last_size: u16,
+	scroll_to_cursor: bool,
+	clamp_scroll: bool,
+	sticky_scroll: bool,
+	multiline: bool,
+	single_line_paste: SingleLinePaste,
+	line_height: f32,
+	follow_bottom: bool,
+	last_line_count: usize,
+	selection_expansions: Vec<((usize, usize), (usize, usize))>,
+	subword_motion: bool,
+	copy_line_when_empty: bool,
+	triple_click_selects_all: bool,
+	soft_tabs: bool,
+	/// Whether typing replaces the grapheme after the caret, toggled with
+	/// Insert.
+	is_overwrite: bool,
+	/// When the caret last moved, which its blink starts from.
+	last_cursor_move: Instant,
+	/// The bottom left of the primary caret when it was last drawn, relative
+	/// to the top left of the input.
+	caret_point: Cell<Point>,
+	/// The query set with [`set_search`](State::set_search), lowercased into
+	/// characters, and the byte ranges of its matches.
+	search: Option<(Vec<char>, Vec<Range<usize>>)>,
+	undo_stack: VecDeque<Snapshot>,
+	redo_stack: Vec<Snapshot>,
+	/// Where the primary caret ended up after the last typed character, and
+	/// that character, while further typing continues the same undo step.
+	last_typed: Option<(usize, char)>,
+}
+
+/// How far a selection dragged past the edge of the text scrolls it per step,
+/// relative to how far past the edge the pointer is.
+const AUTO_SCROLL_SPEED: f32 = 0.25;
+
+/// A selection being dragged past the edge of the text, which keeps scrolling
+/// it until the pointer comes back.
+#[derive(Debug, Clone, Copy)]
+struct AutoScroll {
+	/// The point on the edge of the text closest to the pointer, relative to
+	/// the top left of the text.
+	point: Point,
+	/// How far to scroll per step.
+	velocity: Vector,
+	/// The furthest the text can scroll.
+	max_scroll: Vector,
+}
+
+/// The most edits that can be undone.
+const MAX_UNDO_STEPS: usize = 500;
+
+/// The contents and cursors of a [`State`] before an edit, restored when the
+/// edit is undone.
+#[derive(Debug, Clone)]
+struct Snapshot {
+	value: Rope,
+	cursor: Cursor,
+	extra_cursors: Vec<Cursor>,
+}
+
+impl Default for State {
+	fn default() -> Self {
+		Self {
+			value: Rope::new(),
+			is_focused: false,
+			is_dragging: false,
+			drag_kind: click::Kind::Single,
+			drag_anchor: (0, 0),
+			drag_word_chars: String::new(),
+			scrollbar_drag: None,
+			auto_scroll: None,
+			auto_scroll_target: Cell::new(None),
+			wrap_width: None,
+			rows: Rows::default(),
+			rows_cache: RowsCache::default(),
+			line_length_cache: LineLengthCache::default(),
+			is_pasting: None,
+			last_click: None,
+			cursor: Cursor::default(),
+			extra_cursors: vec![],
+			keyboard_modifiers: keyboard::Modifiers::default(),
+			scroll: Vector::new(0.0, 0.0),
+			last_size: 1,
+			scroll_to_cursor: false,
+			clamp_scroll: false,
+			sticky_scroll: false,
+			multiline: true,
+			single_line_paste: SingleLinePaste::Flatten,
+			line_height: 1.0,
+			follow_bottom: true,
+			last_line_count: 1,
+			selection_expansions: vec![],
+			subword_motion: false,
+			copy_line_when_empty: true,
+			triple_click_selects_all: false,
+			soft_tabs: false,
+			is_overwrite: false,
+			last_cursor_move: Instant::now(),
+			caret_point: Cell::new(Point::ORIGIN),
+			search: None,
+			undo_stack: VecDeque::new(),
+			redo_stack: vec![],
+			last_typed: None,
+		}
+	}
+}
+
+impl State {
+	/// Creates a new [`State`], representing a focused [`TextInput`].
+	pub fn focused() -> Self {
+		Self {
+			is_focused: true,
+			..Default::default()
+		}
+	}
+
+	/// Creates a new [`State`] containing `text`, with the cursor at the
+	/// start.
+	pub fn with_text(text: &str) -> Self {
+		Self {
+			value: Rope::from_str(text),
+			..Self::default()
+		}
+	}
+
+	pub fn contents(&self) -> String {
+		self.value.to_string()
+	}
+
+	/// Returns the text of the [`TextInput`] without copying it, to read with
+	/// [`Rope`]'s methods.
+	pub fn value(&self) -> &Rope {
+		&self.value
+	}
+
+	/// Returns the length of the text in bytes.
+	pub fn len_bytes(&self) -> usize {
+		self.value.len_bytes()
+	}
+
+	/// Returns the number of lines in the text, like
+	/// [`line_count`](State::line_count).
+	pub fn len_lines(&self) -> usize {
+		self.value.len_lines()
+	}
+
+	/// Replaces the contents of the [`TextInput`], moving the cursor to the
+	/// end and forgetting the undo history.
+	///
+	/// With [`sticky_scroll`](TextInput::sticky_scroll), the view only jumps
+	/// to the end if it was following the bottom.
+	pub fn set_text(&mut self, text: &str) {
+		self.value = Rope::from_str(text);
+		self.extra_cursors.clear();
+		self.selection_expansions.clear();
+		self.clear_undo_history();
+		self.refresh_search();
+		self.cursor.move_to_byte(self.value.len_bytes());
+		self.scroll_to_cursor = !self.sticky_scroll || self.follow_bottom;
+	}
+
+	/// Returns whether the [`TextInput`] is currently focused or not.
+	pub fn is_focused(&self) -> bool {
+		self.is_focused
+	}
+
+	/// Moves the cursor to the given byte index and focuses the
+	/// [`TextInput`].
+	///
+	/// The index is clamped to the length of the value and rounded down to a
+	/// character boundary. The view is scrolled to the cursor the next time
+	/// the [`TextInput`] processes an event.
+	pub fn set_cursor_to_byte(&mut self, index: usize) {
+		let index = index.min(self.value.len_bytes());
+		let index = self.value.char_to_byte(self.value.byte_to_char(index));
+
+		self.cursor.move_to_byte(index);
+		self.is_focused = true;
+		self.scroll_to_cursor = true;
+	}
+
+	/// Selects the given byte range and focuses the [`TextInput`], leaving
+	/// the caret at `end`.
+	///
+	/// Both ends are clamped and rounded down like in
+	/// [`set_cursor_to_byte`](State::set_cursor_to_byte).
+	pub fn set_selection(&mut self, start: usize, end: usize) {
+		let clamp = |index: usize| {
+			let index = index.min(self.value.len_bytes());
+			self.value.char_to_byte(self.value.byte_to_char(index))
+		};
+
+		self.cursor.select_range(clamp(start), clamp(end));
+		self.extra_cursors.clear();
+		self.is_focused = true;
+		self.scroll_to_cursor = true;
+	}
+
+	/// Returns whether typing replaces the grapheme after the caret instead
+	/// of inserting before it. Insert toggles this.
+	pub fn is_overwrite(&self) -> bool {
+		self.is_overwrite
+	}
+
+	/// Returns when the caret last moved, which its blink is timed from.
+	pub fn last_cursor_move(&self) -> Instant {
+		self.last_cursor_move
+	}
+
+	/// Returns whether the caret is in the shown half of its blink, given
+	/// how long each blink takes, if it blinks at all.
+	pub fn is_caret_shown(&self, blink_rate: Option<Duration>) -> bool {
+		match blink_rate {
+			Some(rate) if !rate.is_zero() => {
+				let elapsed = self.last_cursor_move.elapsed().as_nanos();
+				elapsed % rate.as_nanos() < rate.as_nanos() / 2
+			}
+			_ => true,
+		}
+	}
+
+	/// Returns the number of lines in the [`TextInput`], including the empty
+	/// line after a trailing line break.
+	pub fn line_count(&self) -> usize {
+		self.value.len_lines()
+	}
+
+	/// Returns the range of lines at least partly visible in a viewport
+	/// `bounds_height` pixels tall, at the current scroll offset and text
+	/// size.
+	pub fn visible_line_range(&self, bounds_height: f32) -> Range<usize> {
+		let pitch = self.line_pitch(self.last_size);
+		let row_count = self.rows.len(&self.value);
+		let first = ((self.scroll.y / pitch).floor() as usize).min(row_count);
+		let end = (((self.scroll.y + bounds_height) / pitch).ceil() as usize)
+			.min(row_count);
+
+		let line_of_row =
+			|row| self.value.byte_to_line(self.rows.start(&self.value, row));
+		let first_line = if first < row_count {
+			line_of_row(first)
+		} else {
+			self.line_count()
+		};
+		let end_line = if end > 0 { line_of_row(end - 1) + 1 } else { 0 };
+
+		first_line..end_line.max(first_line)
+	}
+
+	/// Returns how far the contents of the [`TextInput`] are scrolled, in
+	/// pixels.
+	pub fn scroll_offset(&self) -> Vector {
+		self.scroll
+	}
+
+	/// Scrolls the contents of the [`TextInput`] to the given offset, in
+	/// pixels, clamped to the extents of its contents.
+	///
+	/// The offset holds until the caret is moved out of view, at which point
+	/// the view follows the caret again.
+	pub fn set_scroll_offset(&mut self, offset: Vector) {
+		let max_y =
+			self.rows.len(&self.value) as f32 * self.line_pitch(self.last_size);
+
+		// the horizontal extent depends on the renderer, so it's clamped
+		// the next time the `TextInput` processes an event
+		self.scroll =
+			Vector::new(offset.x.max(0.0), offset.y.max(0.0).min(max_y));
+		self.clamp_scroll = true;
+		self.scroll_to_cursor = false;
+	}
+
+	/// Inserts `text` at the primary caret, replacing its selection, and
+	/// returns the new contents.
+	///
+	/// The caret ends up after the inserted text, and any other cursors stay
+	/// on the text they were on.
+	pub fn insert_at_caret(&mut self, text: &str) -> String {
+		let start = match self.cursor.selection(&self.value) {
+			Some((start, end)) => {
+				self.replace_range(start..end, text);
+				start
+			}
+			None => {
+				let caret = self.cursor.end(&self.value);
+				self.replace_range(caret..caret, text);
+				caret
+			}
+		};
+		self.cursor.move_to_byte(start + text.len());
+		self.scroll_to_cursor = true;
+
+		self.contents()
+	}
+
+	/// Deletes the text in the byte `range`, and returns the new contents.
+	///
+	/// See [`replace_range`](State::replace_range) for how cursors are moved.
+	pub fn delete_range(&mut self, range: Range<usize>) -> String {
+		self.replace_range(range, "")
+	}
+
+	/// Replaces the text in the byte `range` with `text`, and returns the new
+	/// contents.
+	///
+	/// The range is clamped to the contents, and rounded down to character
+	/// boundaries. Cursors stay on the text they were on; those inside the
+	/// range move to the end of the replacement.
+	pub fn replace_range(&mut self, range: Range<usize>, text: &str) -> String {
+		let before = self.snapshot();
+
+		let end = self
+			.value
+			.byte_to_char(range.end.min(self.value.len_bytes()));
+		let start = self
+			.value
+			.byte_to_char(range.start.min(self.value.len_bytes()))
+			.min(end);
+
+		let range =
+			self.value.char_to_byte(start)..self.value.char_to_byte(end);
+
+		self.value.remove(start..end);
+		self.value.insert(start, text);
+
+		for cursor in
+			std::iter::once(&mut self.cursor).chain(&mut self.extra_cursors)
+		{
+			cursor.replaced(range.clone(), text.len());
+		}
+		self.merge_cursors();
+		self.selection_expansions.clear();
+		self.refresh_search();
+		self.push_undo(before);
+
+		self.contents()
+	}
+
+	/// Returns the text selected by the primary cursor, if any.
+	pub fn selected_text(&self) -> Option<String> {
+		self.cursor
+			.selection(&self.value)
+			.map(|(start, end)| self.value.byte_slice(start..end).to_string())
+	}
+
+	/// Moves the cursor to the given zero-based line and column and focuses
+	/// the [`TextInput`].
+	///
+	/// The column is counted in characters. Both are clamped, so the cursor
+	/// ends up on the last line, or at the end of the line, if they're out of
+	/// range.
+	pub fn set_cursor_to_line_column(&mut self, line: usize, column: usize) {
+		self.set_cursor_to_byte(self.byte_at_line_column(line, column));
+	}
+
+	/// Returns the byte index of the given zero-based line and column, clamped
+	/// like in [`set_cursor_to_line_column`](State::set_cursor_to_line_column).
+	pub fn byte_at_line_column(&self, line: usize, column: usize) -> usize {
+		let line = line.min(self.value.len_lines() - 1);
+		let line_start = self.value.line_to_char(line);
+
+		let mut line_len = self.value.line(line).len_chars();
+		let mut chars = self.value.line(line).chars_at(line_len);
+		if chars.prev() == Some('\n') {
+			line_len -= 1;
+			if chars.prev() == Some('\r') {
+				line_len -= 1;
+			}
+		}
+
+		self.value.char_to_byte(line_start + column.min(line_len))
+	}
+
+	/// Returns the zero-based line and column of the caret, as displayed.
+	///
+	/// The column is counted in graphemes, with each tab counting as
+	/// `tab_width` columns.
+	pub fn caret_line_column(&self, tab_width: u8) -> (usize, usize) {
+		let caret = self.cursor.end(&self.value);
+		let line = self.value.byte_to_line(caret);
+
+		(line, display_column(&self.value, caret, tab_width))
+	}
+
+	/// Returns the byte index of the primary caret.
+	pub fn caret_offset(&self) -> usize {
+		self.cursor.end(&self.value)
+	}
+
+	/// Returns the bottom left of the primary caret when the [`TextInput`] was
+	/// last drawn, relative to its top left, such as for showing a popup
+	/// below the caret.
+	///
+	/// The point may be outside of the input if the caret is scrolled out of
+	/// view.
+	pub fn caret_point(&self) -> Point {
+		self.caret_point.get()
+	}
+
+	/// Returns the one-based line and column of the caret, with the column
+	/// counted in graphemes.
+	pub fn cursor_position(&self) -> (usize, usize) {
+		let (line, column) = self.caret_line_column(1);
+		(line + 1, column + 1)
+	}
+
+	/// Returns the number of graphemes selected by the primary cursor, or
+	/// `None` if nothing is selected.
+	///
+	/// A CRLF line break counts as one grapheme.
+	pub fn selection_len(&self) -> Option<usize> {
+		let (start, end) = self.cursor.selection(&self.value)?;
+
+		Some(
+			self.value
+				.byte_slice(start..end)
+				.to_string()
+				.graphemes(true)
+				.count(),
+		)
+	}
+
+	/// Returns whether the selection starts and ends on different lines.
+	fn has_multiline_selection(&self) -> bool {
+		self.cursor
+			.selection(&self.value)
+			.is_some_and(|(start, end)| {
+				self.value.byte_to_line(start) != self.value.byte_to_line(end)
+			})
+	}
+
+	/// Returns the range that copying or cutting acts on: the selection, or
+	/// the line at the caret if there is none and
+	/// [`TextInput::copy_line_when_empty`] is enabled.
+	fn copied_range(&self) -> Option<(usize, usize)> {
+		self.cursor
+			.selection(&self.value)
+			.or_else(|| {
+				let line =
+					self.value.byte_to_line(self.cursor.end(&self.value));
+
+				self.copy_line_when_empty.then(|| {
+					(
+						self.value.line_to_byte(line),
+						self.value.line_to_byte(line + 1),
+					)
+				})
+			})
+			.filter(|(start, end)| start < end)
+	}
+
+	/// Returns the primary cursor, followed by any extra cursors.
+	fn cursors(&self) -> impl Iterator<Item = &Cursor> {
+		iter::once(&self.cursor).chain(&self.extra_cursors)
+	}
+
+	/// Applies `edit` at every cursor; see [`edit_all`].
+	fn edit_all(&mut self, edit: impl FnMut(&mut Editor<'_>)) {
+		edit_all(
+			&mut self.value,
+			&mut self.cursor,
+			&mut self.extra_cursors,
+			edit,
+		);
+		self.merge_cursors();
+		self.refresh_search();
+	}
+
+	/// Removes trailing spaces and tabs from every line, as one undoable edit,
+	/// and returns whether there were any.
+	fn trim_trailing_whitespace(&mut self) -> bool {
+		let before = self.snapshot();
+		let removed = Editor::new(&mut self.value, &mut self.cursor)
+			.trim_trailing_whitespace();
+
+		for range in &removed {
+			for cursor in &mut self.extra_cursors {
+				cursor.replaced(range.clone(), 0);
+			}
+		}
+		self.merge_cursors();
+		self.selection_expansions.clear();
+		self.refresh_search();
+		self.push_undo(before);
+
+		!removed.is_empty()
+	}
+
+	/// Returns the contents and cursors, to restore when undoing or redoing.
+	fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			value: self.value.clone(),
+			cursor: self.cursor,
+			extra_cursors: self.extra_cursors.clone(),
+		}
+	}
+
+	/// Replaces the contents and cursors with `snapshot`, returning the ones
+	/// it replaced.
+	fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+		let current = self.snapshot();
+
+		self.value = snapshot.value;
+		self.cursor = snapshot.cursor;
+		self.extra_cursors = snapshot.extra_cursors;
+		self.selection_expansions.clear();
+		self.last_typed = None;
+		self.refresh_search();
+		self.scroll_to_cursor = true;
+
+		current
+	}
+
+	/// Remembers `before`, the contents and cursors before an edit, so the
+	/// edit can be undone, dropping anything that was undone before it.
+	///
+	/// Edits that didn't change the contents, like deleting at the end, are
+	/// left out.
+	fn push_undo(&mut self, before: Snapshot) {
+		self.last_typed = None;
+
+		if before.value == self.value {
+			return;
+		}
+
+		self.undo_stack.push_back(before);
+		if self.undo_stack.len() > MAX_UNDO_STEPS {
+			self.undo_stack.pop_front();
+		}
+
+		self.redo_stack.clear();
+	}
+
+	/// Like [`push_undo`](State::push_undo), after typing `c`, but continues
+	/// the last undo step instead if `c` was typed right after the last typed
+	/// character, within the same word and the whitespace after it.
+	fn push_undo_typing(&mut self, before: Snapshot, c: char) {
+		let continues = match self.last_typed {
+			Some((caret, previous)) => {
+				caret == before.cursor.end(&before.value)
+					&& before.cursor.selection(&before.value).is_none()
+					&& (!previous.is_whitespace() || c.is_whitespace())
+			}
+			None => false,
+		};
+
+		if !continues {
+			self.push_undo(before);
+		}
+		self.last_typed = Some((self.cursor.end(&self.value), c));
+	}
+
+	/// Reverts the last edit, returning whether there was one.
+	fn undo(&mut self) -> bool {
+		match self.undo_stack.pop_back() {
+			Some(snapshot) => {
+				let current = self.restore(snapshot);
+				self.redo_stack.push(current);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Applies the last undone edit again, returning whether there was one.
+	fn redo(&mut self) -> bool {
+		match self.redo_stack.pop() {
+			Some(snapshot) => {
+				let current = self.restore(snapshot);
+				self.undo_stack.push_back(current);
+				true
+			}
+			None => false,
+		}
+	}
+
+	fn clear_undo_history(&mut self) {
+		self.undo_stack.clear();
+		self.redo_stack.clear();
+		self.last_typed = None;
+	}
+
+	/// Removes extra cursors that ended up in the same place as another.
+	fn merge_cursors(&mut self) {
+		let value = &self.value;
+		let mut seen = vec![(self.cursor.start(value), self.cursor.end(value))];
+
+		self.extra_cursors.retain(|cursor| {
+			let range = (cursor.start(value), cursor.end(value));
+			if seen.contains(&range) {
+				false
+			} else {
+				seen.push(range);
+				true
+			}
+		});
+	}
+
+	/// Selects every occurrence of the primary cursor's selection, or of the
+	/// word around the cursor if nothing is selected, with a cursor each.
+	///
+	/// The primary cursor stays on the occurrence it was on.
+	fn select_all_occurrences(&mut self) {
+		let (start, end) = match self.cursor.selection(&self.value) {
+			Some(range) => range,
+			None => {
+				let position = self.cursor.end(&self.value);
+				(
+					self.value.previous_start_of_word(position),
+					self.value.next_end_of_word(position),
+				)
+			}
+		};
+
+		if start == end {
+			return;
+		}
+
+		let needle = self.value.byte_slice(start..end).to_string();
+
+		self.cursor.select_range(start, end);
+		self.extra_cursors = self
+			.find_all(&needle)
+			.into_iter()
+			.filter(|found| found.start != start)
+			.map(|found| {
+				let mut cursor = Cursor::default();
+				cursor.select_range(found.start, found.end);
+				cursor
+			})
+			.collect();
+	}
+
+	/// Returns the byte ranges of every occurrence of `needle`, from first to
+	/// last. Occurrences don't overlap.
+	pub fn find_all(&self, needle: &str) -> Vec<Range<usize>> {
+		if needle.is_empty() {
+			return vec![];
+		}
+
+		self.value
+			.to_string()
+			.match_indices(needle)
+			.map(|(start, found)| start..start + found.len())
+			.collect()
+	}
+
+	/// Highlights every match of `query`, ignoring case, or removes the
+	/// highlights if it's empty.
+	///
+	/// Matches are kept up to date as the contents change. Use
+	/// [`next_match`](State::next_match) and
+	/// [`prev_match`](State::prev_match) to select them.
+	pub fn set_search(&mut self, query: &str) {
+		self.search =
+			Some(query.chars().flat_map(char::to_lowercase).collect())
+				.filter(|query: &Vec<char>| !query.is_empty())
+				.map(|query| (query, vec![]));
+		self.refresh_search();
+	}
+
+	/// Returns the byte ranges of the matches of the search, if any.
+	pub fn search_matches(&self) -> &[Range<usize>] {
+		self.search.as_ref().map_or(&[], |(_, matches)| matches)
+	}
+
+	/// Selects the first match of the search after the primary cursor,
+	/// wrapping around to the first one, and scrolls to it.
+	///
+	/// Returns `false`, changing nothing, if there are no matches.
+	pub fn next_match(&mut self) -> bool {
+		let (start, end) = self.cursor_range();
+		let matches = self.search_matches();
+		let found = matches
+			.iter()
+			.find(|found| found.start >= start.max(end))
+			.or_else(|| matches.first())
+			.cloned();
+
+		self.select_match(found)
+	}
+
+	/// Like [`next_match`](State::next_match), but selects the last match
+	/// before the primary cursor, wrapping around to the last one.
+	pub fn prev_match(&mut self) -> bool {
+		let (start, end) = self.cursor_range();
+		let matches = self.search_matches();
+		let found = matches
+			.iter()
+			.rev()
+			.find(|found| found.start < start.min(end))
+			.or_else(|| matches.last())
+			.cloned();
+
+		self.select_match(found)
+	}
+
+	fn select_match(&mut self, found: Option<Range<usize>>) -> bool {
+		match found {
+			Some(found) => {
+				self.set_selection(found.start, found.end);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Finds the matches of the search again, after the contents changed.
+	fn refresh_search(&mut self) {
+		if let Some((query, matches)) = &mut self.search {
+			*matches = find_ignoring_case(&self.value, query);
+		}
+	}
+
+	/// Adds a cursor selecting the next occurrence of the primary cursor's
+	/// selection, wrapping around to the start. If nothing is selected,
+	/// selects the word around the cursor instead.
+	fn add_next_occurrence(&mut self) {
+		let (start, end) = match self.cursor.selection(&self.value) {
+			Some(range) => range,
+			None => {
+				let position = self.cursor.end(&self.value);
+				self.cursor.select_range(
+					self.value.previous_start_of_word(position),
+					self.value.next_end_of_word(position),
+				);
+				return;
+			}
+		};
+
+		let text = self.value.to_string();
+		let needle = &text[start..end];
+
+		let found = text[end..]
+			.find(needle)
+			.map(|i| end + i)
+			.or_else(|| text[..start].find(needle));
+
+		let range = match found {
+			Some(found) => (found, found + needle.len()),
+			None => return,
+		};
+
+		if self
+			.cursors()
+			.any(|cursor| cursor.selection(&self.value) == Some(range))
+		{
+			return;
+		}
+
+		self.extra_cursors.push(self.cursor);
+		self.cursor.select_range(range.0, range.1);
+	}
+
+	fn cursor_range(&self) -> (usize, usize) {
+		(self.cursor.start(&self.value), self.cursor.end(&self.value))
+	}
+
+	/// Expands the selection, remembering the previous selection so that
+	/// [`State::shrink_selection`] can restore it.
+	fn expand_selection(&mut self) {
+		let previous = self.cursor_range();
+
+		// Any other change to the selection invalidates the remembered ones
+		if self.selection_expansions.last().map(|&(_, to)| to) != Some(previous)
+		{
+			self.selection_expansions.clear();
+		}
+
+		if self.cursor.expand_selection(&self.value) {
+			self.selection_expansions
+				.push((previous, self.cursor_range()));
+		}
+	}
+
+	/// Restores the selection from before the last
+	/// [`State::expand_selection`].
+	fn shrink_selection(&mut self) {
+		match self.selection_expansions.pop() {
+			Some((previous, expanded)) if expanded == self.cursor_range() => {
+				self.cursor.shrink_selection(previous);
+			}
+			_ => self.selection_expansions.clear(),
+		}
+	}
+
+	fn recalculate_scroll_offset<Renderer: text::Renderer>(
+		&mut self,
+		renderer: &Renderer,
+		bounds_size: Size<f32>,
+		font: Renderer::Font,
+		size: u16,
+		tab_width: u8,
+	) {
+		// edits since the start of the update may have moved rows around
+		self.refresh_rows(renderer, font.clone(), size, tab_width);
+
+		let cursor_index = self.cursor.end(&self.value);
+		let line_height = self.line_pitch(size);
+		let cursor = offset_of_index(
+			cursor_index,
+			&self.value,
+			&self.rows,
+			renderer,
+			font,
+			size,
+			line_height,
+			tab_width,
+		);
+
+		let x = if self.rows.is_wrapping() {
+			0.0
+		} else if cursor.x < self.scroll.x {
+			cursor.x
+		} else if cursor.x > self.scroll.x + bounds_size.width {
+			cursor.x - bounds_size.width
+		} else {
+			self.scroll.x
+		};
+
+		let y = if cursor.y < self.scroll.y {
+			cursor.y
+		} else if cursor.y + line_height > self.scroll.y + bounds_size.height {
+			cursor.y + line_height - bounds_size.height
+		} else {
+			self.scroll.y
+		};
+
+		self.scroll = Vector::new(x, y);
+	}
+
+	/// Returns the vertical scroll offset that shows the last row at the
+	/// bottom of a viewport `height` pixels tall.
+	fn bottom_scroll_offset(&self, size: u16, height: f32) -> f32 {
+		(self.rows.len(&self.value) as f32 * self.line_pitch(size) - height)
+			.max(0.0)
+	}
+
+	/// Returns the width of the longest line, measuring the lines again only
+	/// if the text or its size changed since the last call.
+	fn max_line_length<Renderer: text::Renderer>(
+		&self,
+		renderer: &Renderer,
+		font: Renderer::Font,
+		size: u16,
+		tab_width: u8,
+	) -> f32 {
+		self.line_length_cache
+			.get(&self.value, size, tab_width, || {
+				max_line_length(&self.value, renderer, font, size, tab_width)
+			})
+	}
+
+	/// Breaks the text into rows again, wrapped to the width last given to
+	/// [`update`], if any.
+	fn refresh_rows<Renderer: text::Renderer>(
+		&mut self,
+		renderer: &Renderer,
+		font: Renderer::Font,
+		size: u16,
+		tab_width: u8,
+	) {
+		self.rows = self.rows_cache.get(
+			&self.value,
+			renderer,
+			font,
+			size,
+			tab_width,
+			self.wrap_width,
+		);
+	}
+
+	/// Returns whether a selection is being dragged past the edge of the
+	/// text, in which case [`auto_scroll`](State::auto_scroll) should be
+	/// called periodically, such as every frame, to keep scrolling it.
+	pub fn is_auto_scrolling(&self) -> bool {
+		self.is_dragging && self.auto_scroll.is_some()
+	}
+
+	/// Scrolls a selection being dragged past the edge of the text one step
+	/// further, and extends it to the text now at the edge.
+	///
+	/// Does nothing if no selection is being dragged past the edge.
+	pub fn auto_scroll(&mut self) {
+		let auto_scroll = match self.auto_scroll {
+			Some(auto_scroll) if self.is_dragging => auto_scroll,
+			_ => return,
+		};
+
+		self.step_auto_scroll(&auto_scroll);
+
+		// until it's been drawn at this scroll, the selection catches up on
+		// the next step
+		let target = self.auto_scroll_target.take();
+		if let Some((_, index)) =
+			target.filter(|&(scroll, _)| scroll == self.scroll)
+		{
+			self.drag_to(index);
+		}
+	}
+
+	/// Extends the selection being dragged to byte `index`.
+	///
+	/// Drags that began with a double click extend by whole words, and those
+	/// that began with a triple click by whole lines, always keeping what the
+	/// click first selected.
+	fn drag_to(&mut self, index: usize) {
+		let bounds = match self.drag_kind {
+			click::Kind::Single => {
+				self.cursor
+					.select_range(self.cursor.start(&self.value), index);
+				return;
+			}
+			click::Kind::Double => {
+				word_bounds_at(&self.value, index, &self.drag_word_chars)
+			}
+			click::Kind::Triple => line_bounds_at(&self.value, index),
+		};
+
+		let (anchor_start, anchor_end) = self.drag_anchor;
+		if bounds.0 < anchor_start {
+			self.cursor.select_range(anchor_end, bounds.0);
+		} else {
+			self.cursor
+				.select_range(anchor_start, bounds.1.max(anchor_end));
+		}
+	}
+
+	/// Returns where one step of `auto_scroll` scrolls to, clamped to the
+	/// extents of the text.
+	fn next_auto_scroll(&self, auto_scroll: &AutoScroll) -> Vector {
+		let scroll = self.scroll + auto_scroll.velocity;
+		Vector::new(
+			scroll.x.max(0.0).min(auto_scroll.max_scroll.x),
+			scroll.y.max(0.0).min(auto_scroll.max_scroll.y),
+		)
+	}
+
+	/// Scrolls one step of `auto_scroll`, clamped to the extents of the
+	/// text.
+	fn step_auto_scroll(&mut self, auto_scroll: &AutoScroll) {
+		self.scroll = self.next_auto_scroll(auto_scroll);
+
+		if self.sticky_scroll {
+			self.follow_bottom = self.scroll.y >= auto_scroll.max_scroll.y;
+		}
+	}
+
+	/// Scrolls so that the thumb of `scrollbar`, grabbed `grab` pixels from
+	/// its start, follows `point`.
+	fn drag_scrollbar(
+		&mut self,
+		scrollbar: Scrollbar,
+		geometry: &ScrollbarGeometry,
+		grab: f32,
+		point: Point,
+	) {
+		let (track_start, track_length) = scrollbar.extent(geometry.track);
+		let (_, thumb_length) = scrollbar.extent(geometry.thumb);
+		let travel = (track_length - thumb_length).max(1.0);
+		let fraction = ((scrollbar.along(point) - grab - track_start) / travel)
+			.clamp(0.0, 1.0);
+		let scroll = fraction * geometry.max_scroll;
+
+		match scrollbar {
+			Scrollbar::Horizontal => self.scroll.x = scroll,
+			Scrollbar::Vertical => {
+				self.scroll.y = scroll;
+
+				if self.sticky_scroll {
+					self.follow_bottom = scroll >= geometry.max_scroll;
+				}
+			}
+		}
+	}
+
+	/// Returns the distance between lines of text of the given size.
+	fn line_pitch(&self, size: u16) -> f32 {
+		f32::from(size) * self.line_height
+	}
+
+	fn new_size(&mut self, size: u16) {
+		if size != self.last_size {
+			let factor = f32::from(size) / f32::from(self.last_size);
+			self.last_size = size;
+
+			self.scroll = self.scroll * factor;
+		}
+	}
+}
+
+mod platform {
+	use crate::keyboard;
+
+	pub fn is_jump_modifier_pressed(modifiers: keyboard::Modifiers) -> bool {
+		if cfg!(target_os = "macos") {
+			modifiers.alt()
+		} else {
+			modifiers.control()
+		}
+	}
+
+	/// Deleting by words is bound to Ctrl on every platform, as well as to
+	/// the jump modifier.
+	pub fn is_delete_word_modifier_pressed(
+		modifiers: keyboard::Modifiers,
+	) -> bool {
+		modifiers.control() || is_jump_modifier_pressed(modifiers)
+	}
+}
+
+/// Finds the line around `position` for triple-click selection, excluding its
+/// line break.
+fn line_bounds_at(value: &Rope, position: usize) -> (usize, usize) {
+	let line = value.byte_to_line(position.min(value.len_bytes()));
+
+	(value.line_to_byte(line), find_line_content_end(line, value))
+}
+
+/// Finds the word around `position` for double-click selection.
+///
+/// With no `word_chars`, this uses unicode word boundaries. Otherwise, a word
+/// is a run of alphanumeric characters, underscores, and `word_chars`, falling
+/// back to unicode word boundaries if there is no such run at `position`.
+fn word_bounds_at(
+	value: &Rope,
+	position: usize,
+	word_chars: &str,
+) -> (usize, usize) {
+	let unicode_bounds = || {
+		(
+			value.previous_start_of_word(position),
+			value.next_end_of_word(position),
+		)
+	};
+
+	if word_chars.is_empty() {
+		return unicode_bounds();
+	}
+
+	let is_word_char =
+		|c: char| c.is_alphanumeric() || c == '_' || word_chars.contains(c);
+
+	let line_start = value.line_to_byte(value.byte_to_line(position));
+	let line = value.line(value.byte_to_line(position)).to_string();
+	let offset = position - line_start;
+
+	let start = line[..offset]
+		.char_indices()
+		.rev()
+		.take_while(|&(_, c)| is_word_char(c))
+		.last()
+		.map_or(offset, |(i, _)| i);
+	let end = line[offset..]
+		.char_indices()
+		.take_while(|&(_, c)| is_word_char(c))
+		.last()
+		.map_or(offset, |(i, c)| offset + i + c.len_utf8());
+
+	if start == end {
+		unicode_bounds()
+	} else {
+		(line_start + start, line_start + end)
+	}
+}
+
+/// Computes the position of the text cursor at the given point of a
+/// [`TextInput`].
+fn index_at_point<Renderer>(
+	renderer: &Renderer,
+	font: Renderer::Font,
+	size: u16,
+	tab_width: u8,
+	state: &State,
+	mut point: Point,
+) -> usize
 where
 	Renderer: text::Renderer,
 {
 	point = point + state.scroll;
 
-	let line_num = (point.y / f32::from(size)).floor() as usize;
+	let (value, rows) = (&state.value, &state.rows);
+	let row = (point.y / state.line_pitch(size)).floor() as usize;
 
-	let line_start = match state.value.try_line_to_byte(line_num) {
-		Ok(i) if i < state.value.len_bytes() => i,
-		_ => return Some(state.value.len_bytes()),
+	let line_start = match rows.start(value, row) {
+		i if row < rows.len(value) && i < value.len_bytes() => i,
+		_ => return value.len_bytes(),
 	};
 
-	let line = state.value.line(line_num);
+	let line = value.byte_slice(line_start..rows.end(value, row));
 
 	let line_text = line
 		.display(tab_width)
@@ -1251,19 +4005,54 @@ where
 		.expect("No line produced for hit test");
 
 	if line_text.trim().is_empty() {
-		return Some(line_start);
+		return line_start;
 	}
 
-	hit_byte_index(
-		renderer,
-		line,
-		line_text.as_ref(),
-		size,
-		font,
-		tab_width,
-		point,
-	)
-	.map(|offset| line_start + offset)
+	let index = line_start
+		+ hit_byte_index_or_nearest(
+			renderer,
+			line,
+			line_text.as_ref(),
+			size,
+			font,
+			tab_width,
+			point,
+		);
+
+	rows.clamp_to_row(value, row, index)
+}
+
+/// Like [`hit_byte_index`], but falls back to the start or end of `line`
+/// (excluding its line break) when the renderer can't hit test it.
+fn hit_byte_index_or_nearest<'t, Renderer: text::Renderer>(
+	renderer: &Renderer,
+	line: RopeSlice<'_>,
+	line_text: impl Into<Option<&'t str>>,
+	size: u16,
+	font: Renderer::Font,
+	tab_width: u8,
+	point: Point,
+) -> usize {
+	hit_byte_index(renderer, line, line_text, size, font, tab_width, point)
+		.unwrap_or_else(|| {
+			log::warn!(
+				"Failed to hit test for point ({}, {}), \
+				falling back to the nearest end of the line",
+				point.x,
+				point.y,
+			);
+
+			if point.x <= 0.0 {
+				0
+			} else {
+				let mut end = line.len_bytes();
+				while end > 0 && matches!(line.byte(end - 1), b'\n' | b'\r') {
+					end -= 1;
+				}
+
+				end
+			}
+		})
 }
 
 fn hit_byte_index<'t, Renderer: text::Renderer>(
@@ -1334,6 +4123,7 @@ fn hit_byte_index<'t, Renderer: text::Renderer>(
 fn offset_x_of_index<Renderer>(
 	index: usize,
 	value: &Rope,
+	rows: &Rows,
 	renderer: &Renderer,
 	font: Renderer::Font,
 	size: Option<u16>,
@@ -1342,13 +4132,18 @@ fn offset_x_of_index<Renderer>(
 where
 	Renderer: text::Renderer,
 {
-	let line_start = value.line_to_byte(value.byte_to_line(index));
-	width_of_range(line_start, index, value, renderer, font, size, tab_width)
+	let row_start = rows.start(value, rows.row_of(value, index));
+	width_of_range(row_start, index, value, renderer, font, size, tab_width)
 }
 
-fn offset_y_of_index(index: usize, value: &Rope, size: u16) -> f32 {
-	let lines_before = value.byte_to_line(index);
-	lines_before as f32 * f32::from(size)
+fn offset_y_of_index(
+	index: usize,
+	value: &Rope,
+	rows: &Rows,
+	line_height: f32,
+) -> f32 {
+	let rows_before = rows.row_of(value, index);
+	rows_before as f32 * line_height
 }
 
 fn width_of_range<Renderer>(
@@ -1365,147 +4160,585 @@ where
 {
 	let size = size.unwrap_or_else(|| renderer.default_size());
 
-	let space_width = renderer.measure_width(" ", size, font.clone());
-	width_of_slice(
-		value.byte_slice(start..end),
-		renderer,
-		font,
-		size,
-		tab_width,
-		space_width,
-	)
-}
+	width_of_slice(
+		value.byte_slice(start..end),
+		renderer,
+		font,
+		size,
+		tab_width,
+	)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn offset_of_index<Renderer>(
+	index: usize,
+	value: &Rope,
+	rows: &Rows,
+	renderer: &Renderer,
+	font: Renderer::Font,
+	size: u16,
+	line_height: f32,
+	tab_width: u8,
+) -> Point
+where
+	Renderer: text::Renderer,
+{
+	Point::new(
+		offset_x_of_index(
+			index,
+			value,
+			rows,
+			renderer,
+			font,
+			Some(size),
+			tab_width,
+		),
+		offset_y_of_index(index, value, rows, line_height),
+	)
+}
+
+fn max_line_length<Renderer>(
+	value: &Rope,
+	renderer: &Renderer,
+	font: Renderer::Font,
+	size: u16,
+	tab_width: u8,
+) -> f32
+where
+	Renderer: text::Renderer,
+{
+	value
+		.lines()
+		.map(|s| {
+			NotNan::new(width_of_slice(
+				s,
+				renderer,
+				font.clone(),
+				size,
+				tab_width,
+			))
+			.unwrap()
+		})
+		.max()
+		.map(|x| x.into_inner())
+		.unwrap_or(0.0)
+}
+
+/// Measures the width of `slice` as it's displayed.
+///
+/// Tabs are expanded exactly as they are for display and hit testing, so that
+/// measured positions always agree with where text is drawn and clicked.
+fn width_of_slice<Renderer: text::Renderer>(
+	slice: RopeSlice<'_>,
+	renderer: &Renderer,
+	font: Renderer::Font,
+	size: u16,
+	tab_width: u8,
+) -> f32 {
+	let text = slice
+		.chunks()
+		.map(|chunk| replace_tab(chunk, tab_width))
+		.collect::<String>();
+
+	if text.is_empty() {
+		return 0.0;
+	}
+
+	renderer.measure_width(&text, size, font)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::iter::repeat;
+
+	use iced_graphics::{Background, Font};
+	use iced_native::text::Renderer;
+	use ropey::{Rope, RopeBuilder};
+
+	use super::*;
+	use crate::test_support::{Drawn, Harness, Mock, Recorder};
+
+	#[test]
+	fn mock_text_renderer() {
+		assert_eq!(Mock.measure_width(" ", 10, Font::default()), 10.0);
+		assert_eq!(Mock.measure_width("hello", 10, Font::default()), 50.0);
+	}
+
+	#[test]
+	fn width_of_slice_basic() {
+		let rope = Rope::from_str("hello");
+		assert_eq!(
+			width_of_slice(rope.slice(..), &Mock, Font::default(), 10, 4),
+			50.0
+		);
+	}
+
+	#[test]
+	fn width_of_slice_long() {
+		let iters = 10_000;
+		let string = "this is a long string ";
+		let rope = {
+			let mut builder = RopeBuilder::new();
+			for s in repeat(string).take(iters) {
+				builder.append(s)
+			}
+			builder.finish()
+		};
+
+		{
+			let mut chunks = rope.chunks();
+
+			chunks.next();
+
+			assert_ne!(chunks.next(), None);
+		}
+
+		let size = 10;
+		assert_eq!(
+			width_of_slice(rope.slice(..), &Mock, Font::default(), size, 4),
+			(iters * string.len() * usize::from(size)) as f32
+		);
+	}
+
+	#[test]
+	fn width_of_slice_tabs() {
+		let rope = Rope::from_str("\t\thello\tworld");
+		let size = 10;
+		let tab_width = 4;
+		assert_eq!(
+			width_of_slice(
+				rope.slice(..),
+				&Mock,
+				Font::default(),
+				size,
+				tab_width,
+			),
+			(3 * u16::from(tab_width) * size + 10 * size) as f32
+		);
+	}
+
+	#[test]
+	fn tab_offsets_round_trip() {
+		let rope = Rope::from_str("\tfoo\t\tbar");
+		let size = 10;
+		let tab_width = 4;
+
+		for index in [0, 1, 4, 5, 6, rope.len_bytes()] {
+			let x = offset_x_of_index(
+				index,
+				&rope,
+				&Rows::default(),
+				&Mock,
+				Font::default(),
+				Some(size),
+				tab_width,
+			);
+
+			let hit = hit_byte_index(
+				&Mock,
+				rope.line(0),
+				None,
+				size,
+				Font::default(),
+				tab_width,
+				Point::new(x, f32::from(size) / 2.0),
+			);
+
+			assert_eq!(hit, Some(index), "round trip at byte {index}");
+		}
+	}
+
+	fn selection_widths(text: &str, start: usize, end: usize) -> Vec<f32> {
+		let rope = Rope::from_str(text);
+		let mut cursor = Cursor::default();
+		cursor.select_range(start, end);
+
+		let (quads, _) = cursor_geometry(
+			&cursor,
+			&rope,
+			&Rows::default(),
+			&Mock,
+			&Font::default(),
+			10,
+			10.0,
+			4,
+			Rectangle::default(),
+			Color::BLACK,
+		);
+
+		quads.iter().map(|(quad, _)| quad.bounds.width).collect()
+	}
+
+	#[test]
+	fn selection_across_empty_line() {
+		let text = "ab\n\ncd";
+
+		assert_eq!(selection_widths(text, 0, text.len()), [25.0, 5.0, 20.0]);
+		assert_eq!(selection_widths(text, 3, 4), [5.0]);
+	}
+
+	#[test]
+	fn selection_of_line_break() {
+		assert_eq!(selection_widths("hello\n", 0, 6), [55.0]);
+		assert_eq!(selection_widths("hello\r\nworld", 5, 7), [5.0]);
+		assert_eq!(selection_widths("hello\r\nworld", 3, 9), [25.0, 20.0]);
+
+		for width in selection_widths("hello\nworld", 2, 5) {
+			assert!(width >= 0.0);
+		}
+	}
+
+	#[test]
+	fn shrink_height_fits_contents() {
+		let limits =
+			layout::Limits::new(Size::ZERO, Size::new(500.0, f32::INFINITY));
+		let height = |text: &str| {
+			layout(
+				&Mock,
+				&limits,
+				Length::Fill,
+				Length::Shrink,
+				Some(100),
+				&State::with_text(text),
+				Padding::new(5),
+				Some(10),
+				true,
+				1.0,
+				false,
+				4,
+				false,
+				false,
+				&Font::default(),
+			)
+			.bounds()
+			.height
+		};
+
+		assert_eq!(height("one"), 30.0);
+		assert_eq!(height("one\ntwo\nthree"), 50.0);
+		assert_eq!(height(&"line\n".repeat(20)), 100.0);
+	}
+
+	#[test]
+	fn wrapped_lines_take_more_rows() {
+		let limits =
+			layout::Limits::new(Size::ZERO, Size::new(100.0, f32::INFINITY));
+		let height = |word_wrap| {
+			layout(
+				&Mock,
+				&limits,
+				Length::Fill,
+				Length::Shrink,
+				None,
+				&State::with_text("one two three four five six"),
+				Padding::ZERO,
+				Some(10),
+				false,
+				1.0,
+				false,
+				4,
+				word_wrap,
+				false,
+				&Font::default(),
+			)
+			.bounds()
+			.height
+		};
+
+		assert_eq!(height(false), 10.0);
+		assert_eq!(height(true), 30.0);
+	}
+
+	#[test]
+	fn search_matches_are_highlighted() {
+		let mut state = State::with_text("one two one");
+		state.set_search("one");
+		state.next_match();
+
+		let size = Size::new(500.0, 500.0);
+		let node =
+			layout::Node::with_children(size, vec![layout::Node::new(size)]);
+		let style_sheet = <Box<dyn StyleSheet>>::default();
+
+		let mut recorder = Recorder::default();
+		draw(
+			&mut recorder,
+			Layout::new(&node),
+			Point::ORIGIN,
+			&state,
+			"",
+			Some(10),
+			4,
+			RenderWhitespace::None,
+			false,
+			false,
+			false,
+			None,
+			&[],
+			&Font::default(),
+			style_sheet.as_ref(),
+		);
+
+		let count = |color| {
+			recorder
+				.0
+				.iter()
+				.filter(|drawn| **drawn == Drawn::Quad(color))
+				.count()
+		};
+		assert_eq!(count(style_sheet.active_search_highlight_color()), 1);
+		assert_eq!(count(style_sheet.search_highlight_color()), 1);
 
-fn offset_of_index<Renderer>(
-	index: usize,
-	value: &Rope,
-	renderer: &Renderer,
-	font: Renderer::Font,
-	size: u16,
-	tab_width: u8,
-) -> Point
-where
-	Renderer: text::Renderer,
-{
-	Point::new(
-		offset_x_of_index(index, value, renderer, font, Some(size), tab_width),
-		offset_y_of_index(index, value, size),
-	)
-}
+		// matches scrolled out of view aren't drawn
+		let mut state = State::with_text(&"one\n".repeat(200));
+		state.set_search("one");
 
-fn max_line_length<Renderer>(
-	value: &Rope,
-	renderer: &Renderer,
-	font: Renderer::Font,
-	size: u16,
-	tab_width: u8,
-) -> f32
-where
-	Renderer: text::Renderer,
-{
-	let space_width = renderer.measure_width(" ", size, font.clone());
+		let mut recorder = Recorder::default();
+		draw(
+			&mut recorder,
+			Layout::new(&node),
+			Point::ORIGIN,
+			&state,
+			"",
+			Some(10),
+			4,
+			RenderWhitespace::None,
+			false,
+			false,
+			false,
+			None,
+			&[],
+			&Font::default(),
+			style_sheet.as_ref(),
+		);
 
-	value
-		.lines()
-		.map(|s| {
-			NotNan::new(width_of_slice(
-				s,
-				renderer,
-				font.clone(),
-				size,
-				tab_width,
-				space_width,
-			))
-			.unwrap()
-		})
-		.max()
-		.map(|x| x.into_inner())
-		.unwrap_or(0.0)
-}
+		let highlights = recorder
+			.0
+			.iter()
+			.filter(|drawn| {
+				**drawn == Drawn::Quad(style_sheet.search_highlight_color())
+			})
+			.count();
+		// 50 rows fit, and one more is drawn for partial scrolling
+		assert_eq!(highlights, 51);
+	}
 
-fn width_of_slice<Renderer: text::Renderer>(
-	slice: RopeSlice<'_>,
-	renderer: &Renderer,
-	font: Renderer::Font,
-	size: u16,
-	tab_width: u8,
-	space_width: f32,
-) -> f32 {
-	let mut chunks = slice.chunks();
+	#[test]
+	fn whitespace_markers_follow_mode() {
+		let state = State::with_text(" a \tb  ");
+
+		let size = Size::new(500.0, 500.0);
+		let node =
+			layout::Node::with_children(size, vec![layout::Node::new(size)]);
+		let style_sheet = <Box<dyn StyleSheet>>::default();
+
+		// the number of space and tab markers drawn
+		let markers = |mode| {
+			let mut recorder = Recorder::default();
+			draw(
+				&mut recorder,
+				Layout::new(&node),
+				Point::ORIGIN,
+				&state,
+				"",
+				Some(10),
+				4,
+				mode,
+				false,
+				false,
+				false,
+				None,
+				&[],
+				&Font::default(),
+				style_sheet.as_ref(),
+			);
 
-	let mut width = 0.0;
+			let count = |marker: &str| {
+				recorder
+					.0
+					.iter()
+					.filter(|drawn| **drawn == Drawn::Text(marker.to_owned()))
+					.count()
+			};
+			(count("·"), count("→"))
+		};
 
-	let mut s = match chunks.next() {
-		Some(s) => Cow::Borrowed(s),
-		None => return width,
-	};
+		assert_eq!(markers(RenderWhitespace::None), (0, 0));
+		assert_eq!(markers(RenderWhitespace::Boundary), (3, 0));
+		assert_eq!(markers(RenderWhitespace::All), (4, 1));
+	}
 
-	loop {
-		let mut i = 0;
-		let mut text_start = 0;
-		let b = s.as_bytes();
-
-		while i < b.len() {
-			if b[i] == b'\t' {
-				let text = &s[text_start..i];
-				if !text.is_empty() {
-					width += renderer.measure_width(text, size, font.clone());
-				}
+	#[test]
+	fn caret_point_is_below_caret() {
+		let mut state = State::with_text("one\ntwo");
+		state.set_cursor_to_byte(5);
+		assert_eq!(state.caret_offset(), 5);
 
-				let tab_start = i;
-				i += 1;
+		let size = Size::new(500.0, 500.0);
+		let mut text = layout::Node::new(size);
+		text.move_to(Point::new(5.0, 5.0));
+		let node = layout::Node::with_children(size, vec![text]);
 
-				while i < b.len() && b[i] == b'\t' {
-					i += 1;
-				}
+		draw(
+			&mut Recorder::default(),
+			Layout::new(&node),
+			Point::ORIGIN,
+			&state,
+			"",
+			Some(10),
+			4,
+			RenderWhitespace::None,
+			false,
+			false,
+			false,
+			None,
+			&[],
+			&Font::default(),
+			<Box<dyn StyleSheet>>::default().as_ref(),
+		);
 
-				width +=
-					space_width * f32::from(tab_width) * (i - tab_start) as f32;
-				text_start = i;
-			} else {
-				i += 1;
-			}
-		}
+		assert_eq!(state.caret_point(), Point::new(15.0, 25.0));
+	}
 
-		s = if text_start == s.len() {
-			match chunks.next() {
-				Some(s) => Cow::Borrowed(s),
-				None => {
-					let text = &s[text_start..i];
-					if !text.is_empty() {
-						width += renderer.measure_width(text, size, font);
-					}
-					return width;
-				}
-			}
-		} else {
-			match chunks.next() {
-				Some(next) => Cow::Owned(s[text_start..].to_owned() + next),
-				None => {
-					let text = &s[text_start..i];
-					if !text.is_empty() {
-						width += renderer.measure_width(text, size, font);
-					}
-					return width;
-				}
-			}
+	#[test]
+	fn gutter_fits_line_numbers() {
+		let limits =
+			layout::Limits::new(Size::ZERO, Size::new(500.0, f32::INFINITY));
+		let state = State::with_text(&"line\n".repeat(9));
+		let node = |state: &State| {
+			layout(
+				&Mock,
+				&limits,
+				Length::Fill,
+				Length::Shrink,
+				None,
+				state,
+				Padding::new(5),
+				Some(10),
+				false,
+				1.0,
+				true,
+				4,
+				false,
+				false,
+				&Font::default(),
+			)
+		};
+
+		let text_bounds = |node: &layout::Node| {
+			Layout::new(node).children().next().unwrap().bounds()
 		};
+
+		// ten lines, including the empty one at the end
+		let two_digits = node(&state);
+		assert_eq!(two_digits.bounds().width, 500.0);
+		assert_eq!(text_bounds(&two_digits).x, 5.0 + 20.0 + 16.0);
+		assert_eq!(text_bounds(&two_digits).width, 490.0 - 36.0);
+
+		let one_digit = node(&State::with_text("line"));
+		assert_eq!(text_bounds(&one_digit).x, 5.0 + 10.0 + 16.0);
+
+		let mut recorder = Recorder::default();
+		draw(
+			&mut recorder,
+			Layout::new(&two_digits),
+			Point::ORIGIN,
+			&state,
+			"",
+			Some(10),
+			4,
+			RenderWhitespace::None,
+			false,
+			true,
+			false,
+			None,
+			&[],
+			&Font::default(),
+			<Box<dyn StyleSheet>>::default().as_ref(),
+		);
+		for number in 1..=10 {
+			assert!(recorder.0.contains(&Drawn::Text(number.to_string())));
+		}
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use std::iter::repeat;
+	#[test]
+	fn ruler_keeps_room_right_of_text() {
+		let limits =
+			layout::Limits::new(Size::ZERO, Size::new(500.0, f32::INFINITY));
+		let state = State::with_text("one\ntwo");
+		let node = |ruler| {
+			layout(
+				&Mock,
+				&limits,
+				Length::Fill,
+				Length::Shrink,
+				None,
+				&state,
+				Padding::new(5),
+				Some(10),
+				false,
+				1.0,
+				false,
+				4,
+				false,
+				ruler,
+				&Font::default(),
+			)
+		};
+		let text_bounds = |node: &layout::Node| {
+			Layout::new(node).children().next().unwrap().bounds()
+		};
 
-	use iced_graphics::Font;
-	use iced_native::text::Renderer;
-	use ropey::{Rope, RopeBuilder};
+		let without = node(false);
+		let with = node(true);
+		assert_eq!(with.bounds().width, without.bounds().width);
+		assert_eq!(
+			text_bounds(&with).width,
+			text_bounds(&without).width - RULER_WIDTH,
+		);
 
-	use super::*;
+		let text_bounds = text_bounds(&with);
+		let tick = ruler_tick(4, &state.value, text_bounds);
+		assert_eq!(tick.x, text_bounds.x + text_bounds.width);
+		assert!(tick.x + tick.width <= with.bounds().width - 5.0);
+	}
+
+	#[test]
+	fn phantom_line_height() {
+		let limits =
+			layout::Limits::new(Size::ZERO, Size::new(500.0, f32::INFINITY));
+		let height = |text: &str, phantom_line: bool| {
+			layout(
+				&Mock,
+				&limits,
+				Length::Fill,
+				Length::Shrink,
+				None,
+				&State::with_text(text),
+				Padding::ZERO,
+				Some(10),
+				phantom_line,
+				1.0,
+				false,
+				4,
+				false,
+				false,
+				&Font::default(),
+			)
+			.bounds()
+			.height
+		};
+
+		assert_eq!(height("abc", true), 20.0);
+		assert_eq!(height("abc\n", true), 30.0);
+		assert_eq!(height("abc", false), 10.0);
+		assert_eq!(height("abc\n", false), 20.0);
+	}
 
-	struct Mock;
+	/// A renderer that can't hit test anything.
+	struct NoHit;
 
-	impl iced_native::Renderer for Mock {
+	impl iced_native::Renderer for NoHit {
 		fn with_layer(&mut self, _: Rectangle, f: impl FnOnce(&mut Self)) {
 			f(self);
 		}
@@ -1516,15 +4749,10 @@ mod tests {
 
 		fn clear(&mut self) {}
 
-		fn fill_quad(
-			&mut self,
-			_: renderer::Quad,
-			_: impl Into<iced_graphics::Background>,
-		) {
-		}
+		fn fill_quad(&mut self, _: renderer::Quad, _: impl Into<Background>) {}
 	}
 
-	impl Renderer for Mock {
+	impl text::Renderer for NoHit {
 		type Font = Font;
 
 		const ICON_FONT: Self::Font = Font::Default;
@@ -1534,20 +4762,17 @@ mod tests {
 		const ARROW_DOWN_ICON: char = '⬇';
 
 		fn default_size(&self) -> u16 {
-			12
+			Mock.default_size()
 		}
 
 		fn measure(
 			&self,
 			content: &str,
 			size: u16,
-			_: Self::Font,
+			font: Self::Font,
 			bounds: Size,
 		) -> (f32, f32) {
-			(
-				((content.len() * size as usize) as f32).min(bounds.width),
-				f32::from(size).min(bounds.height),
-			)
+			Mock.measure(content, size, font, bounds)
 		}
 
 		fn hit_test(
@@ -1559,76 +4784,466 @@ mod tests {
 			_: Point,
 			_: bool,
 		) -> Option<text::Hit> {
-			unimplemented!()
+			None
 		}
 
 		fn fill_text(&mut self, _: Text<'_, Self::Font>) {}
 	}
 
 	#[test]
-	fn mock_text_renderer() {
-		assert_eq!(Mock.measure_width(" ", 10, Font::default()), 10.0);
-		assert_eq!(Mock.measure_width("hello", 10, Font::default()), 50.0);
+	fn selection_is_drawn_under_text() {
+		let mut state = State::with_text("hello\nworld");
+		state.is_focused = true;
+		state.set_selection(2, 8);
+
+		let size = Size::new(500.0, 500.0);
+		let node =
+			layout::Node::with_children(size, vec![layout::Node::new(size)]);
+		let style_sheet = <Box<dyn StyleSheet>>::default();
+
+		let mut recorder = Recorder::default();
+		draw(
+			&mut recorder,
+			Layout::new(&node),
+			Point::ORIGIN,
+			&state,
+			"",
+			Some(10),
+			4,
+			RenderWhitespace::None,
+			false,
+			false,
+			false,
+			None,
+			&[],
+			&Font::default(),
+			style_sheet.as_ref(),
+		);
+		let drawn = recorder.0;
+
+		let selection = style_sheet.selection_color();
+		assert!(selection.a < 1.0);
+
+		let first_text = drawn
+			.iter()
+			.position(|drawn| matches!(drawn, Drawn::Text(_)))
+			.unwrap();
+		let selections = drawn
+			.iter()
+			.enumerate()
+			.filter(|(_, drawn)| **drawn == Drawn::Quad(selection))
+			.map(|(i, _)| i)
+			.collect::<Vec<_>>();
+
+		// background, then selections, then text, then the caret
+		assert!(matches!(drawn[0], Drawn::Quad(_)));
+		assert!(!selections.is_empty());
+		assert!(selections.iter().all(|&i| 0 < i && i < first_text));
+		assert_eq!(
+			drawn.last(),
+			Some(&Drawn::Quad(style_sheet.cursor_color()))
+		);
 	}
 
 	#[test]
-	fn width_of_slice_basic() {
-		let rope = Rope::from_str("hello");
+	fn failed_hit_test_falls_back_to_line_bounds() {
+		let state = State {
+			value: Rope::from_str("hello world\r\nfoo"),
+			..State::focused()
+		};
+		let index_at = |x, y| {
+			index_at_point(
+				&NoHit,
+				Font::default(),
+				10,
+				4,
+				&state,
+				Point::new(x, y),
+			)
+		};
+
+		assert_eq!(index_at(400.0, 5.0), 11);
+		assert_eq!(index_at(0.0, 5.0), 0);
+		assert_eq!(index_at(400.0, 15.0), 16);
+		assert_eq!(index_at(-5.0, 15.0), 13);
+	}
+
+	#[test]
+	fn caret_line_column_counts_tabs_and_graphemes() {
+		let mut state = State {
+			value: Rope::from_str("ab\n\tx\nhé👍🏽!"),
+			..State::focused()
+		};
+		let mut at = |index| {
+			state.set_cursor_to_byte(index);
+			state.caret_line_column(4)
+		};
+
+		assert_eq!(at(0), (0, 0));
+		assert_eq!(at(2), (0, 2));
+		assert_eq!(at(3), (1, 0));
+		assert_eq!(at(4), (1, 4));
+		assert_eq!(at(5), (1, 5));
+		assert_eq!(at(9), (2, 2));
+		assert_eq!(at(17), (2, 3));
+		assert_eq!(at(18), (2, 4));
+	}
+
+	#[test]
+	fn indent_guides_follow_blocks() {
+		let rope = Rope::from_str(
+			"fn main() {\n\tif x {\n\n        y();\n\t}\n  \n}\n",
+		);
+		let indents = rope
+			.lines()
+			.map(|line| indent_columns(line, 4))
+			.collect::<Vec<_>>();
+
 		assert_eq!(
-			width_of_slice(rope.slice(..), &Mock, Font::default(), 10, 4, 10.0),
-			50.0
+			indents,
+			[
+				Some(0),
+				Some(4),
+				None,
+				Some(8),
+				Some(4),
+				None,
+				Some(0),
+				None
+			]
 		);
+		assert_eq!(indent_guide_levels(&indents, 4), [0, 1, 1, 2, 1, 0, 0, 0]);
 	}
 
 	#[test]
-	fn width_of_slice_long() {
-		let iters = 10_000;
-		let string = "this is a long string ";
-		let rope = {
-			let mut builder = RopeBuilder::new();
-			for s in repeat(string).take(iters) {
-				builder.append(s)
-			}
-			builder.finish()
+	fn selected_text() {
+		let mut state = State {
+			value: Rope::from_str("let x = 1;\nx + 1"),
+			..State::focused()
 		};
+		assert_eq!(state.selected_text(), None);
 
-		{
-			let mut chunks = rope.chunks();
+		state.set_selection(15, 11);
+		assert_eq!(state.selected_text().as_deref(), Some("x + "));
 
-			chunks.next();
+		state.set_selection(4, 100);
+		assert_eq!(state.selected_text().as_deref(), Some("x = 1;\nx + 1"));
+	}
 
-			assert_ne!(chunks.next(), None);
-		}
+	#[test]
+	fn word_bounds_with_word_chars() {
+		let value = Rope::from_str("let x = foo.bar-baz;\nqux");
+
+		assert_eq!(word_bounds_at(&value, 17, ""), (16, 19));
+		assert_eq!(word_bounds_at(&value, 17, "."), (16, 19));
+		assert_eq!(word_bounds_at(&value, 17, "-"), (12, 19));
+		assert_eq!(word_bounds_at(&value, 19, ".-"), (8, 19));
+		assert_eq!(word_bounds_at(&value, 21, ".-"), (21, 24));
+	}
 
-		let size = 10;
-		assert_eq!(
-			width_of_slice(
-				rope.slice(..),
-				&Mock,
-				Font::default(),
-				size,
-				4,
-				size.into()
-			),
-			(iters * string.len() * usize::from(size)) as f32
+	#[test]
+	fn sticky_scroll_follows_growth() {
+		let mut harness = Harness::new("");
+		harness.state.sticky_scroll = true;
+		let append = |harness: &mut Harness, lines: usize| {
+			harness.state.set_text(&"\n".repeat(lines - 1));
+			harness.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+				keyboard::Modifiers::default(),
+			)));
+			harness.state.scroll.y
+		};
+		let wheel = |harness: &mut Harness, lines: f32| {
+			harness.apply(Event::Mouse(mouse::Event::WheelScrolled {
+				delta: mouse::ScrollDelta::Lines { x: 0.0, y: -lines },
+			}));
+			harness.state.scroll.y
+		};
+
+		// lines are 12 pixels tall, in a 500 pixel tall viewport
+		assert_eq!(append(&mut harness, 100), 700.0);
+
+		assert_eq!(wheel(&mut harness, -5.0), 640.0);
+		assert_eq!(append(&mut harness, 150), 640.0);
+
+		wheel(&mut harness, 100.0);
+		assert_eq!(append(&mut harness, 200), 1900.0);
+	}
+
+	#[test]
+	fn caret_on_trailing_empty_line() {
+		let value = Rope::from_str("foo\n");
+		let text_bounds = Rectangle::new(Point::ORIGIN, Size::new(100.0, 24.0));
+
+		let point = offset_of_index(
+			4,
+			&value,
+			&Rows::default(),
+			&Mock,
+			Font::default(),
+			12,
+			12.0,
+			4,
 		);
+		assert_eq!(point, Point::new(0.0, 12.0));
+
+		// scrolled by a fraction of a pixel, leaving the caret just left of
+		// the text bounds
+		let quad = caret_quad(point - Vector::new(0.5, 0.0), text_bounds, 12.0)
+			.expect("caret on trailing empty line wasn't drawn");
+		assert_eq!(quad.bounds.y, 11.0);
+		assert_eq!(quad.bounds.height, 13.0);
+
+		assert!(caret_quad(Point::new(0.0, 30.0), text_bounds, 12.0).is_none());
 	}
 
 	#[test]
-	fn width_of_slice_tabs() {
-		let rope = Rope::from_str("\t\thello\tworld");
-		let size = 10;
-		let tab_width = 4;
-		assert_eq!(
-			width_of_slice(
-				rope.slice(..),
+	fn line_height_scales_geometry() {
+		let state = State {
+			value: Rope::from_str("one\ntwo\nthree"),
+			line_height: 2.0,
+			..State::focused()
+		};
+		let index_at = |y| {
+			index_at_point(
 				&Mock,
 				Font::default(),
-				size,
-				tab_width,
-				size.into()
-			),
-			(3 * u16::from(tab_width) * size + 10 * size) as f32
+				10,
+				4,
+				&state,
+				Point::new(0.0, y),
+			)
+		};
+
+		assert_eq!(index_at(15.0), 0);
+		assert_eq!(index_at(25.0), 4);
+		assert_eq!(index_at(45.0), 8);
+
+		let point = offset_of_index(
+			8,
+			&state.value,
+			&state.rows,
+			&Mock,
+			Font::default(),
+			10,
+			state.line_pitch(10),
+			4,
 		);
+		assert_eq!(point.y, 40.0);
+
+		let height = layout(
+			&Mock,
+			&layout::Limits::new(Size::ZERO, Size::new(500.0, f32::INFINITY)),
+			Length::Fill,
+			Length::Shrink,
+			None,
+			&state,
+			Padding::ZERO,
+			Some(10),
+			false,
+			state.line_height,
+			false,
+			4,
+			false,
+			false,
+			&Font::default(),
+		)
+		.bounds()
+		.height;
+		assert_eq!(height, 60.0);
+	}
+
+	#[test]
+	fn set_text_round_trips() {
+		let mut state = State::with_text("hello");
+		assert_eq!(state.contents(), "hello");
+
+		state.set_selection(1, 5);
+		state.insert_at_caret("i");
+		state.set_text("hé");
+
+		assert_eq!(state.contents(), "hé");
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Index(3)
+		));
+		assert!(state.scroll_to_cursor);
+		assert!(!state.undo());
+
+		state.set_text("");
+		assert_eq!(state.contents(), "");
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Index(0)
+		));
+	}
+
+	#[test]
+	fn cursor_placed_after_inserting_lines() {
+		let mut state = State::default();
+		state.insert_at_caret("first\nsecond\nthird");
+
+		state.set_cursor_to_byte(9);
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Index(9)
+		));
+		assert_eq!(state.caret_line_column(4), (1, 3));
+		assert!(state.is_focused);
+		assert!(state.scroll_to_cursor);
+
+		state.set_selection(6, 12);
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Selection { start: 6, end: 12 }
+		));
+
+		state.set_selection(13, 100);
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Selection { start: 13, end: 18 }
+		));
+	}
+
+	#[test]
+	fn insert_at_caret_replaces_selection() {
+		let mut state = State::with_text("hello world");
+		state.set_selection(0, 5);
+
+		assert_eq!(state.insert_at_caret("goodbye"), "goodbye world");
+		assert!(matches!(
+			state.cursor.state(&state.value),
+			cursor::State::Index(7)
+		));
+
+		state.set_cursor_to_byte(state.value.len_bytes());
+		assert_eq!(state.insert_at_caret("!"), "goodbye world!");
+		assert_eq!(state.cursor.end(&state.value), 14);
+	}
+
+	#[test]
+	fn delete_range_moves_cursors() {
+		let mut state = State::with_text("hello big world");
+		state.set_cursor_to_byte(12);
+
+		assert_eq!(state.delete_range(5..9), "hello world");
+		assert_eq!(state.cursor.end(&state.value), 8);
+
+		// out of range ends are clamped
+		assert_eq!(state.delete_range(5..100), "hello");
+		assert_eq!(state.cursor.end(&state.value), 5);
+	}
+
+	#[test]
+	fn replace_range_keeps_selection_on_its_text() {
+		let mut state = State::with_text("let x = 1; let y = 2;");
+
+		// an edit before the selection shifts it
+		state.set_selection(11, 21);
+		state.replace_range(4..5, "long");
+		assert_eq!(state.selected_text().as_deref(), Some("let y = 2;"));
+
+		// an edit inside the selection resizes it
+		state.replace_range(22..23, "100");
+		assert_eq!(state.selected_text().as_deref(), Some("let y = 100;"));
+
+		// an edit spanning the start of the selection clips it
+		assert_eq!(state.replace_range(9..18, "0; "), "let long 0; y = 100;");
+		assert_eq!(state.selected_text().as_deref(), Some("y = 100;"));
+
+		// a range past the end appends
+		assert_eq!(state.replace_range(100..200, "!"), "let long 0; y = 100;!");
+	}
+
+	#[test]
+	fn cursor_position_is_one_based() {
+		let mut state = State::with_text("a\té\r\nb\r\n");
+		assert_eq!(state.cursor_position(), (1, 1));
+
+		state.set_cursor_to_byte(4);
+		assert_eq!(state.cursor_position(), (1, 4));
+
+		// inside the line break, and after it
+		state.set_cursor_to_byte(5);
+		assert_eq!(state.cursor_position(), (1, 4));
+		state.set_cursor_to_byte(6);
+		assert_eq!(state.cursor_position(), (2, 1));
+
+		state.set_cursor_to_byte(state.value.len_bytes());
+		assert_eq!(state.cursor_position(), (3, 1));
+
+		assert_eq!(state.selection_len(), None);
+		state.set_selection(2, state.value.len_bytes());
+		assert_eq!(state.selection_len(), Some(4));
+	}
+
+	#[test]
+	fn search_matches_ignore_case() {
+		let mut state = State::with_text("Foo foo\nfoO bar");
+		state.set_search("FOO");
+		assert_eq!(state.search_matches(), [0..3, 4..7, 8..11]);
+
+		assert!(state.next_match());
+		assert_eq!(state.cursor.selection(&state.value), Some((0, 3)));
+		assert!(state.next_match());
+		assert!(state.next_match());
+		assert_eq!(state.cursor.selection(&state.value), Some((8, 11)));
+		assert!(state.next_match());
+		assert_eq!(state.cursor.selection(&state.value), Some((0, 3)));
+		assert!(state.prev_match());
+		assert_eq!(state.cursor.selection(&state.value), Some((8, 11)));
+
+		// edits update the matches
+		state.insert_at_caret("x");
+		assert_eq!(state.search_matches(), [0..3, 4..7]);
+		state.set_text("fooFOO");
+		assert_eq!(state.search_matches(), [0..3, 3..6]);
+
+		state.set_search("");
+		assert!(state.search_matches().is_empty());
+		assert!(!state.next_match());
+
+		// lowercasing changes the length of some characters
+		let mut state = State::with_text("İx ix İx");
+		state.set_search("i̇x");
+		assert_eq!(state.search_matches(), [0..3, 7..10]);
+	}
+
+	#[test]
+	fn caret_blinks_after_a_pause() {
+		let rate = Some(Duration::from_secs(1));
+		let mut state = State::focused();
+
+		assert!(state.is_caret_shown(rate));
+
+		state.last_cursor_move -= Duration::from_millis(700);
+		assert!(!state.is_caret_shown(rate));
+		assert!(state.is_caret_shown(None));
+
+		state.last_cursor_move -= Duration::from_millis(500);
+		assert!(state.is_caret_shown(rate));
+	}
+
+	#[test]
+	fn value_reads_the_text_in_place() {
+		let state = State::with_text("héllo\nworld");
+
+		assert_eq!(state.len_bytes(), 12);
+		assert_eq!(state.len_lines(), 2);
+		assert_eq!(state.value().line(1), "world");
+	}
+
+	#[test]
+	fn visible_lines_follow_scroll() {
+		let mut state = State::with_text(&"line\n".repeat(9));
+		state.last_size = 10;
+
+		assert_eq!(state.line_count(), 10);
+		assert_eq!(state.visible_line_range(35.0), 0..4);
+
+		state.scroll.y = 25.0;
+		assert_eq!(state.visible_line_range(30.0), 2..6);
+
+		state.scroll.y = 80.0;
+		assert_eq!(state.visible_line_range(50.0), 8..10);
 	}
 }
@@ -12,11 +12,30 @@ pub(crate) trait RopeExt {
 
 	fn previous_start_of_word(&self, byte_index: usize) -> usize;
 
+	/// Like [`next_end_of_word`](RopeExt::next_end_of_word), but also stops
+	/// at the ends of `snake_case` and `camelCase` parts of identifiers.
+	fn next_end_of_subword(&self, byte_index: usize) -> usize;
+
+	/// Like [`previous_start_of_word`](RopeExt::previous_start_of_word), but
+	/// also stops at the starts of `snake_case` and `camelCase` parts of
+	/// identifiers.
+	fn previous_start_of_subword(&self, byte_index: usize) -> usize;
+
 	fn next_grapheme(&self, byte_index: usize) -> usize;
 
 	fn previous_grapheme(&self, byte_index: usize) -> usize;
+
+	/// Finds the nearest pair of brackets around the given byte range,
+	/// returning the byte indices of the opening and closing bracket.
+	fn enclosing_brackets(
+		&self,
+		start: usize,
+		end: usize,
+	) -> Option<(usize, usize)>;
 }
 
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
 impl RopeExt for RopeSlice<'_> {
 	fn display(&self, tab_width: u8) -> RopeDisplay<'_> {
 		RopeDisplay {
@@ -43,7 +62,7 @@ impl RopeExt for RopeSlice<'_> {
 		UnicodeSegmentation::split_word_bound_indices(line.as_str())
 			.find(|(_, word)| !word.trim_start().is_empty())
 			.map(|(i, next_word)| byte_index + i + next_word.len())
-			.unwrap_or_else(|| self.len_bytes())
+			.unwrap_or(line_end)
 	}
 
 	fn previous_start_of_word(&self, byte_index: usize) -> usize {
@@ -60,7 +79,52 @@ impl RopeExt for RopeSlice<'_> {
 			.filter(|(_, word)| !word.trim_start().is_empty())
 			.next_back()
 			.map(|(i, _)| line_start + i)
-			.unwrap_or(0)
+			.unwrap_or(line_start)
+	}
+
+	fn next_end_of_subword(&self, byte_index: usize) -> usize {
+		let line_index = self.byte_to_line(byte_index);
+		let next_line_start = self.line_to_byte(line_index + 1);
+		let line_end = if next_line_start == self.len_bytes() {
+			self.len_bytes()
+		} else {
+			next_line_start - 1
+		};
+
+		if byte_index == line_end {
+			return next_line_start;
+		}
+
+		let line = self.byte_slice(byte_index..line_end).to_string();
+
+		UnicodeSegmentation::split_word_bound_indices(line.as_str())
+			.filter(|(_, word)| !word.trim_start().is_empty())
+			.flat_map(|(i, word)| {
+				subwords(word).into_iter().map(move |(_, end)| i + end)
+			})
+			.next()
+			.map(|end| byte_index + end)
+			.unwrap_or(line_end)
+	}
+
+	fn previous_start_of_subword(&self, byte_index: usize) -> usize {
+		let line_index = self.byte_to_line(byte_index);
+		let line_start = self.line_to_byte(line_index);
+
+		if byte_index == line_start {
+			return byte_index.saturating_sub(1);
+		}
+
+		let line = self.byte_slice(line_start..byte_index).to_string();
+
+		UnicodeSegmentation::split_word_bound_indices(line.as_str())
+			.filter(|(_, word)| !word.trim_start().is_empty())
+			.flat_map(|(i, word)| {
+				subwords(word).into_iter().map(move |(start, _)| i + start)
+			})
+			.next_back()
+			.map(|start| line_start + start)
+			.unwrap_or(line_start)
 	}
 
 	fn next_grapheme(&self, byte_index: usize) -> usize {
@@ -100,6 +164,59 @@ impl RopeExt for RopeSlice<'_> {
 		}
 	}
 
+	fn enclosing_brackets(
+		&self,
+		start: usize,
+		end: usize,
+	) -> Option<(usize, usize)> {
+		let mut open_index = self.byte_to_char(start);
+		let mut chars = self.chars_at(open_index);
+		let mut unmatched_closes = vec![];
+
+		while let Some(c) = chars.prev() {
+			open_index -= 1;
+
+			if BRACKETS.iter().any(|&(_, close)| close == c) {
+				unmatched_closes.push(c);
+				continue;
+			}
+
+			let (open, close) =
+				match BRACKETS.iter().find(|&&(open, _)| open == c) {
+					Some(&pair) => pair,
+					None => continue,
+				};
+
+			if !unmatched_closes.is_empty() {
+				if unmatched_closes.last() == Some(&close) {
+					unmatched_closes.pop();
+				}
+				continue;
+			}
+
+			let search_start = self.byte_to_char(end);
+			let mut depth = 0usize;
+
+			for (offset, c) in self.chars_at(search_start).enumerate() {
+				if c == open {
+					depth += 1;
+				} else if c == close {
+					match depth.checked_sub(1) {
+						Some(d) => depth = d,
+						None => {
+							return Some((
+								self.char_to_byte(open_index),
+								self.char_to_byte(search_start + offset),
+							))
+						}
+					}
+				}
+			}
+		}
+
+		None
+	}
+
 	fn previous_grapheme(&self, byte_index: usize) -> usize {
 		if byte_index == self.len_bytes() && byte_index == 0 {
 			return 0;
@@ -171,6 +288,14 @@ impl RopeExt for Rope {
 		self.byte_slice(..).previous_start_of_word(byte_index)
 	}
 
+	fn next_end_of_subword(&self, byte_index: usize) -> usize {
+		self.byte_slice(..).next_end_of_subword(byte_index)
+	}
+
+	fn previous_start_of_subword(&self, byte_index: usize) -> usize {
+		self.byte_slice(..).previous_start_of_subword(byte_index)
+	}
+
 	fn next_grapheme(&self, byte_index: usize) -> usize {
 		self.byte_slice(..).next_grapheme(byte_index)
 	}
@@ -178,6 +303,14 @@ impl RopeExt for Rope {
 	fn previous_grapheme(&self, byte_index: usize) -> usize {
 		self.byte_slice(..).previous_grapheme(byte_index)
 	}
+
+	fn enclosing_brackets(
+		&self,
+		start: usize,
+		end: usize,
+	) -> Option<(usize, usize)> {
+		self.byte_slice(..).enclosing_brackets(start, end)
+	}
 }
 
 pub(crate) struct RopeDisplay<'r> {
@@ -211,7 +344,46 @@ impl<'r> Iterator for RopeDisplay<'r> {
 	}
 }
 
-fn replace_tab(chunk: &str, tab_width: u8) -> Cow<'_, str> {
+/// Splits a word into the byte ranges of its `snake_case` and `camelCase`
+/// parts, leaving out underscores.
+///
+/// Words made only of underscores are kept whole.
+fn subwords(word: &str) -> Vec<(usize, usize)> {
+	let mut parts = vec![];
+	let mut start = None;
+	let mut previous_is_lower = false;
+
+	for (i, c) in word.char_indices() {
+		if c == '_' {
+			if let Some(start) = start.take() {
+				parts.push((start, i));
+			}
+			previous_is_lower = false;
+			continue;
+		}
+
+		if c.is_uppercase() && previous_is_lower {
+			if let Some(start) = start.take() {
+				parts.push((start, i));
+			}
+		}
+
+		start.get_or_insert(i);
+		previous_is_lower = c.is_lowercase() || c.is_numeric();
+	}
+
+	if let Some(start) = start {
+		parts.push((start, word.len()));
+	}
+
+	if parts.is_empty() {
+		parts.push((0, word.len()));
+	}
+
+	parts
+}
+
+pub(crate) fn replace_tab(chunk: &str, tab_width: u8) -> Cow<'_, str> {
 	// 255 spaces
 	const SPACES: &str = "                                                                                                                                                                                                                                                               ";
 
@@ -278,4 +450,41 @@ mod tests {
 		assert_eq!(rope.previous_grapheme(4), 3);
 		assert_eq!(rope.previous_grapheme(0), 0);
 	}
+
+	#[test]
+	fn word_motion() {
+		for text in ["fooBarBaz", "foo_bar_baz"] {
+			let rope = Rope::from_str(text);
+			assert_eq!(rope.next_end_of_word(0), text.len());
+			assert_eq!(rope.previous_start_of_word(text.len()), 0);
+		}
+	}
+
+	#[test]
+	fn subword_motion() {
+		let rope = Rope::from_str("fooBarBaz");
+		assert_eq!(rope.next_end_of_subword(0), 3);
+		assert_eq!(rope.next_end_of_subword(3), 6);
+		assert_eq!(rope.next_end_of_subword(6), 9);
+		assert_eq!(rope.previous_start_of_subword(9), 6);
+		assert_eq!(rope.previous_start_of_subword(6), 3);
+		assert_eq!(rope.previous_start_of_subword(3), 0);
+
+		let rope = Rope::from_str("foo_bar_baz");
+		assert_eq!(rope.next_end_of_subword(0), 3);
+		assert_eq!(rope.next_end_of_subword(3), 7);
+		assert_eq!(rope.next_end_of_subword(7), 11);
+		assert_eq!(rope.previous_start_of_subword(11), 8);
+		assert_eq!(rope.previous_start_of_subword(8), 4);
+		assert_eq!(rope.previous_start_of_subword(4), 0);
+	}
+
+	#[test]
+	fn enclosing_brackets() {
+		let rope = Rope::from_str("f(a, [b, c], {d})");
+		assert_eq!(rope.enclosing_brackets(6, 6), Some((5, 10)));
+		assert_eq!(rope.enclosing_brackets(5, 11), Some((1, 16)));
+		assert_eq!(rope.enclosing_brackets(14, 15), Some((13, 15)));
+		assert_eq!(rope.enclosing_brackets(0, 1), None);
+	}
 }
@@ -1,5 +1,7 @@
 use iced_graphics::{Background, Color};
 
+use crate::MarkKind;
+
 /// The appearance of a text input.
 #[derive(Debug, Clone, Copy)]
 pub struct Style {
@@ -32,10 +34,84 @@ pub trait StyleSheet {
 
 	fn value_color(&self) -> Color;
 
-	fn selection_color(&self) -> Color;
+	/// Produces the color of selected text's background.
+	///
+	/// Selections are drawn after the background and before the text, so the
+	/// text stays legible under them whatever the color. A translucent color
+	/// also lets the background show through; by default, the value color at
+	/// a quarter of its opacity.
+	fn selection_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.25,
+			..self.value_color()
+		}
+	}
 
 	fn cursor_color(&self) -> Color;
 
+	/// Produces the color of visible whitespace markers.
+	fn whitespace_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.3,
+			..self.value_color()
+		}
+	}
+
+	/// Produces the color of indent guides.
+	fn indent_guide_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.15,
+			..self.value_color()
+		}
+	}
+
+	/// Produces the color drawn behind matches of a search.
+	fn search_highlight_color(&self) -> Color {
+		Color::from_rgba(1.0, 0.8, 0.2, 0.3)
+	}
+
+	/// Produces the color drawn behind the selected match of a search.
+	fn active_search_highlight_color(&self) -> Color {
+		Color::from_rgba(1.0, 0.6, 0.1, 0.6)
+	}
+
+	/// Produces the color of line numbers in the gutter.
+	fn gutter_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.4,
+			..self.value_color()
+		}
+	}
+
+	/// Produces the color of the thumbs of scrollbars.
+	fn scrollbar_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.2,
+			..self.value_color()
+		}
+	}
+
+	/// Produces the color of the thumb of a scrollbar under the mouse or
+	/// being dragged.
+	fn scrollbar_hovered_color(&self) -> Color {
+		Color {
+			a: self.value_color().a * 0.4,
+			..self.value_color()
+		}
+	}
+
+	/// Produces the color of the ticks of marks of the given kind in the
+	/// ruler.
+	fn mark_color(&self, kind: MarkKind) -> Color {
+		match kind {
+			MarkKind::Match => Color {
+				a: 1.0,
+				..self.selection_color()
+			},
+			MarkKind::Error => Color::from_rgb(0.9, 0.2, 0.2),
+		}
+	}
+
 	/// Produces the style of an hovered text input.
 	fn hovered(&self) -> Style {
 		self.focused()
@@ -70,7 +146,7 @@ impl StyleSheet for Default {
 	}
 
 	fn selection_color(&self) -> Color {
-		Color::from_rgb(0.8, 0.8, 1.0)
+		Color::from_rgba(0.5, 0.5, 1.0, 0.4)
 	}
 
 	fn cursor_color(&self) -> Color {
@@ -0,0 +1,385 @@
+//! Utilities for testing [`TextInput`](crate::TextInput) behavior without a
+//! window or a real renderer.
+//!
+//! Only available with the `test-util` feature.
+
+use iced_graphics::{Background, Font};
+use iced_native::{
+	event::{self, Event},
+	keyboard::{self, KeyCode, Modifiers},
+	layout, mouse, renderer,
+	text::{self, Text},
+	Clipboard, Color, Layout, Point, Rectangle, Shell, Size, Vector,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+	style::StyleSheet, LineEnding, MarkKind, Normalization, RenderWhitespace,
+	Rope, SingleLinePaste, State,
+};
+
+/// A text renderer where every byte is exactly `size` wide and high.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mock;
+
+impl iced_native::Renderer for Mock {
+	fn with_layer(&mut self, _: Rectangle, f: impl FnOnce(&mut Self)) {
+		f(self);
+	}
+
+	fn with_translation(&mut self, _: Vector, f: impl FnOnce(&mut Self)) {
+		f(self)
+	}
+
+	fn clear(&mut self) {}
+
+	fn fill_quad(&mut self, _: renderer::Quad, _: impl Into<Background>) {}
+}
+
+impl text::Renderer for Mock {
+	type Font = Font;
+
+	const ICON_FONT: Self::Font = Font::Default;
+
+	const CHECKMARK_ICON: char = '✅';
+
+	const ARROW_DOWN_ICON: char = '⬇';
+
+	fn default_size(&self) -> u16 {
+		12
+	}
+
+	fn measure(
+		&self,
+		content: &str,
+		size: u16,
+		_: Self::Font,
+		bounds: Size,
+	) -> (f32, f32) {
+		(
+			((content.len() * size as usize) as f32).min(bounds.width),
+			f32::from(size).min(bounds.height),
+		)
+	}
+
+	fn hit_test(
+		&self,
+		content: &str,
+		size: f32,
+		_: Font,
+		_: Size,
+		point: Point,
+		_: bool,
+	) -> Option<text::Hit> {
+		let len = content.graphemes(true).count();
+		let index = (point.x / size).round().max(0.0) as usize;
+		Some(text::Hit::CharOffset(index.min(len)))
+	}
+
+	fn fill_text(&mut self, _: Text<'_, Self::Font>) {}
+}
+
+/// Something drawn by a [`Recorder`].
+#[derive(Debug, PartialEq)]
+pub enum Drawn {
+	Quad(Color),
+	Text(String),
+}
+
+/// A renderer that records what's drawn, in order, and otherwise behaves
+/// like [`Mock`].
+#[derive(Debug, Default)]
+pub struct Recorder(pub Vec<Drawn>);
+
+impl iced_native::Renderer for Recorder {
+	fn with_layer(&mut self, _: Rectangle, f: impl FnOnce(&mut Self)) {
+		f(self);
+	}
+
+	fn with_translation(&mut self, _: Vector, f: impl FnOnce(&mut Self)) {
+		f(self)
+	}
+
+	fn clear(&mut self) {}
+
+	fn fill_quad(
+		&mut self,
+		_: renderer::Quad,
+		background: impl Into<Background>,
+	) {
+		match background.into() {
+			Background::Color(color) => self.0.push(Drawn::Quad(color)),
+		}
+	}
+}
+
+impl text::Renderer for Recorder {
+	type Font = Font;
+
+	const ICON_FONT: Self::Font = Font::Default;
+
+	const CHECKMARK_ICON: char = '✅';
+
+	const ARROW_DOWN_ICON: char = '⬇';
+
+	fn default_size(&self) -> u16 {
+		Mock.default_size()
+	}
+
+	fn measure(
+		&self,
+		content: &str,
+		size: u16,
+		font: Self::Font,
+		bounds: Size,
+	) -> (f32, f32) {
+		Mock.measure(content, size, font, bounds)
+	}
+
+	fn hit_test(
+		&self,
+		content: &str,
+		size: f32,
+		font: Font,
+		bounds: Size,
+		point: Point,
+		nearest_only: bool,
+	) -> Option<text::Hit> {
+		Mock.hit_test(content, size, font, bounds, point, nearest_only)
+	}
+
+	fn fill_text(&mut self, text: Text<'_, Self::Font>) {
+		self.0.push(Drawn::Text(text.content.to_owned()));
+	}
+}
+
+/// A clipboard that stores its contents in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MockClipboard {
+	pub contents: Option<String>,
+}
+
+impl Clipboard for MockClipboard {
+	fn read(&self) -> Option<String> {
+		self.contents.clone()
+	}
+
+	fn write(&mut self, contents: String) {
+		self.contents = Some(contents);
+	}
+}
+
+/// Drives a focused [`State`] with synthesized events, recording the
+/// messages it publishes.
+///
+/// Messages are the contents of the input, as produced by `on_change`.
+#[derive(Debug)]
+pub struct Harness {
+	pub state: State,
+	pub clipboard: MockClipboard,
+	pub messages: Vec<String>,
+	pub paste_line_ending: LineEnding,
+	pub normalization: Normalization,
+	/// Marks shown in the ruler.
+	pub marks: Vec<(usize, MarkKind)>,
+	/// Where the mouse is, relative to the top left of the input.
+	pub pointer: Point,
+	/// Published when Enter is pressed.
+	pub on_submit: Option<String>,
+	/// Whether trailing whitespace is trimmed before submitting.
+	pub trim_trailing_whitespace: bool,
+	/// Whether lines wrap to the width of the input.
+	pub word_wrap: bool,
+	/// The prefix Ctrl+/ toggles on the selected lines.
+	pub line_comment: Option<String>,
+	/// Published when the input loses focus.
+	pub on_blur: Option<String>,
+	/// Whether typing a line break inserts one, rather than only submitting.
+	pub multiline: bool,
+	/// How line breaks are removed from text pasted while not multiline.
+	pub single_line_paste: SingleLinePaste,
+	/// Whether typing a tab inserts spaces up to the next tab stop.
+	pub soft_tabs: bool,
+	/// Whether triple clicking selects all of the text.
+	pub triple_click_selects_all: bool,
+}
+
+impl Harness {
+	/// Creates a [`Harness`] with the given text, the cursor at the start, and
+	/// a 500x500 viewport.
+	pub fn new(text: &str) -> Self {
+		Self {
+			state: State {
+				value: Rope::from_str(text),
+				..State::focused()
+			},
+			clipboard: MockClipboard::default(),
+			messages: vec![],
+			paste_line_ending: LineEnding::Lf,
+			normalization: Normalization::None,
+			marks: vec![],
+			pointer: Point::ORIGIN,
+			on_submit: None,
+			trim_trailing_whitespace: false,
+			word_wrap: false,
+			line_comment: None,
+			on_blur: None,
+			multiline: true,
+			single_line_paste: SingleLinePaste::Flatten,
+			soft_tabs: false,
+			triple_click_selects_all: false,
+		}
+	}
+
+	/// Lays out the input, with room for the ruler right of the text if there
+	/// are marks, like [`layout`](crate::layout).
+	fn node(&self) -> layout::Node {
+		let text = Size::new(500.0, 500.0);
+		let ruler_width = if self.marks.is_empty() {
+			0.0
+		} else {
+			crate::RULER_WIDTH
+		};
+
+		layout::Node::with_children(
+			Size::new(text.width + ruler_width, text.height),
+			vec![layout::Node::new(text)],
+		)
+	}
+
+	/// Presses and releases `key` while holding `modifiers`.
+	pub fn apply_key(
+		&mut self,
+		key: KeyCode,
+		modifiers: Modifiers,
+	) -> event::Status {
+		self.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+			modifiers,
+		)));
+
+		let status = self.apply(Event::Keyboard(keyboard::Event::KeyPressed {
+			key_code: key,
+			modifiers,
+		}));
+
+		self.apply(Event::Keyboard(keyboard::Event::KeyReleased {
+			key_code: key,
+			modifiers,
+		}));
+
+		self.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+			Modifiers::default(),
+		)));
+
+		status
+	}
+
+	/// Moves the mouse to `point` and clicks the left button while holding
+	/// `modifiers`.
+	pub fn click(&mut self, point: Point, modifiers: Modifiers) {
+		self.pointer = point;
+
+		self.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+			modifiers,
+		)));
+		self.apply(Event::Mouse(mouse::Event::ButtonPressed(
+			mouse::Button::Left,
+		)));
+		self.apply(Event::Mouse(mouse::Event::ButtonReleased(
+			mouse::Button::Left,
+		)));
+		self.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+			Modifiers::default(),
+		)));
+	}
+
+	/// Types each character of `text`.
+	pub fn type_text(&mut self, text: &str) {
+		for c in text.chars() {
+			self.apply(Event::Keyboard(keyboard::Event::CharacterReceived(c)));
+		}
+	}
+
+	/// Processes a single event.
+	pub fn apply(&mut self, event: Event) -> event::Status {
+		let on_change = |contents: String| contents;
+		// set on the state by the builder methods of TextInput
+		self.state.multiline = self.multiline;
+		self.state.single_line_paste = self.single_line_paste;
+		self.state.soft_tabs = self.soft_tabs;
+		self.state.triple_click_selects_all = self.triple_click_selects_all;
+		let node = self.node();
+		let mut shell = Shell::new(&mut self.messages);
+
+		crate::update(
+			event,
+			Layout::new(&node),
+			self.pointer,
+			&Mock,
+			&mut self.clipboard,
+			&mut shell,
+			None,
+			4,
+			self.paste_line_ending,
+			self.normalization,
+			"",
+			&self.marks,
+			self.trim_trailing_whitespace,
+			self.word_wrap,
+			self.line_comment.as_deref(),
+			&Font::default(),
+			&on_change,
+			&self.on_submit,
+			&None,
+			&self.on_blur,
+			|| &mut self.state,
+		)
+	}
+
+	/// Draws the input with the default style, as a window would after each
+	/// event.
+	pub fn draw(&self) {
+		crate::draw(
+			&mut Mock,
+			Layout::new(&self.node()),
+			self.pointer,
+			&self.state,
+			"",
+			None,
+			4,
+			RenderWhitespace::None,
+			false,
+			false,
+			self.word_wrap,
+			None,
+			&self.marks,
+			&Font::default(),
+			<Box<dyn StyleSheet>>::default().as_ref(),
+		);
+	}
+
+	/// Returns the contents of the input.
+	pub fn contents(&self) -> String {
+		self.state.contents()
+	}
+
+	/// Returns the byte index of the end of the cursor, where the caret is
+	/// drawn.
+	pub fn cursor_position(&self) -> usize {
+		self.state.cursor.end(&self.state.value)
+	}
+
+	/// Returns the selected byte range, if any.
+	pub fn selection(&self) -> Option<(usize, usize)> {
+		self.state.cursor.selection(&self.state.value)
+	}
+}
+
+/// Returns the modifier used to move by words on this platform.
+pub fn jump_modifier() -> Modifiers {
+	if cfg!(target_os = "macos") {
+		Modifiers::ALT
+	} else {
+		Modifiers::CTRL
+	}
+}
@@ -0,0 +1,373 @@
+//! Break the lines of a text input into the rows they're shown in.
+use std::cell::RefCell;
+
+use iced_native::text;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+	rope_ext::{replace_tab, RopeExt},
+	Rope,
+};
+
+/// The rows a text input shows its text in: one for each line, and more for
+/// lines wrapped to fit its width.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Rows {
+	/// Whether lines are wrapped at all, even if none needed to be.
+	wrapping: bool,
+	/// The byte index of each row that starts inside a line rather than at
+	/// its start, in order.
+	breaks: Vec<usize>,
+}
+
+impl Rows {
+	/// Breaks each line of `value` that's wider than `width` into rows at word
+	/// boundaries, or only at graphemes for words that are wider on their own.
+	/// Whitespace at a break stays at the end of the row before it.
+	///
+	/// Without a `width`, each line is a single row.
+	pub(crate) fn new<Renderer>(
+		value: &Rope,
+		renderer: &Renderer,
+		font: Renderer::Font,
+		size: u16,
+		tab_width: u8,
+		width: Option<f32>,
+	) -> Self
+	where
+		Renderer: text::Renderer,
+	{
+		let width = match width {
+			Some(width) => width,
+			None => return Self::default(),
+		};
+
+		let measure = |text: &str| {
+			renderer.measure_width(
+				&replace_tab(text, tab_width),
+				size,
+				font.clone(),
+			)
+		};
+
+		let mut breaks = vec![];
+
+		for (i, line) in value.lines().enumerate() {
+			let text = line.to_string();
+			let text = text.trim_end_matches(&['\n', '\r'][..]);
+
+			if measure(text) <= width {
+				continue;
+			}
+
+			let line_start = value.line_to_byte(i);
+			let mut row_start = 0;
+
+			for (start, word) in text.split_word_bound_indices() {
+				let end = start + word.len();
+
+				if word.chars().all(char::is_whitespace)
+					|| measure(&text[row_start..end]) <= width
+				{
+					continue;
+				}
+
+				if start > row_start {
+					breaks.push(line_start + start);
+					row_start = start;
+
+					if measure(&text[row_start..end]) <= width {
+						continue;
+					}
+				}
+
+				for (offset, grapheme) in word.grapheme_indices(true) {
+					let grapheme_start = start + offset;
+					let grapheme_end = grapheme_start + grapheme.len();
+
+					if grapheme_start > row_start
+						&& measure(&text[row_start..grapheme_end]) > width
+					{
+						breaks.push(line_start + grapheme_start);
+						row_start = grapheme_start;
+					}
+				}
+			}
+		}
+
+		Self {
+			wrapping: true,
+			breaks,
+		}
+	}
+
+	/// Returns whether lines are wrapped, in which case the text never needs
+	/// to be scrolled sideways.
+	pub(crate) fn is_wrapping(&self) -> bool {
+		self.wrapping
+	}
+
+	/// Returns the number of rows.
+	pub(crate) fn len(&self, value: &Rope) -> usize {
+		value.len_lines() + self.breaks.len()
+	}
+
+	/// Returns the row that byte `index` is shown in.
+	///
+	/// An index at a break is shown at the start of the row after it.
+	pub(crate) fn row_of(&self, value: &Rope, index: usize) -> usize {
+		let index = index.min(value.len_bytes());
+		value.byte_to_line(index)
+			+ self.breaks.partition_point(|&start| start <= index)
+	}
+
+	/// Returns the byte index of the start of `row`, clamped to the last row.
+	pub(crate) fn start(&self, value: &Rope, row: usize) -> usize {
+		let row = row.min(self.len(value) - 1);
+
+		// the row of the break at each position counts the break itself
+		let breaks_before = self
+			.breaks
+			.partition_point(|&start| self.row_of(value, start) <= row);
+
+		match breaks_before.checked_sub(1).map(|i| self.breaks[i]) {
+			Some(start) if self.row_of(value, start) == row => {
+				start.min(value.len_bytes())
+			}
+			_ => value.line_to_byte(row - breaks_before),
+		}
+	}
+
+	/// Returns the byte index of the end of `row`: the start of the next row,
+	/// so including any line break, or the end of `value`.
+	pub(crate) fn end(&self, value: &Rope, row: usize) -> usize {
+		if row + 1 < self.len(value) {
+			self.start(value, row + 1)
+		} else {
+			value.len_bytes()
+		}
+	}
+
+	/// Keeps `index`, found in `row`, from landing at the start of the next
+	/// row, as it would past the end of a row that ends at a break.
+	pub(crate) fn clamp_to_row(
+		&self,
+		value: &Rope,
+		row: usize,
+		index: usize,
+	) -> usize {
+		let end = self.end(value, row);
+
+		if index >= end && self.is_broken(value, row) {
+			value.previous_grapheme(end)
+		} else {
+			index
+		}
+	}
+
+	/// Returns whether `row` ends at a break rather than at the end of its
+	/// line.
+	pub(crate) fn is_broken(&self, value: &Rope, row: usize) -> bool {
+		row + 1 < self.len(value)
+			&& self
+				.breaks
+				.binary_search(&self.start(value, row + 1))
+				.is_ok()
+	}
+}
+
+/// The [`Rows`] a text input's lines were last broken into, reused until its
+/// contents, the width they're wrapped to, or the text size change.
+///
+/// Breaking lines measures their text many times over, and rows are needed
+/// for layout, every event, and drawing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RowsCache(RefCell<Option<(RowsKey, Rows)>>);
+
+/// What [`Rows`] were computed from.
+#[derive(Debug, Clone, PartialEq)]
+struct RowsKey {
+	value: Rope,
+	size: u16,
+	tab_width: u8,
+	width: Option<f32>,
+}
+
+impl RowsCache {
+	/// Returns the rows of `value`, as from [`Rows::new`], breaking its lines
+	/// again only if something changed since the last call.
+	pub(crate) fn get<Renderer>(
+		&self,
+		value: &Rope,
+		renderer: &Renderer,
+		font: Renderer::Font,
+		size: u16,
+		tab_width: u8,
+		width: Option<f32>,
+	) -> Rows
+	where
+		Renderer: text::Renderer,
+	{
+		let key = RowsKey {
+			value: value.clone(),
+			size,
+			tab_width,
+			width,
+		};
+
+		let mut cached = self.0.borrow_mut();
+		match &*cached {
+			Some((cached_key, rows)) if *cached_key == key => rows.clone(),
+			_ => {
+				let rows =
+					Rows::new(value, renderer, font, size, tab_width, width);
+				*cached = Some((key, rows.clone()));
+				rows
+			}
+		}
+	}
+}
+
+/// The width of the longest line of some text, kept until the text or its
+/// size changes.
+///
+/// Finding it measures every line, and it's needed to clamp sideways
+/// scrolling on events and to draw the horizontal scrollbar.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LineLengthCache(RefCell<Option<(LineLengthKey, f32)>>);
+
+/// What a line length was measured from.
+#[derive(Debug, Clone, PartialEq)]
+struct LineLengthKey {
+	value: Rope,
+	size: u16,
+	tab_width: u8,
+}
+
+impl LineLengthCache {
+	/// Returns the width of the longest line of `value`, calling `measure` to
+	/// find it only if something changed since the last call.
+	pub(crate) fn get(
+		&self,
+		value: &Rope,
+		size: u16,
+		tab_width: u8,
+		measure: impl FnOnce() -> f32,
+	) -> f32 {
+		let key = LineLengthKey {
+			value: value.clone(),
+			size,
+			tab_width,
+		};
+
+		let mut cached = self.0.borrow_mut();
+		match &*cached {
+			Some((cached_key, length)) if *cached_key == key => *length,
+			_ => {
+				let length = measure();
+				*cached = Some((key, length));
+				length
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::Mock;
+
+	/// Breaks `text` into rows `columns` characters of the mock renderer wide.
+	fn rows(text: &str, columns: usize) -> (Rope, Rows) {
+		let value = Rope::from_str(text);
+		let rows = Rows::new(
+			&value,
+			&Mock,
+			Default::default(),
+			10,
+			4,
+			Some(columns as f32 * 10.0),
+		);
+
+		(value, rows)
+	}
+
+	#[test]
+	fn wraps_at_words() {
+		let (value, rows) = rows("one two three\nfour", 9);
+
+		assert_eq!(rows.len(&value), 3);
+		assert_eq!(rows.start(&value, 1), 8);
+		assert_eq!(rows.end(&value, 0), 8);
+		assert_eq!(rows.start(&value, 2), 14);
+		assert!(rows.is_broken(&value, 0));
+		assert!(!rows.is_broken(&value, 1));
+
+		// the break itself is at the start of the second row
+		assert_eq!(rows.row_of(&value, 7), 0);
+		assert_eq!(rows.row_of(&value, 8), 1);
+		assert_eq!(rows.row_of(&value, 16), 2);
+	}
+
+	#[test]
+	fn long_words_wrap_at_graphemes() {
+		let (value, rows) = rows("abcdefgh ij", 3);
+
+		let starts = (0..rows.len(&value))
+			.map(|row| rows.start(&value, row))
+			.collect::<Vec<_>>();
+		assert_eq!(starts, [0, 3, 6, 9]);
+	}
+
+	#[test]
+	fn cached_rows_follow_changes() {
+		let cache = RowsCache::default();
+		let get = |value: &Rope, width| {
+			cache.get(value, &Mock, Default::default(), 10, 4, width)
+		};
+
+		let (value, expected) = rows("one two three\nfour", 9);
+		assert_eq!(get(&value, Some(90.0)), expected);
+		assert_eq!(get(&value, Some(90.0)), expected);
+
+		assert_eq!(get(&value, None), Rows::default());
+
+		let (value, expected) = rows("one two three four\nfive", 9);
+		assert_eq!(get(&value, Some(90.0)), expected);
+	}
+
+	#[test]
+	fn no_width_means_no_wrapping() {
+		let value = Rope::from_str("one two three\nfour");
+		let rows = Rows::new(&value, &Mock, Default::default(), 10, 4, None);
+
+		assert!(!rows.is_wrapping());
+		assert_eq!(rows.len(&value), 2);
+		assert_eq!(rows.start(&value, 1), 14);
+	}
+
+	#[test]
+	fn line_lengths_are_measured_once_per_change() {
+		let cache = LineLengthCache::default();
+		let measured = std::cell::Cell::new(0);
+		let get = |value: &Rope, size| {
+			cache.get(value, size, 4, || {
+				measured.set(measured.get() + 1);
+				value.len_chars() as f32
+			})
+		};
+
+		let value = Rope::from_str("one");
+		assert_eq!(get(&value, 10), 3.0);
+		assert_eq!(get(&value, 10), 3.0);
+		assert_eq!(measured.get(), 1);
+
+		get(&value, 20);
+		assert_eq!(measured.get(), 2);
+
+		let mut value = value;
+		value.insert(3, " two");
+		assert_eq!(get(&value, 20), 7.0);
+		assert_eq!(measured.get(), 3);
+	}
+}
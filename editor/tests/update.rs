@@ -0,0 +1,799 @@
+use evalvana_editor::{
+	test_support::{jump_modifier, Harness},
+	LineEnding, MarkKind, Normalization, SingleLinePaste,
+};
+use iced_native::{
+	event,
+	keyboard::{self, KeyCode, Modifiers},
+	mouse, Event, Point, Vector,
+};
+
+#[test]
+fn copy_selection() {
+	let mut harness = Harness::new("hello world");
+
+	harness.apply_key(KeyCode::A, Modifiers::COMMAND);
+	harness.apply_key(KeyCode::C, Modifiers::COMMAND);
+
+	assert_eq!(harness.clipboard.contents.as_deref(), Some("hello world"));
+	assert_eq!(harness.contents(), "hello world");
+	assert!(harness.messages.is_empty());
+}
+
+#[test]
+fn cut_selection() {
+	let mut harness = Harness::new("hello world");
+
+	harness.apply_key(KeyCode::Right, jump_modifier() | Modifiers::SHIFT);
+	harness.apply_key(KeyCode::X, Modifiers::COMMAND);
+
+	assert_eq!(harness.clipboard.contents.as_deref(), Some("hello"));
+	assert_eq!(harness.contents(), " world");
+	assert_eq!(harness.messages, [" world"]);
+	assert_eq!(harness.cursor_position(), 0);
+}
+
+#[test]
+fn paste_at_cursor() {
+	let mut harness = Harness::new("hello world");
+	harness.clipboard.contents = Some("big ".to_owned());
+
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::V, Modifiers::COMMAND);
+
+	assert_eq!(harness.contents(), "hello big world");
+	assert_eq!(harness.cursor_position(), 10);
+}
+
+#[test]
+fn paste_replaces_selection() {
+	let mut harness = Harness::new("hello world");
+	harness.clipboard.contents = Some("goodbye".to_owned());
+
+	harness.apply_key(KeyCode::Right, jump_modifier() | Modifiers::SHIFT);
+	harness.apply_key(KeyCode::V, Modifiers::COMMAND);
+
+	assert_eq!(harness.contents(), "goodbye world");
+}
+
+#[test]
+fn paste_normalizes_line_endings() {
+	for (line_ending, expected) in [
+		(LineEnding::Lf, "a\nb\nc\nd"),
+		(LineEnding::Crlf, "a\r\nb\r\nc\r\nd"),
+		(LineEnding::AsIs, "a\r\nb\nc\rd"),
+	] {
+		let mut harness = Harness::new("");
+		harness.paste_line_ending = line_ending;
+		harness.clipboard.contents = Some("a\r\nb\nc\rd".to_owned());
+
+		harness.apply_key(KeyCode::V, Modifiers::COMMAND);
+
+		assert_eq!(harness.contents(), expected);
+	}
+}
+
+#[test]
+fn nfc_composes_inserted_text() {
+	let mut harness = Harness::new("");
+	harness.normalization = Normalization::Nfc;
+
+	harness.type_text("cafe\u{301}");
+	assert_eq!(harness.contents(), "caf\u{e9}");
+	assert_eq!(harness.cursor_position(), "caf\u{e9}".len());
+
+	harness.clipboard.contents = Some(" re\u{301}sume\u{301}".to_owned());
+	harness.apply_key(KeyCode::V, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "caf\u{e9} r\u{e9}sum\u{e9}");
+	assert_eq!(harness.cursor_position(), harness.contents().len());
+
+	let mut harness = Harness::new("");
+	harness.type_text("e\u{301}");
+	assert_eq!(harness.contents(), "e\u{301}");
+}
+
+#[test]
+fn word_motion() {
+	let mut harness = Harness::new("hello big world");
+
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	assert_eq!(harness.cursor_position(), 5);
+
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	assert_eq!(harness.cursor_position(), 9);
+
+	harness.apply_key(KeyCode::Left, jump_modifier());
+	assert_eq!(harness.cursor_position(), 6);
+}
+
+#[test]
+fn end_stops_before_line_breaks() {
+	let mut harness = Harness::new("foo\r\nbar");
+
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	assert_eq!(harness.cursor_position(), 3);
+
+	// the last line has no line break to stop before
+	harness.apply_key(KeyCode::Down, Modifiers::default());
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	assert_eq!(harness.cursor_position(), 8);
+}
+
+#[test]
+fn shift_selection() {
+	let mut harness = Harness::new("hello world");
+
+	harness.apply_key(KeyCode::Right, Modifiers::SHIFT);
+	harness.apply_key(KeyCode::Right, Modifiers::SHIFT);
+	assert_eq!(harness.selection(), Some((0, 2)));
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	assert_eq!(harness.selection(), None);
+	assert_eq!(harness.cursor_position(), 2);
+}
+
+#[test]
+fn typing_replaces_selection() {
+	let mut harness = Harness::new("hello world");
+
+	harness.apply_key(KeyCode::A, Modifiers::COMMAND);
+	harness.type_text("hi");
+
+	assert_eq!(harness.contents(), "hi");
+	assert_eq!(harness.messages.last().map(String::as_str), Some("hi"));
+}
+
+#[test]
+fn type_at_next_occurrences() {
+	let mut harness = Harness::new("foo bar foo");
+
+	harness.apply_key(KeyCode::D, Modifiers::COMMAND);
+	assert_eq!(harness.selection(), Some((0, 3)));
+
+	harness.apply_key(KeyCode::D, Modifiers::COMMAND);
+	assert_eq!(harness.selection(), Some((8, 11)));
+
+	harness.type_text("x");
+	assert_eq!(harness.contents(), "x bar x");
+
+	harness.apply_key(KeyCode::Backspace, Modifiers::default());
+	assert_eq!(harness.contents(), " bar ");
+}
+
+#[test]
+fn type_at_all_occurrences() {
+	let mut harness = Harness::new("foo bar foo foobar");
+	let select_all = Modifiers::COMMAND | Modifiers::SHIFT;
+
+	// into the middle of the second "foo"
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::L, select_all);
+	assert_eq!(harness.selection(), Some((8, 11)));
+	assert_eq!(harness.state.find_all("foo"), [0..3, 8..11, 12..15]);
+
+	harness.type_text("x");
+	assert_eq!(harness.contents(), "x bar x xbar");
+}
+
+#[test]
+fn undo_restores_deleted_selection() {
+	let mut harness = Harness::new("hello big world");
+
+	harness.apply_key(KeyCode::Right, jump_modifier());
+	harness.apply_key(KeyCode::Right, jump_modifier() | Modifiers::SHIFT);
+	let selection = harness.selection();
+	harness.apply_key(KeyCode::Backspace, Modifiers::default());
+	assert_eq!(harness.contents(), "hello world");
+
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "hello big world");
+	assert_eq!(harness.selection(), selection);
+	assert_eq!(
+		harness.messages.last().map(String::as_str),
+		Some("hello big world")
+	);
+
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND | Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "hello world");
+
+	// nothing left to redo
+	harness.apply_key(KeyCode::Y, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "hello world");
+}
+
+#[test]
+fn undo_typing_by_words() {
+	let mut harness = Harness::new("");
+
+	harness.type_text("one two");
+	harness.apply_key(KeyCode::Backspace, Modifiers::default());
+	harness.apply_key(KeyCode::Backspace, Modifiers::default());
+	harness.type_text("o");
+	assert_eq!(harness.contents(), "one to");
+
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "one t");
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "one two");
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "one ");
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "");
+	assert_eq!(harness.cursor_position(), 0);
+
+	// typing after undoing drops what could be redone
+	harness.type_text("x");
+	harness.apply_key(KeyCode::Y, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "x");
+}
+
+#[test]
+fn delete_words_at_buffer_bounds() {
+	let mut harness = Harness::new("hello big world");
+
+	harness.apply_key(KeyCode::Backspace, Modifiers::CTRL);
+	assert_eq!(harness.contents(), "hello big world");
+
+	harness.apply_key(KeyCode::Delete, Modifiers::CTRL);
+	assert_eq!(harness.contents(), " big world");
+	assert_eq!(harness.cursor_position(), 0);
+
+	harness.apply_key(KeyCode::End, jump_modifier());
+	harness.apply_key(KeyCode::Delete, Modifiers::CTRL);
+	assert_eq!(harness.contents(), " big world");
+
+	harness.apply_key(KeyCode::Backspace, Modifiers::CTRL);
+	assert_eq!(harness.contents(), " big ");
+	assert_eq!(harness.cursor_position(), 5);
+}
+
+#[test]
+fn delete_words_across_line_breaks() {
+	let mut harness = Harness::new("foo\nbar");
+
+	for _ in 0..4 {
+		harness.apply_key(KeyCode::Right, Modifiers::default());
+	}
+	harness.apply_key(KeyCode::Backspace, Modifiers::CTRL);
+	assert_eq!(harness.contents(), "foobar");
+	assert_eq!(harness.cursor_position(), 3);
+
+	let mut harness = Harness::new("foo  \r\nbar");
+
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	harness.apply_key(KeyCode::Delete, Modifiers::CTRL);
+	assert_eq!(harness.contents(), "foo  bar");
+	assert_eq!(harness.cursor_position(), 5);
+}
+
+#[test]
+fn copy_line_without_selection() {
+	let mut harness = Harness::new("hello\nworld");
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::C, Modifiers::COMMAND);
+
+	assert_eq!(harness.clipboard.contents.as_deref(), Some("hello\n"));
+	assert_eq!(harness.contents(), "hello\nworld");
+	assert_eq!(harness.cursor_position(), 1);
+}
+
+#[test]
+fn cut_line_without_selection() {
+	let mut harness = Harness::new("hello\nworld");
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::X, Modifiers::COMMAND);
+
+	assert_eq!(harness.clipboard.contents.as_deref(), Some("hello\n"));
+	assert_eq!(harness.contents(), "world");
+	assert_eq!(harness.messages, ["world"]);
+	assert_eq!(harness.cursor_position(), 0);
+
+	harness.apply_key(KeyCode::X, Modifiers::COMMAND);
+
+	assert_eq!(harness.clipboard.contents.as_deref(), Some("world"));
+	assert_eq!(harness.contents(), "");
+}
+
+#[test]
+fn shift_tab_inserts_tab() {
+	let mut harness = Harness::new("a\nb");
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+		Modifiers::SHIFT,
+	)));
+	harness.apply(Event::Keyboard(keyboard::Event::KeyPressed {
+		key_code: KeyCode::Tab,
+		modifiers: Modifiers::SHIFT,
+	}));
+	// some platforms also send the character
+	harness.type_text("\t");
+	assert_eq!(harness.contents(), "a\t\nb");
+
+	harness.apply_key(KeyCode::A, Modifiers::COMMAND);
+	harness.apply_key(KeyCode::Tab, Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "a\t\nb");
+
+	// even with soft tabs
+	let mut harness = Harness::new("a");
+	harness.soft_tabs = true;
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	harness.apply_key(KeyCode::Tab, Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "a\t");
+}
+
+#[test]
+fn soft_tabs_insert_spaces_to_next_tab_stop() {
+	let mut harness = Harness::new("ab\n\tx");
+	harness.soft_tabs = true;
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.type_text("\t");
+	assert_eq!(harness.contents(), "a   b\n\tx");
+	assert_eq!(harness.cursor_position(), 4);
+
+	harness.type_text("\t");
+	assert_eq!(harness.contents(), "a       b\n\tx");
+
+	// a tab on the line counts as a whole tab width
+	harness.apply_key(KeyCode::End, jump_modifier());
+	harness.type_text("y\t");
+	assert_eq!(harness.contents(), "a       b\n\txy  ");
+}
+
+#[test]
+fn overwrite_mode_types_over_graphemes() {
+	let mut harness = Harness::new("e\u{301}xy\nab");
+
+	harness.apply_key(KeyCode::Insert, Modifiers::default());
+	assert!(harness.state.is_overwrite());
+	harness.type_text("ab");
+	assert_eq!(harness.contents(), "aby\nab");
+
+	// nothing is left to type over at the end of the line, and line breaks
+	// are inserted
+	harness.type_text("cd\n");
+	assert_eq!(harness.contents(), "abcd\n\nab");
+
+	harness.apply_key(KeyCode::Insert, Modifiers::default());
+	harness.type_text("z");
+	assert_eq!(harness.contents(), "abcd\nz\nab");
+}
+
+#[test]
+fn line_breaks_keep_indentation() {
+	let mut harness = Harness::new("");
+
+	harness.type_text("    foo\n");
+	assert_eq!(harness.contents(), "    foo\n    ");
+	assert_eq!(harness.cursor_position(), 12);
+
+	harness.type_text("\tbar\r");
+	assert_eq!(harness.contents(), "    foo\n    \tbar\r\n    \t");
+
+	let mut harness = Harness::new("\tfoo");
+	harness.soft_tabs = true;
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	harness.type_text("\n");
+	assert_eq!(harness.contents(), "\tfoo\n    ");
+}
+
+#[test]
+fn duplicate_lines_below() {
+	let mut harness = Harness::new("one\ntwo");
+	let duplicate = Modifiers::COMMAND | Modifiers::SHIFT;
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::D, duplicate);
+	assert_eq!(harness.contents(), "one\none\ntwo");
+	assert_eq!(harness.cursor_position(), 5);
+
+	// the last line has no line break of its own
+	harness.apply_key(KeyCode::End, jump_modifier());
+	harness.apply_key(KeyCode::D, duplicate);
+	assert_eq!(harness.contents(), "one\none\ntwo\ntwo");
+	assert_eq!(harness.cursor_position(), 15);
+	assert_eq!(harness.messages.len(), 2);
+
+	harness.apply_key(KeyCode::Z, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "one\none\ntwo");
+}
+
+#[test]
+fn move_lines_up_and_down() {
+	let mut harness = Harness::new("one\ntwo\nthree");
+
+	harness.state.set_selection(5, 7);
+	harness.apply_key(KeyCode::Down, Modifiers::ALT);
+	assert_eq!(harness.contents(), "one\nthree\ntwo");
+	assert_eq!(harness.selection(), Some((11, 13)));
+
+	// already at the bottom
+	harness.apply_key(KeyCode::Down, Modifiers::ALT);
+	assert_eq!(harness.contents(), "one\nthree\ntwo");
+
+	harness.apply_key(KeyCode::Up, Modifiers::ALT);
+	harness.apply_key(KeyCode::Up, Modifiers::ALT);
+	assert_eq!(harness.contents(), "two\none\nthree");
+	assert_eq!(harness.selection(), Some((1, 3)));
+	assert_eq!(harness.messages.len(), 3);
+
+	// only the primary cursor's line moves
+	let mut harness = Harness::new("one\ntwo\nthree");
+	harness.click(Point::new(0.0, 5.0), Modifiers::default());
+	harness.click(Point::new(0.0, 17.0), Modifiers::ALT);
+	harness.apply_key(KeyCode::Down, Modifiers::ALT);
+	assert_eq!(harness.contents(), "one\nthree\ntwo");
+	assert_eq!(harness.cursor_position(), 10);
+	harness.type_text("x");
+	assert_eq!(harness.contents(), "one\nthree\nxtwo");
+}
+
+#[test]
+fn tab_indents_selected_lines() {
+	let mut harness = Harness::new("one\ntwo\nthree");
+
+	// up to the start of the last line, which is left alone
+	harness.state.set_selection(1, 8);
+	harness.apply_key(KeyCode::Tab, Modifiers::default());
+	harness.type_text("\t");
+	assert_eq!(harness.contents(), "\tone\n\ttwo\nthree");
+	assert_eq!(harness.selection(), Some((2, 10)));
+	assert_eq!(harness.messages, ["\tone\n\ttwo\nthree"]);
+
+	harness.apply_key(KeyCode::Tab, Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "one\ntwo\nthree");
+	assert_eq!(harness.selection(), Some((1, 8)));
+}
+
+#[test]
+fn ctrl_slash_toggles_line_comments() {
+	let mut harness = Harness::new("  one\n\ntwo\n# three");
+	harness.line_comment = Some("#".to_owned());
+
+	// lines that aren't all commented out are commented, after their
+	// indentation, skipping blank lines
+	harness.apply_key(KeyCode::A, Modifiers::COMMAND);
+	harness.apply_key(KeyCode::Slash, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "  # one\n\n# two\n# # three");
+	assert_eq!(harness.selection(), Some((0, 24)));
+	assert_eq!(harness.messages, ["  # one\n\n# two\n# # three"]);
+
+	harness.apply_key(KeyCode::Slash, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "  one\n\ntwo\n# three");
+
+	// the caret follows the text it was on
+	harness.state.set_cursor_to_byte(14);
+	harness.apply_key(KeyCode::Slash, Modifiers::COMMAND);
+	assert_eq!(harness.contents(), "  one\n\ntwo\nthree");
+	assert_eq!(harness.cursor_position(), 12);
+}
+
+#[test]
+fn soft_tabs_indent_with_spaces() {
+	let mut harness = Harness::new("  a\nb");
+	harness.soft_tabs = true;
+
+	harness.apply_key(KeyCode::A, Modifiers::COMMAND);
+	harness.apply_key(KeyCode::Tab, Modifiers::default());
+	assert_eq!(harness.contents(), "      a\n    b");
+
+	harness.apply_key(KeyCode::Tab, Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "  a\nb");
+	harness.apply_key(KeyCode::Tab, Modifiers::SHIFT);
+	assert_eq!(harness.contents(), "a\nb");
+	assert_eq!(harness.selection(), Some((0, 3)));
+}
+
+#[test]
+fn scroll_offset_holds_until_caret_leaves_view() {
+	let text = format!("{}{}", "\n".repeat(99), "x".repeat(60));
+	let mut harness = Harness::new(&text);
+	// sizes the viewport, so the offset isn't rescaled later
+	harness.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+		Modifiers::default(),
+	)));
+
+	harness.state.set_scroll_offset(Vector::new(1000.0, -5.0));
+	assert_eq!(harness.state.scroll_offset(), Vector::new(1000.0, 0.0));
+
+	harness.apply(Event::Keyboard(keyboard::Event::ModifiersChanged(
+		Modifiers::default(),
+	)));
+	assert_eq!(harness.state.scroll_offset(), Vector::new(220.0, 0.0));
+
+	// moving the caret out of view scrolls just far enough to show it, with
+	// its line of 12 pixels at the top
+	harness.state.set_scroll_offset(Vector::new(0.0, 240.0));
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	assert_eq!(harness.state.scroll_offset(), Vector::new(0.0, 12.0));
+}
+
+#[test]
+fn shift_click_extends_selection() {
+	let mut harness = Harness::new("hello world");
+
+	harness.click(Point::new(24.0, 5.0), Modifiers::default());
+	assert_eq!(harness.cursor_position(), 2);
+
+	harness.click(Point::new(96.0, 5.0), Modifiers::SHIFT);
+	assert_eq!(harness.selection(), Some((2, 8)));
+
+	harness.click(Point::new(12.0, 5.0), Modifiers::SHIFT);
+	assert_eq!(harness.selection(), Some((1, 2)));
+	assert_eq!(harness.cursor_position(), 1);
+}
+
+#[test]
+fn enter_inserts_line_break_when_multiline() {
+	let mut harness = Harness::new("ab");
+	harness.on_submit = Some("submitted".to_owned());
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::Enter, Modifiers::default());
+	harness.type_text("\r");
+
+	assert_eq!(harness.contents(), "a\r\nb");
+	assert_eq!(harness.messages, ["submitted", "a\r\nb"]);
+}
+
+#[test]
+fn enter_only_submits_when_single_line() {
+	let mut harness = Harness::new("ab");
+	harness.on_submit = Some("submitted".to_owned());
+	harness.multiline = false;
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::Enter, Modifiers::default());
+	harness.type_text("\r\n");
+
+	assert_eq!(harness.contents(), "ab");
+	assert_eq!(harness.messages, ["submitted"]);
+}
+
+#[test]
+fn submit_trims_trailing_whitespace() {
+	let text = "let s = \"a  \";\t \r\nfoo \t\n  bar";
+
+	let mut harness = Harness::new(text);
+	harness.on_submit = Some("submitted".to_owned());
+	harness.multiline = false;
+
+	// without trimming, submitting leaves the text alone
+	harness.apply_key(KeyCode::Enter, Modifiers::default());
+	assert_eq!(harness.contents(), text);
+	assert_eq!(harness.messages, ["submitted"]);
+
+	// the caret is in the whitespace after the semicolon
+	harness.messages.clear();
+	harness.trim_trailing_whitespace = true;
+	harness.state.set_cursor_to_byte(15);
+	harness.apply_key(KeyCode::Enter, Modifiers::default());
+
+	let trimmed = "let s = \"a  \";\r\nfoo\n  bar";
+	assert_eq!(harness.contents(), trimmed);
+	assert_eq!(harness.messages, [trimmed, "submitted"]);
+	assert_eq!(harness.cursor_position(), 14);
+}
+
+#[test]
+fn single_line_paste_removes_line_breaks() {
+	for (mode, expected) in [
+		(SingleLinePaste::Flatten, "one two three"),
+		(SingleLinePaste::FirstLine, "one"),
+	] {
+		let mut harness = Harness::new("");
+		harness.clipboard.contents = Some("one\r\ntwo\nthree".to_owned());
+		harness.multiline = false;
+		harness.single_line_paste = mode;
+
+		harness.apply_key(KeyCode::V, Modifiers::COMMAND);
+
+		assert_eq!(harness.contents(), expected);
+		assert_eq!(harness.cursor_position(), expected.len());
+	}
+}
+
+#[test]
+fn escape_deselects_then_blurs() {
+	let mut harness = Harness::new("hello world");
+	harness.on_blur = Some("blurred".to_owned());
+
+	harness.apply_key(KeyCode::Right, jump_modifier() | Modifiers::SHIFT);
+	harness.apply_key(KeyCode::Escape, Modifiers::default());
+
+	assert_eq!(harness.selection(), None);
+	assert_eq!(harness.cursor_position(), 5);
+	assert!(harness.messages.is_empty());
+
+	harness.apply_key(KeyCode::Escape, Modifiers::default());
+
+	assert_eq!(harness.messages, ["blurred"]);
+	assert_eq!(
+		harness.apply_key(KeyCode::Right, Modifiers::default()),
+		event::Status::Ignored
+	);
+	assert_eq!(harness.cursor_position(), 5);
+}
+
+#[test]
+fn clicking_a_mark_scrolls_to_it() {
+	let mut harness = Harness::new(&"line\n".repeat(100));
+	harness.marks = vec![(250, MarkKind::Error)];
+
+	// the ruler is right of the text and spans all of it, so line 50 of 101
+	// is about halfway down
+	harness.click(Point::new(503.0, 248.0), Modifiers::default());
+
+	// lines are 12 pixels tall, and line 50 ends up centered in the 500
+	// pixel tall viewport
+	assert_eq!(harness.state.scroll_offset().y, 50.0 * 12.0 - 244.0);
+	assert_eq!(harness.cursor_position(), 0);
+
+	// anywhere else in the ruler is just text
+	harness.click(Point::new(503.0, 100.0), Modifiers::default());
+	assert_eq!(harness.state.scroll_offset().y, 50.0 * 12.0 - 244.0);
+	assert_ne!(harness.cursor_position(), 0);
+}
+
+#[test]
+fn word_wrap_moves_by_rows() {
+	// 500 pixels fit 41 characters, so each row has 8 words
+	let mut harness = Harness::new(&"word ".repeat(20));
+	harness.word_wrap = true;
+
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::Right, Modifiers::default());
+	harness.apply_key(KeyCode::Down, Modifiers::default());
+	assert_eq!(harness.cursor_position(), 42);
+	harness.apply_key(KeyCode::Down, Modifiers::default());
+	assert_eq!(harness.cursor_position(), 82);
+	harness.apply_key(KeyCode::Up, Modifiers::default());
+	assert_eq!(harness.cursor_position(), 42);
+
+	// clicks land in the row they're on
+	harness.click(Point::new(30.0, 13.0), Modifiers::default());
+	assert_eq!(harness.cursor_position(), 43);
+
+	// wrapped text never scrolls sideways
+	harness.apply_key(KeyCode::End, Modifiers::default());
+	assert_eq!(harness.state.scroll_offset().x, 0.0);
+}
+
+/// Clicks at `point`, then presses the button there again without releasing
+/// it, starting a drag with a double click.
+fn press_twice(harness: &mut Harness, point: Point) {
+	harness.click(point, Modifiers::default());
+	harness.apply(Event::Mouse(mouse::Event::ButtonPressed(
+		mouse::Button::Left,
+	)));
+}
+
+#[test]
+fn double_click_drag_extends_by_words() {
+	let mut harness = Harness::new("one two three four");
+
+	press_twice(&mut harness, Point::new(66.0, 5.0));
+	assert_eq!(harness.selection(), Some((4, 7)));
+
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(186.0, 5.0),
+	}));
+	assert_eq!(harness.selection(), Some((4, 18)));
+
+	// dragging back before the word keeps the word selected
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(6.0, 5.0),
+	}));
+	assert_eq!(harness.selection(), Some((0, 7)));
+	assert_eq!(harness.cursor_position(), 0);
+}
+
+#[test]
+fn triple_click_drag_extends_by_lines() {
+	let mut harness = Harness::new("one\ntwo\nthree\n");
+
+	harness.click(Point::new(6.0, 13.0), Modifiers::default());
+	press_twice(&mut harness, Point::new(6.0, 13.0));
+
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(6.0, 30.0),
+	}));
+	assert_eq!(harness.selection(), Some((4, 13)));
+
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(6.0, 2.0),
+	}));
+	assert_eq!(harness.selection(), Some((0, 7)));
+}
+
+#[test]
+fn triple_click_selects_the_line() {
+	let triple_click = |selects_all| {
+		let mut harness = Harness::new("one\ntwo\nthree");
+		harness.triple_click_selects_all = selects_all;
+
+		for _ in 0..3 {
+			harness.click(Point::new(6.0, 13.0), Modifiers::default());
+		}
+
+		harness.selection()
+	};
+
+	assert_eq!(triple_click(false), Some((4, 7)));
+	assert_eq!(triple_click(true), Some((0, 13)));
+}
+
+#[test]
+fn dragging_past_the_edge_scrolls() {
+	let mut harness = Harness::new(&"line\n".repeat(100));
+
+	harness.apply(Event::Mouse(mouse::Event::ButtonPressed(
+		mouse::Button::Left,
+	)));
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(30.0, 600.0),
+	}));
+
+	// 100 pixels past the bottom scrolls a quarter of that per step, and
+	// selects up to the bottom edge
+	assert_eq!(harness.state.scroll_offset().y, 25.0);
+	assert_eq!(harness.state.caret_line_column(4).0, 43);
+	assert!(harness.state.is_auto_scrolling());
+
+	// holding the pointer still keeps scrolling, two and a half columns in
+	harness.draw();
+	harness.state.auto_scroll();
+	assert_eq!(harness.state.scroll_offset().y, 50.0);
+	assert_eq!(harness.state.caret_line_column(4), (45, 3));
+	assert_eq!(harness.selection(), Some((0, 45 * 5 + 3)));
+
+	harness.apply(Event::Mouse(mouse::Event::ButtonReleased(
+		mouse::Button::Left,
+	)));
+	assert!(!harness.state.is_auto_scrolling());
+	harness.state.auto_scroll();
+	assert_eq!(harness.state.scroll_offset().y, 50.0);
+}
+
+#[test]
+fn dragging_the_scrollbar_scrolls() {
+	let mut harness = Harness::new(&"line\n".repeat(100));
+	let bottom = 101.0 * 12.0 - 500.0;
+
+	// the vertical scrollbar is along the right edge of the text, and
+	// clicking its track below the thumb jumps there
+	harness.click(Point::new(497.0, 490.0), Modifiers::default());
+	assert_eq!(harness.state.scroll_offset().y, bottom);
+	assert_eq!(harness.cursor_position(), 0);
+
+	// the thumb is now at the bottom; drag it back to the top
+	harness.pointer = Point::new(497.0, 450.0);
+	harness.apply(Event::Mouse(mouse::Event::ButtonPressed(
+		mouse::Button::Left,
+	)));
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(497.0, 150.0),
+	}));
+	assert!(harness.state.scroll_offset().y < bottom);
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(497.0, -100.0),
+	}));
+	assert_eq!(harness.state.scroll_offset().y, 0.0);
+
+	harness.apply(Event::Mouse(mouse::Event::ButtonReleased(
+		mouse::Button::Left,
+	)));
+	harness.apply(Event::Mouse(mouse::Event::CursorMoved {
+		position: Point::new(497.0, 450.0),
+	}));
+	assert_eq!(harness.state.scroll_offset().y, 0.0);
+	assert_eq!(harness.selection(), None);
+}
@@ -1,7 +1,11 @@
 // Copyright 2021 ThatsNoMoon
 // Licensed under the Open Software License version 3.0
 
-use std::{borrow::Cow, fmt, path::Path};
+use std::{
+	borrow::Cow,
+	fmt,
+	path::{Path, PathBuf},
+};
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
@@ -19,6 +23,45 @@ impl<Id> RpcMessage<Id> {
 	}
 }
 
+/// The ID of a JSON-RPC message, which may be either a string or a number.
+///
+/// Evalvana sends each call with a numeric ID, and plugins should echo the ID
+/// of a call back unchanged in the responses and chunks answering it. String
+/// IDs of the form `<environment>/<seq>` are also accepted in responses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StringOrNumber<'s> {
+	String(Cow<'s, str>),
+	Number(i64),
+}
+
+impl<'s> From<Cow<'s, str>> for StringOrNumber<'s> {
+	fn from(s: Cow<'s, str>) -> Self {
+		StringOrNumber::String(s)
+	}
+}
+
+impl<'s> From<&'s str> for StringOrNumber<'s> {
+	fn from(s: &'s str) -> Self {
+		StringOrNumber::String(Cow::Borrowed(s))
+	}
+}
+
+impl From<i64> for StringOrNumber<'_> {
+	fn from(n: i64) -> Self {
+		StringOrNumber::Number(n)
+	}
+}
+
+impl fmt::Display for StringOrNumber<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StringOrNumber::String(s) => fmt::Display::fmt(s, f),
+			StringOrNumber::Number(n) => fmt::Display::fmt(n, f),
+		}
+	}
+}
+
 struct RpcVersionVisitor;
 impl<'de> de::Visitor<'de> for RpcVersionVisitor {
 	type Value = ();
@@ -46,18 +89,53 @@ fn serialize_jsonrpc<S: Serializer>(_: &(), ser: S) -> Result<S::Ok, S::Error> {
 	ser.serialize_str("2.0")
 }
 
+/// The `"jsonrpc": "2.0"` member of a message.
+#[derive(Debug, Clone, Copy)]
+struct JsonRpcVersion;
+
+impl Serialize for JsonRpcVersion {
+	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		serialize_jsonrpc(&(), ser)
+	}
+}
+
+impl<'de> Deserialize<'de> for JsonRpcVersion {
+	fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+		deserialize_jsonrpc(d).map(|()| JsonRpcVersion)
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcMethodCall<'id, 'm, Args> {
 	#[serde(flatten)]
-	pub rpc: RpcMessage<Cow<'id, str>>,
+	pub rpc: RpcMessage<StringOrNumber<'id>>,
 	pub method: Cow<'m, str>,
 	pub params: Args,
 }
 
+/// A method call that isn't answered, and so has no ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RpcNotification<'m, Params> {
+	jsonrpc: JsonRpcVersion,
+	pub method: Cow<'m, str>,
+	pub params: Params,
+}
+
+impl<'m, Params> RpcNotification<'m, Params> {
+	pub fn new(method: impl Into<Cow<'m, str>>, params: Params) -> Self {
+		Self {
+			jsonrpc: JsonRpcVersion,
+			method: method.into(),
+			params,
+		}
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcResponse<'id, 'e, T> {
 	#[serde(flatten)]
-	pub rpc: RpcMessage<Option<Cow<'id, str>>>,
+	pub rpc: RpcMessage<Option<StringOrNumber<'id>>>,
 	#[serde(flatten)]
 	pub data: RpcResponseResult<'e, T>,
 }
@@ -85,6 +163,21 @@ pub struct RpcError<'m> {
 	pub message: Cow<'m, str>,
 }
 
+impl<'m> RpcError<'m> {
+	/// The code of the error plugins respond with to calls of methods they
+	/// don't support.
+	pub const METHOD_NOT_FOUND: i32 = -32601;
+
+	/// Creates the error a call of `method` is answered with if it isn't
+	/// supported.
+	pub fn method_not_found(method: &str) -> Self {
+		Self {
+			code: Self::METHOD_NOT_FOUND,
+			message: Cow::Owned(format!("Method not found: {}", method)),
+		}
+	}
+}
+
 impl<'m> fmt::Display for RpcError<'m> {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, "jsonrpc error: {} - {}", self.code, self.message)
@@ -108,17 +201,415 @@ pub struct EvalFileArgs<'p> {
 
 pub type EvalFileCall<'id, 'n, 'p> = RpcMethodCall<'id, 'n, EvalFileArgs<'p>>;
 
+/// Asks a plugin to stop an evaluation that's still running.
+///
+/// The cancelled eval call should still be responded to, such as with the
+/// results so far or an error. Plugins that can't cancel evaluations should
+/// respond to this call with [`RpcError::METHOD_NOT_FOUND`], and otherwise
+/// with no results.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CancelArgs<'id> {
+	/// The ID of the eval call to cancel.
+	pub id: StringOrNumber<'id>,
+}
+
+pub type CancelCall<'id, 'n, 'c> = RpcMethodCall<'id, 'n, CancelArgs<'c>>;
+
+/// Asks a plugin how the code at the caret could be completed.
+///
+/// Evalvana only sends this call to plugins that advertise support for it in
+/// their capabilities.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompleteArgs<'s> {
+	pub code: Cow<'s, str>,
+	/// The byte offset of the caret in `code`.
+	pub offset: usize,
+}
+
+pub type CompleteCall<'id, 'n, 's> = RpcMethodCall<'id, 'n, CompleteArgs<'s>>;
+
+/// A way the code at the caret could be completed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Completion {
+	/// What the completion is listed as.
+	pub label: String,
+	/// The text inserted at the caret when the completion is picked.
+	pub insert_text: String,
+	/// Something shown beside the label, such as the type of the completion.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub detail: Option<String>,
+}
+
+/// The result of a complete call.
+///
+/// The completions are wrapped in an object, rather than sent as a list like
+/// the results of an eval call, so the two responses can be told apart.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompleteResult {
+	pub completions: Vec<Completion>,
+}
+
+pub type CompleteResponse<'id, 'e> = RpcResponse<'id, 'e, CompleteResult>;
+
 pub type EvalResponse<'id, 'e> = RpcResponse<'id, 'e, Vec<EvalResult>>;
 
+/// The method of [`EvalChunk`] notifications.
+pub const EVAL_CHUNK_METHOD: &str = "eval-chunk";
+
+/// A result of an evaluation that's still running.
+///
+/// Plugins may send any number of chunks for an eval call before responding
+/// to it, and each result is shown after the ones before it as soon as it
+/// arrives. The results in the response are shown after those of the chunks.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvalChunkArgs<'id> {
+	/// The ID of the eval call this result is from.
+	pub id: StringOrNumber<'id>,
+	pub result: EvalResult,
+	/// Whether this is the last result of the evaluation, in which case the
+	/// eval call doesn't need a response.
+	#[serde(rename = "final", default)]
+	pub is_final: bool,
+}
+
+pub type EvalChunk<'id, 'm> = RpcNotification<'m, EvalChunkArgs<'id>>;
+
+/// Anything a plugin sends in reply to a call.
+///
+/// Error responses are always parsed as [`PluginMessage::Response`], whichever
+/// call they answer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PluginMessage<'id, 'e, 'm> {
+	Chunk(Box<EvalChunk<'id, 'm>>),
+	Response(EvalResponse<'id, 'e>),
+	Completions(CompleteResponse<'id, 'e>),
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "level", content = "text")]
 pub enum EvalResult {
 	Success(EvalMessage),
 	Warning(EvalMessage),
 	Error(EvalMessage),
+	Compound(CompoundResult),
+}
+
+impl EvalResult {
+	pub fn level(&self) -> Level {
+		match self {
+			EvalResult::Success(_) => Level::Success,
+			EvalResult::Warning(_) => Level::Warning,
+			EvalResult::Error(_) => Level::Error,
+			EvalResult::Compound(compound) => compound.level(),
+		}
+	}
+
+	pub fn message(&self) -> &EvalMessage {
+		match self {
+			EvalResult::Success(msg)
+			| EvalResult::Warning(msg)
+			| EvalResult::Error(msg) => msg,
+			EvalResult::Compound(compound) => &compound.summary,
+		}
+	}
+}
+
+/// A result made up of other results grouped by level, such as the report of
+/// a test run with sections for passing and failing tests.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompoundResult {
+	pub summary: EvalMessage,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub successes: Vec<EvalResult>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub warnings: Vec<EvalResult>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub errors: Vec<EvalResult>,
+}
+
+impl CompoundResult {
+	/// Returns the most severe level of the contained results, or
+	/// [`Level::Success`] if there are none.
+	pub fn level(&self) -> Level {
+		if !self.errors.is_empty()
+			|| self.results().any(|r| r.level() == Level::Error)
+		{
+			Level::Error
+		} else if !self.warnings.is_empty()
+			|| self.results().any(|r| r.level() == Level::Warning)
+		{
+			Level::Warning
+		} else {
+			Level::Success
+		}
+	}
+
+	/// Returns the non-empty lists of contained results, with their levels.
+	pub fn groups(&self) -> impl Iterator<Item = (Level, &[EvalResult])> {
+		IntoIterator::into_iter([
+			(Level::Success, &self.successes[..]),
+			(Level::Warning, &self.warnings[..]),
+			(Level::Error, &self.errors[..]),
+		])
+		.filter(|(_, results)| !results.is_empty())
+	}
+
+	fn results(&self) -> impl Iterator<Item = &EvalResult> {
+		self.groups().flat_map(|(_, results)| results)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Level {
+	Success,
+	Warning,
+	Error,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EvalMessage {
 	pub text: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub location: Option<Location>,
+	/// A plugin-defined category of the message, such as `"info"` or
+	/// `"debug"`, which Evalvana colors by instead of the level of its result.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub category: Option<String>,
+	/// Something to show instead of `text`, which is still shown where the
+	/// content can't be, such as when copying results.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub content: Option<Content>,
+}
+
+/// Content of a message richer than plain text.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind")]
+pub enum Content {
+	/// Rows of text in columns, under a row of headers.
+	Table {
+		headers: Vec<String>,
+		rows: Vec<Vec<String>>,
+	},
+	/// An image, such as a PNG, encoded in base64 `data`.
+	Image { mime: String, data: String },
+	/// Content of a kind this version of Evalvana doesn't know about, in
+	/// which case the text of the message is shown instead.
+	#[serde(other)]
+	Unknown,
+}
+
+/// A position in source code that a result refers to.
+///
+/// `line` and `column` are 1-based, as most compilers report them. `path` is
+/// the file the position is in, or `None` for the evaluated code itself.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Location {
+	pub line: u32,
+	pub column: u32,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub path: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	fn message(text: &str) -> EvalMessage {
+		EvalMessage {
+			text: text.to_owned(),
+			location: None,
+			category: None,
+			content: None,
+		}
+	}
+
+	#[test]
+	fn content_round_trips() {
+		let table = Content::Table {
+			headers: vec!["x".to_owned(), "y".to_owned()],
+			rows: vec![vec!["1".to_owned(), "2".to_owned()]],
+		};
+
+		let value = serde_json::to_value(&table).unwrap();
+		assert_eq!(
+			value,
+			json!({ "kind": "Table", "headers": ["x", "y"], "rows": [["1", "2"]] })
+		);
+		assert_eq!(serde_json::from_value::<Content>(value).unwrap(), table);
+	}
+
+	#[test]
+	fn unknown_content_falls_back_to_text() {
+		let result: EvalResult = serde_json::from_value(json!({
+			"level": "Success",
+			"text": {
+				"text": "<plot>",
+				"content": { "kind": "Plot", "points": [[0, 1]] },
+			},
+		}))
+		.unwrap();
+
+		assert_eq!(result.message().text, "<plot>");
+		assert_eq!(result.message().content, Some(Content::Unknown));
+	}
+
+	#[test]
+	fn compound_results_take_their_most_severe_level() {
+		let result: EvalResult = serde_json::from_value(json!({
+			"level": "Compound",
+			"text": {
+				"summary": { "text": "1 passed, 1 failed" },
+				"successes": [{ "level": "Success", "text": { "text": "a" } }],
+				"errors": [{
+					"level": "Compound",
+					"text": {
+						"summary": { "text": "b" },
+						"warnings": [
+							{ "level": "Warning", "text": { "text": "slow" } },
+						],
+						"errors": [
+							{ "level": "Error", "text": { "text": "failed" } },
+						],
+					},
+				}],
+			},
+		}))
+		.unwrap();
+
+		assert_eq!(result.level(), Level::Error);
+		assert_eq!(result.message().text, "1 passed, 1 failed");
+
+		let compound = match result {
+			EvalResult::Compound(compound) => compound,
+			_ => panic!("Expected a compound result"),
+		};
+		// groups without results are left out
+		let levels = compound.groups().map(|(level, _)| level);
+		assert_eq!(levels.collect::<Vec<_>>(), [Level::Success, Level::Error]);
+
+		let nested = &compound.errors[0];
+		assert_eq!(nested.level(), Level::Error);
+
+		let empty = CompoundResult {
+			summary: message("nothing ran"),
+			successes: vec![],
+			warnings: vec![],
+			errors: vec![],
+		};
+		assert_eq!(empty.level(), Level::Success);
+	}
+
+	#[test]
+	fn chunks_round_trip() {
+		let chunk = EvalChunk::new(
+			EVAL_CHUNK_METHOD,
+			EvalChunkArgs {
+				id: "cargo/0/1".into(),
+				result: EvalResult::Success(message("partial")),
+				is_final: true,
+			},
+		);
+
+		let value = serde_json::to_value(&chunk).unwrap();
+		assert_eq!(
+			value,
+			json!({
+				"jsonrpc": "2.0",
+				"method": "eval-chunk",
+				"params": {
+					"id": "cargo/0/1",
+					"result": { "level": "Success", "text": { "text": "partial" } },
+					"final": true,
+				},
+			})
+		);
+
+		match serde_json::from_value(value).unwrap() {
+			PluginMessage::Chunk(chunk) => {
+				assert_eq!(chunk.method, EVAL_CHUNK_METHOD);
+				assert_eq!(chunk.params.id, "cargo/0/1".into());
+				assert_eq!(chunk.params.result.message().text, "partial");
+				assert!(chunk.params.is_final);
+			}
+			other => panic!("chunk parsed as {:?}", other),
+		}
+	}
+
+	#[test]
+	fn chunks_are_not_final_by_default() {
+		let chunk: EvalChunk = serde_json::from_value(json!({
+			"jsonrpc": "2.0",
+			"method": "eval-chunk",
+			"params": {
+				"id": 3,
+				"result": { "level": "Warning", "text": { "text": "slow" } },
+			},
+		}))
+		.unwrap();
+
+		assert_eq!(chunk.params.id, StringOrNumber::Number(3));
+		assert!(!chunk.params.is_final);
+	}
+
+	#[test]
+	fn responses_are_not_chunks() {
+		let response = json!({
+			"jsonrpc": "2.0",
+			"id": "cargo/0/1",
+			"result": [{ "level": "Success", "text": { "text": "done" } }],
+		});
+
+		match serde_json::from_value(response).unwrap() {
+			PluginMessage::Response(response) => {
+				let results = Result::from(response.data).unwrap();
+				assert_eq!(results[0].message().text, "done");
+			}
+			other => panic!("response parsed as {:?}", other),
+		}
+	}
+
+	#[test]
+	fn completions_are_not_eval_results() {
+		let response = json!({
+			"jsonrpc": "2.0",
+			"id": "cargo/0/2",
+			"result": {
+				"completions": [
+					{ "label": "len", "insert_text": "len()", "detail": "usize" },
+					{ "label": "is_empty", "insert_text": "is_empty()" },
+				],
+			},
+		});
+
+		match serde_json::from_value(response).unwrap() {
+			PluginMessage::Completions(response) => {
+				let result = Result::from(response.data).unwrap();
+				assert_eq!(
+					result.completions,
+					[
+						Completion {
+							label: "len".to_owned(),
+							insert_text: "len()".to_owned(),
+							detail: Some("usize".to_owned()),
+						},
+						Completion {
+							label: "is_empty".to_owned(),
+							insert_text: "is_empty()".to_owned(),
+							detail: None,
+						},
+					]
+				);
+			}
+			other => panic!("completions parsed as {:?}", other),
+		}
+
+		let empty = json!({ "jsonrpc": "2.0", "id": 1, "result": [] });
+		assert!(matches!(
+			serde_json::from_value(empty).unwrap(),
+			PluginMessage::Response(_)
+		));
+	}
 }
@@ -10,14 +10,36 @@ fn main() -> std::io::Result<()> {
 	for line in stdin.lock().lines() {
 		let line = line?;
 
+		let call: api::RpcMethodCall<serde_json::Value> =
+			serde_json::from_str(&line).unwrap();
+
+		if call.method != "eval-string" {
+			let response = api::RpcResponse::<()> {
+				rpc: api::RpcMessage::new(Some(call.rpc.id)),
+				data: api::RpcResponseResult::Failure(
+					api::RpcError::method_not_found(&call.method),
+				),
+			};
+
+			println!("{}", serde_json::to_string(&response).unwrap());
+			continue;
+		}
+
 		let call: api::EvalStringCall = serde_json::from_str(&line).unwrap();
 
 		let text = call.params.code.into_owned();
 
+		let message = api::EvalMessage {
+			text,
+			location: None,
+			category: None,
+			content: None,
+		};
+
 		let result = match state {
-			0 => api::EvalResult::Success(api::EvalMessage { text }),
-			1 => api::EvalResult::Warning(api::EvalMessage { text }),
-			2 => api::EvalResult::Error(api::EvalMessage { text }),
+			0 => api::EvalResult::Success(message),
+			1 => api::EvalResult::Warning(message),
+			2 => api::EvalResult::Error(message),
 			_ => unreachable!(),
 		};
 
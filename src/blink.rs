@@ -0,0 +1,56 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use std::{
+	hash::{Hash, Hasher},
+	time::{Duration, Instant},
+};
+
+use futures::stream;
+use iced::Subscription;
+use iced_futures::{subscription::Recipe, BoxStream};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::message::Message;
+
+/// Redraws a caret each time it's shown or hidden, given when it last moved
+/// and how long each blink takes.
+///
+/// Carets are shown for the first half of each blink after they move, so a
+/// timer on its own schedule would redraw them late. This one ticks at every
+/// half blink after `last_move`, and starts over whenever it changes.
+pub(crate) fn subscription(
+	last_move: Instant,
+	rate: Duration,
+) -> Subscription<Message> {
+	Subscription::from_recipe(CaretBlink { last_move, rate })
+		.map(|()| Message::CursorBlinkTick)
+}
+
+struct CaretBlink {
+	last_move: Instant,
+	rate: Duration,
+}
+
+impl<H: Hasher, E> Recipe<H, E> for CaretBlink {
+	type Output = ();
+
+	fn hash(&self, state: &mut H) {
+		std::any::TypeId::of::<Self>().hash(state);
+		self.last_move.hash(state);
+		self.rate.hash(state);
+	}
+
+	fn stream(self: Box<Self>, _: BoxStream<E>) -> BoxStream<Self::Output> {
+		let half = self.rate / 2;
+		let mut interval =
+			time::interval_at((self.last_move + half).into(), half);
+		// after a stall, stay on the caret's schedule rather than catching up
+		interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+		Box::pin(stream::unfold(interval, |mut interval| async move {
+			interval.tick().await;
+			Some(((), interval))
+		}))
+	}
+}
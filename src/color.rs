@@ -1,7 +1,10 @@
 // Copyright 2020 Benjamin Scherer
 // Licensed under the Open Software License version 3.0
 
+use std::collections::HashMap;
+
 use iced::Color;
+use serde::{de, Deserialize, Deserializer, Serializer};
 
 pub(crate) trait ColorExt {
 	fn from_rgb32(rgb: u32) -> Self;
@@ -18,3 +21,102 @@ impl ColorExt for Color {
 		Self::from_rgb8(r, g, b)
 	}
 }
+
+/// Parses a color written as `#RRGGBB`, or as `#RRGGBBAA` with an alpha
+/// channel.
+pub(crate) fn parse_hex(hex: &str) -> Option<Color> {
+	let digits = hex.strip_prefix('#')?;
+	// from_str_radix also accepts a sign
+	if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None;
+	}
+	let value = u32::from_str_radix(digits, 16).ok()?;
+
+	match digits.len() {
+		6 => Some(Color::from_rgb32(value)),
+		8 => Some(Color {
+			a: (value & 0xFF) as f32 / 255.0,
+			..Color::from_rgb32(value >> 8)
+		}),
+		_ => None,
+	}
+}
+
+/// Writes a color as `#RRGGBB`, or as `#RRGGBBAA` if it isn't opaque, the
+/// reverse of [`parse_hex`].
+fn to_hex(color: Color) -> String {
+	let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+	let (r, g, b, a) = (
+		channel(color.r),
+		channel(color.g),
+		channel(color.b),
+		channel(color.a),
+	);
+
+	if a == 0xFF {
+		format!("#{:02X}{:02X}{:02X}", r, g, b)
+	} else {
+		format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+	}
+}
+
+fn invalid_hex<E: de::Error>(hex: &str) -> E {
+	E::invalid_value(de::Unexpected::Str(hex), &"a color like \"#RRGGBB\"")
+}
+
+/// (De)serializes a color as a hex string, for use with `#[serde(with)]`.
+pub(crate) mod hex {
+	use super::*;
+
+	pub(crate) fn serialize<S>(
+		color: &Color,
+		serializer: S,
+	) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.serialize_str(&to_hex(*color))
+	}
+
+	pub(crate) fn deserialize<'de, D>(
+		deserializer: D,
+	) -> Result<Color, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let hex = String::deserialize(deserializer)?;
+		parse_hex(&hex).ok_or_else(|| invalid_hex(&hex))
+	}
+}
+
+/// (De)serializes a map of names to colors written as hex strings.
+pub(crate) mod hex_map {
+	use super::*;
+
+	pub(crate) fn serialize<S>(
+		colors: &HashMap<String, Color>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.collect_map(
+			colors.iter().map(|(name, &color)| (name, to_hex(color))),
+		)
+	}
+
+	pub(crate) fn deserialize<'de, D>(
+		deserializer: D,
+	) -> Result<HashMap<String, Color>, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		HashMap::<String, String>::deserialize(deserializer)?
+			.into_iter()
+			.map(|(name, hex)| match parse_hex(&hex) {
+				Some(color) => Ok((name, color)),
+				None => Err(invalid_hex(&hex)),
+			})
+			.collect()
+	}
+}
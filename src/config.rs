@@ -1,30 +1,284 @@
 // Copyright 2022 ThatsNoMoon
 // Licensed under the Open Software License version 3.0
 
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use anyhow::{Context as _, Result};
 use iced::Color;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::color::ColorExt;
+use crate::color::{hex, hex_map, ColorExt};
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Config {
 	pub(crate) ui_colors: UiColors,
 	pub(crate) editor_colors: EditorColors,
 	pub(crate) text_settings: TextSettings,
+	pub(crate) eval_settings: EvalSettings,
+	/// Whether anything is animated, such as the spinner of running cells.
+	/// When disabled, animations are shown as a single still frame, carets
+	/// stay shown without blinking, and the app doesn't wake up on a timer
+	/// for them. Disabled at startup if the OS asks apps to reduce motion.
+	pub(crate) animations_enabled: bool,
+	/// Whether the tabs open when Evalvana exits, and the input of their
+	/// cells, are opened again when it starts.
+	pub(crate) restore_session: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Config {
+	/// Returns how long each blink of the caret takes, if carets blink at
+	/// all.
+	pub(crate) fn cursor_blink_rate(&self) -> Option<Duration> {
+		self.text_settings
+			.cursor_blink_rate
+			.filter(|rate| self.animations_enabled && !rate.is_zero())
+	}
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			ui_colors: UiColors::default(),
+			editor_colors: EditorColors::default(),
+			text_settings: TextSettings::default(),
+			eval_settings: EvalSettings::default(),
+			animations_enabled: true,
+			restore_session: true,
+		}
+	}
+}
+
+/// Returns whether the OS asks apps to reduce motion, or `false` if that
+/// can't be found out.
+///
+/// This is the animation setting of GNOME on Linux, of the accessibility
+/// settings on macOS, and of window animations on Windows.
+pub(crate) async fn os_reduces_motion() -> bool {
+	// the last word of each command's output, when motion is reduced
+	let (program, args, reduced): (_, &[_], _) = if cfg!(target_os = "macos") {
+		(
+			"defaults",
+			&["read", "com.apple.universalaccess", "reduceMotion"],
+			"1",
+		)
+	} else if cfg!(windows) {
+		(
+			"reg",
+			&[
+				"query",
+				r"HKCU\Control Panel\Desktop\WindowMetrics",
+				"/v",
+				"MinAnimate",
+			],
+			"0",
+		)
+	} else {
+		(
+			"gsettings",
+			&["get", "org.gnome.desktop.interface", "enable-animations"],
+			"false",
+		)
+	};
+
+	match tokio::process::Command::new(program)
+		.args(args)
+		.output()
+		.await
+	{
+		Ok(output) if output.status.success() => {
+			String::from_utf8_lossy(&output.stdout)
+				.split_whitespace()
+				.last() == Some(reduced)
+		}
+		_ => false,
+	}
+}
+
+/// The parts of [`Config`] that are read from `config.toml` in Evalvana's
+/// data directory. Settings left out of the file keep their defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct ConfigFile {
+	pub(crate) ui_colors: UiColors,
+	pub(crate) editor_colors: EditorColors,
+	pub(crate) text_settings: TextSettings,
+}
+
+impl ConfigFile {
+	/// Returns the path of the config file in Evalvana's data directory.
+	pub(crate) fn path(data_dir: &Path) -> PathBuf {
+		data_dir.join("config.toml")
+	}
+
+	/// Reads the config file at `path`, writing a default one there with
+	/// every setting commented out if there isn't one.
+	pub(crate) async fn load(path: &Path) -> Result<Self> {
+		let text = match tokio::fs::read_to_string(path).await {
+			Ok(text) => text,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				tokio::fs::write(path, DEFAULT_CONFIG_FILE)
+					.await
+					.with_context(|| {
+						format!("Failed to write default config to {:?}", path)
+					})?;
+				return Ok(Self::default());
+			}
+			Err(e) => {
+				return Err(e).with_context(|| {
+					format!("Failed to read config from {:?}", path)
+				})
+			}
+		};
+
+		toml::from_str(&text)
+			.with_context(|| format!("Failed to parse config at {:?}", path))
+	}
+
+	/// Writes every setting to the config file at `path`, replacing it.
+	pub(crate) async fn save(&self, path: &Path) -> Result<()> {
+		let text =
+			toml::to_string(self).context("Failed to serialize config")?;
+
+		let temp = path.with_extension("toml.tmp");
+		tokio::fs::write(&temp, text)
+			.await
+			.with_context(|| format!("Failed to write config to {:?}", temp))?;
+		tokio::fs::rename(&temp, path)
+			.await
+			.with_context(|| format!("Failed to write config to {:?}", path))
+	}
+}
+
+impl Config {
+	/// Returns the settings that can be set in the config file.
+	pub(crate) fn file(&self) -> ConfigFile {
+		ConfigFile {
+			ui_colors: self.ui_colors.clone(),
+			editor_colors: self.editor_colors.clone(),
+			text_settings: self.text_settings.clone(),
+		}
+	}
+
+	/// Replaces the settings that can be set in the config file.
+	pub(crate) fn set_file(&mut self, file: ConfigFile) {
+		self.ui_colors = file.ui_colors;
+		self.editor_colors = file.editor_colors;
+		self.text_settings = file.text_settings;
+	}
+}
+
+/// The config file written when there isn't one, matching the defaults.
+const DEFAULT_CONFIG_FILE: &str = r##"# Evalvana's configuration. Uncomment a setting to change it; settings that
+# are left out keep their defaults. Colors are written as "#RRGGBB", or as
+# "#RRGGBBAA" with an alpha channel.
+
+# [ui_colors]
+# bg = "#282C34"
+# secondary_bg = "#1D2026"
+# hovered_bg = "#2F343D"
+# focused_bg = "#333842"
+# unfocused_bg = "#1D2026"
+# secondary_unfocused_bg = "#313640"
+# text = "#C1C8D6"
+# unfocused_text = "#8C919C"
+# unfocused_icon = "#676B73"
+# bg_icon = "#43474F"
+# accent = "#61AFEF"
+# borders = "#4B5263"
+
+# [editor_colors]
+# bg = "#282C34"
+# main = "#ABB2BF"
+# selection = "#61AFEF4D"
+# cursor = "#61AFEF"
+# gutter = "#838891"
+# strings = "#98C379"
+# numbers = "#D19A66"
+# operators = "#C678DD"
+# keywords = "#E06C75"
+# variables = "#E5C07B"
+# parameters = "#E5C07B"
+# constants = "#56B6C2"
+# types = "#61AFEF"
+# functions = "#ABB2BF"
+# success = "#5DD47F"
+# warnings = "#EBCD2E"
+# errors = "#FF4545"
+# info = "#61AFEF"
+#
+# Other categories of results that plugins use can be colored by name in a
+# table named [editor_colors.categories].
+
+# [text_settings]
+# ui_font_size = 16
+# editor_font_size = 16
+# header_font_size = 20
+# line_numbers = true
+# word_wrap = false
+# How many columns a tab character in a cell takes up.
+# tab_width = 4
+# The tallest the input of a cell in a multi-cell tab grows to, in pixels,
+# before it scrolls.
+# max_cell_height = 600
+# How long each blink of the caret takes in milliseconds, or 0 to keep it
+# shown.
+# cursor_blink_rate = 1000
+"##;
+
+/// Serializes a duration as a number of milliseconds, where `None` is 0.
+fn serialize_millis<S>(
+	rate: &Option<Duration>,
+	serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	serializer.serialize_u64(rate.map_or(0, |rate| rate.as_millis() as u64))
+}
+
+/// Deserializes a duration from a number of milliseconds, where 0 means
+/// `None`.
+fn deserialize_millis<'de, D>(
+	deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let millis = u64::deserialize(deserializer)?;
+	Ok(Some(Duration::from_millis(millis)).filter(|rate| !rate.is_zero()))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub(crate) struct UiColors {
+	#[serde(with = "hex")]
 	pub(crate) bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) secondary_bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) hovered_bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) focused_bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) unfocused_bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) secondary_unfocused_bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) text: Color,
+	#[serde(with = "hex")]
 	pub(crate) unfocused_text: Color,
+	#[serde(with = "hex")]
 	pub(crate) unfocused_icon: Color,
+	#[serde(with = "hex")]
 	pub(crate) bg_icon: Color,
+	#[serde(with = "hex")]
 	pub(crate) accent: Color,
+	#[serde(with = "hex")]
 	pub(crate) borders: Color,
 }
 
@@ -47,26 +301,49 @@ impl Default for UiColors {
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub(crate) struct EditorColors {
+	#[serde(with = "hex")]
 	pub(crate) bg: Color,
+	#[serde(with = "hex")]
 	pub(crate) main: Color,
+	#[serde(with = "hex")]
 	pub(crate) selection: Color,
+	#[serde(with = "hex")]
 	pub(crate) cursor: Color,
+	#[serde(with = "hex")]
 	pub(crate) gutter: Color,
+	#[serde(with = "hex")]
 	pub(crate) strings: Color,
+	#[serde(with = "hex")]
 	pub(crate) numbers: Color,
+	#[serde(with = "hex")]
 	pub(crate) operators: Color,
+	#[serde(with = "hex")]
 	pub(crate) keywords: Color,
+	#[serde(with = "hex")]
 	pub(crate) variables: Color,
+	#[serde(with = "hex")]
 	pub(crate) parameters: Color,
+	#[serde(with = "hex")]
 	pub(crate) constants: Color,
+	#[serde(with = "hex")]
 	pub(crate) types: Color,
+	#[serde(with = "hex")]
 	pub(crate) functions: Color,
 
+	#[serde(with = "hex")]
 	pub(crate) success: Color,
+	#[serde(with = "hex")]
 	pub(crate) warnings: Color,
+	#[serde(with = "hex")]
 	pub(crate) errors: Color,
+	#[serde(with = "hex")]
+	pub(crate) info: Color,
+	/// Colors of other result categories that plugins use, by name.
+	#[serde(with = "hex_map")]
+	pub(crate) categories: HashMap<String, Color>,
 }
 
 impl Default for EditorColors {
@@ -89,15 +366,34 @@ impl Default for EditorColors {
 			success: Color::from_rgb32(0x5DD47F),
 			warnings: Color::from_rgb32(0xEBCD2E),
 			errors: Color::from_rgb32(0xFF4545),
+			info: Color::from_rgb32(0x61AFEF),
+			categories: HashMap::new(),
 		}
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub(crate) struct TextSettings {
 	pub(crate) ui_font_size: u16,
 	pub(crate) editor_font_size: u16,
 	pub(crate) header_font_size: u16,
+	/// Whether cells show line numbers beside their input.
+	pub(crate) line_numbers: bool,
+	/// Whether long lines in cells wrap instead of scrolling sideways.
+	pub(crate) word_wrap: bool,
+	/// How many columns a tab character in a cell takes up.
+	pub(crate) tab_width: u8,
+	/// The tallest the input of a cell in a multi-cell tab grows to, in
+	/// pixels, before it scrolls.
+	pub(crate) max_cell_height: u32,
+	/// How long each blink of the caret takes, or `None` to keep it shown.
+	/// Carets don't blink with animations disabled.
+	#[serde(
+		serialize_with = "serialize_millis",
+		deserialize_with = "deserialize_millis"
+	)]
+	pub(crate) cursor_blink_rate: Option<Duration>,
 }
 
 impl Default for TextSettings {
@@ -106,6 +402,165 @@ impl Default for TextSettings {
 			ui_font_size: 16,
 			editor_font_size: 16,
 			header_font_size: 20,
+			line_numbers: true,
+			word_wrap: false,
+			tab_width: 4,
+			max_cell_height: 600,
+			cursor_blink_rate: Some(Duration::from_millis(1000)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EvalSettings {
+	/// How many evaluations to keep in each cell's history.
+	pub(crate) history_len: usize,
+	/// The longest line of output, in bytes, read from a plugin. Longer lines
+	/// are skipped with a protocol error instead of being buffered without
+	/// bound. Defaults to 16 MiB.
+	pub(crate) max_message_len: usize,
+	/// How many results are shown in a cell before the rest are hidden behind
+	/// a "show all" button.
+	pub(crate) max_shown_results: usize,
+	/// How long sending a message to a plugin may take, such as when the
+	/// plugin isn't reading its input, before it's abandoned with an error.
+	pub(crate) send_timeout: Duration,
+	/// Whether running every cell evaluates them one at a time, stopping at
+	/// the first with an error, rather than sending them all at once.
+	pub(crate) stop_eval_all_at_error: bool,
+}
+
+impl Default for EvalSettings {
+	fn default() -> Self {
+		Self {
+			history_len: 10,
+			max_message_len: 16 * 1024 * 1024,
+			max_shown_results: 200,
+			send_timeout: Duration::from_secs(5),
+			stop_eval_all_at_error: false,
+		}
+	}
+}
+
+impl TextSettings {
+	const MIN_EDITOR_FONT_SIZE: u16 = 8;
+	const MAX_EDITOR_FONT_SIZE: u16 = 48;
+
+	pub(crate) fn adjust_editor_font_size(&mut self, delta: i16) {
+		let size = i32::from(self.editor_font_size) + i32::from(delta);
+		self.editor_font_size = size.clamp(
+			Self::MIN_EDITOR_FONT_SIZE.into(),
+			Self::MAX_EDITOR_FONT_SIZE.into(),
+		) as u16;
+	}
+
+	pub(crate) fn reset_editor_font_size(&mut self) {
+		self.editor_font_size = Self::default().editor_font_size;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn default_config_file_matches_defaults() {
+		let uncommented: String = DEFAULT_CONFIG_FILE
+			.lines()
+			.map(|line| match line.strip_prefix("# ") {
+				Some(setting)
+					if setting.starts_with('[') || setting.contains(" = ") =>
+				{
+					setting
+				}
+				_ => line,
+			})
+			.flat_map(|line| [line, "\n"])
+			.collect();
+
+		let mut file: ConfigFile = toml::from_str(&uncommented).unwrap();
+		let defaults = ConfigFile::default();
+
+		// The default selection's alpha isn't exactly representable in hex
+		let selection = file.editor_colors.selection;
+		assert!(
+			(selection.a - defaults.editor_colors.selection.a).abs() < 0.01
+		);
+		file.editor_colors.selection = defaults.editor_colors.selection;
+
+		assert_eq!(file, defaults);
+	}
+
+	#[test]
+	fn missing_settings_keep_defaults() {
+		let file: ConfigFile = toml::from_str(
+			r##"
+			[ui_colors]
+			accent = "#FF0000"
+
+			[editor_colors.categories]
+			note = "#00FF0080"
+
+			[text_settings]
+			editor_font_size = 20
+			cursor_blink_rate = 0
+			"##,
+		)
+		.unwrap();
+
+		assert_eq!(file.ui_colors.accent, Color::from_rgb8(0xFF, 0, 0));
+		assert_eq!(file.ui_colors.bg, UiColors::default().bg);
+		assert_eq!(
+			file.editor_colors.categories["note"],
+			Color::from_rgba8(0, 0xFF, 0, 128.0 / 255.0),
+		);
+		assert_eq!(file.text_settings.editor_font_size, 20);
+		assert_eq!(file.text_settings.ui_font_size, 16);
+		assert_eq!(file.text_settings.cursor_blink_rate, None);
+	}
+
+	#[test]
+	fn saved_config_files_round_trip() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let dir = std::env::temp_dir()
+			.join(format!("evalvana-config-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = ConfigFile::path(&dir);
+
+		let mut file = ConfigFile::default();
+		file.editor_colors.selection =
+			Color::from_rgba8(0x61, 0xAF, 0xEF, 128.0 / 255.0);
+		file.editor_colors
+			.categories
+			.insert("note".to_owned(), Color::from_rgb8(0, 0xFF, 0));
+		file.text_settings.editor_font_size = 20;
+		file.text_settings.cursor_blink_rate = None;
+
+		runtime.block_on(file.save(&path)).unwrap();
+		let loaded = runtime.block_on(ConfigFile::load(&path)).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		assert_eq!(loaded, file);
+	}
+
+	#[test]
+	fn carets_stay_still_without_animations() {
+		let mut config = Config::default();
+		assert!(config.cursor_blink_rate().is_some());
+
+		config.animations_enabled = false;
+		assert_eq!(config.cursor_blink_rate(), None);
+	}
+
+	#[test]
+	fn malformed_colors_are_errors() {
+		for text in [
+			"[ui_colors]\naccent = \"61AFEF\"",
+			"[ui_colors]\naccent = \"#61AFE\"",
+			"[ui_colors]\naccent = \"#+1AFEF\"",
+			"[ui_colors]\naccent = 6418159",
+		] {
+			assert!(toml::from_str::<ConfigFile>(text).is_err(), "{}", text);
 		}
 	}
 }
@@ -0,0 +1,117 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use std::fmt;
+
+/// A language evalvana knows about, used to pick file extensions and comment
+/// prefixes for the tabs of plugins that list it.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Language {
+	/// The name plugins use for this language in their manifest.
+	pub(crate) name: &'static str,
+	pub(crate) display_name: &'static str,
+	/// File extensions, without the leading dot, with the default first.
+	pub(crate) extensions: &'static [&'static str],
+	/// The prefix of comments running to the end of the line, if there are
+	/// any.
+	pub(crate) line_comment: Option<&'static str>,
+}
+
+const LANGUAGES: &[Language] = &[
+	Language {
+		name: "rust",
+		display_name: "Rust",
+		extensions: &["rs"],
+		line_comment: Some("//"),
+	},
+	Language {
+		name: "python",
+		display_name: "Python",
+		extensions: &["py", "pyw"],
+		line_comment: Some("#"),
+	},
+	Language {
+		name: "javascript",
+		display_name: "JavaScript",
+		extensions: &["js", "mjs", "cjs"],
+		line_comment: Some("//"),
+	},
+	Language {
+		name: "typescript",
+		display_name: "TypeScript",
+		extensions: &["ts", "mts", "cts"],
+		line_comment: Some("//"),
+	},
+	Language {
+		name: "ruby",
+		display_name: "Ruby",
+		extensions: &["rb"],
+		line_comment: Some("#"),
+	},
+	Language {
+		name: "lua",
+		display_name: "Lua",
+		extensions: &["lua"],
+		line_comment: Some("--"),
+	},
+	Language {
+		name: "haskell",
+		display_name: "Haskell",
+		extensions: &["hs"],
+		line_comment: Some("--"),
+	},
+	Language {
+		name: "shell",
+		display_name: "Shell",
+		extensions: &["sh", "bash"],
+		line_comment: Some("#"),
+	},
+];
+
+impl Language {
+	/// Returns every language evalvana knows about.
+	pub(crate) fn all() -> impl Iterator<Item = &'static Language> {
+		LANGUAGES.iter()
+	}
+
+	/// Finds a language by its name or one of its file extensions, ignoring
+	/// case.
+	pub(crate) fn find(name: &str) -> Option<&'static Language> {
+		LANGUAGES.iter().find(|language| {
+			language.name.eq_ignore_ascii_case(name)
+				|| language
+					.extensions
+					.iter()
+					.any(|extension| extension.eq_ignore_ascii_case(name))
+		})
+	}
+
+	/// Finds the first language in `names` that evalvana knows about.
+	pub(crate) fn find_first<'n>(
+		names: impl IntoIterator<Item = &'n String>,
+	) -> Option<&'static Language> {
+		names.into_iter().find_map(|name| Language::find(name))
+	}
+}
+
+impl fmt::Display for Language {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.display_name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn find_by_name_or_extension() {
+		let rust = Language::find("rust").unwrap();
+		assert_eq!(rust.display_name, "Rust");
+		assert_eq!(Language::find("RS"), Some(rust));
+		assert_eq!(Language::find("brainfuck"), None);
+
+		let names = ["unknown".to_owned(), "py".to_owned()];
+		assert_eq!(Language::find_first(&names).unwrap().name, "python");
+	}
+}
@@ -3,33 +3,53 @@
 
 #![cfg_attr(windows, windows_subsystem = "windows")]
 
-use std::{collections::HashMap, env, sync::Arc};
+use std::{
+	collections::HashMap,
+	env,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
 
 use anyhow::{anyhow, Context as _, Error};
+use evalvana_api::StringOrNumber;
 use futures::executor::block_on;
 use iced::{
+	clipboard, time,
 	window::{self, Icon},
 	Application, Color, Command, Container, Element, Length, Row, Settings,
 	Space, Subscription,
 };
+use iced_native::{subscription, window::Event as WindowEvent, Event};
 use lazy_regex::{regex_captures, regex_is_match};
 
 pub(crate) mod assets;
+pub(crate) mod blink;
 pub(crate) mod color;
 pub(crate) mod config;
+pub(crate) mod language;
 pub(crate) mod message;
 pub(crate) mod model;
 pub(crate) mod plugin;
+pub(crate) mod session;
+pub(crate) mod shortcuts;
 pub(crate) mod style;
 
 use crate::{
 	assets::ICON64,
-	config::Config,
-	message::{InitMessage, Message},
-	model::{PluginListing, Plugins, Tab, Tabs},
-	plugin::{EnvironmentOutput, Plugin},
+	config::{Config, ConfigFile},
+	language::Language,
+	message::{InitMessage, Message, TabRequest},
+	model::{
+		cell::Placement, CellIndex, PluginListing, Plugins, Tab, TabId,
+		TabIndex, Tabs,
+	},
+	plugin::{EnvironmentEvent, EnvironmentOutput, Plugin},
+	session::Session,
 };
 
+const SIDEBAR_WIDTH: u16 = 230;
+
 #[derive(Debug, Default)]
 pub(crate) struct State {
 	pub(crate) tabs: Tabs,
@@ -38,6 +58,11 @@ pub(crate) struct State {
 	pub(crate) config: Config,
 	running_envs: Vec<EnvironmentOutput>,
 	loaded: bool,
+	/// Whether the config file was read, so saving it won't replace settings
+	/// that failed to load with their defaults.
+	config_file_loaded: bool,
+	/// Whether the window was asked to close, after which the app exits.
+	exiting: bool,
 }
 
 impl Application for State {
@@ -48,15 +73,32 @@ impl Application for State {
 	type Flags = ();
 
 	fn new(_flags: ()) -> (Self, Command<Message>) {
-		let this = Self::default();
+		let mut this = Self::default();
+
+		let (width, _) = window::Settings::default().size;
+		this.tabs
+			.set_width(width.saturating_sub(u32::from(SIDEBAR_WIDTH)));
+
+		let restore_session = this.config.restore_session;
 
-		let fut = async {
-			let data_dir = dirs::data_dir()
-				.context("Failed to get data dir")?
-				.join("Evalvana");
+		let fut = async move {
+			let data_dir = data_dir()?;
 			tokio::fs::create_dir_all(&data_dir)
 				.await
 				.context("Failed to create data dir")?;
+
+			// A broken config file shouldn't keep Evalvana from starting
+			let config =
+				match ConfigFile::load(&ConfigFile::path(&data_dir)).await {
+					Ok(file) => Some(Message::Init(InitMessage::ConfigLoaded(
+						Box::new(file),
+					))),
+					Err(e) => {
+						log::error!("{:?}; using the default config", e);
+						None
+					}
+				};
+
 			let plugin_dir = data_dir.join("plugins");
 			tokio::fs::create_dir_all(&plugin_dir)
 				.await
@@ -131,6 +173,9 @@ impl Application for State {
 							)
 						})?;
 
+						plugin.icon = plugin.icon.map(|icon| dir.join(icon));
+						plugin.dir = dir.clone();
+
 						Ok(plugin)
 					}) {
 					Ok(plugin) => plugins.push(plugin),
@@ -141,25 +186,47 @@ impl Application for State {
 				}
 			}
 
-			let msg = match errors.len() {
-				0 => Message::Init(InitMessage::PluginListLoaded(plugins)),
-				_ => {
-					errors.push(Message::Init(InitMessage::PluginListLoaded(
-						plugins,
-					)));
-					Message::Batch(errors)
+			let mut messages: Vec<_> =
+				config.into_iter().chain(errors).collect();
+			messages
+				.push(Message::Init(InitMessage::PluginListLoaded(plugins)));
+
+			// Tabs are restored once their plugins are loaded
+			if restore_session {
+				match Session::load(&Session::path(&data_dir)).await {
+					Ok(Some(session)) => messages.push(Message::Init(
+						InitMessage::SessionLoaded(session),
+					)),
+					Ok(None) => {}
+					Err(e) => messages.push(Message::Error(e.into())),
 				}
+			}
+
+			let msg = match messages.len() {
+				1 => messages.pop().expect("Init messages were empty"),
+				_ => Message::Batch(messages),
 			};
 
 			Ok(msg)
 		};
 
+		let reduce_motion =
+			Command::perform(config::os_reduces_motion(), |reduce| {
+				Message::Init(InitMessage::ReduceMotion(reduce))
+			});
+
 		(
 			this,
-			Command::perform(fut, |result: Result<_, Error>| match result {
-				Ok(msg) => msg,
-				Err(e) => Message::Init(InitMessage::Error(e.into())),
-			}),
+			Command::batch([
+				Command::perform(
+					fut,
+					|result: Result<_, Error>| match result {
+						Ok(msg) => msg,
+						Err(e) => Message::Init(InitMessage::Error(e.into())),
+					},
+				),
+				reduce_motion,
+			]),
 		)
 	}
 
@@ -174,35 +241,92 @@ impl Application for State {
 	fn update(&mut self, message: Self::Message) -> Command<Message> {
 		match message {
 			Message::OpenTab(plugin_name) => {
-				let plugin = self
-					.plugin_map
-					.get_mut(&*plugin_name)
-					.expect("Tried to open tab with non-existent plugin");
+				self.open_tab(TabRequest::new(plugin_name), 0)
+			}
 
-				let (env, output) = match plugin.open() {
-					Ok(x) => x,
-					Err(e) => {
-						return Command::perform(async move { e }, Into::into)
-					}
+			Message::OpenScratch(language) => {
+				let id = self.tabs.new_id();
+				self.tabs.push(Tab::scratch(id, language));
+				Command::none()
+			}
+
+			Message::RetryOpenTab(request, attempt) => {
+				self.open_tab(request, attempt)
+			}
+
+			Message::SwitchTab(index) => {
+				self.tabs.set_active(index);
+				Command::none()
+			}
+
+			Message::DragTab(index) => {
+				self.tabs.drag(index);
+				Command::none()
+			}
+
+			Message::DropTab(to) => match self.tabs.take_dragged() {
+				Some(from) => self.update(Message::MoveTab(from, to)),
+				None => Command::none(),
+			},
+
+			Message::MoveTab(from, to) => {
+				self.tabs.move_tab(from, to);
+				Command::none()
+			}
+
+			Message::DuplicateTab(index) => {
+				let source = match self.tabs.tabs.get(index.0) {
+					Some(tab) => tab,
+					None => return Command::none(),
 				};
+				let contents = source.cells.contents();
+				let plugin_name = source.plugin_name().cloned();
+				let language = source.language();
 
-				let tab = Tab::new(env, plugin.capabilities.clone());
+				match plugin_name {
+					Some(plugin_name) => self.open_tab(
+						TabRequest {
+							plugin_name,
+							cells: contents,
+							index: Some(TabIndex(index.0 + 1)),
+						},
+						0,
+					),
+					None => {
+						let mut tab =
+							Tab::scratch(self.tabs.new_id(), language);
+						tab.cells.set_contents(&contents);
 
-				self.running_envs.push(output);
+						self.tabs.insert(TabIndex(index.0 + 1), tab);
 
-				self.tabs.push(tab);
+						Command::none()
+					}
+				}
+			}
 
+			Message::DuplicateActiveTab => match self.tabs.active_index() {
+				Some(index) => self.update(Message::DuplicateTab(index)),
+				None => Command::none(),
+			},
+
+			Message::ScrollTabs(delta) => {
+				self.tabs.scroll(delta, &self.config);
 				Command::none()
 			}
 
-			Message::SwitchTab(index) => {
-				self.tabs.set_active(index);
+			Message::WindowResized(width) => {
+				self.tabs
+					.set_width(width.saturating_sub(u32::from(SIDEBAR_WIDTH)));
 				Command::none()
 			}
 
 			Message::CloseTab(index) => {
-				let env = self.tabs.remove(index).env;
-				self.running_envs.remove(index.0);
+				let env = match self.tabs.remove(index).env {
+					Some(env) => env,
+					None => return Command::none(),
+				};
+				let env_id = block_on(env.read()).id.clone();
+				self.running_envs.retain(|output| !output.is_for(&env_id));
 
 				Command::perform(
 					async move { env.write().await.kill().await },
@@ -210,41 +334,200 @@ impl Application for State {
 				)
 			}
 
-			Message::Eval(tab_index, cell) => {
-				let tab = &mut self.tabs[tab_index];
-				let code = tab.cells[cell].contents();
-				let env = tab.env.clone();
+			Message::Eval(tab_id, cell) => self.eval(tab_id, cell, false),
+
+			Message::EvalSelection(tab_id, cell) => {
+				self.eval(tab_id, cell, true)
+			}
+
+			Message::EvalAll(tab_id) => self.eval_all(tab_id),
+
+			Message::RequestInFlight(tab, cell, seq) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.request_in_flight(cell, seq);
+				}
+
+				Command::none()
+			}
+
+			Message::Cancel(tab, cell) => {
+				let tab = match self.tabs.get_mut(tab) {
+					Some(tab) => tab,
+					None => return Command::none(),
+				};
+				let seq = tab.cells.running_seq(cell);
+
+				match (tab.env.clone(), seq) {
+					(Some(env), Some(seq)) => Command::perform(
+						async move { env.write().await.cancel(seq).await },
+						Message::from,
+					),
+					_ => Command::none(),
+				}
+			}
+
+			Message::Complete(tab_id, cell) => {
+				let tab = match self.tabs.get_mut(tab_id) {
+					Some(tab) => tab,
+					None => return Command::none(),
+				};
+				let (env, (code, offset)) =
+					match (tab.env.clone(), tab.begin_complete(cell)) {
+						(Some(env), Some(request)) => (env, request),
+						_ => return Command::none(),
+					};
 
 				Command::perform(
-					async move { env.write().await.eval_string(&code).await },
+					async move {
+						let mut env = env.write().await;
+						env.complete(&code, offset).await
+					},
 					move |res| match res {
 						Ok(seq) => {
-							Message::RequestInFlight(tab_index, cell, seq)
+							Message::CompletionRequested(tab_id, cell, seq)
 						}
 						Err(e) => Message::Error(e.into()),
 					},
 				)
 			}
 
-			Message::RequestInFlight(tab, cell, seq) => {
+			Message::CompletionRequested(tab, cell, seq) => {
 				if let Some(t) = self.tabs.get_mut(tab) {
-					t.request_in_flight(cell, seq);
+					t.completion_requested(cell, seq);
 				}
 
 				Command::none()
 			}
 
-			Message::EvalComplete(env, seq, results) => {
-				match self
-					.tabs
-					.iter_mut()
-					.find(|tab| *block_on(tab.env.read()).id == *env)
+			Message::CompletionsReady(env, seq, completions) => {
+				if let Some(t) =
+					self.tabs.iter_mut().find(|tab| tab.is_for_env(&env))
 				{
-					Some(t) => {
-						t.eval_complete(seq, results);
-					}
-					None => eprintln!(
-						"Received eval results for an \
+					t.completions_ready(seq, completions);
+				}
+
+				Command::none()
+			}
+
+			Message::PickCompletion(tab, cell, index) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].pick_completion(index);
+					t.cells.focus(cell);
+				}
+
+				Command::none()
+			}
+
+			Message::MoveCompletionSelection(tab, cell, offset) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].move_completion_selection(offset);
+				}
+
+				Command::none()
+			}
+
+			Message::DismissCompletions(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].dismiss_completions();
+				}
+
+				Command::none()
+			}
+
+			Message::EvalFailed(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].cancel_eval();
+					t.cancel_queued_evals();
+				}
+
+				Command::none()
+			}
+
+			Message::SpinnerTick => {
+				for tab in self.tabs.iter_mut() {
+					tab.cells.tick();
+				}
+
+				Command::none()
+			}
+
+			Message::AutoScrollTick => {
+				for tab in self.tabs.iter_mut() {
+					tab.cells.auto_scroll();
+				}
+
+				Command::none()
+			}
+
+			// Updating at all redraws the carets
+			Message::CursorBlinkTick => Command::none(),
+
+			Message::EvalComplete(env, seq, results) => {
+				let next =
+					match self.tabs.iter_mut().find(|tab| tab.is_for_env(&env))
+					{
+						Some(t) => {
+							t.eval_complete(
+								seq,
+								results,
+								self.config.eval_settings.history_len,
+							);
+
+							let tab = t.id();
+							t.next_queued_eval().map(|cell| (tab, cell))
+						}
+						None => {
+							log::warn!(
+								"Received eval results for an \
+							environment with no tab: {}",
+								env
+							);
+							None
+						}
+					};
+
+				match next {
+					Some((tab, cell)) => self.eval(tab, cell, false),
+					None => Command::none(),
+				}
+			}
+
+			Message::EvalChunk(env, seq, result, is_final) => {
+				let next =
+					match self.tabs.iter_mut().find(|tab| tab.is_for_env(&env))
+					{
+						Some(t) => {
+							t.eval_chunk(
+								seq,
+								result,
+								is_final,
+								self.config.eval_settings.history_len,
+							);
+
+							let tab = t.id();
+							t.next_queued_eval().map(|cell| (tab, cell))
+						}
+						None => {
+							log::warn!(
+								"Received eval results for an \
+							environment with no tab: {}",
+								env
+							);
+							None
+						}
+					};
+
+				match next {
+					Some((tab, cell)) => self.eval(tab, cell, false),
+					None => Command::none(),
+				}
+			}
+
+			Message::AppendResult(env, result) => {
+				match self.tabs.iter_mut().find(|tab| tab.is_for_env(&env)) {
+					Some(t) => t.append_result(result),
+					None => log::warn!(
+						"Received unrequested results for an \
 						environment with no tab: {}",
 						env
 					),
@@ -252,21 +535,286 @@ impl Application for State {
 				Command::none()
 			}
 
+			Message::NewCellAt(tab, CellIndex(cell), placement) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.insert_cell(match placement {
+						Placement::Above => cell,
+						Placement::Below => cell + 1,
+					});
+				}
+
+				Command::none()
+			}
+
+			Message::NewCellAtFocused(placement) => {
+				match self.tabs.active_index() {
+					Some(index) if self.tabs[index].cells.is_multiple() => {
+						let tab = &self.tabs[index];
+						let (tab, cell) = (tab.id(), tab.cells.focused_index());
+						self.update(Message::NewCellAt(tab, cell, placement))
+					}
+					_ => Command::none(),
+				}
+			}
+
+			Message::FocusCell(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.focus(cell);
+				}
+
+				Command::none()
+			}
+
+			Message::EvalFocused => match self.tabs.active_index() {
+				Some(index) => {
+					let tab = &self.tabs[index];
+					let (tab, cell) = (tab.id(), tab.cells.focused_index());
+					self.update(Message::Eval(tab, cell))
+				}
+				None => Command::none(),
+			},
+
+			Message::EvalFocusedSelection => match self.tabs.active_index() {
+				Some(index) => {
+					let tab = &self.tabs[index];
+					let (tab, cell) = (tab.id(), tab.cells.focused_index());
+					self.update(Message::EvalSelection(tab, cell))
+				}
+				None => Command::none(),
+			},
+
+			Message::CompleteFocused => match self.tabs.active_index() {
+				Some(index) => {
+					let tab = &self.tabs[index];
+					let (tab, cell) = (tab.id(), tab.cells.focused_index());
+					self.update(Message::Complete(tab, cell))
+				}
+				None => Command::none(),
+			},
+
 			Message::NewCell(tab) => {
-				self.tabs[tab].cells.new_cell();
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.new_cell();
+				}
+
+				Command::none()
+			}
+
+			Message::ClearAllResults(tab) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.clear_results();
+				}
+
+				Command::none()
+			}
+
+			Message::ClearResults(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.clear_cell_results(cell);
+				}
+
+				Command::none()
+			}
+
+			Message::InsertPreviousResult(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells.insert_previous_result(cell);
+					t.cells.focus(cell);
+				}
+
+				Command::none()
+			}
+
+			Message::CopyCellAsSnippet(tab, cell) => {
+				match self.tabs.get_mut(tab) {
+					Some(t) => {
+						clipboard::write(t.cells[cell].snippet(t.language()))
+					}
+					None => Command::none(),
+				}
+			}
+
+			Message::CopyResult(text) => {
+				self.tabs.copied_text = Some(text.clone());
+				clipboard::write(text)
+			}
+
+			Message::SaveCell(tab_id, cell) => {
+				let tab = match self.tabs.get_mut(tab_id) {
+					Some(tab) => tab,
+					None => return Command::none(),
+				};
+				let contents = tab.cells[cell].contents();
+				let dialog =
+					file_dialog(tab.cells[cell].file_path(), tab.language());
+
+				Command::perform(
+					async move {
+						let path = match dialog.save_file().await {
+							Some(file) => file.path().to_owned(),
+							None => return Ok(None),
+						};
+						tokio::fs::write(&path, &contents).await.with_context(
+							|| format!("Failed to save cell to {:?}", path),
+						)?;
+
+						Ok(Some((path, contents)))
+					},
+					move |res: Result<_, Error>| match res {
+						Ok(Some((path, contents))) => {
+							Message::CellSaved(tab_id, cell, path, contents)
+						}
+						Ok(None) => Message::Nothing,
+						Err(e) => e.into(),
+					},
+				)
+			}
+
+			Message::CellSaved(tab, cell, path, contents) => {
+				// the cell may have been removed while the file was written
+				if let Some(c) =
+					self.tabs.get_mut(tab).and_then(|t| t.cells.get_mut(cell))
+				{
+					c.saved(path, contents);
+				}
+
+				Command::none()
+			}
+
+			Message::LoadCell(tab_id, cell) => {
+				let tab = match self.tabs.get_mut(tab_id) {
+					Some(tab) => tab,
+					None => return Command::none(),
+				};
+				let dialog =
+					file_dialog(tab.cells[cell].file_path(), tab.language());
+
+				Command::perform(
+					async move {
+						let path = match dialog.pick_file().await {
+							Some(file) => file.path().to_owned(),
+							None => return Ok(None),
+						};
+						let contents = tokio::fs::read_to_string(&path)
+							.await
+							.with_context(|| {
+							format!("Failed to load cell from {:?}", path)
+						})?;
+
+						Ok(Some((path, contents)))
+					},
+					move |res: Result<_, Error>| match res {
+						Ok(Some((path, contents))) => {
+							Message::CellLoaded(tab_id, cell, path, contents)
+						}
+						Ok(None) => Message::Nothing,
+						Err(e) => e.into(),
+					},
+				)
+			}
+
+			Message::CellLoaded(tab, cell, path, contents) => {
+				// the cell may have been removed while the file was read
+				if let Some(t) = self.tabs.get_mut(tab) {
+					if let Some(c) = t.cells.get_mut(cell) {
+						c.load(path, contents);
+						t.cells.focus(cell);
+					}
+				}
+
+				Command::none()
+			}
+
+			Message::ToggleResultGroup(tab, cell, level) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].toggle_result_group(level);
+				}
+
+				Command::none()
+			}
+
+			Message::ShowAllResults(tab, cell) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].show_all_results();
+				}
+
+				Command::none()
+			}
+
+			Message::JumpToLocation(tab, cell, location) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].jump_to(&location);
+				}
+
+				Command::none()
+			}
+
+			Message::SelectHistory(tab, cell, entry) => {
+				if let Some(t) = self.tabs.get_mut(tab) {
+					t.cells[cell].select_history(entry);
+				}
+
+				Command::none()
+			}
+
+			Message::AdjustFontSize(delta) => {
+				self.config.text_settings.adjust_editor_font_size(delta);
+
+				self.save_config_file()
+			}
+
+			Message::ResetFontSize => {
+				self.config.text_settings.reset_editor_font_size();
+
+				self.save_config_file()
+			}
+
+			Message::ToggleCategory(category) => {
+				self.plugins.toggle_category(category);
+
+				Command::none()
+			}
+
+			Message::OpenPluginFolder(plugin_name) => {
+				let dir = match self.plugin_map.get(&plugin_name) {
+					Some(plugin) => plugin.dir.clone(),
+					None => return Command::none(),
+				};
+
+				Command::perform(
+					async move {
+						opener::open(&dir).with_context(|| {
+							format!("Failed to open plugin folder {:?}", dir)
+						})
+					},
+					Into::into,
+				)
+			}
+
+			Message::FilterPlugins(query) => {
+				self.plugins.filter = query;
 
 				Command::none()
 			}
 
 			Message::Init(m) => match m {
+				InitMessage::ConfigLoaded(file) => {
+					self.config.set_file(*file);
+					self.config_file_loaded = true;
+					Command::none()
+				}
+
+				InitMessage::ReduceMotion(reduce) => {
+					if reduce {
+						self.config.animations_enabled = false;
+					}
+					Command::none()
+				}
+
 				InitMessage::PluginListLoaded(plugins) => {
-					self.plugins.list = plugins
-						.iter()
-						.map(|plugin| PluginListing::new(plugin.name.clone()))
-						.collect();
-					self.plugins
-						.list
-						.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+					self.plugins.list =
+						plugins.iter().map(PluginListing::new).collect();
+					self.plugins.sort();
 					self.plugin_map = plugins
 						.into_iter()
 						.map(|plugin| (plugin.name.clone(), plugin))
@@ -275,14 +823,84 @@ impl Application for State {
 					Command::none()
 				}
 
+				InitMessage::SessionLoaded(session) => {
+					let mut commands = vec![];
+					let mut restored = self.tabs.tabs.len();
+
+					for saved in session.tabs {
+						// plugin tabs are inserted where they were saved, even
+						// if they're opened after later tabs by a retry
+						let index = Some(TabIndex(restored));
+
+						let mut tab = match saved.plugin {
+							Some(plugin_name)
+								if self
+									.plugin_map
+									.contains_key(plugin_name.as_str()) =>
+							{
+								commands.push(self.open_tab(
+									TabRequest {
+										plugin_name: plugin_name.into(),
+										cells: saved.cells,
+										index,
+									},
+									0,
+								));
+								restored += 1;
+								continue;
+							}
+							Some(plugin_name) => {
+								log::warn!(
+									"Skipped restoring a tab of plugin {}, \
+									which isn't installed",
+									plugin_name
+								);
+								continue;
+							}
+							None => Tab::scratch(
+								self.tabs.new_id(),
+								saved
+									.language
+									.as_deref()
+									.and_then(Language::find),
+							),
+						};
+						tab.cells.set_contents(&saved.cells);
+
+						self.tabs.push(tab);
+						restored += 1;
+					}
+
+					Command::batch(commands)
+				}
+
 				InitMessage::Error(e) => {
-					eprintln!("Error: {:?}", e);
+					log::error!("{:?}", e);
 					Command::none()
 				}
 			},
 
 			Message::Error(e) => {
-				eprintln!("Error: {:?}", e);
+				log::error!("{:?}", e);
+				Command::none()
+			}
+
+			Message::ProtocolError(env_id, e) => {
+				log::error!("Protocol error from {}: {:?}", env_id, e);
+				Command::none()
+			}
+
+			Message::PluginExited(env) => {
+				self.running_envs.retain(|output| !output.is_for(&env));
+
+				// The tab may already have been closed, which kills its
+				// environment
+				if let Some(t) =
+					self.tabs.iter_mut().find(|tab| tab.is_for_env(&env))
+				{
+					t.mark_exited();
+				}
+
 				Command::none()
 			}
 
@@ -290,35 +908,139 @@ impl Application for State {
 				Command::batch(msgs.into_iter().map(|msg| self.update(msg)))
 			}
 
+			Message::Exit => {
+				if self.config.restore_session {
+					if let Err(e) = data_dir().and_then(|data_dir| {
+						Session::from_tabs(&self.tabs.tabs)
+							.save(&Session::path(&data_dir))
+					}) {
+						log::error!("{:?}; the session wasn't saved", e);
+					}
+				}
+
+				self.exiting = true;
+				Command::none()
+			}
+
 			Message::Nothing => Command::none(),
 		}
 	}
 
+	fn should_exit(&self) -> bool {
+		self.exiting
+	}
+
 	fn subscription(&self) -> Subscription<Self::Message> {
-		Subscription::batch(
+		let envs = Subscription::batch(
 			self.running_envs
 				.iter()
 				.map(|env| Subscription::from_recipe(env.take())),
 		)
-		.map(|result| {
-			let response = result?;
-			let results = Result::from(response.data)?;
-			let resp_id = response
-				.rpc
-				.id
-				.context("Eval RPC response contained no ID")?;
-			let (_, env_id, seq) =
-				regex_captures!(r"^([^/]+/[^/]+)/([^/]+)$", &resp_id)
-					.with_context(|| {
-						format!("Invalid RPC response ID: {}", resp_id)
+		.map(|(env_id, event)| {
+			let result = match event {
+				EnvironmentEvent::Response(result) => result,
+				EnvironmentEvent::Chunk(chunk) => {
+					let (env_id, seq) = parse_response_id(chunk.id)?;
+					return Ok(Message::EvalChunk(
+						env_id,
+						seq,
+						chunk.result,
+						chunk.is_final,
+					));
+				}
+				EnvironmentEvent::Completions(response) => {
+					let result = Result::from(response.data)?;
+					let id = response.rpc.id.ok_or_else(|| {
+						anyhow!("Received completions without an ID")
 					})?;
-			let seq = seq.parse().with_context(|| {
-				format!("Invalid RPC response seq: {}", seq)
-			})?;
+					let (env_id, seq) = parse_response_id(id)?;
+					return Ok(Message::CompletionsReady(
+						env_id,
+						seq,
+						result.completions,
+					));
+				}
+				EnvironmentEvent::Exited => {
+					return Ok(Message::PluginExited(env_id.to_string()))
+				}
+			};
+			let response = match result {
+				Ok(response) => response,
+				Err(e) => {
+					return Ok(Message::ProtocolError(
+						env_id.to_string(),
+						e.into(),
+					))
+				}
+			};
+			let results = Result::from(response.data)?;
+			let resp_id = match response.rpc.id {
+				Some(id) => id,
+				// Results without an ID weren't requested, such as logs
+				None => {
+					return Ok(Message::Batch(
+						results
+							.into_iter()
+							.map(|result| {
+								Message::AppendResult(
+									env_id.to_string(),
+									result,
+								)
+							})
+							.collect(),
+					))
+				}
+			};
+			let (env_id, seq) = parse_response_id(resp_id)?;
 
-			Ok(Message::EvalComplete(env_id.to_owned(), seq, results))
+			Ok(Message::EvalComplete(env_id, seq, results))
 		})
-		.map(|result| result.into())
+		.map(|result| result.into());
+
+		let window_events = subscription::events_with(|event, _| match event {
+			Event::Window(WindowEvent::Resized { width, .. }) => {
+				Some(Message::WindowResized(width))
+			}
+			Event::Window(WindowEvent::CloseRequested) => Some(Message::Exit),
+			_ => None,
+		});
+
+		// Only animate while there's something to animate, at about 15fps
+		let spinner = if self.config.animations_enabled
+			&& self.tabs.is_evaluating()
+		{
+			time::every(Duration::from_millis(66)).map(|_| Message::SpinnerTick)
+		} else {
+			Subscription::none()
+		};
+
+		// Inputs only see mouse events, which stop while the pointer is held
+		// still past the edge, so drive their scrolling from here
+		let auto_scroll = if self.tabs.is_auto_scrolling() {
+			time::every(Duration::from_millis(16))
+				.map(|_| Message::AutoScrollTick)
+		} else {
+			Subscription::none()
+		};
+
+		let cursor_blink = match (
+			self.config.cursor_blink_rate(),
+			self.tabs.last_cursor_move(),
+		) {
+			(Some(rate), Some(last_move)) if !rate.is_zero() => {
+				blink::subscription(last_move, rate)
+			}
+			_ => Subscription::none(),
+		};
+
+		Subscription::batch([
+			envs,
+			shortcuts::subscription(),
+			window_events,
+			spinner,
+			auto_scroll,
+			cursor_blink,
+		])
 	}
 
 	fn view(&mut self) -> Element<'_, Self::Message> {
@@ -329,7 +1051,7 @@ impl Application for State {
 		let sidebar = self.plugins.view(&self.config);
 		let sidebar = Container::new(sidebar)
 			.style(style::container::secondary_bg(&self.config))
-			.width(Length::Units(230))
+			.width(Length::Units(SIDEBAR_WIDTH))
 			.height(Length::Fill)
 			.padding([15, 0])
 			.into();
@@ -340,6 +1062,216 @@ impl Application for State {
 	}
 }
 
+impl State {
+	/// Opens a new tab of a plugin, retrying with a delay if the plugin's
+	/// program fails to start for a reason that might pass.
+	fn open_tab(
+		&mut self,
+		request: TabRequest,
+		attempt: u32,
+	) -> Command<Message> {
+		let plugin = self
+			.plugin_map
+			.get_mut(&*request.plugin_name)
+			.expect("Tried to open tab with non-existent plugin");
+
+		let (env, output) = match plugin.open(&self.config.eval_settings) {
+			Ok(x) => x,
+			Err(e) => {
+				return match plugin.retry_delay(attempt, &e) {
+					Some(delay) => Command::perform(
+						async move {
+							tokio::time::sleep(delay).await;
+							request
+						},
+						move |request| {
+							Message::RetryOpenTab(request, attempt + 1)
+						},
+					),
+					None => Command::perform(async move { e }, Into::into),
+				}
+			}
+		};
+
+		let mut tab =
+			Tab::new(self.tabs.new_id(), env, plugin.capabilities.clone());
+		tab.cells.set_contents(&request.cells);
+
+		self.running_envs.push(output);
+
+		match request.index {
+			// other tabs may have closed while this one was retried
+			Some(index) if index.0 < self.tabs.len() => {
+				self.tabs.insert(index, tab)
+			}
+			_ => self.tabs.push(tab),
+		}
+
+		Command::none()
+	}
+
+	/// Evaluates every cell of a tab in order, either all at once, or one at
+	/// a time if evaluation should stop at the first error.
+	fn eval_all(&mut self, tab_id: TabId) -> Command<Message> {
+		let tab = match self.tabs.get_mut(tab_id) {
+			Some(tab) => tab,
+			None => return Command::none(),
+		};
+		let env = match tab.env.clone() {
+			Some(env) => env,
+			None => return Command::none(),
+		};
+
+		if self.config.eval_settings.stop_eval_all_at_error {
+			return match tab.queue_eval_all() {
+				Some(cell) => self.eval(tab_id, cell, false),
+				None => Command::none(),
+			};
+		}
+
+		let cells = tab.begin_eval_all();
+
+		// sent from one future so they're sent in order
+		Command::perform(
+			async move {
+				let mut env = env.write().await;
+				let mut messages = vec![];
+				let mut sent = vec![];
+
+				// buffered, then flushed together
+				for (cell, code) in cells {
+					match env.queue_eval_string(&code).await {
+						Ok(seq) => sent.push((cell, seq)),
+						Err(e) => messages.extend([
+							Message::EvalFailed(tab_id, cell),
+							Message::Error(e.into()),
+						]),
+					}
+				}
+
+				match env.flush().await {
+					Ok(()) => {
+						messages.extend(sent.into_iter().map(|(cell, seq)| {
+							Message::RequestInFlight(tab_id, cell, seq)
+						}))
+					}
+					Err(e) => {
+						messages.extend(sent.into_iter().map(|(cell, _)| {
+							Message::EvalFailed(tab_id, cell)
+						}));
+						messages.push(Message::Error(e.into()));
+					}
+				}
+
+				Message::Batch(messages)
+			},
+			|message| message,
+		)
+	}
+
+	/// Sends the code of a cell to its environment, or only the selected
+	/// code if `selection_only` is set and there's a selection.
+	fn eval(
+		&mut self,
+		tab_id: TabId,
+		cell: CellIndex,
+		selection_only: bool,
+	) -> Command<Message> {
+		let tab = match self.tabs.get_mut(tab_id) {
+			Some(tab) => tab,
+			None => return Command::none(),
+		};
+		// Scratch tabs have nothing to evaluate their code
+		let env = match tab.env.clone() {
+			Some(env) => env,
+			None => return Command::none(),
+		};
+		let code = if selection_only {
+			tab.begin_eval_selection(cell)
+		} else {
+			tab.begin_eval(cell)
+		};
+
+		Command::perform(
+			async move { env.write().await.eval_string(&code).await },
+			move |res| match res {
+				Ok(seq) => Message::RequestInFlight(tab_id, cell, seq),
+				Err(e) => Message::Batch(vec![
+					Message::EvalFailed(tab_id, cell),
+					Message::Error(e.into()),
+				]),
+			},
+		)
+	}
+
+	/// Writes the settings that can be set in the config file back to it,
+	/// such as after the editor font size changes.
+	fn save_config_file(&self) -> Command<Message> {
+		if !self.config_file_loaded {
+			return Command::none();
+		}
+
+		let file = self.config.file();
+		Command::perform(
+			async move { file.save(&ConfigFile::path(&data_dir()?)).await },
+			Into::into,
+		)
+	}
+}
+
+/// Creates a dialog for picking the file of a cell, starting from the file
+/// it was last saved to or loaded from, and showing files of `language`.
+fn file_dialog(
+	path: Option<&Path>,
+	language: Option<&Language>,
+) -> rfd::AsyncFileDialog {
+	let mut dialog = rfd::AsyncFileDialog::new();
+
+	if let Some(language) = language {
+		dialog = dialog.add_filter(language.display_name, language.extensions);
+	}
+
+	if let Some(path) = path {
+		if let Some(dir) = path.parent() {
+			dialog = dialog.set_directory(dir);
+		}
+		if let Some(name) = path.file_name() {
+			dialog = dialog.set_file_name(&name.to_string_lossy());
+		}
+	}
+
+	dialog
+}
+
+/// Returns the directory Evalvana keeps its plugins and session in.
+fn data_dir() -> Result<PathBuf, Error> {
+	Ok(dirs::data_dir()
+		.context("Failed to get data dir")?
+		.join("Evalvana"))
+}
+
+/// Splits the ID of a response into the ID of the environment it's for and
+/// the `seq` of the request it answers. Numeric IDs of calls that were sent
+/// were already replaced with their string IDs by the environment's output.
+fn parse_response_id(id: StringOrNumber<'_>) -> Result<(String, u32), Error> {
+	match id {
+		StringOrNumber::String(id) => {
+			let (_, env_id, seq) =
+				regex_captures!(r"^([^/]+/[^/]+)/([^/]+)$", &id).with_context(
+					|| format!("Invalid RPC response ID: {}", id),
+				)?;
+			let seq = seq.parse().with_context(|| {
+				format!("Invalid RPC response seq: {}", seq)
+			})?;
+
+			Ok((env_id.to_owned(), seq))
+		}
+		StringOrNumber::Number(number) => {
+			Err(anyhow!("Unknown numeric RPC response ID: {}", number))
+		}
+	}
+}
+
 fn main() {
 	env_logger::init();
 
@@ -362,8 +1294,70 @@ fn main() {
 			min_size: Some((630, 400)),
 			..window::Settings::default()
 		},
+		// the session is saved before exiting
+		exit_on_close_request: false,
 		..Settings::default()
 	};
 
 	State::run(settings).expect("Failed to run app");
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exited_plugins_stop_being_read() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		// exits right away
+		let plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": env!("CARGO"),
+			"args": ["--version"],
+			"capabilities": {},
+		}))
+		.unwrap();
+
+		let mut state = State::default();
+		state.plugin_map.insert(plugin.name.clone(), plugin);
+		let _ = state.open_tab(TabRequest::new("test".into()), 0);
+		assert_eq!(state.running_envs.len(), 1);
+
+		let env = state.tabs[TabIndex(0)].env.clone().unwrap();
+		let env_id = block_on(env.read()).id.to_string();
+
+		let _ = state.update(Message::PluginExited(env_id));
+		assert!(state.running_envs.is_empty());
+		assert!(state.tabs[TabIndex(0)].has_exited());
+	}
+
+	#[test]
+	fn duplicated_plugin_tabs_follow_their_source() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": env!("CARGO"),
+			"args": ["--version"],
+			"capabilities": {},
+		}))
+		.unwrap();
+
+		let mut state = State::default();
+		state.plugin_map.insert(plugin.name.clone(), plugin);
+		let _ = state.update(Message::OpenTab("test".into()));
+		let _ = state.update(Message::OpenTab("test".into()));
+
+		let cells = vec!["1 + 1".to_owned(), "2 + 2".to_owned()];
+		state.tabs[TabIndex(0)].cells.set_contents(&cells);
+
+		let _ = state.update(Message::DuplicateTab(TabIndex(0)));
+		assert_eq!(state.tabs.tabs.len(), 3);
+		assert_eq!(state.running_envs.len(), 3);
+		assert_eq!(state.tabs.active_index(), Some(TabIndex(1)));
+		assert_eq!(state.tabs[TabIndex(1)].cells.contents(), cells);
+	}
+}
@@ -1,34 +1,149 @@
 // Copyright 2022 ThatsNoMoon
 // Licensed under the Open Software License version 3.0
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use anyhow::Error;
-use evalvana_api::EvalResult;
+use evalvana_api::{Completion, EvalResult, Level, Location};
 
 use crate::{
-	model::{CellIndex, TabIndex},
+	config::ConfigFile,
+	language::Language,
+	model::{cell::Placement, CellIndex, TabId, TabIndex},
 	plugin::Plugin,
+	session::Session,
 };
 
+/// A tab of a plugin to open, which is retried if the plugin fails to start.
+#[derive(Debug, Clone)]
+pub(crate) struct TabRequest {
+	pub(crate) plugin_name: Arc<str>,
+	/// The contents of the tab's cells, such as those of a duplicated tab.
+	pub(crate) cells: Vec<String>,
+	/// Where to insert the tab, or `None` to add it after the other tabs.
+	pub(crate) index: Option<TabIndex>,
+}
+
+impl TabRequest {
+	/// Requests an empty tab after the other tabs.
+	pub(crate) fn new(plugin_name: Arc<str>) -> Self {
+		Self {
+			plugin_name,
+			cells: vec![],
+			index: None,
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum Message {
 	Init(InitMessage),
 	OpenTab(Arc<str>),
+	/// Opens a scratch tab, which isn't tied to a plugin, of the given
+	/// language.
+	OpenScratch(Option<&'static Language>),
+	/// Tries opening a tab of a plugin again, after the given number of failed
+	/// attempts.
+	RetryOpenTab(TabRequest, u32),
 	SwitchTab(TabIndex),
 	CloseTab(TabIndex),
+	/// The mouse was pressed on the handle of a tab, which may be dragged.
+	DragTab(TabIndex),
+	/// The mouse was released on the handle of a tab, which the dragged tab
+	/// is moved to.
+	DropTab(TabIndex),
+	MoveTab(TabIndex, TabIndex),
+	DuplicateTab(TabIndex),
+	DuplicateActiveTab,
+	ScrollTabs(isize),
+	WindowResized(u32),
 	Error(Arc<Error>),
+	/// Output from the environment with the given ID that didn't follow the
+	/// plugin protocol.
+	ProtocolError(String, Arc<Error>),
+	/// The process of the environment with the given ID exited.
+	PluginExited(String),
 	Batch(Vec<Message>),
-	Eval(TabIndex, CellIndex),
-	RequestInFlight(TabIndex, CellIndex, u32),
+	Eval(TabId, CellIndex),
+	/// Evaluates every cell of a tab, from top to bottom.
+	EvalAll(TabId),
+	EvalSelection(TabId, CellIndex),
+	RequestInFlight(TabId, CellIndex, u32),
+	EvalFailed(TabId, CellIndex),
+	/// Asks the environment of a tab to stop evaluating the code of a cell.
+	Cancel(TabId, CellIndex),
 	EvalComplete(String, u32, Vec<EvalResult>),
-	NewCell(TabIndex),
+	/// A result of the evaluation with the given environment and `seq` that's
+	/// still running, and whether it's the last one.
+	EvalChunk(String, u32, EvalResult, bool),
+	AppendResult(String, EvalResult),
+	/// Asks the environment of a tab how the code at the caret of a cell
+	/// could be completed.
+	Complete(TabId, CellIndex),
+	CompletionRequested(TabId, CellIndex, u32),
+	/// Completions answering the complete call with the given environment
+	/// and `seq`.
+	CompletionsReady(String, u32, Vec<Completion>),
+	/// Inserts the completion at the given index in the list shown in a cell.
+	PickCompletion(TabId, CellIndex, usize),
+	/// Moves the selection of the completions shown in a cell by the given
+	/// number of entries.
+	MoveCompletionSelection(TabId, CellIndex, isize),
+	DismissCompletions(TabId, CellIndex),
+	NewCell(TabId),
+	ClearAllResults(TabId),
+	ClearResults(TabId, CellIndex),
+	InsertPreviousResult(TabId, CellIndex),
+	/// Copies the code and shown results of a cell to the clipboard as
+	/// Markdown.
+	CopyCellAsSnippet(TabId, CellIndex),
+	/// Copies the text of a result to the clipboard.
+	CopyResult(String),
+	/// Asks where to save the input of a cell, and saves it there.
+	SaveCell(TabId, CellIndex),
+	/// The input of a cell was saved to the given file.
+	CellSaved(TabId, CellIndex, PathBuf, String),
+	/// Asks for a file to load into a cell, replacing its input.
+	LoadCell(TabId, CellIndex),
+	CellLoaded(TabId, CellIndex, PathBuf, String),
+	NewCellAt(TabId, CellIndex, Placement),
+	/// Inserts a cell next to the focused cell, bound to Ctrl+Alt+Enter for
+	/// below and Ctrl+Alt+Shift+Enter for above.
+	NewCellAtFocused(Placement),
+	FocusCell(TabId, CellIndex),
+	EvalFocused,
+	EvalFocusedSelection,
+	CompleteFocused,
+	SpinnerTick,
+	/// Scrolls selections being dragged past the edge of an input.
+	AutoScrollTick,
+	/// Redraws focused inputs so their carets blink.
+	CursorBlinkTick,
+	ToggleResultGroup(TabId, CellIndex, Level),
+	ShowAllResults(TabId, CellIndex),
+	JumpToLocation(TabId, CellIndex, Location),
+	SelectHistory(TabId, CellIndex, usize),
+	AdjustFontSize(i16),
+	ResetFontSize,
+	ToggleCategory(String),
+	FilterPlugins(String),
+	/// Opens the directory of the plugin with the given name in the file
+	/// manager.
+	OpenPluginFolder(Arc<str>),
+	/// Saves the session and exits, once the window is asked to close.
+	Exit,
 	Nothing,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) enum InitMessage {
+	/// The settings read from the config file.
+	ConfigLoaded(Box<ConfigFile>),
 	PluginListLoaded(Vec<Plugin>),
+	/// Whether the OS asks apps to reduce motion, which disables animations.
+	ReduceMotion(bool),
+	/// The tabs open when Evalvana last exited, to open again.
+	SessionLoaded(Session),
 	Error(Arc<Error>),
 }
 
@@ -2,33 +2,94 @@
 // Licensed under the Open Software License version 3.0
 
 use std::{
-	collections::HashMap,
+	collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+	hash::{Hash, Hasher},
+	iter,
 	ops::{Index, IndexMut},
+	path::{Path, PathBuf},
+	slice,
+	time::Instant,
 };
 
-use evalvana_api::EvalResult;
-use evalvana_editor::{self as editor, TextInput};
+use evalvana_api::{
+	Completion, Content, EvalMessage, EvalResult, Level, Location,
+};
+use evalvana_editor::{self as editor, MarkKind, TextInput};
 use iced::{
-	button, scrollable, Button, Column, Container, Element, Length, Row, Rule,
-	Scrollable, Space, Text,
+	button, image, scrollable,
+	tooltip::{Position, Tooltip},
+	Alignment, Button, Color, Column, Container, Element, Image, Length, Row,
+	Rule, Scrollable, Space, Text,
 };
+use iced_native::keyboard::KeyCode;
 
-use super::{CellIndex, TabIndex};
+use super::{popup::Popup, CellIndex, TabId};
 use crate::{
 	assets::{
 		font,
 		icons::{self, NEW_CELL},
 	},
 	config::Config,
+	language::Language,
 	message::Message,
 	style::{self, text_input::TextInputStyleSheet},
 };
 
+/// The frames of the indicator shown while a cell is being evaluated.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
 #[derive(Debug)]
 pub(crate) struct Cell {
 	input_state: editor::State,
 	eval_button_state: button::State,
-	pub(crate) results: Vec<EvalResult>,
+	cancel_button_state: button::State,
+	clear_button_state: button::State,
+	history: VecDeque<HistoryEntry>,
+	selected_history: usize,
+	/// The input of the latest evaluation, until its eval call is sent.
+	unsent_input: Option<String>,
+	/// The inputs of evaluations whose results haven't arrived, by the `seq`
+	/// of their eval calls.
+	sent_inputs: HashMap<u32, String>,
+	/// Whether results of the evaluation in progress are arriving one at a
+	/// time, the first of which added its history entry.
+	is_streaming: bool,
+	/// The `seq` of the eval call of the latest evaluation, once it's sent.
+	request_seq: Option<u32>,
+	spinner_frame: usize,
+	history_prev_button_state: button::State,
+	history_next_button_state: button::State,
+	collapsed_levels: HashSet<Level>,
+	result_group_button_states: Vec<button::State>,
+	/// The states of the buttons of each shown result: one jumping to its
+	/// location, and one copying it.
+	result_button_states: Vec<[button::State; 2]>,
+	insert_above_button_state: button::State,
+	insert_below_button_state: button::State,
+	insert_previous_button_state: button::State,
+	/// Where errors in the shown results are, marked beside the input.
+	marks: Vec<(usize, MarkKind)>,
+	/// Whether all results are shown, rather than only the first
+	/// `max_shown_results`.
+	show_all_results: bool,
+	show_all_button_state: button::State,
+	copy_snippet_button_state: button::State,
+	/// The file this cell's input was last saved to or loaded from, and the
+	/// input at the time.
+	file: Option<(PathBuf, String)>,
+	save_button_state: button::State,
+	load_button_state: button::State,
+	/// Decoded images of results, by a hash of their encoded data, or `None`
+	/// for those that couldn't be decoded.
+	images: HashMap<u64, Option<image::Handle>>,
+	/// Where the caret was, and how long the input was, when completions were
+	/// last requested. The completions are only shown while both stay the
+	/// same.
+	completion_at: Option<(usize, usize)>,
+	completions: Vec<Completion>,
+	/// The index of the completion picked by pressing Enter.
+	selected_completion: usize,
+	completion_button_states: Vec<button::State>,
 }
 
 impl Default for Cell {
@@ -36,62 +97,181 @@ impl Default for Cell {
 		Self {
 			input_state: editor::State::focused(),
 			eval_button_state: button::State::new(),
-			results: vec![],
+			cancel_button_state: button::State::new(),
+			clear_button_state: button::State::new(),
+			history: VecDeque::new(),
+			selected_history: 0,
+			unsent_input: None,
+			sent_inputs: HashMap::new(),
+			is_streaming: false,
+			request_seq: None,
+			spinner_frame: 0,
+			history_prev_button_state: button::State::new(),
+			history_next_button_state: button::State::new(),
+			collapsed_levels: HashSet::from([Level::Warning]),
+			result_group_button_states: vec![],
+			result_button_states: vec![],
+			insert_above_button_state: button::State::new(),
+			insert_below_button_state: button::State::new(),
+			insert_previous_button_state: button::State::new(),
+			marks: vec![],
+			show_all_results: false,
+			show_all_button_state: button::State::new(),
+			copy_snippet_button_state: button::State::new(),
+			file: None,
+			save_button_state: button::State::new(),
+			load_button_state: button::State::new(),
+			images: HashMap::new(),
+			completion_at: None,
+			completions: vec![],
+			selected_completion: 0,
+			completion_button_states: vec![],
 		}
 	}
 }
 
 impl Cell {
+	#[allow(clippy::too_many_arguments)]
 	pub(super) fn view<'s>(
 		&'s mut self,
 		config: &Config,
-		tab_index: TabIndex,
+		tab_id: TabId,
 		index: CellIndex,
+		is_multiple: bool,
+		can_eval: bool,
+		line_comment: Option<&str>,
+		copied_text: Option<&str>,
 	) -> Element<'s, Message> {
+		let tab_width = config.text_settings.tab_width;
+		let (line, column) = self.input_state.caret_line_column(tab_width);
+		let caret_position =
+			Text::new(format!("Ln {}, Col {}", line + 1, column + 1))
+				.color(config.ui_colors.unfocused_text)
+				.size(config.text_settings.ui_font_size)
+				.font(font::MONO);
+
+		self.marks.clear();
+		if let Some(entry) = self.history.get(self.selected_history) {
+			error_marks(&self.input_state, &entry.results, &mut self.marks);
+		}
+
+		if self.completion_at
+			!= Some((
+				self.input_state.caret_offset(),
+				self.input_state.len_bytes(),
+			)) {
+			self.dismiss_completions();
+		}
+		let caret_point = self.input_state.caret_point();
+		let show_completions =
+			self.has_completions() && self.input_state.is_focused();
+
+		// asked before the fields of this cell are borrowed by its view
+		let is_running = self.is_running();
+		let running_seq = self.running_seq();
+		let has_unsaved_changes = self.has_unsaved_changes();
+
 		let input = TextInput::new(&mut self.input_state, "", move |_| {
 			Message::Nothing
 		})
 		.size(config.text_settings.editor_font_size)
+		// the editor of a single-cell tab fills it, with no need for room
+		// below its contents
+		.phantom_line(is_multiple)
+		.tab_width(tab_width)
+		.line_numbers(config.text_settings.line_numbers)
+		.word_wrap(config.text_settings.word_wrap)
+		.line_comment(line_comment.map(str::to_owned))
+		.cursor_blink_rate(config.cursor_blink_rate())
 		.style(Box::new(style::text_input::Editor::from(config))
 			as Box<dyn TextInputStyleSheet + 'static>)
-		.font(font::MONO);
+		.font(font::MONO)
+		.marks(&self.marks)
+		.on_focus(Message::FocusCell(tab_id, index));
+
+		let (input, height) = if is_multiple {
+			(
+				input
+					.height(Length::Shrink)
+					.max_height(config.text_settings.max_cell_height),
+				Length::Shrink,
+			)
+		} else {
+			(input, Length::Fill)
+		};
 
 		let input = Container::new(input)
 			.style(style::container::ui_bg(config))
 			.width(Length::Fill)
-			.height(Length::Fill);
+			.height(height);
+
+		let input = if show_completions {
+			let selected = self.selected_completion;
+			let list = self
+				.completions
+				.iter()
+				.zip(&mut self.completion_button_states)
+				.enumerate()
+				.map(|(i, (completion, state))| {
+					completion_view(config, completion, state, i == selected)
+						.on_press(Message::PickCompletion(tab_id, index, i))
+						.into()
+				})
+				.collect();
+
+			let list = Container::new(Column::with_children(list))
+				.style(style::container::secondary_bg(config))
+				.padding(5);
+
+			Popup::new(input)
+				.popup(list, caret_point)
+				.on_key(
+					KeyCode::Up,
+					Message::MoveCompletionSelection(tab_id, index, -1),
+				)
+				.on_key(
+					KeyCode::Down,
+					Message::MoveCompletionSelection(tab_id, index, 1),
+				)
+				.on_key(
+					KeyCode::Enter,
+					Message::PickCompletion(tab_id, index, selected),
+				)
+				.on_key(
+					KeyCode::Escape,
+					Message::DismissCompletions(tab_id, index),
+				)
+		} else {
+			Popup::new(input)
+		};
 
 		let divider =
 			Rule::horizontal(21).style(style::rule::cell_divider(config, 1));
 
-		let results = self
-			.results
-			.iter()
-			.map(|result| {
-				let (color, msg) = match result {
-					EvalResult::Success(msg) => {
-						(config.editor_colors.success, &*msg.text)
-					}
-					EvalResult::Warning(msg) => {
-						(config.editor_colors.warnings, &*msg.text)
-					}
-					EvalResult::Error(msg) => {
-						(config.editor_colors.errors, &*msg.text)
-					}
-				};
-
-				Text::new(msg)
-					.size(config.text_settings.editor_font_size)
-					.color(color)
-					.font(font::MONO)
-					.into()
-			})
-			.collect();
+		let entry = self.history.get(self.selected_history);
+		let results = results_view(
+			config,
+			entry.map_or(&[][..], |e| &e.results[..]),
+			self.show_all_results,
+			&self.collapsed_levels,
+			&mut self.result_group_button_states,
+			&mut self.result_button_states,
+			&mut self.show_all_button_state,
+			&mut self.images,
+			copied_text,
+			tab_id,
+			index,
+		);
 
 		let results = Column::with_children(results).spacing(10);
 
-		let eval_button = {
-			let text = Text::new("Eval")
+		let eval_button: Element<_> = if can_eval {
+			let label = if is_running {
+				format!("Running {}", SPINNER[self.spinner_frame])
+			} else {
+				"Eval".to_owned()
+			};
+			let text = Text::new(label)
 				.color(config.ui_colors.text)
 				.size(config.text_settings.ui_font_size);
 
@@ -104,152 +284,2109 @@ impl Cell {
 
 			Button::new(&mut self.eval_button_state, contents)
 				.style(style::button::primary(config))
-				.on_press(Message::Eval(tab_index, index))
+				.on_press(Message::Eval(tab_id, index))
+				.into()
+		} else {
+			Space::with_width(Length::Shrink).into()
+		};
+
+		let cancel_button: Element<_> = match running_seq {
+			Some(_) if can_eval => Row::new()
+				.push(Space::with_width(Length::Units(10)))
+				.push(small_button(
+					config,
+					&mut self.cancel_button_state,
+					"cancel",
+					Some(Message::Cancel(tab_id, index)),
+				))
+				.align_items(Alignment::Center)
+				.into(),
+			_ => Space::with_width(Length::Shrink).into(),
+		};
+
+		let clear_button: Element<_> = if self.history.is_empty() {
+			Space::with_width(Length::Shrink).into()
+		} else {
+			Row::new()
+				.push(Space::with_width(Length::Units(10)))
+				.push(small_button(
+					config,
+					&mut self.clear_button_state,
+					"clear",
+					Some(Message::ClearResults(tab_id, index)),
+				))
+				.align_items(Alignment::Center)
+				.into()
+		};
+
+		let history_controls: Element<_> = if self.history.len() > 1 {
+			let selected = self.selected_history;
+
+			let prev = small_button(
+				config,
+				&mut self.history_prev_button_state,
+				"‹",
+				selected
+					.checked_sub(1)
+					.map(|i| Message::SelectHistory(tab_id, index, i)),
+			);
+
+			let position =
+				Text::new(format!("{}/{}", selected + 1, self.history.len()))
+					.color(config.ui_colors.unfocused_text)
+					.size(config.text_settings.ui_font_size);
+
+			let next = small_button(
+				config,
+				&mut self.history_next_button_state,
+				"›",
+				Some(selected + 1)
+					.filter(|&i| i < self.history.len())
+					.map(|i| Message::SelectHistory(tab_id, index, i)),
+			);
+
+			Row::new()
+				.push(prev)
+				.push(position)
+				.push(next)
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.into()
+		} else {
+			Space::with_width(Length::Shrink).into()
+		};
+
+		let is_latest = self.selected_history + 1 >= self.history.len();
+
+		let past_input: Element<_> = match entry {
+			Some(entry) if !is_latest => Text::new(&*entry.input)
+				.size(config.text_settings.editor_font_size)
+				.color(config.ui_colors.unfocused_text)
+				.font(font::MONO)
+				.into(),
+			_ => Space::with_height(Length::Shrink).into(),
+		};
+
+		let insert_controls: Element<_> = if is_multiple {
+			let above = small_button(
+				config,
+				&mut self.insert_above_button_state,
+				"+ above",
+				Some(Message::NewCellAt(tab_id, index, Placement::Above)),
+			);
+			let below = small_button(
+				config,
+				&mut self.insert_below_button_state,
+				"+ below",
+				Some(Message::NewCellAt(tab_id, index, Placement::Below)),
+			);
+			let previous = small_button(
+				config,
+				&mut self.insert_previous_button_state,
+				"+ previous result",
+				Some(index)
+					.filter(|index| index.0 > 0)
+					.map(|index| Message::InsertPreviousResult(tab_id, index)),
+			);
+
+			Row::new()
+				.push(above)
+				.push(below)
+				.push(previous)
+				.spacing(10)
+				.into()
+		} else {
+			Space::with_width(Length::Shrink).into()
+		};
+
+		let file_name: Element<_> = match &self.file {
+			Some((path, _)) => {
+				let name = path
+					.file_name()
+					.unwrap_or_else(|| path.as_os_str())
+					.to_string_lossy();
+				let marker = if has_unsaved_changes { " •" } else { "" };
+
+				Row::new()
+					.push(
+						Text::new(format!("{}{}", name, marker))
+							.color(config.ui_colors.unfocused_text)
+							.size(config.text_settings.ui_font_size)
+							.font(font::MONO),
+					)
+					.push(Space::with_width(Length::Units(20)))
+					.into()
+			}
+			None => Space::with_width(Length::Shrink).into(),
 		};
 
+		let save = small_button(
+			config,
+			&mut self.save_button_state,
+			"save",
+			Some(Message::SaveCell(tab_id, index)),
+		);
+		let load = small_button(
+			config,
+			&mut self.load_button_state,
+			"open",
+			Some(Message::LoadCell(tab_id, index)),
+		);
+
+		let copy_snippet = small_button(
+			config,
+			&mut self.copy_snippet_button_state,
+			"copy snippet",
+			Some(Message::CopyCellAsSnippet(tab_id, index)),
+		);
+
+		let controls = Row::new()
+			.push(eval_button)
+			.push(cancel_button)
+			.push(clear_button)
+			.push(Space::with_width(Length::Units(10)))
+			.push(insert_controls)
+			.push(Space::with_width(Length::Fill))
+			.push(file_name)
+			.push(caret_position)
+			.push(Space::with_width(Length::Units(20)))
+			.push(save)
+			.push(Space::with_width(Length::Units(10)))
+			.push(load)
+			.push(Space::with_width(Length::Units(10)))
+			.push(copy_snippet)
+			.push(Space::with_width(Length::Units(20)))
+			.push(history_controls)
+			.align_items(Alignment::Center);
+
 		Column::new()
 			.push(input)
 			.push(divider)
+			.push(past_input)
 			.push(results)
 			.push(Space::new(Length::Shrink, Length::Units(10)))
-			.push(eval_button)
+			.push(controls)
 			.into()
 	}
 
+	/// Marks where the caret is placed in a plugin's cell template.
+	const CURSOR_MARKER: &'static str = "${cursor}";
+
+	/// Creates a cell whose input is `template`, with the caret at its
+	/// [`CURSOR_MARKER`](Cell::CURSOR_MARKER), or an empty cell if there's no
+	/// template.
+	pub(crate) fn from_template(template: Option<&str>) -> Self {
+		let template = match template {
+			Some(template) => template,
+			None => return Self::default(),
+		};
+
+		let mut cell = Self::default();
+
+		match template.split_once(Self::CURSOR_MARKER) {
+			Some((before, after)) => {
+				cell.input_state.set_text(&format!("{}{}", before, after));
+				cell.input_state.set_cursor_to_byte(before.len());
+			}
+			None => cell.input_state.set_text(template),
+		}
+
+		cell
+	}
+
+	/// Creates a cell with the given input and no results.
+	pub(crate) fn with_contents(contents: &str) -> Self {
+		let mut cell = Self::default();
+		cell.input_state.set_text(contents);
+		cell
+	}
+
 	pub(crate) fn contents(&self) -> String {
 		self.input_state.contents()
 	}
-}
 
-#[derive(Debug)]
-pub(crate) enum Cells {
-	Single(Cell),
-	Multiple {
-		cells: Vec<Cell>,
-		scrollable_state: scrollable::State,
-		new_cell_button_state: button::State,
-		in_flight_requests: HashMap<u32, CellIndex>,
-	},
-}
+	/// Returns the selected part of the input, if any.
+	pub(crate) fn selected_text(&self) -> Option<String> {
+		self.input_state.selected_text()
+	}
 
-impl Cells {
-	pub(super) fn view<'s>(
-		&'s mut self,
-		config: &Config,
-		tab_index: TabIndex,
-	) -> Element<'s, Message> {
-		match self {
-			Cells::Single(cell) => {
-				let cell_contents = cell.view(config, tab_index, CellIndex(0));
+	/// Returns the code to evaluate, remembering it so it can be shown
+	/// alongside the results once they arrive.
+	pub(crate) fn begin_eval(&mut self) -> String {
+		let code = self.contents();
+		self.unsent_input = Some(code.clone());
+		self.is_streaming = false;
+		self.request_seq = None;
+		code
+	}
 
-				let contents = Container::new(cell_contents)
-					.padding(20)
-					.width(Length::Fill)
-					.height(Length::Fill);
+	/// Like [`begin_eval`](Cell::begin_eval), but only evaluates the selected
+	/// code if there is a selection.
+	pub(crate) fn begin_eval_selection(&mut self) -> String {
+		let code = self.selected_text().unwrap_or_else(|| self.contents());
+		self.unsent_input = Some(code.clone());
+		self.is_streaming = false;
+		self.request_seq = None;
+		code
+	}
 
-				contents.into()
-			}
+	/// Returns the text of the shown results, one per line, leaving out
+	/// compound results. Returns `None` if there's no such text.
+	fn result_text(&self) -> Option<String> {
+		let entry = self.history.get(self.selected_history)?;
 
-			Cells::Multiple {
-				cells,
-				scrollable_state,
-				new_cell_button_state,
-				..
-			} => {
-				let scrollable = cells
-					.iter_mut()
-					.enumerate()
-					.map(|(cell_index, cell)| {
-						let contents =
-							cell.view(config, tab_index, CellIndex(cell_index));
-						let contents = Container::new(contents)
-							.padding(20)
-							.width(Length::Fill)
-							.height(Length::Units(300));
+		let lines = entry
+			.results
+			.iter()
+			.filter(|result| !matches!(result, EvalResult::Compound(_)))
+			.map(|result| result.message().text.as_str())
+			.collect::<Vec<_>>();
 
-						let divider = Rule::horizontal(3)
-							.style(style::rule::cell_divider(config, 2));
+		Some(lines.join("\n")).filter(|text| !text.is_empty())
+	}
 
-						Column::new().push(contents).push(divider).into()
-					})
-					.fold(
-						Scrollable::new(scrollable_state),
-						|scrollable, cell: Element<_>| scrollable.push(cell),
-					);
+	/// Formats this cell's code and shown results as Markdown, with the code
+	/// fenced as `language`, for sharing outside Evalvana.
+	pub(crate) fn snippet(&self, language: Option<&Language>) -> String {
+		let code = self.contents();
+		let results = self
+			.history
+			.get(self.selected_history)
+			.map_or(&[][..], |entry| &entry.results[..]);
 
-				let new_cell = {
-					let contents = Container::new(
-						Text::new(NEW_CELL).font(icons::FONT).size(35),
-					)
-					.center_x()
-					.center_y()
-					.width(Length::Fill)
-					.height(Length::Fill);
-					Button::new(new_cell_button_state, contents)
-						.style(style::button::new_cell(config))
-						.on_press(Message::NewCell(tab_index))
-						.width(Length::Fill)
-						.height(Length::Units(200))
-				};
+		let mut output = String::new();
+		snippet_lines(&mut output, results, 0);
 
-				let scrollable = scrollable.push(new_cell);
+		let fence = fence_for(&code).max(fence_for(&output));
+		let mut snippet = format!(
+			"{}{}\n{}\n{}\n",
+			fence,
+			language.map_or("", |language| language.name),
+			code.trim_end_matches('\n'),
+			fence,
+		);
 
-				let contents = Container::new(scrollable)
-					.style(style::container::editor_bg(config))
-					.height(Length::Fill);
+		if !output.is_empty() {
+			snippet.push_str(&format!(
+				"\nOutput:\n\n{}\n{}{}\n",
+				fence, output, fence
+			));
+		}
 
-				contents.into()
-			}
+		snippet
+	}
+
+	/// Returns the file this cell was last saved to or loaded from, if any.
+	pub(crate) fn file_path(&self) -> Option<&Path> {
+		self.file.as_ref().map(|(path, _)| path.as_path())
+	}
+
+	/// Records that `contents` were saved to the file at `path`.
+	pub(crate) fn saved(&mut self, path: PathBuf, contents: String) {
+		self.file = Some((path, contents));
+	}
+
+	/// Replaces this cell's input with `contents`, loaded from the file at
+	/// `path`.
+	pub(crate) fn load(&mut self, path: PathBuf, contents: String) {
+		self.input_state.set_text(&contents);
+		self.file = Some((path, contents));
+	}
+
+	/// Returns whether this cell's input has changed since it was last saved
+	/// or loaded. Cells that were never saved don't count as changed.
+	pub(crate) fn has_unsaved_changes(&self) -> bool {
+		self.file.as_ref().is_some_and(|(_, saved)| {
+			*self.input_state.value() != saved.as_str()
+		})
+	}
+
+	/// Inserts `text` at the caret of this cell's input.
+	fn insert_text(&mut self, text: &str) {
+		self.input_state.insert_at_caret(text);
+	}
+
+	/// Returns the code to complete and the byte offset of the caret in it,
+	/// hiding any completions shown until new ones arrive.
+	pub(crate) fn begin_complete(&mut self) -> (String, usize) {
+		let offset = self.input_state.caret_offset();
+		self.dismiss_completions();
+		self.completion_at = Some((offset, self.input_state.len_bytes()));
+		(self.contents(), offset)
+	}
+
+	/// Shows completions of the code at the caret, unless it's changed since
+	/// they were requested.
+	pub(crate) fn show_completions(&mut self, completions: Vec<Completion>) {
+		if self.completion_at.is_none() {
+			return;
 		}
+
+		self.completion_button_states
+			.resize_with(completions.len(), button::State::new);
+		self.completions = completions;
+		self.selected_completion = 0;
 	}
 
-	pub(crate) fn new_cell(&mut self) {
-		match self {
-			Cells::Single(_) => panic!(
-				"Attempted to create a new cell \
-    	in a tab without multiple cells"
-			),
-			Cells::Multiple { cells, .. } => {
-				cells.push(Cell::default());
-			}
+	/// Returns whether completions are shown below the caret.
+	pub(crate) fn has_completions(&self) -> bool {
+		!self.completions.is_empty()
+	}
+
+	/// Moves the selection of the completions shown by `offset` entries,
+	/// wrapping around at either end of the list.
+	pub(crate) fn move_completion_selection(&mut self, offset: isize) {
+		let len = self.completions.len() as isize;
+		if len == 0 {
+			return;
 		}
+
+		self.selected_completion = (self.selected_completion as isize + offset)
+			.rem_euclid(len) as usize;
 	}
-}
 
-impl Index<CellIndex> for Cells {
-	type Output = Cell;
+	/// Inserts the text of the completion at `index` at the caret, and hides
+	/// the completions.
+	pub(crate) fn pick_completion(&mut self, index: usize) {
+		let completion = match self.completions.get(index) {
+			Some(completion) => completion,
+			None => return,
+		};
 
-	fn index(&self, index: CellIndex) -> &Self::Output {
-		match self {
-			Self::Single(cell) => {
-				if index.0 == 0 {
-					cell
-				} else {
-					panic!(
-						"Non-zero index used to get cell \
-        				for tab without multiple cells"
-					)
-				}
+		self.input_state.insert_at_caret(&completion.insert_text);
+		// picking a completion takes focus from the input
+		self.input_state
+			.set_cursor_to_byte(self.input_state.caret_offset());
+		self.dismiss_completions();
+	}
+
+	pub(crate) fn dismiss_completions(&mut self) {
+		self.completion_at = None;
+		self.completions.clear();
+		self.selected_completion = 0;
+		self.completion_button_states.clear();
+	}
+
+	/// Returns whether this cell's code was sent to be evaluated and its
+	/// results haven't arrived yet.
+	pub(crate) fn is_running(&self) -> bool {
+		self.unsent_input.is_some()
+			|| !self.sent_inputs.is_empty()
+			|| self.is_streaming
+	}
+
+	/// Returns the `seq` of the eval call of the evaluation in progress, if
+	/// it's been sent.
+	fn running_seq(&self) -> Option<u32> {
+		self.request_seq.filter(|_| self.is_running())
+	}
+
+	/// Returns whether results answering the call with the given `seq` are
+	/// for this cell: any results until its latest eval call is sent, and
+	/// then only those answering it.
+	fn is_requested(&self, seq: u32) -> bool {
+		self.request_seq
+			.is_none_or(|request_seq| request_seq == seq)
+	}
+
+	/// Returns whether the latest results of this cell include an error.
+	fn has_errors(&self) -> bool {
+		self.history.back().is_some_and(|entry| {
+			entry
+				.results
+				.iter()
+				.any(|result| result.level() == Level::Error)
+		})
+	}
+
+	/// Stops waiting for the results of an evaluation that failed to start.
+	pub(crate) fn cancel_eval(&mut self) {
+		self.unsent_input = None;
+	}
+
+	/// Records that the latest evaluation was sent in the eval call with the
+	/// given `seq`, whose results are shown with its input.
+	fn request_sent(&mut self, seq: u32) {
+		self.request_seq = Some(seq);
+		if let Some(input) = self.unsent_input.take() {
+			self.sent_inputs.insert(seq, input);
+		}
+	}
+
+	/// Returns the input evaluated by the eval call with the given `seq`,
+	/// or that of the latest evaluation if its call wasn't recorded as sent.
+	fn take_input(&mut self, seq: u32) -> String {
+		self.sent_inputs
+			.remove(&seq)
+			.or_else(|| self.unsent_input.take())
+			.unwrap_or_default()
+	}
+
+	/// Removes every result and history entry, and stops waiting for any
+	/// evaluation in progress.
+	fn clear_results(&mut self) {
+		self.history.clear();
+		self.selected_history = 0;
+		self.unsent_input = None;
+		self.sent_inputs.clear();
+		self.is_streaming = false;
+		self.result_group_button_states.clear();
+		self.images.clear();
+		self.result_button_states.clear();
+	}
+
+	/// Advances the indicator shown while this cell is running.
+	fn tick(&mut self) {
+		if self.is_running() {
+			self.spinner_frame = (self.spinner_frame + 1) % SPINNER.len();
+		} else {
+			self.spinner_frame = 0;
+		}
+	}
+
+	/// Adds a set of results of the eval call with the given `seq` to this
+	/// cell's history and shows them, dropping the oldest entries beyond
+	/// `history_len`.
+	pub(crate) fn push_results(
+		&mut self,
+		seq: u32,
+		results: Vec<EvalResult>,
+		history_len: usize,
+	) {
+		// the first result of a streamed evaluation already added its entry
+		if std::mem::take(&mut self.is_streaming) {
+			for result in results {
+				self.append_result(result);
 			}
-			Cells::Multiple { cells, .. } => &cells[index.0],
+			return;
+		}
+
+		let input = self.take_input(seq);
+		self.history.push_back(HistoryEntry { input, results });
+
+		let pruned = self.history.len().saturating_sub(history_len.max(1));
+		if pruned > 0 {
+			self.history.drain(..pruned);
+			self.evict_images();
+		}
+
+		self.selected_history = self.history.len() - 1;
+		self.show_all_results = false;
+	}
+
+	/// Drops the decoded images of results no longer in this cell's history.
+	fn evict_images(&mut self) {
+		let mut keys = HashSet::new();
+		for entry in &self.history {
+			image_keys(&entry.results, &mut keys);
+		}
+
+		self.images.retain(|key, _| keys.contains(key));
+	}
+
+	/// Shows a result of the evaluation in progress after the ones before it,
+	/// adding a history entry for the evaluation at its first result. The
+	/// evaluation is over after its last result, or once its response
+	/// arrives.
+	pub(crate) fn push_chunk(
+		&mut self,
+		seq: u32,
+		result: EvalResult,
+		is_final: bool,
+		history_len: usize,
+	) {
+		if self.is_streaming {
+			self.append_result(result);
+		} else {
+			self.push_results(seq, vec![result], history_len);
+		}
+
+		self.is_streaming = !is_final;
+	}
+
+	/// Adds a result to the latest history entry, creating one with no input
+	/// if this cell was never evaluated.
+	pub(crate) fn append_result(&mut self, result: EvalResult) {
+		if self.history.is_empty() {
+			self.history.push_back(HistoryEntry {
+				input: String::new(),
+				results: vec![],
+			});
+		}
+
+		self.history
+			.back_mut()
+			.expect("History was empty after adding an entry")
+			.results
+			.push(result);
+	}
+
+	pub(crate) fn select_history(&mut self, index: usize) {
+		if index < self.history.len() {
+			self.selected_history = index;
+		}
+	}
+
+	/// Moves the cursor of this cell's input to the given location.
+	pub(crate) fn jump_to(&mut self, location: &Location) {
+		self.input_state.set_cursor_to_line_column(
+			location.line.saturating_sub(1) as usize,
+			location.column.saturating_sub(1) as usize,
+		);
+	}
+
+	pub(crate) fn show_all_results(&mut self) {
+		self.show_all_results = true;
+	}
+
+	pub(crate) fn toggle_result_group(&mut self, level: Level) {
+		if !self.collapsed_levels.remove(&level) {
+			self.collapsed_levels.insert(level);
 		}
 	}
 }
 
-impl IndexMut<CellIndex> for Cells {
-	fn index_mut(&mut self, index: CellIndex) -> &mut Self::Output {
-		match self {
-			Self::Single(cell) => {
-				if index.0 == 0 {
-					cell
-				} else {
-					panic!(
-						"Non-zero index used to get cell \
-        				for tab without multiple cells"
-					)
-				}
+/// Appends the text of `results` to `output`, one line per line of text, with
+/// the results of compound results indented under their summary.
+fn snippet_lines(output: &mut String, results: &[EvalResult], depth: usize) {
+	for result in results {
+		for line in result.message().text.lines() {
+			output.push_str(&"  ".repeat(depth));
+			output.push_str(line);
+			output.push('\n');
+		}
+
+		if let EvalResult::Compound(compound) = result {
+			for (_, results) in compound.groups() {
+				snippet_lines(output, results, depth + 1);
 			}
-			Cells::Multiple { cells, .. } => &mut cells[index.0],
 		}
 	}
 }
+
+/// Returns a Markdown code fence long enough to enclose `text`, which is
+/// longer than any run of backticks in it.
+fn fence_for(text: &str) -> String {
+	let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+
+	"`".repeat(longest_run.max(2) + 1)
+}
+
+/// Where a new cell is inserted, relative to an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Placement {
+	Above,
+	Below,
+}
+
+/// The input and results of one evaluation of a [`Cell`].
+#[derive(Debug)]
+struct HistoryEntry {
+	input: String,
+	results: Vec<EvalResult>,
+}
+
+fn small_button<'s>(
+	config: &Config,
+	state: &'s mut button::State,
+	label: &str,
+	on_press: Option<Message>,
+) -> Button<'s, Message> {
+	let text = Text::new(label)
+		.color(config.ui_colors.text)
+		.size(config.text_settings.ui_font_size)
+		.font(font::MONO);
+
+	let button = Button::new(state, Container::new(text).padding(5))
+		.style(style::button::primary(config));
+
+	match on_press {
+		Some(message) => button.on_press(message),
+		None => button,
+	}
+}
+
+/// Renders an entry of the completions shown below the caret, with its detail
+/// beside its label, highlighted if it's the one picked by pressing Enter.
+fn completion_view<'s>(
+	config: &Config,
+	completion: &Completion,
+	state: &'s mut button::State,
+	is_selected: bool,
+) -> Button<'s, Message> {
+	let label = Text::new(completion.label.as_str())
+		.color(config.ui_colors.text)
+		.size(config.text_settings.editor_font_size)
+		.font(font::MONO);
+
+	let mut row = Row::new().push(label).spacing(20);
+	if let Some(detail) = &completion.detail {
+		row = row.push(
+			Text::new(detail.as_str())
+				.color(config.ui_colors.unfocused_text)
+				.size(config.text_settings.editor_font_size)
+				.font(font::MONO),
+		);
+	}
+
+	Button::new(state, row)
+		.style(style::button::completion(config, is_selected))
+		.padding([2, 5])
+}
+
+/// Renders a result, and those it contains if it's a compound result.
+///
+/// Takes one pair of button states from `button_states` for each result
+/// rendered. The copy button of results whose text is `copied_text` says
+/// they were copied.
+#[allow(clippy::too_many_arguments)]
+fn result_view<'s>(
+	config: &Config,
+	result: &EvalResult,
+	color: Color,
+	button_states: &mut slice::IterMut<'s, [button::State; 2]>,
+	images: &mut HashMap<u64, Option<image::Handle>>,
+	copied_text: Option<&str>,
+	tab_id: TabId,
+	index: CellIndex,
+) -> Element<'s, Message> {
+	let message = result.message();
+	let [button_state, copy_button_state] =
+		button_states.next().expect("Too few result button states");
+
+	let color = message
+		.category
+		.as_deref()
+		.map_or(color, |category| category_color(config, category));
+
+	let text = content_view(config, message, color, images);
+
+	let line: Element<_> = match &message.location {
+		Some(location) => Button::new(button_state, text)
+			.style(style::button::text(config))
+			.padding(0)
+			.on_press(Message::JumpToLocation(tab_id, index, location.clone()))
+			.into(),
+		None => text,
+	};
+
+	let copy = {
+		let icon = Text::new("⧉")
+			.color(config.ui_colors.unfocused_text)
+			.size(config.text_settings.ui_font_size);
+		let button = Button::new(copy_button_state, icon)
+			.style(style::button::text(config))
+			.padding(0)
+			.on_press(Message::CopyResult(message.text.clone()));
+
+		let tip = if copied_text == Some(message.text.as_str()) {
+			"Copied"
+		} else {
+			"Copy"
+		};
+
+		Tooltip::new(button, tip, Position::Left)
+			.size(config.text_settings.ui_font_size)
+			.padding(5)
+			.style(style::container::ui_bg(config))
+	};
+
+	let line: Element<_> = Row::new()
+		.push(line)
+		.push(Space::with_width(Length::Units(10)))
+		.push(copy)
+		.align_items(Alignment::Center)
+		.into();
+
+	let compound = match result {
+		EvalResult::Compound(compound) => compound,
+		_ => return line,
+	};
+
+	let mut children = vec![];
+
+	for (level, results) in compound.groups() {
+		let color = level_color(config, level);
+
+		for result in results {
+			children.push(result_view(
+				config,
+				result,
+				color,
+				button_states,
+				images,
+				copied_text,
+				tab_id,
+				index,
+			));
+		}
+	}
+
+	let children = Container::new(Column::with_children(children).spacing(10))
+		.padding([0, 0, 0, config.text_settings.editor_font_size * 2]);
+
+	Column::new().push(line).push(children).spacing(10).into()
+}
+
+/// Renders the content of `message`, or its text if it has no content that
+/// can be shown.
+fn content_view<'s>(
+	config: &Config,
+	message: &EvalMessage,
+	color: Color,
+	images: &mut HashMap<u64, Option<image::Handle>>,
+) -> Element<'s, Message> {
+	let text = |text: &str| {
+		Text::new(text)
+			.size(config.text_settings.editor_font_size)
+			.color(color)
+			.font(font::MONO)
+	};
+
+	match &message.content {
+		Some(Content::Table { headers, rows }) => {
+			// laid out by column, so each is as wide as its widest cell
+			let columns = iter::once(headers.len())
+				.chain(rows.iter().map(Vec::len))
+				.max()
+				.unwrap_or(0);
+
+			let columns = (0..columns)
+				.map(|column| {
+					let header = headers.get(column).map_or("", String::as_str);
+					let cells = rows.iter().map(|row| {
+						text(row.get(column).map_or("", String::as_str)).into()
+					});
+
+					Column::with_children(
+						[
+							text(header).into(),
+							Rule::horizontal(1)
+								.style(style::rule::cell_divider(config, 1))
+								.into(),
+						]
+						.into_iter()
+						.chain(cells)
+						.collect(),
+					)
+					.spacing(5)
+					.into()
+				})
+				.collect();
+
+			Row::with_children(columns).spacing(20).into()
+		}
+		Some(Content::Image { mime, data })
+			if mime.starts_with("image/") && mime != "image/svg+xml" =>
+		{
+			let handle = images.entry(image_key(data)).or_insert_with(|| {
+				decode_base64(data).map(image::Handle::from_memory)
+			});
+
+			match handle {
+				Some(handle) => Image::new(handle.clone()).into(),
+				None => text(&message.text).into(),
+			}
+		}
+		_ => text(&message.text).into(),
+	}
+}
+
+/// Returns the key in a cell's decoded images of the image encoded in `data`.
+fn image_key(data: &str) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	data.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Adds the keys of the images in `results` to `keys`, including those of
+/// compound results.
+fn image_keys(results: &[EvalResult], keys: &mut HashSet<u64>) {
+	for result in results {
+		if let Some(Content::Image { data, .. }) = &result.message().content {
+			keys.insert(image_key(data));
+		}
+
+		if let EvalResult::Compound(compound) = result {
+			for (_, results) in compound.groups() {
+				image_keys(results, keys);
+			}
+		}
+	}
+}
+
+/// Decodes standard base64, ignoring whitespace such as line breaks, or
+/// returns `None` if `data` isn't base64.
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+	let data: String = data.split_ascii_whitespace().collect();
+	base64::decode(data).ok()
+}
+
+/// Marks the location in `input` of each error in `results`, including those
+/// contained in compound results.
+fn error_marks(
+	input: &editor::State,
+	results: &[EvalResult],
+	marks: &mut Vec<(usize, MarkKind)>,
+) {
+	for result in results {
+		// errors in other files can't be marked in the input
+		if let (Level::Error, Some(location @ Location { path: None, .. })) =
+			(result.level(), &result.message().location)
+		{
+			let index = input.byte_at_line_column(
+				location.line.saturating_sub(1) as usize,
+				location.column.saturating_sub(1) as usize,
+			);
+			marks.push((index, MarkKind::Error));
+		}
+
+		if let EvalResult::Compound(compound) = result {
+			for (_, results) in compound.groups() {
+				error_marks(input, results, marks);
+			}
+		}
+	}
+}
+
+/// Returns the rows of the results shown in a cell: a header for each group
+/// of results other than successes, followed by the group unless it's
+/// collapsed, and a row offering to show the results left out by
+/// `max_shown_results` unless `show_all` is set.
+#[allow(clippy::too_many_arguments)]
+fn results_view<'s>(
+	config: &Config,
+	results: &[EvalResult],
+	show_all: bool,
+	collapsed_levels: &HashSet<Level>,
+	group_button_states: &'s mut Vec<button::State>,
+	result_button_states: &'s mut Vec<[button::State; 2]>,
+	show_all_button_state: &'s mut button::State,
+	images: &mut HashMap<u64, Option<image::Handle>>,
+	copied_text: Option<&str>,
+	tab_id: TabId,
+	index: CellIndex,
+) -> Vec<Element<'s, Message>> {
+	let (shown_results, hidden_count) =
+		cap_results(results, config.eval_settings.max_shown_results, show_all);
+
+	let groups = result_groups(shown_results);
+
+	let header_count = groups
+		.iter()
+		.filter(|(level, _)| *level != Level::Success)
+		.count();
+	group_button_states.resize_with(header_count, button::State::new);
+	result_button_states.resize_with(
+		shown_results.iter().map(count_results).sum(),
+		Default::default,
+	);
+
+	let mut header_states = group_button_states.iter_mut();
+	let mut result_states = result_button_states.iter_mut();
+
+	let mut rows = vec![];
+
+	for (level, group) in groups {
+		let color = level_color(config, level);
+
+		if level != Level::Success {
+			let is_collapsed = collapsed_levels.contains(&level);
+
+			let marker = if is_collapsed { '▸' } else { '▾' };
+			let text = Text::new(format!(
+				"{} {}",
+				marker,
+				describe_count(level, group.len())
+			))
+			.size(config.text_settings.ui_font_size)
+			.color(color)
+			.font(font::MONO);
+
+			let header = Button::new(
+				header_states
+					.next()
+					.expect("Too few result group button states"),
+				text,
+			)
+			.style(style::button::text(config))
+			.padding(0)
+			.on_press(Message::ToggleResultGroup(tab_id, index, level));
+
+			rows.push(header.into());
+
+			if is_collapsed {
+				let count = group.iter().map(count_results).sum();
+				result_states.by_ref().take(count).for_each(drop);
+				continue;
+			}
+		}
+
+		for result in group {
+			rows.push(result_view(
+				config,
+				result,
+				color,
+				&mut result_states,
+				images,
+				copied_text,
+				tab_id,
+				index,
+			));
+		}
+	}
+
+	if hidden_count > 0 {
+		let text = Text::new(format!("… and {} more (show all)", hidden_count))
+			.size(config.text_settings.ui_font_size)
+			.color(config.ui_colors.unfocused_text)
+			.font(font::MONO);
+
+		let show_all = Button::new(show_all_button_state, text)
+			.style(style::button::text(config))
+			.padding(0)
+			.on_press(Message::ShowAllResults(tab_id, index));
+
+		rows.push(show_all.into());
+	}
+
+	rows
+}
+
+/// Returns the first `max` of `results`, or all of them with `show_all`, along
+/// with how many are left out.
+fn cap_results(
+	results: &[EvalResult],
+	max: usize,
+	show_all: bool,
+) -> (&[EvalResult], usize) {
+	if show_all || results.len() <= max {
+		(results, 0)
+	} else {
+		(&results[..max], results.len() - max)
+	}
+}
+
+/// Counts a result and all of the results it contains.
+fn count_results(result: &EvalResult) -> usize {
+	match result {
+		EvalResult::Compound(compound) => {
+			1 + compound
+				.groups()
+				.flat_map(|(_, results)| results)
+				.map(count_results)
+				.sum::<usize>()
+		}
+		_ => 1,
+	}
+}
+
+/// Splits results into runs of consecutive results with the same level.
+fn result_groups(results: &[EvalResult]) -> Vec<(Level, &[EvalResult])> {
+	let mut groups = vec![];
+	let mut rest = results;
+
+	while let Some(first) = rest.first() {
+		let level = first.level();
+		let len = rest.iter().take_while(|r| r.level() == level).count();
+		let (group, remaining) = rest.split_at(len);
+		groups.push((level, group));
+		rest = remaining;
+	}
+
+	groups
+}
+
+fn describe_count(level: Level, count: usize) -> String {
+	let noun = match level {
+		Level::Success => "result",
+		Level::Warning => "warning",
+		Level::Error => "error",
+	};
+
+	if count == 1 {
+		format!("1 {}", noun)
+	} else {
+		format!("{} {}s", count, noun)
+	}
+}
+
+fn level_color(config: &Config, level: Level) -> Color {
+	match level {
+		Level::Success => config.editor_colors.success,
+		Level::Warning => config.editor_colors.warnings,
+		Level::Error => config.editor_colors.errors,
+	}
+}
+
+/// Finds the color of a plugin-defined result category, falling back to the
+/// main text color for categories that aren't configured.
+fn category_color(config: &Config, category: &str) -> Color {
+	let colors = &config.editor_colors;
+
+	match category {
+		"success" => colors.success,
+		"warning" => colors.warnings,
+		"error" => colors.errors,
+		"info" => colors.info,
+		other => colors.categories.get(other).copied().unwrap_or(colors.main),
+	}
+}
+
+#[derive(Debug)]
+pub(crate) enum Cells {
+	Single(Box<Cell>),
+	Multiple {
+		cells: Vec<Cell>,
+		scrollable_state: scrollable::State,
+		new_cell_button_state: button::State,
+		clear_all_button_state: button::State,
+		eval_all_button_state: button::State,
+		in_flight_requests: HashMap<u32, CellIndex>,
+		focused: usize,
+		/// The code new cells start with.
+		cell_template: Option<String>,
+	},
+}
+
+impl Cells {
+	/// Renders the cells, with buttons to evaluate them if `can_eval` is set.
+	pub(super) fn view<'s>(
+		&'s mut self,
+		config: &Config,
+		tab_id: TabId,
+		can_eval: bool,
+		line_comment: Option<&str>,
+		copied_text: Option<&str>,
+	) -> Element<'s, Message> {
+		match self {
+			Cells::Single(cell) => {
+				let cell_contents = cell.view(
+					config,
+					tab_id,
+					CellIndex(0),
+					false,
+					can_eval,
+					line_comment,
+					copied_text,
+				);
+
+				let contents = Container::new(cell_contents)
+					.padding(20)
+					.width(Length::Fill)
+					.height(Length::Fill);
+
+				contents.into()
+			}
+
+			Cells::Multiple {
+				cells,
+				scrollable_state,
+				new_cell_button_state,
+				clear_all_button_state,
+				eval_all_button_state,
+				..
+			} => {
+				let eval_all: Element<_> = if can_eval {
+					Row::new()
+						.push(small_button(
+							config,
+							eval_all_button_state,
+							"Run all",
+							Some(Message::EvalAll(tab_id)),
+						))
+						.push(Space::with_width(Length::Units(10)))
+						.into()
+				} else {
+					Space::with_width(Length::Shrink).into()
+				};
+
+				let toolbar = Row::new()
+					.push(Space::with_width(Length::Fill))
+					.push(eval_all)
+					.push(small_button(
+						config,
+						clear_all_button_state,
+						"Clear all",
+						Some(Message::ClearAllResults(tab_id)),
+					))
+					.padding([10, 20]);
+
+				let scrollable = cells
+					.iter_mut()
+					.enumerate()
+					.map(|(cell_index, cell)| {
+						let contents = cell.view(
+							config,
+							tab_id,
+							CellIndex(cell_index),
+							true,
+							can_eval,
+							line_comment,
+							copied_text,
+						);
+						let contents = Container::new(contents)
+							.padding(20)
+							.width(Length::Fill);
+
+						let divider = Rule::horizontal(3)
+							.style(style::rule::cell_divider(config, 2));
+
+						Column::new().push(contents).push(divider).into()
+					})
+					.fold(
+						Scrollable::new(scrollable_state),
+						|scrollable, cell: Element<_>| scrollable.push(cell),
+					);
+
+				let new_cell = {
+					let contents = Container::new(
+						Text::new(NEW_CELL).font(icons::FONT).size(35),
+					)
+					.center_x()
+					.center_y()
+					.width(Length::Fill)
+					.height(Length::Fill);
+					Button::new(new_cell_button_state, contents)
+						.style(style::button::new_cell(config))
+						.on_press(Message::NewCell(tab_id))
+						.width(Length::Fill)
+						.height(Length::Units(200))
+				};
+
+				let scrollable = scrollable.push(new_cell);
+
+				let contents = Container::new(
+					Column::new().push(toolbar).push(scrollable),
+				)
+				.style(style::container::editor_bg(config))
+				.height(Length::Fill);
+
+				contents.into()
+			}
+		}
+	}
+
+	/// Returns the input of each cell, in order.
+	pub(crate) fn contents(&self) -> Vec<String> {
+		match self {
+			Cells::Single(cell) => vec![cell.contents()],
+			Cells::Multiple { cells, .. } => {
+				cells.iter().map(Cell::contents).collect()
+			}
+		}
+	}
+
+	/// Replaces every cell with one containing the corresponding input, and
+	/// no results.
+	///
+	/// A single cell only takes the first input.
+	pub(crate) fn set_contents(&mut self, contents: &[String]) {
+		match self {
+			Cells::Single(cell) => {
+				if let Some(contents) = contents.first() {
+					**cell = Cell::with_contents(contents);
+				}
+			}
+			Cells::Multiple {
+				cells,
+				in_flight_requests,
+				focused,
+				..
+			} => {
+				if contents.is_empty() {
+					return;
+				}
+
+				*cells = contents
+					.iter()
+					.map(|contents| Cell::with_contents(contents))
+					.collect();
+				in_flight_requests.clear();
+				*focused = 0;
+			}
+		}
+	}
+
+	pub(crate) fn eval_complete(
+		&mut self,
+		seq: u32,
+		results: Vec<EvalResult>,
+		history_len: usize,
+	) {
+		match self {
+			Cells::Single(cell) => {
+				// such as the answer to a cancellation
+				if cell.is_requested(seq) {
+					cell.push_results(seq, results, history_len);
+				} else {
+					cell.sent_inputs.remove(&seq);
+				}
+			}
+			Cells::Multiple {
+				cells,
+				in_flight_requests,
+				..
+			} => {
+				if let Some(cell) = in_flight_requests
+					.get(&seq)
+					.and_then(|&CellIndex(i)| cells.get_mut(i))
+				{
+					cell.push_results(seq, results, history_len);
+				}
+			}
+		}
+	}
+
+	pub(crate) fn eval_chunk(
+		&mut self,
+		seq: u32,
+		result: EvalResult,
+		is_final: bool,
+		history_len: usize,
+	) {
+		let cell = match self {
+			Cells::Single(cell) => {
+				Some(&mut **cell).filter(|cell| cell.is_requested(seq))
+			}
+			Cells::Multiple {
+				cells,
+				in_flight_requests,
+				..
+			} => in_flight_requests
+				.get(&seq)
+				.and_then(|&CellIndex(i)| cells.get_mut(i)),
+		};
+
+		if let Some(cell) = cell {
+			cell.push_chunk(seq, result, is_final, history_len);
+		}
+	}
+
+	/// Returns the cell that results which weren't requested are shown in:
+	/// the cell most recently evaluated, or the last cell if none have been.
+	pub(crate) fn output_cell(&self) -> CellIndex {
+		match self {
+			Cells::Single(_) => CellIndex(0),
+			Cells::Multiple {
+				cells,
+				in_flight_requests,
+				..
+			} => in_flight_requests
+				.iter()
+				.max_by_key(|(&seq, _)| seq)
+				.map(|(_, &cell)| cell)
+				.filter(|&cell| cell < CellIndex(cells.len()))
+				.unwrap_or(CellIndex(cells.len() - 1)),
+		}
+	}
+
+	/// Returns the index of the cell that was last clicked on.
+	pub(crate) fn focused_index(&self) -> CellIndex {
+		match self {
+			Cells::Single(_) => CellIndex(0),
+			Cells::Multiple { focused, .. } => CellIndex(*focused),
+		}
+	}
+
+	pub(crate) fn focus(&mut self, index: CellIndex) {
+		if let Cells::Multiple { cells, focused, .. } = self {
+			if index < CellIndex(cells.len()) {
+				*focused = index.0;
+			}
+		}
+	}
+
+	/// Returns the cell at `index`, or `None` if it's since been removed.
+	pub(crate) fn get_mut(&mut self, index: CellIndex) -> Option<&mut Cell> {
+		self.iter_mut().nth(index.0)
+	}
+
+	/// Records that the latest evaluation of the cell at `index` was sent in
+	/// the eval call with the given `seq`.
+	pub(crate) fn request_sent(&mut self, index: CellIndex, seq: u32) {
+		if let Some(cell) = self.iter_mut().nth(index.0) {
+			cell.request_sent(seq);
+		}
+	}
+
+	/// Shows completions below the caret of the cell at `index`, if it still
+	/// exists; see [`Cell::show_completions`].
+	pub(crate) fn show_completions(
+		&mut self,
+		index: CellIndex,
+		completions: Vec<Completion>,
+	) {
+		if let Some(cell) = self.iter_mut().nth(index.0) {
+			cell.show_completions(completions);
+		}
+	}
+
+	/// Returns the `seq` of the eval call of the evaluation in progress in
+	/// the cell at `index`, if it's been sent.
+	pub(crate) fn running_seq(&self, index: CellIndex) -> Option<u32> {
+		self.iter().nth(index.0).and_then(Cell::running_seq)
+	}
+
+	fn iter(&self) -> slice::Iter<'_, Cell> {
+		match self {
+			Cells::Single(cell) => slice::from_ref(&**cell).iter(),
+			Cells::Multiple { cells, .. } => cells.iter(),
+		}
+	}
+
+	fn iter_mut(&mut self) -> slice::IterMut<'_, Cell> {
+		match self {
+			Cells::Single(cell) => slice::from_mut(&mut **cell).iter_mut(),
+			Cells::Multiple { cells, .. } => cells.iter_mut(),
+		}
+	}
+
+	pub(crate) fn is_running(&self) -> bool {
+		self.iter().any(Cell::is_running)
+	}
+
+	/// Removes the results of every cell, dropping any evaluations in
+	/// progress.
+	pub(crate) fn clear_results(&mut self) {
+		self.iter_mut().for_each(Cell::clear_results);
+
+		if let Cells::Multiple {
+			in_flight_requests, ..
+		} = self
+		{
+			in_flight_requests.clear();
+		}
+	}
+
+	/// Removes the results of the cell at `index`, dropping any evaluation of
+	/// it in progress.
+	pub(crate) fn clear_cell_results(&mut self, index: CellIndex) {
+		if let Some(cell) = self.iter_mut().nth(index.0) {
+			cell.clear_results();
+		}
+
+		if let Cells::Multiple {
+			in_flight_requests, ..
+		} = self
+		{
+			in_flight_requests.retain(|_, &mut cell| cell != index);
+		}
+	}
+
+	/// Inserts the shown results of the cell above `index` at the caret of
+	/// the cell at `index`, if it has any plain text results.
+	pub(crate) fn insert_previous_result(&mut self, index: CellIndex) {
+		let text = index
+			.0
+			.checked_sub(1)
+			.and_then(|previous| self.iter().nth(previous))
+			.and_then(Cell::result_text);
+
+		if let (Some(text), Some(cell)) = (text, self.iter_mut().nth(index.0)) {
+			cell.insert_text(&text);
+		}
+	}
+
+	/// Advances the indicators of running cells.
+	pub(crate) fn tick(&mut self) {
+		self.iter_mut().for_each(Cell::tick);
+	}
+
+	/// Returns whether a selection is being dragged past the edge of any
+	/// cell's input.
+	pub(crate) fn is_auto_scrolling(&self) -> bool {
+		self.iter().any(|cell| cell.input_state.is_auto_scrolling())
+	}
+
+	/// Returns when the caret of the focused cell's input last moved, if an
+	/// input is focused.
+	pub(crate) fn last_cursor_move(&self) -> Option<Instant> {
+		self.iter()
+			.find(|cell| cell.input_state.is_focused())
+			.map(|cell| cell.input_state.last_cursor_move())
+	}
+
+	/// Scrolls selections being dragged past the edges of inputs one step
+	/// further.
+	pub(crate) fn auto_scroll(&mut self) {
+		for cell in self.iter_mut() {
+			cell.input_state.auto_scroll();
+		}
+	}
+
+	/// Returns the index of every cell, in order.
+	pub(crate) fn indices(&self) -> impl Iterator<Item = CellIndex> {
+		(0..self.iter().len()).map(CellIndex)
+	}
+
+	/// Returns whether the cell at `index` finished evaluating with an error,
+	/// or `None` if it's still being evaluated or doesn't exist.
+	pub(crate) fn failed(&self, index: CellIndex) -> Option<bool> {
+		self.iter()
+			.nth(index.0)
+			.filter(|cell| !cell.is_running())
+			.map(Cell::has_errors)
+	}
+
+	pub(crate) fn is_multiple(&self) -> bool {
+		matches!(self, Cells::Multiple { .. })
+	}
+
+	/// Creates a new cell after the focused one, and focuses it.
+	pub(crate) fn new_cell(&mut self) {
+		let at = self.focused_index().0 + 1;
+		self.insert_cell(at);
+	}
+
+	/// Creates a new cell at index `at`, starting with the plugin's template,
+	/// and focuses it.
+	///
+	/// Pending requests from later cells are updated to their new indices.
+	pub(crate) fn insert_cell(&mut self, at: usize) {
+		match self {
+			Cells::Single(_) => panic!(
+				"Attempted to create a new cell \
+    	in a tab without multiple cells"
+			),
+			Cells::Multiple {
+				cells,
+				in_flight_requests,
+				focused,
+				cell_template,
+				..
+			} => {
+				let at = at.min(cells.len());
+
+				cells.insert(at, Cell::from_template(cell_template.as_deref()));
+
+				for cell in in_flight_requests.values_mut() {
+					if cell.0 >= at {
+						cell.0 += 1;
+					}
+				}
+
+				*focused = at;
+			}
+		}
+	}
+}
+
+impl Index<CellIndex> for Cells {
+	type Output = Cell;
+
+	fn index(&self, index: CellIndex) -> &Self::Output {
+		match self {
+			Self::Single(cell) => {
+				if index.0 == 0 {
+					cell
+				} else {
+					panic!(
+						"Non-zero index used to get cell \
+        				for tab without multiple cells"
+					)
+				}
+			}
+			Cells::Multiple { cells, .. } => &cells[index.0],
+		}
+	}
+}
+
+impl IndexMut<CellIndex> for Cells {
+	fn index_mut(&mut self, index: CellIndex) -> &mut Self::Output {
+		match self {
+			Self::Single(cell) => {
+				if index.0 == 0 {
+					cell
+				} else {
+					panic!(
+						"Non-zero index used to get cell \
+        				for tab without multiple cells"
+					)
+				}
+			}
+			Cells::Multiple { cells, .. } => &mut cells[index.0],
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use evalvana_api::{CompoundResult, EvalMessage};
+
+	use super::*;
+
+	fn log(text: &str) -> EvalResult {
+		EvalResult::Success(EvalMessage {
+			text: text.to_owned(),
+			location: None,
+			category: None,
+			content: None,
+		})
+	}
+
+	fn multiple(count: usize) -> Cells {
+		Cells::Multiple {
+			cells: (0..count).map(|_| Cell::default()).collect(),
+			scrollable_state: scrollable::State::new(),
+			new_cell_button_state: button::State::new(),
+			clear_all_button_state: button::State::new(),
+			eval_all_button_state: button::State::new(),
+			in_flight_requests: HashMap::new(),
+			focused: 0,
+			cell_template: None,
+		}
+	}
+
+	fn request(cells: &mut Cells, seq: u32, cell: CellIndex) {
+		if let Cells::Multiple {
+			in_flight_requests, ..
+		} = cells
+		{
+			in_flight_requests.insert(seq, cell);
+		}
+	}
+
+	fn shown_results(cell: &Cell) -> Vec<&str> {
+		cell.history.back().map_or(vec![], |entry| {
+			entry
+				.results
+				.iter()
+				.map(|r| r.message().text.as_str())
+				.collect()
+		})
+	}
+
+	#[test]
+	fn missing_cells_are_not_found() {
+		let mut cells = multiple(2);
+		assert!(cells.get_mut(CellIndex(1)).is_some());
+		assert!(cells.get_mut(CellIndex(2)).is_none());
+
+		let mut cells = Cells::Single(Box::default());
+		assert!(cells.get_mut(CellIndex(0)).is_some());
+		assert!(cells.get_mut(CellIndex(1)).is_none());
+	}
+
+	#[test]
+	fn unrequested_results_go_to_last_cell() {
+		let mut cells = multiple(3);
+
+		let cell = cells.output_cell();
+		cells[cell].append_result(log("first"));
+		cells[cell].append_result(log("second"));
+
+		assert_eq!(cell, CellIndex(2));
+		assert_eq!(shown_results(&cells[CellIndex(2)]), ["first", "second"]);
+		assert!(shown_results(&cells[CellIndex(0)]).is_empty());
+	}
+
+	#[test]
+	fn unrequested_results_go_to_last_evaluated_cell() {
+		let mut cells = multiple(3);
+
+		request(&mut cells, 0, CellIndex(2));
+		request(&mut cells, 1, CellIndex(0));
+
+		cells[CellIndex(0)].begin_eval();
+		cells[CellIndex(0)].push_results(0, vec![log("result")], 10);
+
+		let cell = cells.output_cell();
+		cells[cell].append_result(log("log"));
+
+		assert_eq!(cell, CellIndex(0));
+		assert_eq!(shown_results(&cells[CellIndex(0)]), ["result", "log"]);
+		assert!(shown_results(&cells[CellIndex(2)]).is_empty());
+	}
+
+	#[test]
+	fn requests_resolve_after_insert() {
+		let mut cells = multiple(3);
+
+		request(&mut cells, 0, CellIndex(0));
+		request(&mut cells, 1, CellIndex(1));
+		request(&mut cells, 2, CellIndex(2));
+
+		cells.insert_cell(1);
+
+		assert_eq!(cells.focused_index(), CellIndex(1));
+
+		for seq in 0..3 {
+			cells.eval_complete(seq, vec![log(&seq.to_string())], 10);
+		}
+
+		assert_eq!(shown_results(&cells[CellIndex(0)]), ["0"]);
+		assert!(shown_results(&cells[CellIndex(1)]).is_empty());
+		assert_eq!(shown_results(&cells[CellIndex(2)]), ["1"]);
+		assert_eq!(shown_results(&cells[CellIndex(3)]), ["2"]);
+	}
+
+	#[test]
+	fn set_contents_drops_results() {
+		let mut cells = multiple(1);
+		request(&mut cells, 0, CellIndex(0));
+		cells.eval_complete(0, vec![log("result")], 10);
+
+		let contents = ["let x = 1;".to_owned(), "x + 1".to_owned()];
+		cells.set_contents(&contents);
+
+		assert_eq!(cells.contents(), contents);
+		assert!(shown_results(&cells[CellIndex(0)]).is_empty());
+		assert_eq!(cells.focused_index(), CellIndex(0));
+	}
+
+	#[test]
+	fn running_until_results_arrive() {
+		let mut cells = multiple(2);
+		assert!(!cells.is_running());
+
+		cells[CellIndex(1)].begin_eval();
+		request(&mut cells, 0, CellIndex(1));
+		assert!(cells.is_running());
+
+		cells.tick();
+		assert_eq!(cells[CellIndex(1)].spinner_frame, 1);
+		assert_eq!(cells[CellIndex(0)].spinner_frame, 0);
+
+		cells.eval_complete(0, vec![log("done")], 10);
+		assert!(!cells.is_running());
+	}
+
+	#[test]
+	fn decodes_base64() {
+		assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+		assert_eq!(decode_base64("aGVs\nbG8h").unwrap(), b"hello!");
+		assert_eq!(decode_base64("").unwrap(), b"");
+		assert_eq!(decode_base64("not base64!"), None);
+		assert_eq!(decode_base64("aGVsbG8=aGVsbG8="), None);
+	}
+
+	#[test]
+	fn pruned_history_drops_its_images() {
+		let image = |data: &str| {
+			EvalResult::Success(EvalMessage {
+				text: String::new(),
+				location: None,
+				category: None,
+				content: Some(Content::Image {
+					mime: "image/png".to_owned(),
+					data: data.to_owned(),
+				}),
+			})
+		};
+
+		let mut cell = Cell::default();
+		cell.push_results(0, vec![image("AAAA")], 2);
+		cell.images.insert(image_key("AAAA"), None);
+		cell.push_results(0, vec![image("BBBB")], 2);
+		cell.images.insert(image_key("BBBB"), None);
+		assert_eq!(cell.images.len(), 2);
+
+		cell.push_results(0, vec![log("text")], 2);
+		assert_eq!(cell.images.len(), 1);
+		assert!(cell.images.contains_key(&image_key("BBBB")));
+	}
+
+	#[test]
+	fn chunks_grow_results_until_response() {
+		let mut cells = multiple(2);
+		request(&mut cells, 0, CellIndex(1));
+		cells[CellIndex(1)].begin_eval();
+
+		cells.eval_chunk(0, log("one"), false, 10);
+		cells.eval_chunk(0, log("two"), false, 10);
+		assert_eq!(shown_results(&cells[CellIndex(1)]), ["one", "two"]);
+		assert!(cells.is_running());
+
+		cells.eval_complete(0, vec![log("done")], 10);
+		assert_eq!(cells[CellIndex(1)].history.len(), 1);
+		assert_eq!(shown_results(&cells[CellIndex(1)]), ["one", "two", "done"]);
+		assert!(!cells.is_running());
+
+		// a final chunk ends the evaluation without a response
+		request(&mut cells, 1, CellIndex(1));
+		cells[CellIndex(1)].begin_eval();
+		cells.eval_chunk(1, log("last"), true, 10);
+		assert_eq!(cells[CellIndex(1)].history.len(), 2);
+		assert_eq!(shown_results(&cells[CellIndex(1)]), ["last"]);
+		assert!(!cells.is_running());
+	}
+
+	#[test]
+	fn overlapping_evals_keep_their_inputs() {
+		let mut cells = multiple(1);
+
+		cells[CellIndex(0)].input_state.set_text("first");
+		cells[CellIndex(0)].begin_eval();
+		request(&mut cells, 0, CellIndex(0));
+		cells.request_sent(CellIndex(0), 0);
+
+		cells[CellIndex(0)].input_state.set_text("second");
+		cells[CellIndex(0)].begin_eval();
+		request(&mut cells, 1, CellIndex(0));
+		cells.request_sent(CellIndex(0), 1);
+
+		cells.eval_complete(0, vec![log("1")], 10);
+		assert!(cells.is_running());
+		cells.eval_complete(1, vec![log("2")], 10);
+		assert!(!cells.is_running());
+
+		let inputs = cells[CellIndex(0)]
+			.history
+			.iter()
+			.map(|entry| entry.input.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(inputs, ["first", "second"]);
+	}
+
+	#[test]
+	fn single_cells_ignore_other_calls() {
+		let mut cells = Cells::Single(Box::default());
+		cells[CellIndex(0)].begin_eval();
+		cells.request_sent(CellIndex(0), 4);
+		assert_eq!(cells.running_seq(CellIndex(0)), Some(4));
+
+		// the answer to cancelling the evaluation
+		cells.eval_complete(5, vec![], 10);
+		assert!(cells.is_running());
+
+		cells.eval_complete(4, vec![log("cancelled")], 10);
+		assert_eq!(shown_results(&cells[CellIndex(0)]), ["cancelled"]);
+		assert_eq!(cells.running_seq(CellIndex(0)), None);
+	}
+
+	#[test]
+	fn clear_cell_results_leaves_other_cells() {
+		let mut cells = multiple(2);
+
+		request(&mut cells, 0, CellIndex(0));
+		request(&mut cells, 1, CellIndex(1));
+		cells.eval_complete(0, vec![log("first")], 10);
+		cells[CellIndex(0)].begin_eval();
+		request(&mut cells, 2, CellIndex(0));
+
+		cells.clear_cell_results(CellIndex(0));
+		assert!(shown_results(&cells[CellIndex(0)]).is_empty());
+		assert!(!cells[CellIndex(0)].is_running());
+
+		cells.eval_complete(2, vec![log("late")], 10);
+		cells.eval_complete(1, vec![log("second")], 10);
+		assert!(shown_results(&cells[CellIndex(0)]).is_empty());
+		assert_eq!(shown_results(&cells[CellIndex(1)]), ["second"]);
+	}
+
+	#[test]
+	fn clear_results_empties_every_cell() {
+		let mut cells = multiple(3);
+
+		request(&mut cells, 0, CellIndex(0));
+		request(&mut cells, 1, CellIndex(1));
+		cells.eval_complete(0, vec![log("first")], 10);
+		cells[CellIndex(2)].append_result(log("log"));
+
+		cells[CellIndex(1)].begin_eval();
+		request(&mut cells, 2, CellIndex(1));
+
+		cells.clear_results();
+
+		for i in 0..3 {
+			assert!(shown_results(&cells[CellIndex(i)]).is_empty());
+		}
+		assert!(!cells.is_running());
+
+		// Results of evaluations that were in progress are dropped
+		cells.eval_complete(1, vec![log("late")], 10);
+		cells.eval_complete(2, vec![log("later")], 10);
+		for i in 0..3 {
+			assert!(shown_results(&cells[CellIndex(i)]).is_empty());
+		}
+	}
+
+	#[test]
+	fn eval_selection_sends_selected_code() {
+		let mut cell = Cell::with_contents("let x = 1;\nx + 1");
+
+		assert_eq!(cell.begin_eval_selection(), "let x = 1;\nx + 1");
+
+		cell.input_state.set_selection(11, 16);
+		assert_eq!(cell.begin_eval_selection(), "x + 1");
+
+		cell.push_results(0, vec![log("2")], 10);
+		assert_eq!(cell.history.back().unwrap().input, "x + 1");
+		assert_eq!(shown_results(&cell), ["2"]);
+	}
+
+	#[test]
+	fn category_colors() {
+		let mut config = Config::default();
+		let trace = Color::from_rgb(0.5, 0.5, 0.5);
+		config
+			.editor_colors
+			.categories
+			.insert("trace".to_owned(), trace);
+
+		let colors = &config.editor_colors;
+		assert_eq!(category_color(&config, "info"), colors.info);
+		assert_eq!(category_color(&config, "warning"), colors.warnings);
+		assert_eq!(category_color(&config, "trace"), trace);
+		assert_eq!(category_color(&config, "unknown"), colors.main);
+	}
+
+	#[test]
+	fn insert_previous_result_at_caret() {
+		let mut cells = multiple(2);
+		request(&mut cells, 0, CellIndex(0));
+		cells.eval_complete(0, vec![log("1"), log("2")], 10);
+		cells[CellIndex(1)].input_state.set_text("x = ");
+
+		cells.insert_previous_result(CellIndex(1));
+		assert_eq!(cells.contents()[1], "x = 1\n2");
+
+		cells.insert_previous_result(CellIndex(0));
+		assert_eq!(cells.contents()[0], "");
+	}
+
+	#[test]
+	fn picked_completions_insert_at_caret() {
+		let completion = |label: &str| Completion {
+			label: label.to_owned(),
+			insert_text: format!("{}()", label),
+			detail: None,
+		};
+
+		let mut cell = Cell::from_template(Some("v.${cursor};"));
+		assert_eq!(cell.begin_complete(), ("v.;".to_owned(), 2));
+
+		cell.show_completions(vec![completion("len"), completion("iter")]);
+		cell.move_completion_selection(-1);
+		assert_eq!(cell.selected_completion, 1);
+		cell.move_completion_selection(1);
+		assert_eq!(cell.selected_completion, 0);
+		cell.move_completion_selection(1);
+		cell.pick_completion(cell.selected_completion);
+		assert_eq!(cell.contents(), "v.iter();");
+		assert_eq!(cell.input_state.caret_offset(), 8);
+		assert!(cell.completions.is_empty());
+
+		// completions arriving after the list was dismissed aren't shown
+		cell.begin_complete();
+		cell.dismiss_completions();
+		cell.show_completions(vec![completion("len")]);
+		assert!(cell.completions.is_empty());
+	}
+
+	#[test]
+	fn edits_after_saving_are_unsaved() {
+		let mut cell = Cell::with_contents("1 + 1");
+		assert!(!cell.has_unsaved_changes());
+
+		cell.saved("sum.txt".into(), cell.contents());
+		assert!(!cell.has_unsaved_changes());
+		assert_eq!(cell.file_path(), Some(Path::new("sum.txt")));
+
+		cell.insert_text(" + 1");
+		assert!(cell.has_unsaved_changes());
+
+		cell.load("two.txt".into(), "2".to_owned());
+		assert_eq!(cell.contents(), "2");
+		assert!(!cell.has_unsaved_changes());
+	}
+
+	#[test]
+	fn new_cells_start_from_template() {
+		let mut cells = multiple(1);
+		if let Cells::Multiple { cell_template, .. } = &mut cells {
+			*cell_template = Some("fn main() {\n\t${cursor}\n}".to_owned());
+		}
+
+		cells.new_cell();
+
+		let tab_width = Config::default().text_settings.tab_width;
+		let cell = &cells[CellIndex(1)];
+		assert_eq!(cell.contents(), "fn main() {\n\t\n}");
+		assert_eq!(cell.input_state.caret_line_column(tab_width), (1, 4));
+
+		let cell = Cell::from_template(Some("print()"));
+		assert_eq!(cell.input_state.caret_line_column(tab_width), (0, 7));
+		assert_eq!(Cell::from_template(None).contents(), "");
+	}
+
+	#[test]
+	fn errors_are_marked_at_their_locations() {
+		let input = editor::State::with_text("let x = 1;\nlet y = z;");
+		let error = |line, column| {
+			EvalResult::Error(EvalMessage {
+				text: "error".to_owned(),
+				location: Some(Location {
+					line,
+					column,
+					path: None,
+				}),
+				category: None,
+				content: None,
+			})
+		};
+		let results = [
+			log("no location"),
+			EvalResult::Compound(CompoundResult {
+				summary: EvalMessage {
+					text: "1 error".to_owned(),
+					location: None,
+					category: None,
+					content: None,
+				},
+				successes: vec![],
+				warnings: vec![],
+				errors: vec![error(2, 9)],
+			}),
+			error(1, 100),
+			// in another file
+			EvalResult::Error(EvalMessage {
+				text: "error".to_owned(),
+				location: Some(Location {
+					line: 1,
+					column: 1,
+					path: Some("lib.rs".into()),
+				}),
+				category: None,
+				content: None,
+			}),
+		];
+
+		let mut marks = vec![];
+		error_marks(&input, &results, &mut marks);
+
+		assert_eq!(marks, [(19, MarkKind::Error), (10, MarkKind::Error)]);
+	}
+
+	#[test]
+	fn shown_results_are_capped() {
+		let config = Config::default();
+		assert_eq!(config.eval_settings.max_shown_results, 200);
+
+		// one row per result, and one to show the rest
+		let rendered = |cell: &mut Cell| {
+			results_view(
+				&config,
+				&cell.history[cell.selected_history].results,
+				cell.show_all_results,
+				&cell.collapsed_levels,
+				&mut cell.result_group_button_states,
+				&mut cell.result_button_states,
+				&mut cell.show_all_button_state,
+				&mut cell.images,
+				None,
+				TabId::default(),
+				CellIndex(0),
+			)
+			.len()
+		};
+
+		let mut cell = Cell::default();
+		cell.begin_eval();
+		cell.push_results(
+			0,
+			(0..1000).map(|i| log(&i.to_string())).collect(),
+			10,
+		);
+		assert_eq!(rendered(&mut cell), 201);
+
+		cell.show_all_results();
+		assert_eq!(rendered(&mut cell), 1000);
+
+		// new results are capped again
+		cell.begin_eval();
+		cell.push_results(
+			0,
+			(0..300).map(|i| log(&i.to_string())).collect(),
+			10,
+		);
+		assert_eq!(rendered(&mut cell), 201);
+	}
+
+	#[test]
+	fn compound_results_render_nested_results() {
+		let message = |text: &str| EvalMessage {
+			text: text.to_owned(),
+			location: None,
+			category: None,
+			content: None,
+		};
+		let report = EvalResult::Compound(CompoundResult {
+			summary: message("2 passed, 1 failed"),
+			successes: vec![log("a"), log("b")],
+			warnings: vec![],
+			errors: vec![EvalResult::Compound(CompoundResult {
+				summary: message("c"),
+				successes: vec![],
+				warnings: vec![],
+				errors: vec![EvalResult::Error(message("assertion failed"))],
+			})],
+		});
+
+		let config = Config::default();
+		let mut cell = Cell::default();
+		cell.begin_eval();
+		cell.push_results(0, vec![report, log("flat")], 10);
+
+		let rows = results_view(
+			&config,
+			&cell.history[0].results,
+			false,
+			&cell.collapsed_levels,
+			&mut cell.result_group_button_states,
+			&mut cell.result_button_states,
+			&mut cell.show_all_button_state,
+			&mut cell.images,
+			None,
+			TabId::default(),
+			CellIndex(0),
+		)
+		.len();
+
+		// a header for errors, the report with its results inside it, and the
+		// flat result
+		assert_eq!(rows, 3);
+		// buttons for each result, however deeply it's nested
+		assert_eq!(cell.result_button_states.len(), 6);
+	}
+
+	#[test]
+	fn snippet_contains_code_and_results() {
+		let mut cell = Cell::with_contents("let x = 1;\nx + 1");
+		cell.begin_eval();
+		cell.push_results(
+			0,
+			vec![
+				log("2"),
+				EvalResult::Compound(CompoundResult {
+					summary: EvalMessage {
+						text: "1 warning".to_owned(),
+						location: None,
+						category: None,
+						content: None,
+					},
+					successes: vec![],
+					warnings: vec![log("unused variable")],
+					errors: vec![],
+				}),
+			],
+			10,
+		);
+
+		let snippet = cell.snippet(Language::find("rust"));
+		assert!(snippet.starts_with("```rust\nlet x = 1;\nx + 1\n```\n"));
+		for line in ["2", "1 warning", "  unused variable"] {
+			assert!(
+				snippet.lines().any(|l| l == line),
+				"{:?} missing from {:?}",
+				line,
+				snippet,
+			);
+		}
+
+		// fences are lengthened to enclose backticks in the code
+		let cell = Cell::with_contents("```");
+		assert!(cell.snippet(None).starts_with("````\n```\n````"));
+	}
+}
@@ -0,0 +1,124 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use iced_native::{
+	event, layout, mouse, renderer, Clipboard, Element, Event, Layout, Length,
+	Point, Rectangle, Shell, Widget,
+};
+
+/// Wraps an element, producing messages when the left mouse button is pressed
+/// or released over it, so it can be dragged onto another `DragHandle`.
+///
+/// Events are still passed to the wrapped element, so buttons inside it keep
+/// working.
+pub(crate) struct DragHandle<'a, Message, Renderer> {
+	content: Element<'a, Message, Renderer>,
+	on_press: Message,
+	on_release: Message,
+}
+
+impl<'a, Message, Renderer> DragHandle<'a, Message, Renderer> {
+	pub(crate) fn new(
+		content: impl Into<Element<'a, Message, Renderer>>,
+		on_press: Message,
+		on_release: Message,
+	) -> Self {
+		Self {
+			content: content.into(),
+			on_press,
+			on_release,
+		}
+	}
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer>
+	for DragHandle<'a, Message, Renderer>
+where
+	Message: Clone,
+	Renderer: iced_native::Renderer,
+{
+	fn width(&self) -> Length {
+		self.content.width()
+	}
+
+	fn height(&self) -> Length {
+		self.content.height()
+	}
+
+	fn layout(
+		&self,
+		renderer: &Renderer,
+		limits: &layout::Limits,
+	) -> layout::Node {
+		self.content.layout(renderer, limits)
+	}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		renderer: &Renderer,
+		clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> event::Status {
+		if layout.bounds().contains(cursor_position) {
+			match event {
+				Event::Mouse(mouse::Event::ButtonPressed(
+					mouse::Button::Left,
+				)) => shell.publish(self.on_press.clone()),
+				Event::Mouse(mouse::Event::ButtonReleased(
+					mouse::Button::Left,
+				)) => shell.publish(self.on_release.clone()),
+				_ => {}
+			}
+		}
+
+		self.content.on_event(
+			event,
+			layout,
+			cursor_position,
+			renderer,
+			clipboard,
+			shell,
+		)
+	}
+
+	fn mouse_interaction(
+		&self,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		viewport: &Rectangle,
+		renderer: &Renderer,
+	) -> mouse::Interaction {
+		self.content.mouse_interaction(
+			layout,
+			cursor_position,
+			viewport,
+			renderer,
+		)
+	}
+
+	fn draw(
+		&self,
+		renderer: &mut Renderer,
+		style: &renderer::Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		viewport: &Rectangle,
+	) {
+		self.content
+			.draw(renderer, style, layout, cursor_position, viewport)
+	}
+}
+
+impl<'a, Message, Renderer> From<DragHandle<'a, Message, Renderer>>
+	for Element<'a, Message, Renderer>
+where
+	Message: 'a + Clone,
+	Renderer: 'a + iced_native::Renderer,
+{
+	fn from(handle: DragHandle<'a, Message, Renderer>) -> Self {
+		Element::new(handle)
+	}
+}
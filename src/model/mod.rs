@@ -2,25 +2,41 @@
 // Licensed under the Open Software License version 3.0
 
 pub(crate) mod cell;
+pub(crate) mod drag;
+pub(crate) mod popup;
+pub(crate) mod scroll;
 
-use std::{collections::HashMap, fmt, sync::Arc};
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	fmt,
+	sync::Arc,
+	time::Instant,
+};
 
-use evalvana_api::EvalResult;
+use evalvana_api::{Completion, EvalResult};
+use futures::executor::block_on;
 use iced::{
-	alignment, button, scrollable, Button, Column, Container, Element, Length,
-	Row, Rule, Scrollable, Space, Text,
+	alignment, button, image, pick_list, scrollable, text_input,
+	tooltip::{Position, Tooltip},
+	Alignment, Button, Column, Container, Element, Image, Length, PickList,
+	Row, Rule, Scrollable, Space, Text, TextInput,
 };
 use tokio::sync::RwLock;
 
-use self::cell::{Cell, Cells};
+use self::{
+	cell::{Cell, Cells},
+	drag::DragHandle,
+	scroll::HorizontalScrollable,
+};
 use crate::{
 	assets::{
 		font,
 		icons::{self, CLOSE_TAB, EMPTY_TAB},
 	},
 	config::Config,
+	language::Language,
 	message::Message,
-	plugin::{Capabilities, Environment},
+	plugin::{Capabilities, Environment, Plugin},
 	style,
 };
 
@@ -33,6 +49,21 @@ impl fmt::Display for TabIndex {
 	}
 }
 
+/// Identifies a [`Tab`] for as long as it's open, regardless of how other
+/// tabs are opened, closed, or moved.
+///
+/// Messages produced by slow [`Command`](iced::Command)s should refer to tabs
+/// by [`TabId`] rather than [`TabIndex`], as the index may have changed by the
+/// time they're handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TabId(u64);
+
+impl fmt::Display for TabId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Display::fmt(&self.0, f)
+	}
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct CellIndex(pub(crate) usize);
 
@@ -44,8 +75,34 @@ impl fmt::Display for CellIndex {
 
 #[derive(Debug)]
 pub(crate) struct Tab {
-	pub(crate) env: Arc<RwLock<Environment>>,
-	plugin_name: Arc<str>,
+	id: TabId,
+	/// The environment code is evaluated in, or `None` for a scratch tab,
+	/// which is only for editing text.
+	pub(crate) env: Option<Arc<RwLock<Environment>>>,
+	plugin_name: Option<Arc<str>>,
+	/// The language of this tab, which picks the file extensions shown when
+	/// saving and loading cells, and how snippets are fenced.
+	language: Option<&'static Language>,
+	/// The prefix of comments running to the end of the line in this tab's
+	/// code, if it's known.
+	line_comment: Option<String>,
+	/// Whether this tab's plugin answers complete calls.
+	supports_completion: bool,
+	/// The `seq` of the latest complete call, and the cell it's for.
+	completion_request: Option<(u32, CellIndex)>,
+	/// Completions that arrived before the `seq` of their complete call was
+	/// recorded, and that `seq`.
+	early_completions: Option<(u32, Vec<Completion>)>,
+	/// Results the plugin sent before anything was evaluated, such as a
+	/// version banner, shown above the cells.
+	banner: Vec<EvalResult>,
+	has_evaluated: bool,
+	/// The cells of a run of every cell that stops at the first error, from
+	/// the one being evaluated to the last.
+	queued_evals: VecDeque<CellIndex>,
+	/// Whether the environment's process has exited, after which nothing can
+	/// be evaluated.
+	exited: bool,
 	tab_button_state: button::State,
 	close_button_state: button::State,
 	pub(crate) cells: Cells,
@@ -53,40 +110,131 @@ pub(crate) struct Tab {
 
 impl Tab {
 	pub(crate) fn new(
+		id: TabId,
 		env: Environment,
 		plugin_capabilities: Capabilities,
 	) -> Self {
 		let plugin_name = env.plugin_name.clone();
+		let language = env.language;
+
+		let line_comment = plugin_capabilities
+			.line_comment
+			.or_else(|| language_line_comment(language));
+		let supports_completion = plugin_capabilities.completion;
+		let template = plugin_capabilities.cell_template;
+		let first_cell = Cell::from_template(template.as_deref());
 
 		let cells = if plugin_capabilities.multiple_cells {
 			Cells::Multiple {
-				cells: vec![Cell::default()],
+				cells: vec![first_cell],
 				scrollable_state: scrollable::State::new(),
 				new_cell_button_state: button::State::new(),
+				clear_all_button_state: button::State::new(),
+				eval_all_button_state: button::State::new(),
 				in_flight_requests: HashMap::new(),
+				focused: 0,
+				cell_template: template,
 			}
 		} else {
-			Cells::Single(Cell::default())
+			Cells::Single(Box::new(first_cell))
 		};
 
 		Self {
-			env: Arc::new(RwLock::new(env)),
-			plugin_name,
+			id,
+			env: Some(Arc::new(RwLock::new(env))),
+			plugin_name: Some(plugin_name),
+			language,
+			line_comment,
+			supports_completion,
+			completion_request: None,
+			early_completions: None,
+			banner: vec![],
+			has_evaluated: false,
+			queued_evals: VecDeque::new(),
+			exited: false,
 			tab_button_state: button::State::new(),
 			close_button_state: button::State::new(),
 			cells,
 		}
 	}
 
+	/// Creates a scratch tab, which has a single cell for editing text
+	/// without a plugin to evaluate it.
+	pub(crate) fn scratch(
+		id: TabId,
+		language: Option<&'static Language>,
+	) -> Self {
+		Self {
+			id,
+			env: None,
+			plugin_name: None,
+			language,
+			line_comment: language_line_comment(language),
+			supports_completion: false,
+			completion_request: None,
+			early_completions: None,
+			banner: vec![],
+			has_evaluated: false,
+			queued_evals: VecDeque::new(),
+			exited: false,
+			tab_button_state: button::State::new(),
+			close_button_state: button::State::new(),
+			cells: Cells::Single(Box::default()),
+		}
+	}
+
+	pub(crate) fn id(&self) -> TabId {
+		self.id
+	}
+
+	/// Returns the name of the plugin of this tab, or `None` if it's a
+	/// scratch tab.
+	pub(crate) fn plugin_name(&self) -> Option<&Arc<str>> {
+		self.plugin_name.as_ref()
+	}
+
+	pub(crate) fn language(&self) -> Option<&'static Language> {
+		self.language
+	}
+
+	/// Returns whether this tab's environment has the given ID.
+	pub(crate) fn is_for_env(&self, env_id: &str) -> bool {
+		self.env
+			.as_ref()
+			.is_some_and(|env| *block_on(env.read()).id == *env_id)
+	}
+
+	pub(crate) fn mark_exited(&mut self) {
+		self.exited = true;
+	}
+
+	pub(crate) fn has_exited(&self) -> bool {
+		self.exited
+	}
+
 	pub(crate) fn view<'s>(
 		&'s mut self,
 		config: &Config,
 		is_active: bool,
 		index: TabIndex,
+		handle_width: u16,
+		copied_text: Option<&str>,
 	) -> (Element<'s, Message>, Option<Element<'s, Message>>) {
 		let text_size = config.text_settings.ui_font_size;
 		let tab_button = {
-			let label = Text::new(&*self.plugin_name)
+			let name = self.plugin_name.as_deref().unwrap_or("Scratch");
+			let label = match self.language {
+				Some(language) => {
+					format!("{} ({})", name, language.display_name)
+				}
+				None => name.to_owned(),
+			};
+			let label = if self.has_exited() {
+				format!("{} (exited)", label)
+			} else {
+				label
+			};
+			let label = Text::new(label)
 				.color(if is_active {
 					config.ui_colors.text
 				} else {
@@ -106,6 +254,7 @@ impl Tab {
 			]);
 
 			let button = Button::new(&mut self.tab_button_state, row)
+				.width(Length::Fill)
 				.height(Length::Fill)
 				.style(style::button::tab_handle(config));
 
@@ -130,10 +279,48 @@ impl Tab {
 
 		let handle =
 			Row::with_children(vec![tab_button.into(), close_button.into()])
-				.into();
+				.width(Length::Units(handle_width));
+		let handle = DragHandle::new(
+			handle,
+			Message::DragTab(index),
+			Message::DropTab(index),
+		)
+		.into();
 
 		let contents = if is_active {
-			Some(self.cells.view(config, index))
+			let cells = self.cells.view(
+				config,
+				self.id,
+				self.env.is_some(),
+				self.line_comment.as_deref(),
+				copied_text,
+			);
+
+			if self.banner.is_empty() {
+				Some(cells)
+			} else {
+				let banner = self
+					.banner
+					.iter()
+					.map(|result| result.message().text.as_str())
+					.collect::<Vec<_>>()
+					.join("\n");
+				let banner = Text::new(banner)
+					.size(config.text_settings.editor_font_size)
+					.color(config.ui_colors.unfocused_text)
+					.font(font::MONO);
+				let banner = Container::new(banner)
+					.style(style::container::ui_bg(config))
+					.padding(20)
+					.width(Length::Fill);
+
+				let divider = Rule::horizontal(1)
+					.style(style::rule::cell_divider(config, 1));
+
+				Some(
+					Column::new().push(banner).push(divider).push(cells).into(),
+				)
+			}
 		} else {
 			None
 		};
@@ -142,6 +329,8 @@ impl Tab {
 	}
 
 	pub(crate) fn request_in_flight(&mut self, cell: CellIndex, seq: u32) {
+		self.cells.request_sent(cell, seq);
+
 		if let Cells::Multiple {
 			cells,
 			in_flight_requests,
@@ -154,35 +343,185 @@ impl Tab {
 		}
 	}
 
-	pub(crate) fn eval_complete(&mut self, seq: u32, results: Vec<EvalResult>) {
-		match &mut self.cells {
-			Cells::Single(cell) => cell.results = results,
-			Cells::Multiple {
-				cells,
-				in_flight_requests,
-				..
-			} => {
-				if let Some(cell) = in_flight_requests
-					.get(&seq)
-					.and_then(|&CellIndex(i)| cells.get_mut(i))
-				{
-					cell.results = results;
-				}
+	/// Returns the code of `cell` to evaluate; see [`Cell::begin_eval`].
+	pub(crate) fn begin_eval(&mut self, cell: CellIndex) -> String {
+		self.has_evaluated = true;
+		self.cells[cell].begin_eval()
+	}
+
+	/// Returns the selected code of `cell` to evaluate; see
+	/// [`Cell::begin_eval_selection`].
+	pub(crate) fn begin_eval_selection(&mut self, cell: CellIndex) -> String {
+		self.has_evaluated = true;
+		self.cells[cell].begin_eval_selection()
+	}
+
+	/// Returns the code of every cell to evaluate, in order; see
+	/// [`Cell::begin_eval`].
+	pub(crate) fn begin_eval_all(&mut self) -> Vec<(CellIndex, String)> {
+		self.has_evaluated = true;
+		self.queued_evals.clear();
+
+		self.cells
+			.indices()
+			.map(|cell| (cell, self.cells[cell].begin_eval()))
+			.collect()
+	}
+
+	/// Evaluates every cell in order, one at a time, stopping at the first
+	/// that fails. Returns the first cell, which should be evaluated now.
+	pub(crate) fn queue_eval_all(&mut self) -> Option<CellIndex> {
+		self.queued_evals = self.cells.indices().collect();
+		self.queued_evals.front().copied()
+	}
+
+	/// Returns the next cell to evaluate after the one being evaluated
+	/// finishes, if every cell is being evaluated one at a time.
+	///
+	/// The remaining cells are dropped if that cell fails.
+	pub(crate) fn next_queued_eval(&mut self) -> Option<CellIndex> {
+		let &current = self.queued_evals.front()?;
+
+		match self.cells.failed(current) {
+			None => return None,
+			Some(true) => self.queued_evals.clear(),
+			Some(false) => {
+				self.queued_evals.pop_front();
 			}
 		}
+
+		self.queued_evals.front().copied()
+	}
+
+	/// Stops evaluating the remaining cells of a run of every cell.
+	pub(crate) fn cancel_queued_evals(&mut self) {
+		self.queued_evals.clear();
+	}
+
+	/// Returns the code of `cell` to complete and the offset of its caret,
+	/// or `None` if this tab's plugin doesn't support completion.
+	pub(crate) fn begin_complete(
+		&mut self,
+		cell: CellIndex,
+	) -> Option<(String, usize)> {
+		if !self.supports_completion {
+			return None;
+		}
+
+		self.completion_request = None;
+		self.early_completions = None;
+		Some(self.cells[cell].begin_complete())
+	}
+
+	/// Records that completions of `cell` were requested in the complete call
+	/// with the given `seq`, so that those of earlier calls are ignored.
+	///
+	/// If the plugin already answered the call, its completions are shown.
+	pub(crate) fn completion_requested(&mut self, cell: CellIndex, seq: u32) {
+		match self.early_completions.take() {
+			Some((early_seq, completions)) if early_seq == seq => {
+				self.cells.show_completions(cell, completions);
+			}
+			_ => self.completion_request = Some((seq, cell)),
+		}
+	}
+
+	/// Shows completions answering the complete call with the given `seq`
+	/// below the caret of the cell they're for, if it's the latest call.
+	///
+	/// Completions may arrive before their call is recorded, in which case
+	/// they're kept until it is.
+	pub(crate) fn completions_ready(
+		&mut self,
+		seq: u32,
+		completions: Vec<Completion>,
+	) {
+		match self.completion_request {
+			Some((request_seq, cell)) if request_seq == seq => {
+				self.completion_request = None;
+				self.cells.show_completions(cell, completions);
+			}
+			Some((request_seq, _)) if request_seq > seq => {}
+			_ => self.early_completions = Some((seq, completions)),
+		}
+	}
+
+	/// Adds a result that wasn't requested by an evaluation.
+	///
+	/// Until something is evaluated, these results make up the banner of the
+	/// tab. After that, they're added to the latest history entry of the
+	/// cell that was most recently evaluated.
+	pub(crate) fn append_result(&mut self, result: EvalResult) {
+		if self.has_evaluated {
+			let cell = self.cells.output_cell();
+			self.cells[cell].append_result(result);
+		} else {
+			self.banner.push(result);
+		}
+	}
+
+	pub(crate) fn eval_complete(
+		&mut self,
+		seq: u32,
+		results: Vec<EvalResult>,
+		history_len: usize,
+	) {
+		self.cells.eval_complete(seq, results, history_len);
+	}
+
+	pub(crate) fn eval_chunk(
+		&mut self,
+		seq: u32,
+		result: EvalResult,
+		is_final: bool,
+		history_len: usize,
+	) {
+		self.cells.eval_chunk(seq, result, is_final, history_len);
 	}
 }
 
+/// The width of each tab handle, in multiples of the UI font size.
+const TAB_HANDLE_EMS: u16 = 12;
+
 #[derive(Debug, Default)]
 pub(crate) struct Tabs {
 	pub(crate) tabs: Vec<Tab>,
 	active_tab: TabIndex,
+	next_id: u64,
+	/// How far the tab handles are scrolled out of view to the left.
+	scroll: scroll::State,
+	/// The width available to the tab bar, or `None` if it's not known yet.
+	width: Option<u32>,
+	scroll_to_active: bool,
+	/// The tab whose handle the mouse was last pressed on, which is moved to
+	/// wherever it's released.
+	dragged: Option<TabIndex>,
+	scroll_left_button_state: button::State,
+	scroll_right_button_state: button::State,
+	/// The text of the result that was last copied, whose copy button says
+	/// so.
+	pub(crate) copied_text: Option<String>,
 }
 
 impl Tabs {
+	/// Returns an ID that no other tab has had.
+	pub(crate) fn new_id(&mut self) -> TabId {
+		let id = TabId(self.next_id);
+		self.next_id += 1;
+		id
+	}
+
 	pub(crate) fn push(&mut self, tab: Tab) {
 		self.tabs.push(tab);
 		self.active_tab = TabIndex(self.tabs.len() - 1);
+		self.scroll_to_active = true;
+	}
+
+	/// Inserts a tab at `index` and makes it active.
+	pub(crate) fn insert(&mut self, index: TabIndex, tab: Tab) {
+		self.tabs.insert(index.0, tab);
+		self.active_tab = index;
+		self.scroll_to_active = true;
 	}
 
 	pub(crate) fn remove(&mut self, index: TabIndex) -> Tab {
@@ -193,12 +532,73 @@ impl Tabs {
 		tab
 	}
 
+	/// Moves the tab at `from` to `to`, shifting the tabs between them over.
+	/// The active tab stays active wherever it ends up.
+	pub(crate) fn move_tab(&mut self, from: TabIndex, to: TabIndex) {
+		if from == to || from.0 >= self.tabs.len() || to.0 >= self.tabs.len() {
+			return;
+		}
+
+		let tab = self.tabs.remove(from.0);
+		self.tabs.insert(to.0, tab);
+
+		let TabIndex(active) = self.active_tab;
+		self.active_tab = if active == from.0 {
+			to
+		} else if from.0 < active && active <= to.0 {
+			TabIndex(active - 1)
+		} else if to.0 <= active && active < from.0 {
+			TabIndex(active + 1)
+		} else {
+			self.active_tab
+		};
+		self.scroll_to_active = true;
+	}
+
+	/// Starts dragging the tab at `index`.
+	pub(crate) fn drag(&mut self, index: TabIndex) {
+		self.dragged = Some(index);
+	}
+
+	/// Stops dragging a tab, returning its index if one was being dragged.
+	pub(crate) fn take_dragged(&mut self) -> Option<TabIndex> {
+		self.dragged.take()
+	}
+
+	/// Returns how many tabs are open.
+	pub(crate) fn len(&self) -> usize {
+		self.tabs.len()
+	}
+
 	pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Tab> {
 		self.tabs.iter_mut()
 	}
 
-	pub(crate) fn get_mut(&mut self, index: TabIndex) -> Option<&mut Tab> {
-		self.tabs.get_mut(index.0)
+	/// Returns whether any cell of any tab is waiting for results.
+	pub(crate) fn is_evaluating(&self) -> bool {
+		self.tabs.iter().any(|tab| tab.cells.is_running())
+	}
+
+	/// Returns whether a selection is being dragged past the edge of an
+	/// input in any tab.
+	pub(crate) fn is_auto_scrolling(&self) -> bool {
+		self.tabs.iter().any(|tab| tab.cells.is_auto_scrolling())
+	}
+
+	/// Returns when the caret of the focused input in any tab last moved, if
+	/// an input is focused.
+	pub(crate) fn last_cursor_move(&self) -> Option<Instant> {
+		self.tabs
+			.iter()
+			.find_map(|tab| tab.cells.last_cursor_move())
+	}
+
+	pub(crate) fn get_mut(&mut self, id: TabId) -> Option<&mut Tab> {
+		self.tabs.iter_mut().find(|tab| tab.id == id)
+	}
+
+	pub(crate) fn active_index(&self) -> Option<TabIndex> {
+		Some(self.active_tab).filter(|&TabIndex(i)| i < self.tabs.len())
 	}
 
 	pub(crate) fn set_active(&mut self, index: TabIndex) {
@@ -210,6 +610,53 @@ impl Tabs {
 			);
 		}
 		self.active_tab = index;
+		self.scroll_to_active = true;
+	}
+
+	pub(crate) fn set_width(&mut self, width: u32) {
+		self.width = Some(width);
+		self.scroll_to_active = true;
+	}
+
+	/// Scrolls the tab bar by `delta` tab handles, to the right if positive.
+	pub(crate) fn scroll(&mut self, delta: isize, config: &Config) {
+		let offset = self.scroll.offset()
+			+ delta as f32 * spaced_handle_width(config) as f32;
+		self.scroll.scroll_to(offset);
+	}
+
+	/// Returns the width of the tab bar left between the chevrons if the tab
+	/// handles overflow it, after keeping the scroll offset within the
+	/// handles and scrolling to the active tab if it was switched to.
+	fn update_scroll(&mut self, config: &Config) -> Option<f32> {
+		let handle_width = spaced_handle_width(config) as f32;
+		let handles_width = handle_width * self.tabs.len() as f32;
+
+		let visible_width = match self.width {
+			Some(width) if (width as f32) < handles_width => {
+				(width as f32 - 2.0 * f32::from(chevron_width(config))).max(0.0)
+			}
+			_ => {
+				self.scroll.scroll_to(0.0);
+				self.scroll_to_active = false;
+				return None;
+			}
+		};
+
+		let mut offset = self.scroll.offset();
+		if std::mem::take(&mut self.scroll_to_active) {
+			let start = handle_width * self.active_tab.0 as f32;
+			let end = start + handle_width;
+			if start < offset {
+				offset = start;
+			} else if end > offset + visible_width {
+				offset = end - visible_width;
+			}
+		}
+		self.scroll
+			.scroll_to(offset.min(handles_width - visible_width));
+
+		Some(visible_width)
 	}
 
 	pub(crate) fn view<'s>(
@@ -230,14 +677,27 @@ impl Tabs {
 				.into();
 		}
 
+		let visible_width = self.update_scroll(config);
+		let offset = self.scroll.offset();
+		let handles_width =
+			spaced_handle_width(config) as f32 * self.tabs.len() as f32;
+		let handle_width = config.text_settings.ui_font_size * TAB_HANDLE_EMS;
+
 		let active_tab = self.active_tab;
+		let copied_text = self.copied_text.as_deref();
 		let mut content = None;
 		let last_tab = self.tabs.len() - 1;
 		let handles = self.tabs.iter_mut().enumerate().fold(
 			Row::new().height(Length::Fill),
 			|row, (i, tab)| {
 				let i = TabIndex(i);
-				let (handle, contents) = tab.view(config, i == active_tab, i);
+				let (handle, contents) = tab.view(
+					config,
+					i == active_tab,
+					i,
+					handle_width,
+					copied_text,
+				);
 
 				if i == active_tab {
 					content = contents;
@@ -261,6 +721,34 @@ impl Tabs {
 			},
 		);
 
+		let handles = HorizontalScrollable::new(&mut self.scroll, handles);
+
+		let handles: Element<_> = match visible_width {
+			Some(visible_width) => {
+				let left = chevron_button(
+					config,
+					&mut self.scroll_left_button_state,
+					'‹',
+					(offset > 0.0).then(|| Message::ScrollTabs(-1)),
+				);
+				let right = chevron_button(
+					config,
+					&mut self.scroll_right_button_state,
+					'›',
+					(offset + visible_width < handles_width)
+						.then(|| Message::ScrollTabs(1)),
+				);
+
+				Row::new()
+					.push(left)
+					.push(handles)
+					.push(right)
+					.height(Length::Fill)
+					.into()
+			}
+			None => handles.into(),
+		};
+
 		let handles = Container::new(handles)
 			.style(style::container::secondary_bg(config))
 			.height(Length::Units(config.text_settings.ui_font_size * 3))
@@ -277,6 +765,49 @@ impl Tabs {
 	}
 }
 
+/// Returns the line comment prefix of `language`, if it's known.
+fn language_line_comment(language: Option<&Language>) -> Option<String> {
+	language
+		.and_then(|language| language.line_comment)
+		.map(str::to_owned)
+}
+
+/// Returns the width of each tab handle and the divider after it.
+fn spaced_handle_width(config: &Config) -> u32 {
+	u32::from(config.text_settings.ui_font_size * TAB_HANDLE_EMS) + 1
+}
+
+fn chevron_width(config: &Config) -> u16 {
+	config.text_settings.ui_font_size * 2
+}
+
+fn chevron_button<'s>(
+	config: &Config,
+	state: &'s mut button::State,
+	chevron: char,
+	on_press: Option<Message>,
+) -> Element<'s, Message> {
+	let text = Text::new(chevron)
+		.size(config.text_settings.ui_font_size)
+		.horizontal_alignment(alignment::Horizontal::Center);
+	let text = Container::new(text)
+		.center_x()
+		.center_y()
+		.width(Length::Fill)
+		.height(Length::Fill);
+
+	let button = Button::new(state, text)
+		.width(Length::Units(chevron_width(config)))
+		.height(Length::Fill)
+		.padding(0)
+		.style(style::button::tab_close(config, false));
+
+	match on_press {
+		Some(message) => button.on_press(message).into(),
+		None => button.into(),
+	}
+}
+
 impl std::ops::Index<TabIndex> for Tabs {
 	type Output = Tab;
 
@@ -291,13 +822,40 @@ impl std::ops::IndexMut<TabIndex> for Tabs {
 	}
 }
 
+/// The category of plugins whose manifest doesn't specify one.
+const OTHER_CATEGORY: &str = "Other";
+
 #[derive(Debug, Default)]
 pub(crate) struct Plugins {
 	pub(crate) list: Vec<PluginListing>,
+	pub(crate) filter: String,
+	filter_state: text_input::State,
+	collapsed_categories: HashSet<String>,
+	category_button_states: Vec<button::State>,
+	scratch_button_state: button::State,
+	scratch_language_state: pick_list::State<&'static Language>,
 	scrollable_state: scrollable::State,
 }
 
 impl Plugins {
+	/// Sorts the list by category, then by name, with uncategorized plugins
+	/// last.
+	pub(crate) fn sort(&mut self) {
+		self.list.sort_unstable_by(|a, b| {
+			(a.category == OTHER_CATEGORY, &a.category, &a.display_name).cmp(&(
+				b.category == OTHER_CATEGORY,
+				&b.category,
+				&b.display_name,
+			))
+		});
+	}
+
+	pub(crate) fn toggle_category(&mut self, category: String) {
+		if !self.collapsed_categories.remove(&category) {
+			self.collapsed_categories.insert(category);
+		}
+	}
+
 	pub(crate) fn view<'s>(
 		&'s mut self,
 		config: &Config,
@@ -314,27 +872,140 @@ impl Plugins {
 				.width(Length::Fill)
 		};
 
-		let list = Scrollable::new(&mut self.scrollable_state)
+		let filter_input = TextInput::new(
+			&mut self.filter_state,
+			"Filter",
+			&self.filter,
+			Message::FilterPlugins,
+		)
+		.size(config.text_settings.ui_font_size)
+		.padding(5)
+		.style(style::text_input::Filter::from(config));
+
+		let filter_input = Container::new(filter_input).padding([0, 10]);
+
+		let scratch = {
+			let text = Text::new("+ Scratch tab")
+				.size(config.text_settings.ui_font_size)
+				.color(config.ui_colors.unfocused_text)
+				.font(font::BODY);
+
+			let button = Button::new(&mut self.scratch_button_state, text)
+				.style(style::button::text(config))
+				.padding([5, 10])
+				.on_press(Message::OpenScratch(None));
+
+			// picking a language opens a scratch tab in it
+			let language = PickList::new(
+				&mut self.scratch_language_state,
+				Language::all().collect::<Vec<_>>(),
+				None,
+				|language| Message::OpenScratch(Some(language)),
+			)
+			.placeholder("in...")
+			.text_size(config.text_settings.ui_font_size)
+			.padding([5, 10])
+			.style(style::pick_list::PickList::from(config));
+
+			Row::new()
+				.push(button)
+				.push(language)
+				.align_items(Alignment::Center)
+		};
+
+		let mut list = Scrollable::new(&mut self.scrollable_state)
 			.push(header)
-			.push(Space::with_height(Length::Units(15)));
-		self.list
-			.iter_mut()
-			.fold(list, |list, info| list.push(info.view(config)))
-			.into()
+			.push(Space::with_height(Length::Units(10)))
+			.push(filter_input)
+			.push(Space::with_height(Length::Units(5)))
+			.push(scratch)
+			.push(Space::with_height(Length::Units(10)));
+
+		let query = self.filter.to_lowercase();
+
+		let category_count = self
+			.list
+			.windows(2)
+			.filter(|pair| pair[0].category != pair[1].category)
+			.count() + usize::from(!self.list.is_empty());
+		self.category_button_states
+			.resize_with(category_count, button::State::new);
+
+		let mut header_states = self.category_button_states.iter_mut();
+		let mut rest = &mut self.list[..];
+
+		while let Some(first) = rest.first() {
+			let category = first.category.clone();
+			let len = rest
+				.iter()
+				.take_while(|listing| listing.category == category)
+				.count();
+			let (group, tail) = std::mem::take(&mut rest).split_at_mut(len);
+			rest = tail;
+
+			let header_state = header_states
+				.next()
+				.expect("Too few category button states");
+
+			let mut group = group
+				.iter_mut()
+				.filter(|listing| {
+					listing.display_name.to_lowercase().contains(&query)
+				})
+				.peekable();
+
+			if group.peek().is_none() {
+				continue;
+			}
+
+			let is_collapsed = self.collapsed_categories.contains(&category);
+
+			let marker = if is_collapsed { '▸' } else { '▾' };
+			let text = Text::new(format!("{} {}", marker, category))
+				.size(config.text_settings.ui_font_size)
+				.color(config.ui_colors.unfocused_text)
+				.font(font::BODY);
+
+			let header = Button::new(header_state, text)
+				.style(style::button::text(config))
+				.padding(10)
+				.width(Length::Fill)
+				.on_press(Message::ToggleCategory(category));
+
+			list = list.push(header);
+
+			if !is_collapsed {
+				list =
+					group.fold(list, |list, info| list.push(info.view(config)));
+			}
+		}
+
+		list.into()
 	}
 }
 
 #[derive(Debug)]
 pub(crate) struct PluginListing {
 	pub(crate) name: Arc<str>,
+	pub(crate) display_name: String,
+	pub(crate) category: String,
+	icon: Option<image::Handle>,
 	button_state: button::State,
+	folder_button_state: button::State,
 }
 
 impl PluginListing {
-	pub(crate) fn new(name: Arc<str>) -> Self {
+	pub(crate) fn new(plugin: &Plugin) -> Self {
 		Self {
-			name,
+			name: plugin.name.clone(),
+			display_name: plugin.display_name().to_owned(),
+			category: plugin
+				.category
+				.clone()
+				.unwrap_or_else(|| OTHER_CATEGORY.to_owned()),
+			icon: plugin.icon.clone().map(image::Handle::from_path),
 			button_state: button::State::new(),
+			folder_button_state: button::State::new(),
 		}
 	}
 
@@ -342,21 +1013,302 @@ impl PluginListing {
 		&'s mut self,
 		config: &Config,
 	) -> Element<'s, Message> {
-		let text = Text::new(&*self.name)
-			.size(config.text_settings.ui_font_size)
+		let text_size = config.text_settings.ui_font_size;
+
+		let text = Text::new(&*self.display_name)
+			.size(text_size)
 			.color(config.ui_colors.text);
 
-		let inner = Container::new(text)
+		let contents: Element<_> = match &self.icon {
+			Some(icon) => Row::new()
+				.push(
+					Image::new(icon.clone())
+						.width(Length::Units(text_size * 2)),
+				)
+				.push(text)
+				.spacing(10)
+				.align_items(Alignment::Center)
+				.into(),
+			None => text.into(),
+		};
+
+		let inner = Container::new(contents)
 			.center_y()
 			.padding(10)
 			.height(Length::Fill)
 			.width(Length::Fill);
 
-		Button::new(&mut self.button_state, inner)
+		let open_tab = Button::new(&mut self.button_state, inner)
 			.on_press(Message::OpenTab(self.name.clone()))
 			.style(style::button::primary(config))
 			.height(Length::Units(70))
-			.width(Length::Fill)
-			.into()
+			.width(Length::Fill);
+
+		let folder = {
+			let text = Text::new("…")
+				.size(text_size)
+				.color(config.ui_colors.unfocused_text);
+			let text = Container::new(text).center_y().height(Length::Fill);
+
+			let button = Button::new(&mut self.folder_button_state, text)
+				.on_press(Message::OpenPluginFolder(self.name.clone()))
+				.style(style::button::primary(config))
+				.height(Length::Units(70))
+				.padding([0, 10]);
+
+			Tooltip::new(button, "Open plugin folder", Position::Left)
+				.size(text_size)
+				.padding(5)
+				.style(style::container::ui_bg(config))
+		};
+
+		Row::with_children(vec![open_tab.into(), folder.into()]).into()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use evalvana_api::{EvalMessage, Level};
+
+	use crate::config::EvalSettings;
+
+	use super::*;
+
+	fn open_tab(tabs: &mut Tabs) -> TabId {
+		let mut plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": env!("CARGO"),
+			"args": ["--version"],
+			"capabilities": { "multiple_cells": true },
+		}))
+		.unwrap();
+
+		let (env, _) = plugin.open(&EvalSettings::default()).unwrap();
+		let id = tabs.new_id();
+		tabs.push(Tab::new(id, env, plugin.capabilities.clone()));
+
+		id
+	}
+
+	/// Returns the current index of the tab with the given ID, if it's open.
+	fn index_of(tabs: &Tabs, id: TabId) -> Option<TabIndex> {
+		tabs.tabs.iter().position(|tab| tab.id == id).map(TabIndex)
+	}
+
+	#[test]
+	fn ids_survive_closing_earlier_tabs() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut tabs = Tabs::default();
+		let first = open_tab(&mut tabs);
+		let second = open_tab(&mut tabs);
+
+		// An evaluation is started in the second tab while it's at index 1,
+		// then the first tab is closed before the request is sent.
+		assert_eq!(index_of(&tabs, second), Some(TabIndex(1)));
+		tabs.remove(TabIndex(0));
+
+		assert!(tabs.get_mut(first).is_none());
+		assert_eq!(index_of(&tabs, second), Some(TabIndex(0)));
+
+		let tab = tabs.get_mut(second).expect("Second tab was lost");
+		tab.request_in_flight(CellIndex(0), 0);
+		assert_eq!(tab.cells.output_cell(), CellIndex(0));
+
+		let third = open_tab(&mut tabs);
+		assert_ne!(third, first);
+		assert_eq!(index_of(&tabs, third), Some(TabIndex(1)));
+	}
+
+	#[test]
+	fn moving_tabs_keeps_active_tab() {
+		let mut tabs = Tabs::default();
+		let ids: Vec<_> = (0..4)
+			.map(|_| {
+				let id = tabs.new_id();
+				tabs.push(Tab::scratch(id, None));
+				id
+			})
+			.collect();
+		tabs.set_active(TabIndex(1));
+
+		// Moving the active tab takes the selection with it.
+		tabs.move_tab(TabIndex(1), TabIndex(3));
+		assert_eq!(index_of(&tabs, ids[1]), Some(TabIndex(3)));
+		assert_eq!(tabs.active_index(), Some(TabIndex(3)));
+
+		// Moving another tab past it shifts it over.
+		tabs.move_tab(TabIndex(0), TabIndex(3));
+		assert_eq!(index_of(&tabs, ids[0]), Some(TabIndex(3)));
+		assert_eq!(tabs.active_index(), Some(TabIndex(2)));
+		tabs.move_tab(TabIndex(3), TabIndex(0));
+		assert_eq!(tabs.active_index(), Some(TabIndex(3)));
+
+		// Dropping a tab onto itself changes nothing.
+		tabs.move_tab(TabIndex(3), TabIndex(3));
+		assert_eq!(index_of(&tabs, ids[1]), Some(TabIndex(3)));
+		assert_eq!(tabs.active_index(), Some(TabIndex(3)));
+
+		let order: Vec<_> = tabs.tabs.iter().map(|tab| tab.id).collect();
+		assert_eq!(order, [ids[0], ids[2], ids[3], ids[1]]);
+	}
+
+	#[test]
+	fn results_before_first_eval_form_banner() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut tabs = Tabs::default();
+		let id = open_tab(&mut tabs);
+		let tab = tabs.get_mut(id).unwrap();
+
+		let message = |text: &str| {
+			EvalResult::Success(EvalMessage {
+				text: text.to_owned(),
+				location: None,
+				category: None,
+				content: None,
+			})
+		};
+
+		tab.append_result(message("test 1.0"));
+		tab.append_result(message("type :help for help"));
+		assert_eq!(tab.banner.len(), 2);
+
+		tab.begin_eval(CellIndex(0));
+		tab.append_result(message("log"));
+		assert_eq!(tab.banner.len(), 2);
+	}
+
+	#[test]
+	fn queued_evals_stop_at_first_error() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut tabs = Tabs::default();
+		let id = open_tab(&mut tabs);
+		let tab = tabs.get_mut(id).unwrap();
+		tab.cells.new_cell();
+		tab.cells.new_cell();
+
+		let result = |level| {
+			let message = EvalMessage {
+				text: String::new(),
+				location: None,
+				category: None,
+				content: None,
+			};
+			match level {
+				Level::Error => EvalResult::Error(message),
+				_ => EvalResult::Success(message),
+			}
+		};
+
+		assert_eq!(tab.queue_eval_all(), Some(CellIndex(0)));
+		tab.begin_eval(CellIndex(0));
+		tab.request_in_flight(CellIndex(0), 0);
+		assert_eq!(tab.next_queued_eval(), None);
+
+		tab.eval_complete(0, vec![result(Level::Success)], 10);
+		assert_eq!(tab.next_queued_eval(), Some(CellIndex(1)));
+
+		tab.begin_eval(CellIndex(1));
+		tab.request_in_flight(CellIndex(1), 1);
+		tab.eval_complete(1, vec![result(Level::Error)], 10);
+		assert_eq!(tab.next_queued_eval(), None);
+		assert!(tab.queued_evals.is_empty());
+	}
+
+	#[test]
+	fn scratch_tabs_have_no_environment() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut tabs = Tabs::default();
+		let env_tab = open_tab(&mut tabs);
+		let scratch = tabs.new_id();
+		tabs.push(Tab::scratch(scratch, None));
+
+		let env_id = block_on(
+			tabs.get_mut(env_tab).unwrap().env.as_ref().unwrap().read(),
+		)
+		.id
+		.clone();
+
+		let tab = tabs.get_mut(scratch).unwrap();
+		assert!(tab.plugin_name().is_none());
+		assert!(!tab.is_for_env(&env_id));
+		assert!(tabs.get_mut(env_tab).unwrap().is_for_env(&env_id));
+	}
+
+	#[test]
+	fn completions_arriving_before_their_call_is_recorded_are_shown() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut tabs = Tabs::default();
+		let id = open_tab(&mut tabs);
+		let tab = tabs.get_mut(id).unwrap();
+		tab.supports_completion = true;
+
+		let completions = || {
+			vec![Completion {
+				label: "len".to_owned(),
+				insert_text: "len()".to_owned(),
+				detail: None,
+			}]
+		};
+
+		tab.begin_complete(CellIndex(0));
+		tab.completions_ready(0, completions());
+		assert!(!tab.cells[CellIndex(0)].has_completions());
+		tab.completion_requested(CellIndex(0), 0);
+		assert!(tab.cells[CellIndex(0)].has_completions());
+
+		// those of an earlier call are still ignored
+		tab.begin_complete(CellIndex(0));
+		tab.completions_ready(1, completions());
+		tab.completion_requested(CellIndex(0), 2);
+		assert!(!tab.cells[CellIndex(0)].has_completions());
+		tab.completions_ready(1, completions());
+		assert!(!tab.cells[CellIndex(0)].has_completions());
+		tab.completions_ready(2, completions());
+		assert!(tab.cells[CellIndex(0)].has_completions());
+	}
+
+	#[test]
+	fn overflowing_tabs_scroll_to_the_active_tab() {
+		let config = Config::default();
+		let handle_width = spaced_handle_width(&config) as f32;
+		let handles_width = handle_width * 10.0;
+
+		let mut tabs = Tabs::default();
+		for _ in 0..10 {
+			let id = tabs.new_id();
+			tabs.push(Tab::scratch(id, None));
+		}
+
+		tabs.set_width(handles_width as u32);
+		assert_eq!(tabs.update_scroll(&config), None);
+		assert_eq!(tabs.scroll.offset(), 0.0);
+
+		// the last tab pushed is active
+		tabs.set_width((handle_width * 4.0) as u32);
+		let visible_width = tabs.update_scroll(&config).unwrap();
+		assert_eq!(tabs.scroll.offset(), handles_width - visible_width);
+
+		tabs.set_active(TabIndex(0));
+		tabs.update_scroll(&config);
+		assert_eq!(tabs.scroll.offset(), 0.0);
+
+		tabs.scroll(1, &config);
+		tabs.update_scroll(&config);
+		assert_eq!(tabs.scroll.offset(), handle_width);
+
+		// scrolling stops at the last tab
+		tabs.scroll(100, &config);
+		tabs.update_scroll(&config);
+		assert_eq!(tabs.scroll.offset(), handles_width - visible_width);
 	}
 }
@@ -0,0 +1,268 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use iced_native::{
+	event, keyboard, layout, mouse, overlay, renderer, Clipboard, Element,
+	Event, Layout, Length, Point, Rectangle, Shell, Size, Vector, Widget,
+};
+
+/// Wraps an element, showing another element over everything at a point
+/// relative to its top left, such as a list of completions below a caret.
+///
+/// While the popup is shown, the keys given to [`on_key`](Popup::on_key)
+/// produce their messages instead of reaching the wrapped element.
+pub(crate) struct Popup<'a, Message, Renderer> {
+	content: Element<'a, Message, Renderer>,
+	popup: Option<(Element<'a, Message, Renderer>, Point)>,
+	keys: Vec<(keyboard::KeyCode, Message)>,
+}
+
+impl<'a, Message, Renderer> Popup<'a, Message, Renderer> {
+	pub(crate) fn new(
+		content: impl Into<Element<'a, Message, Renderer>>,
+	) -> Self {
+		Self {
+			content: content.into(),
+			popup: None,
+			keys: vec![],
+		}
+	}
+
+	/// Shows `popup` with its top left at `anchor`, relative to the top left
+	/// of the wrapped element.
+	///
+	/// The popup isn't shown while `anchor` is outside of the wrapped element,
+	/// such as when it's been scrolled out of view.
+	pub(crate) fn popup(
+		mut self,
+		popup: impl Into<Element<'a, Message, Renderer>>,
+		anchor: Point,
+	) -> Self {
+		self.popup = Some((popup.into(), anchor));
+		self
+	}
+
+	/// Produces `message` when `key_code` is pressed without modifiers while
+	/// the popup is shown.
+	pub(crate) fn on_key(
+		mut self,
+		key_code: keyboard::KeyCode,
+		message: Message,
+	) -> Self {
+		self.keys.push((key_code, message));
+		self
+	}
+
+	/// Returns whether the popup is shown over the given layout of the
+	/// wrapped element.
+	fn is_shown(&self, layout: Layout<'_>) -> bool {
+		let bounds = layout.bounds();
+
+		match &self.popup {
+			Some((_, anchor)) => {
+				(0.0..=bounds.width).contains(&anchor.x)
+					&& (0.0..=bounds.height).contains(&anchor.y)
+			}
+			None => false,
+		}
+	}
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer>
+	for Popup<'a, Message, Renderer>
+where
+	Message: Clone,
+	Renderer: iced_native::Renderer,
+{
+	fn width(&self) -> Length {
+		self.content.width()
+	}
+
+	fn height(&self) -> Length {
+		self.content.height()
+	}
+
+	fn layout(
+		&self,
+		renderer: &Renderer,
+		limits: &layout::Limits,
+	) -> layout::Node {
+		self.content.layout(renderer, limits)
+	}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		renderer: &Renderer,
+		clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> event::Status {
+		if let Event::Keyboard(keyboard::Event::KeyPressed {
+			key_code,
+			modifiers,
+		}) = event
+		{
+			let message = self
+				.keys
+				.iter()
+				.find(|(key, _)| *key == key_code)
+				.map(|(_, message)| message);
+
+			match message {
+				Some(message)
+					if modifiers.is_empty() && self.is_shown(layout) =>
+				{
+					shell.publish(message.clone());
+					return event::Status::Captured;
+				}
+				_ => {}
+			}
+		}
+
+		self.content.on_event(
+			event,
+			layout,
+			cursor_position,
+			renderer,
+			clipboard,
+			shell,
+		)
+	}
+
+	fn mouse_interaction(
+		&self,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		viewport: &Rectangle,
+		renderer: &Renderer,
+	) -> mouse::Interaction {
+		self.content.mouse_interaction(
+			layout,
+			cursor_position,
+			viewport,
+			renderer,
+		)
+	}
+
+	fn draw(
+		&self,
+		renderer: &mut Renderer,
+		style: &renderer::Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		viewport: &Rectangle,
+	) {
+		self.content
+			.draw(renderer, style, layout, cursor_position, viewport)
+	}
+
+	fn overlay(
+		&mut self,
+		layout: Layout<'_>,
+		renderer: &Renderer,
+	) -> Option<overlay::Element<'_, Message, Renderer>> {
+		if !self.is_shown(layout) {
+			return self.content.overlay(layout, renderer);
+		}
+
+		let (popup, anchor) = self.popup.as_mut()?;
+		let position = layout.position() + Vector::new(anchor.x, anchor.y);
+
+		Some(overlay::Element::new(
+			position,
+			Box::new(Overlay { content: popup }),
+		))
+	}
+}
+
+impl<'a, Message, Renderer> From<Popup<'a, Message, Renderer>>
+	for Element<'a, Message, Renderer>
+where
+	Message: 'a + Clone,
+	Renderer: 'a + iced_native::Renderer,
+{
+	fn from(popup: Popup<'a, Message, Renderer>) -> Self {
+		Element::new(popup)
+	}
+}
+
+/// The popup of a [`Popup`], kept inside the window.
+struct Overlay<'a, 'b, Message, Renderer> {
+	content: &'b mut Element<'a, Message, Renderer>,
+}
+
+impl<'a, 'b, Message, Renderer> overlay::Overlay<Message, Renderer>
+	for Overlay<'a, 'b, Message, Renderer>
+where
+	Renderer: iced_native::Renderer,
+{
+	fn layout(
+		&self,
+		renderer: &Renderer,
+		bounds: Size,
+		position: Point,
+	) -> layout::Node {
+		let limits = layout::Limits::new(Size::ZERO, bounds);
+		let mut node = self.content.layout(renderer, &limits);
+
+		let size = node.size();
+		node.move_to(Point::new(
+			position.x.min(bounds.width - size.width).max(0.0),
+			position.y.min(bounds.height - size.height).max(0.0),
+		));
+
+		node
+	}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		renderer: &Renderer,
+		clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> event::Status {
+		self.content.on_event(
+			event,
+			layout,
+			cursor_position,
+			renderer,
+			clipboard,
+			shell,
+		)
+	}
+
+	fn mouse_interaction(
+		&self,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		viewport: &Rectangle,
+		renderer: &Renderer,
+	) -> mouse::Interaction {
+		self.content.mouse_interaction(
+			layout,
+			cursor_position,
+			viewport,
+			renderer,
+		)
+	}
+
+	fn draw(
+		&self,
+		renderer: &mut Renderer,
+		style: &renderer::Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+	) {
+		self.content.draw(
+			renderer,
+			style,
+			layout,
+			cursor_position,
+			&layout.bounds(),
+		)
+	}
+}
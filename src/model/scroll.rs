@@ -0,0 +1,217 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use iced_native::{
+	event, layout, mouse, renderer, Clipboard, Element, Event, Layout, Length,
+	Point, Rectangle, Shell, Size, Vector, Widget,
+};
+
+/// How far one line of mouse wheel movement scrolls, in pixels.
+const PIXELS_PER_LINE: f32 = 60.0;
+
+/// How far a [`HorizontalScrollable`] is scrolled.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct State {
+	offset: f32,
+}
+
+impl State {
+	/// Returns how far the content is scrolled to the left, in pixels.
+	pub(crate) fn offset(&self) -> f32 {
+		self.offset
+	}
+
+	/// Scrolls the content `offset` pixels to the left.
+	pub(crate) fn scroll_to(&mut self, offset: f32) {
+		self.offset = offset.max(0.0);
+	}
+}
+
+/// Shows as much of an element as fits in its width, scrolled sideways by
+/// the mouse wheel.
+///
+/// Iced's `Scrollable` only scrolls vertically, which doesn't help rows that
+/// are too wide.
+pub(crate) struct HorizontalScrollable<'a, Message, Renderer> {
+	state: &'a mut State,
+	content: Element<'a, Message, Renderer>,
+}
+
+impl<'a, Message, Renderer> HorizontalScrollable<'a, Message, Renderer> {
+	pub(crate) fn new(
+		state: &'a mut State,
+		content: impl Into<Element<'a, Message, Renderer>>,
+	) -> Self {
+		Self {
+			state,
+			content: content.into(),
+		}
+	}
+
+	/// Returns how far the content is scrolled, given where it is and the
+	/// bounds it's shown in, no further than its right edge.
+	fn offset(&self, bounds: Rectangle, content_bounds: Rectangle) -> f32 {
+		self.state
+			.offset
+			.min((content_bounds.width - bounds.width).max(0.0))
+	}
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer>
+	for HorizontalScrollable<'a, Message, Renderer>
+where
+	Renderer: iced_native::Renderer,
+{
+	fn width(&self) -> Length {
+		Length::Fill
+	}
+
+	fn height(&self) -> Length {
+		self.content.height()
+	}
+
+	fn layout(
+		&self,
+		renderer: &Renderer,
+		limits: &layout::Limits,
+	) -> layout::Node {
+		let limits = limits.width(Length::Fill).height(self.content.height());
+
+		// the content is as wide as it likes, and is cut off when drawn
+		let content_limits = layout::Limits::new(
+			Size::new(0.0, limits.min().height),
+			Size::new(f32::INFINITY, limits.max().height),
+		);
+		let content = self.content.layout(renderer, &content_limits);
+		let size = limits.resolve(content.size());
+
+		layout::Node::with_children(size, vec![content])
+	}
+
+	fn on_event(
+		&mut self,
+		event: Event,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		renderer: &Renderer,
+		clipboard: &mut dyn Clipboard,
+		shell: &mut Shell<'_, Message>,
+	) -> event::Status {
+		let bounds = layout.bounds();
+		let content_layout = layout.children().next().unwrap();
+		let offset = self.offset(bounds, content_layout.bounds());
+
+		let status = self.content.on_event(
+			event.clone(),
+			content_layout,
+			scrolled_cursor(bounds, cursor_position, offset),
+			renderer,
+			clipboard,
+			shell,
+		);
+
+		if status == event::Status::Captured
+			|| !bounds.contains(cursor_position)
+		{
+			return status;
+		}
+
+		match event {
+			Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+				let (x, y) = match delta {
+					mouse::ScrollDelta::Lines { x, y } => {
+						(x * PIXELS_PER_LINE, y * PIXELS_PER_LINE)
+					}
+					mouse::ScrollDelta::Pixels { x, y } => (x, y),
+				};
+				// most mice only have a vertical wheel
+				let delta = if x != 0.0 { x } else { y };
+
+				let max_offset =
+					(content_layout.bounds().width - bounds.width).max(0.0);
+				self.state.offset = (offset - delta).clamp(0.0, max_offset);
+
+				event::Status::Captured
+			}
+			_ => event::Status::Ignored,
+		}
+	}
+
+	fn mouse_interaction(
+		&self,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_viewport: &Rectangle,
+		renderer: &Renderer,
+	) -> mouse::Interaction {
+		let bounds = layout.bounds();
+		let content_layout = layout.children().next().unwrap();
+		let offset = self.offset(bounds, content_layout.bounds());
+
+		self.content.mouse_interaction(
+			content_layout,
+			scrolled_cursor(bounds, cursor_position, offset),
+			&scrolled_viewport(bounds, offset),
+			renderer,
+		)
+	}
+
+	fn draw(
+		&self,
+		renderer: &mut Renderer,
+		style: &renderer::Style,
+		layout: Layout<'_>,
+		cursor_position: Point,
+		_viewport: &Rectangle,
+	) {
+		let bounds = layout.bounds();
+		let content_layout = layout.children().next().unwrap();
+		let offset = self.offset(bounds, content_layout.bounds());
+
+		renderer.with_layer(bounds, |renderer| {
+			renderer.with_translation(Vector::new(-offset, 0.0), |renderer| {
+				self.content.draw(
+					renderer,
+					style,
+					content_layout,
+					scrolled_cursor(bounds, cursor_position, offset),
+					&scrolled_viewport(bounds, offset),
+				)
+			})
+		})
+	}
+}
+
+impl<'a, Message, Renderer> From<HorizontalScrollable<'a, Message, Renderer>>
+	for Element<'a, Message, Renderer>
+where
+	Message: 'a,
+	Renderer: 'a + iced_native::Renderer,
+{
+	fn from(scrollable: HorizontalScrollable<'a, Message, Renderer>) -> Self {
+		Element::new(scrollable)
+	}
+}
+
+/// Returns where the cursor is over content scrolled `offset` pixels to the
+/// left, or somewhere outside of it if the cursor is outside of `bounds`.
+fn scrolled_cursor(
+	bounds: Rectangle,
+	cursor_position: Point,
+	offset: f32,
+) -> Point {
+	if bounds.contains(cursor_position) {
+		Point::new(cursor_position.x + offset, cursor_position.y)
+	} else {
+		Point::new(-1.0, -1.0)
+	}
+}
+
+/// Returns the part of content scrolled `offset` pixels to the left that's
+/// shown in `bounds`.
+fn scrolled_viewport(bounds: Rectangle, offset: f32) -> Rectangle {
+	Rectangle {
+		x: bounds.x + offset,
+		..bounds
+	}
+}
@@ -2,29 +2,72 @@
 // Licensed under the Open Software License version 3.0
 
 use std::{
-	borrow::Cow, cell::Cell, fmt, hash::Hasher, path::PathBuf, process::Stdio,
-	sync::Arc,
+	borrow::Cow,
+	cell::Cell,
+	collections::HashMap,
+	fmt,
+	hash::Hasher,
+	io,
+	path::PathBuf,
+	process::Stdio,
+	sync::{Arc, Mutex},
+	time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context as _, Error, Result};
 use evalvana_api::{
-	EvalResponse, EvalStringArgs, EvalStringCall, RpcMessage, RpcMethodCall,
+	CancelArgs, CancelCall, CompleteArgs, CompleteCall, CompleteResponse,
+	EvalChunkArgs, EvalResponse, EvalStringArgs, EvalStringCall, PluginMessage,
+	RpcMessage, RpcMethodCall, StringOrNumber, EVAL_CHUNK_METHOD,
 };
+use futures::stream;
 use iced_futures::{subscription::Recipe, BoxStream};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use tokio::{
-	io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-	process::{Child, ChildStdout, Command},
+	io::{
+		AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader,
+		BufWriter,
+	},
+	process::{Child, ChildStdin, ChildStdout, Command},
 };
-use tokio_stream::{wrappers::LinesStream, StreamExt};
+use tokio_stream::StreamExt;
+
+use crate::{config::EvalSettings, language::Language};
+
+/// The log target of the messages exchanged with plugins, which are logged
+/// in full: calls at `debug` and each line of output at `trace`. Enable it
+/// with `RUST_LOG=evalvana::rpc=trace`.
+const RPC_LOG_TARGET: &str = "evalvana::rpc";
 
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct Plugin {
 	#[serde(deserialize_with = "deserialize_plugin_name")]
 	pub(crate) name: Arc<str>,
+	/// The name shown to users, if different from `name`.
+	#[serde(default)]
+	pub(crate) display_name: Option<String>,
+	/// An image shown beside the plugin's name, relative to its directory.
+	#[serde(default)]
+	pub(crate) icon: Option<PathBuf>,
+	/// The sidebar section the plugin is listed under, such as "Scripting".
+	#[serde(default)]
+	pub(crate) category: Option<String>,
+	/// The languages the plugin evaluates, such as "rust", which pick the
+	/// file extensions and comment prefix of its tabs.
+	#[serde(default)]
+	pub(crate) languages: Vec<String>,
 	pub(crate) program: PathBuf,
 	pub(crate) args: Vec<String>,
 	pub(crate) capabilities: Capabilities,
+	/// How starting the program is retried when it fails.
+	#[serde(default)]
+	pub(crate) spawn_retry: SpawnRetry,
+	/// How messages to and from the program are separated.
+	#[serde(default)]
+	pub(crate) framing: Framing,
+	/// The directory the plugin was loaded from.
+	#[serde(skip)]
+	pub(crate) dir: PathBuf,
 	#[serde(skip)]
 	env_seq: u32,
 }
@@ -50,138 +93,540 @@ where
 }
 
 impl Plugin {
-	pub(crate) fn open(&mut self) -> Result<(Environment, EnvironmentOutput)> {
+	pub(crate) fn display_name(&self) -> &str {
+		self.display_name.as_deref().unwrap_or(&self.name)
+	}
+
+	/// Starts a new environment of this plugin. Messages longer than
+	/// `max_message_len` bytes are skipped rather than read into memory.
+	pub(crate) fn open(
+		&mut self,
+		settings: &EvalSettings,
+	) -> Result<(Environment, EnvironmentOutput)> {
+		self.resolve_program()?;
+
 		let mut child = Command::new(&self.program)
 			.args(&self.args)
 			.stdin(Stdio::piped())
 			.stdout(Stdio::piped())
 			.stderr(Stdio::piped())
-			.spawn()?;
+			.spawn()
+			.with_context(|| {
+				format!(
+					"Plugin {}: failed to start program {:?}",
+					self.name, self.program
+				)
+			})?;
+
+		let id: Arc<str> = format!("{}/{}", self.name, self.env_seq).into();
+		let call_ids = CallIds::default();
 
 		let output = EnvironmentOutput::new(
+			id.clone(),
+			call_ids.clone(),
 			child
 				.stdout
 				.take()
 				.expect("Plugin child process had no stdout"),
+			settings.max_message_len,
+			self.framing,
+		);
+
+		let input = BufWriter::new(
+			child
+				.stdin
+				.take()
+				.expect("Plugin child process had no stdin"),
 		);
 
 		let env = Environment {
 			plugin_name: self.name.clone(),
-			id: format!("{}/{}", self.name, self.env_seq).into(),
+			language: Language::find_first(&self.languages),
+			id,
 			process: child,
+			input: Some(input),
+			framing: self.framing,
+			send_timeout: settings.send_timeout,
 			call_seq: 0,
+			call_ids,
 		};
 
 		self.env_seq += 1;
 
 		Ok((env, output))
 	}
+
+	/// Checks that the program found when the plugin was loaded still exists,
+	/// looking for it on the `PATH` again if it doesn't.
+	fn resolve_program(&mut self) -> Result<()> {
+		if self.program.is_file() {
+			return Ok(());
+		}
+
+		let found = self
+			.program
+			.file_name()
+			.and_then(|name| which::which(name).ok());
+
+		match found {
+			Some(program) => {
+				self.program = program;
+				Ok(())
+			}
+			// An io::Error, so retry_delay knows not to retry
+			None => Err(io::Error::new(
+				io::ErrorKind::NotFound,
+				format!(
+					"Plugin {}: program {:?} not found; reinstall the plugin",
+					self.name, self.program
+				),
+			)
+			.into()),
+		}
+	}
+
+	/// Returns how long to wait before trying [`Plugin::open`] again, after
+	/// `attempt` previous failures and then `error`, or `None` if it shouldn't
+	/// be retried.
+	///
+	/// Errors that won't go away by themselves, such as the program missing,
+	/// aren't retried.
+	pub(crate) fn retry_delay(
+		&self,
+		attempt: u32,
+		error: &anyhow::Error,
+	) -> Option<Duration> {
+		let permanent = matches!(
+			error.downcast_ref::<io::Error>().map(io::Error::kind),
+			Some(io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied)
+		);
+
+		if permanent || attempt + 1 >= self.spawn_retry.attempts {
+			return None;
+		}
+
+		let factor = 2u64.saturating_pow(attempt);
+		Some(Duration::from_millis(
+			self.spawn_retry.base_delay_ms.saturating_mul(factor),
+		))
+	}
+}
+
+/// How many times, and how quickly, starting a plugin's program is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub(crate) struct SpawnRetry {
+	/// How many times to try starting the program, including the first.
+	pub(crate) attempts: u32,
+	/// How long to wait before the first retry, in milliseconds. The delay
+	/// doubles after each retry.
+	pub(crate) base_delay_ms: u64,
+}
+
+impl Default for SpawnRetry {
+	fn default() -> Self {
+		Self {
+			attempts: 3,
+			base_delay_ms: 100,
+		}
+	}
+}
+
+/// How messages exchanged with a plugin are separated from each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Framing {
+	/// Each message is a single line of JSON.
+	#[default]
+	Lines,
+	/// Each message is preceded by headers like those of the Language Server
+	/// Protocol, including its length in a `Content-Length` header, and may
+	/// contain newlines.
+	ContentLength,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct Capabilities {
 	#[serde(default)]
 	pub(crate) multiple_cells: bool,
+	/// Code that new cells start with. The caret is placed at the first
+	/// `${cursor}` in it, which is removed, or at the end if there is none.
+	#[serde(default)]
+	pub(crate) cell_template: Option<String>,
+	/// The prefix of comments running to the end of the line, which
+	/// Ctrl+/ toggles on the selected lines. Defaults to that of the
+	/// plugin's language, if evalvana knows it.
+	#[serde(default)]
+	pub(crate) line_comment: Option<String>,
+	/// Whether the plugin answers complete calls, which Ctrl+Space sends.
+	#[serde(default)]
+	pub(crate) completion: bool,
 }
 
 #[derive(Debug)]
 pub(crate) struct Environment {
 	pub(crate) plugin_name: Arc<str>,
+	/// The first of the plugin's languages that evalvana knows about, if any.
+	pub(crate) language: Option<&'static Language>,
 	pub(crate) id: Arc<str>,
 	process: Child,
+	/// The input of the plugin, buffered until it's flushed, or `None` once
+	/// a send timed out.
+	input: Option<BufWriter<ChildStdin>>,
+	framing: Framing,
+	/// How long sending a message may take before it's abandoned.
+	send_timeout: Duration,
 	call_seq: u32,
+	call_ids: CallIds,
 }
 
 impl Environment {
+	/// Sends the method call with the given `seq` to the plugin, flushing any
+	/// calls buffered before it.
 	async fn send_method_call<Args: Serialize>(
 		&mut self,
+		seq: u32,
 		call: &RpcMethodCall<'_, '_, Args>,
 	) -> Result<()> {
-		let input = self
-			.process
-			.stdin
-			.as_mut()
-			.expect("Plugin child process had no stdin");
+		self.write_method_call(seq, call).await?;
+		self.flush().await
+	}
 
-		let mut bytes = serde_json::to_vec(call)?;
+	/// Buffers the method call with the given `seq` to the plugin, which is
+	/// only sent once the buffer fills up or is
+	/// [flushed](Environment::flush).
+	async fn write_method_call<Args: Serialize>(
+		&mut self,
+		seq: u32,
+		call: &RpcMethodCall<'_, '_, Args>,
+	) -> Result<()> {
+		let bytes = match self.framing {
+			Framing::Lines => serialize_line(call)?,
+			Framing::ContentLength => serialize_content(call)?,
+		};
 
-		// Just a sanity check, as a newline in the middle of a single message
-		// could completely ruin every plugin
-		debug_assert!(!bytes.iter().any(|&x| x == b'\n'));
+		log::debug!(
+			target: RPC_LOG_TARGET,
+			"to {}: {}",
+			self.id,
+			String::from_utf8_lossy(&bytes).trim_end()
+		);
 
-		bytes.push(b'\n');
+		let timeout = self.send_timeout;
+		let input = self.input()?;
 
-		input.write_all(&bytes).await?;
+		match tokio::time::timeout(timeout, input.write_all(&bytes)).await {
+			Ok(result) => result?,
+			Err(_) => return Err(self.timed_out()),
+		}
 
-		input.flush().await?;
+		if let StringOrNumber::Number(id) = call.rpc.id {
+			self.call_ids.record(id, &self.id, seq);
+		}
 
 		Ok(())
 	}
 
+	/// Sends the method calls buffered so far to the plugin.
+	pub(crate) async fn flush(&mut self) -> Result<()> {
+		let timeout = self.send_timeout;
+		let input = self.input()?;
+
+		match tokio::time::timeout(timeout, input.flush()).await {
+			Ok(result) => result.map_err(Into::into),
+			Err(_) => Err(self.timed_out()),
+		}
+	}
+
+	fn input(&mut self) -> Result<&mut BufWriter<ChildStdin>> {
+		match self.input.as_mut() {
+			Some(input) => Ok(input),
+			None => bail!(
+				"Environment {} stopped accepting input after a send timed \
+				out; restart the plugin to keep evaluating",
+				self.id
+			),
+		}
+	}
+
+	/// Closes the plugin's input after a write to it didn't finish within
+	/// the send timeout, returning the error to show.
+	fn timed_out(&mut self) -> Error {
+		// the write may have stopped partway through a message, so anything
+		// sent after it would be read as part of that message
+		self.input = None;
+
+		anyhow!(
+			"Environment {} didn't accept input within {:?}; \
+			is the plugin stuck?",
+			self.id,
+			self.send_timeout
+		)
+	}
+
 	pub(crate) async fn eval_string(&mut self, code: &str) -> Result<u32> {
+		let seq = self.queue_eval_string(code).await?;
+		self.flush().await?;
+
+		Ok(seq)
+	}
+
+	/// Buffers an eval call of `code` without sending it, returning its
+	/// `seq`. Queued calls are sent by [`flush`](Environment::flush).
+	pub(crate) async fn queue_eval_string(
+		&mut self,
+		code: &str,
+	) -> Result<u32> {
 		let args = EvalStringArgs {
 			code: Cow::Borrowed(code),
 		};
 
 		let seq = self.call_seq;
 
-		let id = format!("{}/{}", self.id, seq);
-
 		let call = EvalStringCall {
-			rpc: RpcMessage::new(Cow::Borrowed(&id)),
+			rpc: RpcMessage::new(self.call_ids.next_id().into()),
 			method: Cow::Borrowed("eval-string"),
 			params: args,
 		};
 
-		self.send_method_call(&call).await?;
+		self.write_method_call(seq, &call).await?;
 
 		self.call_seq += 1;
 
 		Ok(seq)
 	}
 
+	/// Asks the plugin how `code` could be completed at the byte `offset`,
+	/// returning the `seq` of the call.
+	pub(crate) async fn complete(
+		&mut self,
+		code: &str,
+		offset: usize,
+	) -> Result<u32> {
+		let seq = self.call_seq;
+
+		let call = CompleteCall {
+			rpc: RpcMessage::new(self.call_ids.next_id().into()),
+			method: Cow::Borrowed("complete"),
+			params: CompleteArgs {
+				code: Cow::Borrowed(code),
+				offset,
+			},
+		};
+
+		self.send_method_call(seq, &call).await?;
+
+		self.call_seq += 1;
+
+		Ok(seq)
+	}
+
+	/// Asks the plugin to stop the evaluation started by the call with the
+	/// given `seq`.
+	pub(crate) async fn cancel(&mut self, seq: u32) -> Result<()> {
+		// the evaluation may have finished while this was on its way
+		let eval_id = match self.call_ids.id_of(seq) {
+			Some(id) => id,
+			None => return Ok(()),
+		};
+		let cancel_seq = self.call_seq;
+
+		let call = CancelCall {
+			rpc: RpcMessage::new(self.call_ids.next_id().into()),
+			method: Cow::Borrowed("cancel"),
+			params: CancelArgs { id: eval_id.into() },
+		};
+
+		self.send_method_call(cancel_seq, &call).await?;
+
+		self.call_seq += 1;
+
+		Ok(())
+	}
+
 	pub(crate) async fn kill(&mut self) -> Result<()> {
 		self.process.kill().await.map_err(Into::into)
 	}
 }
 
+/// Serializes a message as a single line of JSON, including the trailing
+/// newline.
+fn serialize_line(message: &impl Serialize) -> Result<Vec<u8>> {
+	let mut bytes = serde_json::to_vec(message)?;
+
+	// A newline in the middle of a single message could completely ruin every
+	// plugin, as they read messages line by line
+	if bytes.contains(&b'\n') {
+		bail!("Serialized RPC message contained a newline");
+	}
+
+	bytes.push(b'\n');
+
+	Ok(bytes)
+}
+
+/// Serializes a message as JSON, preceded by a `Content-Length` header.
+fn serialize_content(message: &impl Serialize) -> Result<Vec<u8>> {
+	let content = serde_json::to_vec(message)?;
+
+	let mut bytes =
+		format!("Content-Length: {}\r\n\r\n", content.len()).into_bytes();
+	bytes.extend_from_slice(&content);
+
+	Ok(bytes)
+}
+
 pub(crate) struct EnvironmentOutput {
+	env_id: Arc<str>,
+	call_ids: CallIds,
 	inner: Cell<Option<ChildStdout>>,
+	max_message_len: usize,
+	framing: Framing,
 	hash: u128,
 }
 
 impl fmt::Debug for EnvironmentOutput {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		f.debug_struct("EnvironmentOutput")
+			.field("env_id", &self.env_id)
+			.field("call_ids", &self.call_ids)
 			.field("hash", &self.hash)
+			.field("max_message_len", &self.max_message_len)
+			.field("framing", &self.framing)
 			.field("inner", &"[ChildStdout]")
 			.finish()
 	}
 }
 
 impl EnvironmentOutput {
-	fn new(inner: ChildStdout) -> Self {
+	fn new(
+		env_id: Arc<str>,
+		call_ids: CallIds,
+		inner: ChildStdout,
+		max_message_len: usize,
+		framing: Framing,
+	) -> Self {
 		// goal is to just make a collision practically impossible, since this
 		// value is used by `iced` and seems to be assumed to be unique.
 		let mut bytes = [0; 16];
 		getrandom::getrandom(&mut bytes)
 			.expect("Failed to generate random hash");
 		Self {
+			env_id,
+			call_ids,
 			inner: Cell::new(Some(inner)),
+			max_message_len,
+			framing,
 			hash: u128::from_ne_bytes(bytes),
 		}
 	}
 
+	/// Returns whether this is the output of the environment with the given
+	/// ID.
+	pub(crate) fn is_for(&self, env_id: &str) -> bool {
+		*self.env_id == *env_id
+	}
+
 	pub(crate) fn take(&self) -> EnvironmentOutput {
 		Self {
+			env_id: self.env_id.clone(),
+			call_ids: self.call_ids.clone(),
 			inner: Cell::new(self.inner.take()),
+			max_message_len: self.max_message_len,
+			framing: self.framing,
 			hash: self.hash,
 		}
 	}
 }
 
+/// The numeric IDs of the calls sent to an environment, and the environment
+/// and `seq` each stands for. Shared by an [`Environment`] and its
+/// [`EnvironmentOutput`].
+#[derive(Debug, Clone, Default)]
+struct CallIds(Arc<Mutex<CallIdsInner>>);
+
+#[derive(Debug, Default)]
+struct CallIdsInner {
+	next_id: i64,
+	/// The environment and `seq` of each call still awaiting its response.
+	calls: HashMap<i64, (Arc<str>, u32)>,
+}
+
+impl CallIds {
+	/// Returns a numeric ID that no other call to the environment has had.
+	fn next_id(&self) -> i64 {
+		let mut inner = self.0.lock().unwrap();
+		let id = inner.next_id;
+		inner.next_id += 1;
+		id
+	}
+
+	/// Records that the call with the numeric ID `id` and the given `seq` was
+	/// sent to the environment `env_id`.
+	fn record(&self, id: i64, env_id: &Arc<str>, seq: u32) {
+		let mut inner = self.0.lock().unwrap();
+		inner.calls.insert(id, (env_id.clone(), seq));
+	}
+
+	/// Returns the numeric ID of the call with the given `seq`, if it's still
+	/// awaiting its response.
+	fn id_of(&self, seq: u32) -> Option<i64> {
+		let inner = self.0.lock().unwrap();
+		inner
+			.calls
+			.iter()
+			.find(|(_, &(_, call_seq))| call_seq == seq)
+			.map(|(&id, _)| id)
+	}
+
+	/// Replaces a numeric ID with the string ID of the form
+	/// `<environment>/<seq>` of its call, forgetting the call if this is its
+	/// last response. Calls answered with their string ID are forgotten the
+	/// same way.
+	fn resolve(&self, id: &mut StringOrNumber<'_>, is_final: bool) {
+		let mut inner = self.0.lock().unwrap();
+
+		match id {
+			StringOrNumber::Number(number) => {
+				let call = if is_final {
+					inner.calls.remove(number)
+				} else {
+					inner.calls.get(number).cloned()
+				};
+
+				if let Some((env_id, seq)) = call {
+					*id = StringOrNumber::String(
+						format!("{}/{}", env_id, seq).into(),
+					);
+				}
+			}
+			StringOrNumber::String(string_id) => {
+				if is_final {
+					inner.calls.retain(|_, (env_id, seq)| {
+						format!("{}/{}", env_id, seq) != *string_id
+					});
+				}
+			}
+		}
+	}
+}
+
+/// Something that happened in an environment, as reported by its
+/// [`EnvironmentOutput`].
+#[derive(Debug)]
+pub(crate) enum EnvironmentEvent {
+	Response(Result<EvalResponse<'static, 'static>>),
+	/// A result of an evaluation that's still running.
+	Chunk(EvalChunkArgs<'static>),
+	Completions(CompleteResponse<'static, 'static>),
+	/// The environment's output ended, usually because its process exited.
+	/// Nothing more is reported after this.
+	Exited,
+}
+
 impl<H: Hasher, E> Recipe<H, E> for EnvironmentOutput {
-	type Output = Result<EvalResponse<'static, 'static>>;
+	/// Events of the environment, paired with its ID.
+	type Output = (Arc<str>, EnvironmentEvent);
 
 	fn hash(&self, state: &mut H) {
 		state.write_u128(self.hash);
@@ -193,9 +638,485 @@ impl<H: Hasher, E> Recipe<H, E> for EnvironmentOutput {
 			.take()
 			.expect("Tried to use empty EnvironmentOutput");
 
+		let env_id = self.env_id;
+		let call_ids = self.call_ids;
+		let max_len = self.max_message_len;
+		let framing = self.framing;
+
+		let lines = stream::unfold(
+			BufReader::new(output),
+			move |mut reader| async move {
+				let line = match framing {
+					Framing::Lines => read_line(&mut reader, max_len).await,
+					Framing::ContentLength => {
+						read_content(&mut reader, max_len).await
+					}
+				};
+				let line = match line {
+					Ok(Some(line)) => Ok(line),
+					Ok(None) => return None,
+					Err(e) => Err(e),
+				};
+				Some((line, reader))
+			},
+		);
+
+		let exited_id = env_id.clone();
+
 		Box::pin(
-			LinesStream::new(BufReader::new(output).lines())
-				.map(|line| Ok(serde_json::from_str(line?.as_str())?)),
+			lines
+				.map(move |line| {
+					if let Ok(Line::Complete(line)) = &line {
+						log::trace!(
+							target: RPC_LOG_TARGET,
+							"from {}: {}",
+							env_id,
+							line
+						);
+					}
+					let event = match parse_message(line) {
+						Ok(PluginMessage::Chunk(mut chunk)) => {
+							let is_final = chunk.params.is_final;
+							call_ids.resolve(&mut chunk.params.id, is_final);
+							EnvironmentEvent::Chunk(chunk.params)
+						}
+						Ok(PluginMessage::Response(mut response)) => {
+							if let Some(id) = &mut response.rpc.id {
+								call_ids.resolve(id, true);
+							}
+							EnvironmentEvent::Response(Ok(response))
+						}
+						Ok(PluginMessage::Completions(mut response)) => {
+							if let Some(id) = &mut response.rpc.id {
+								call_ids.resolve(id, true);
+							}
+							EnvironmentEvent::Completions(response)
+						}
+						Err(e) => EnvironmentEvent::Response(Err(e)),
+					};
+					(env_id.clone(), event)
+				})
+				.chain(stream::once(async move {
+					(exited_id, EnvironmentEvent::Exited)
+				})),
 		)
 	}
 }
+
+/// A line of output read from a plugin by [`read_line`], or the content of a
+/// message read by [`read_content`].
+#[derive(Debug, PartialEq)]
+enum Line {
+	Complete(String),
+	/// A message longer than the limit, which was skipped. Holds its length
+	/// in bytes.
+	TooLong(usize),
+}
+
+/// Reads a line, without its line ending, keeping at most `max_len` bytes of
+/// it in memory. Returns `None` at the end of the output.
+async fn read_line(
+	reader: &mut (impl AsyncBufRead + Unpin),
+	max_len: usize,
+) -> io::Result<Option<Line>> {
+	let mut line = Vec::new();
+	let mut len = 0;
+	let mut last = None;
+
+	loop {
+		let available = reader.fill_buf().await?;
+		if available.is_empty() {
+			if len == 0 {
+				return Ok(None);
+			}
+			break;
+		}
+
+		let (chunk, done) = match available.iter().position(|&b| b == b'\n') {
+			Some(i) => (&available[..i], true),
+			None => (available, false),
+		};
+		let used = chunk.len() + usize::from(done);
+
+		len += chunk.len();
+		last = chunk.last().copied().or(last);
+		// one extra byte for a carriage return, which is removed below
+		if len <= max_len.saturating_add(1) {
+			line.extend_from_slice(chunk);
+		} else {
+			// drop what's been read so far instead of keeping up to `len`
+			line = Vec::new();
+		}
+
+		reader.consume(used);
+
+		if done {
+			break;
+		}
+	}
+
+	if last == Some(b'\r') {
+		line.pop();
+		len -= 1;
+	}
+
+	if len > max_len {
+		return Ok(Some(Line::TooLong(len)));
+	}
+
+	String::from_utf8(line)
+		.map(|line| Some(Line::Complete(line)))
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The longest header read from a plugin using [`Framing::ContentLength`].
+const MAX_HEADER_LEN: usize = 1024;
+
+/// Reads the content of a message preceded by headers, keeping it in memory
+/// only if it's at most `max_len` bytes long. Returns `None` at the end of the
+/// output.
+async fn read_content(
+	reader: &mut (impl AsyncBufRead + Unpin),
+	max_len: usize,
+) -> io::Result<Option<Line>> {
+	let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+	let mut len = None;
+	let mut has_headers = false;
+
+	loop {
+		let header = match read_line(reader, MAX_HEADER_LEN).await? {
+			Some(Line::Complete(header)) => header,
+			Some(Line::TooLong(len)) => {
+				return Err(invalid(format!(
+					"Skipped a {} byte header from plugin",
+					len
+				)))
+			}
+			None if has_headers => {
+				return Err(io::ErrorKind::UnexpectedEof.into())
+			}
+			None => return Ok(None),
+		};
+
+		if header.is_empty() {
+			// blank lines between messages are ignored
+			if has_headers {
+				break;
+			}
+			continue;
+		}
+		has_headers = true;
+
+		if let Some((name, value)) = header.split_once(':') {
+			if name.trim().eq_ignore_ascii_case("content-length") {
+				len = Some(
+					value
+						.trim()
+						.parse::<usize>()
+						.map_err(|e| invalid(e.to_string()))?,
+				);
+			}
+		}
+	}
+
+	let len = len.ok_or_else(|| {
+		invalid("Message from plugin had no Content-Length header".to_owned())
+	})?;
+
+	if len > max_len {
+		let mut remaining = len;
+		while remaining > 0 {
+			let available = reader.fill_buf().await?.len();
+			if available == 0 {
+				return Err(io::ErrorKind::UnexpectedEof.into());
+			}
+			let used = available.min(remaining);
+			reader.consume(used);
+			remaining -= used;
+		}
+
+		return Ok(Some(Line::TooLong(len)));
+	}
+
+	let mut content = vec![0; len];
+	reader.read_exact(&mut content).await?;
+
+	String::from_utf8(content)
+		.map(|content| Some(Line::Complete(content)))
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn parse_message(
+	line: io::Result<Line>,
+) -> Result<PluginMessage<'static, 'static, 'static>> {
+	let line = match line? {
+		Line::Complete(line) => line,
+		Line::TooLong(len) => {
+			bail!("Skipped a {} byte message from plugin", len)
+		}
+	};
+
+	let message = serde_json::from_str(&line).with_context(|| {
+		format!("Failed to parse response from plugin: {:?}", line)
+	})?;
+
+	if let PluginMessage::Chunk(chunk) = &message {
+		if chunk.method != EVAL_CHUNK_METHOD {
+			bail!("Unknown notification from plugin: {}", chunk.method);
+		}
+	}
+
+	Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn serialized_code_has_no_newlines() {
+		let code = "fn main() {\n\tprintln!(\"hi\");\r\n}\n";
+		let call = EvalStringCall {
+			rpc: RpcMessage::new(0.into()),
+			method: Cow::Borrowed("eval-string"),
+			params: EvalStringArgs {
+				code: Cow::Borrowed(code),
+			},
+		};
+
+		let bytes = serialize_line(&call).unwrap();
+		let (last, message) = bytes.split_last().unwrap();
+
+		assert_eq!(*last, b'\n');
+		assert!(!message.contains(&b'\n'));
+
+		let parsed: EvalStringCall = serde_json::from_slice(message).unwrap();
+		assert_eq!(parsed.params.code, code);
+		assert_eq!(parsed.rpc.id, StringOrNumber::Number(0));
+	}
+
+	#[test]
+	fn parse_errors_include_line() {
+		let line = r#"{"rpc": "not a response"#;
+
+		let error =
+			parse_message(Ok(Line::Complete(line.to_owned()))).unwrap_err();
+
+		assert!(format!("{:?}", error).contains(&format!("{:?}", line)));
+	}
+
+	#[test]
+	fn overlong_lines_are_skipped() {
+		let long = "x".repeat(20);
+		let output = format!("short\n{}\r\n{{}}\r\nlast", long);
+		let mut reader = output.as_bytes();
+
+		let lines = futures::executor::block_on(async {
+			let mut lines = Vec::new();
+			while let Some(line) = read_line(&mut reader, 10).await.unwrap() {
+				lines.push(line);
+			}
+			lines
+		});
+
+		assert_eq!(
+			lines,
+			[
+				Line::Complete("short".to_owned()),
+				Line::TooLong(20),
+				Line::Complete("{}".to_owned()),
+				Line::Complete("last".to_owned()),
+			]
+		);
+
+		let error = parse_message(Ok(Line::TooLong(20))).unwrap_err();
+		assert!(error.to_string().contains("20 byte"));
+	}
+
+	#[test]
+	fn content_length_framing_round_trips() {
+		let code = "fn main() {\n\tprintln!(\"hi\");\n}\n";
+		let call = EvalStringCall {
+			rpc: RpcMessage::new(0.into()),
+			method: Cow::Borrowed("eval-string"),
+			params: EvalStringArgs {
+				code: Cow::Borrowed(code),
+			},
+		};
+
+		let message = serialize_content(&call).unwrap();
+		let long = format!("Content-Length: 500\r\n\r\n{}", "x".repeat(500));
+		let output = [
+			&message[..],
+			&b"\r\n"[..],
+			long.as_bytes(),
+			&b"content-length: 7\r\nContent-Type: json\r\n\r\n{\n\"\":0}"[..],
+		]
+		.concat();
+		let mut reader = &output[..];
+
+		let messages = futures::executor::block_on(async {
+			let mut messages = Vec::new();
+			while let Some(message) =
+				read_content(&mut reader, 300).await.unwrap()
+			{
+				messages.push(message);
+			}
+			messages
+		});
+
+		let content = match &messages[0] {
+			Line::Complete(content) => content,
+			Line::TooLong(len) => panic!("Skipped a {} byte message", len),
+		};
+		let parsed: EvalStringCall = serde_json::from_str(content).unwrap();
+		assert_eq!(parsed.params.code, code);
+
+		assert_eq!(
+			messages[1..],
+			[Line::TooLong(500), Line::Complete("{\n\"\":0}".to_owned())]
+		);
+	}
+
+	#[test]
+	fn retries_back_off_on_transient_errors() {
+		let plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": "test",
+			"args": [],
+			"capabilities": {},
+			"spawn_retry": { "attempts": 3, "base_delay_ms": 50 },
+		}))
+		.unwrap();
+
+		let busy = anyhow::Error::from(io::Error::from(io::ErrorKind::Other));
+		assert_eq!(
+			plugin.retry_delay(0, &busy),
+			Some(Duration::from_millis(50))
+		);
+		assert_eq!(
+			plugin.retry_delay(1, &busy),
+			Some(Duration::from_millis(100))
+		);
+		assert_eq!(plugin.retry_delay(2, &busy), None);
+
+		let missing =
+			anyhow::Error::from(io::Error::from(io::ErrorKind::NotFound));
+		assert_eq!(plugin.retry_delay(0, &missing), None);
+	}
+
+	#[test]
+	fn missing_program_is_reported() {
+		let mut plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": "/nonexistent/evalvana-missing-program",
+			"args": [],
+			"capabilities": {},
+		}))
+		.unwrap();
+
+		let error = plugin.open(&EvalSettings::default()).unwrap_err();
+
+		assert_eq!(
+			error.to_string(),
+			"Plugin test: program \"/nonexistent/evalvana-missing-program\" \
+			not found; reinstall the plugin"
+		);
+		assert_eq!(plugin.retry_delay(0, &error), None);
+	}
+
+	#[test]
+	fn output_reports_exit() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		let mut plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": env!("CARGO"),
+			"args": ["--version"],
+			"capabilities": {},
+		}))
+		.unwrap();
+
+		let (_env, output) = plugin.open(&EvalSettings::default()).unwrap();
+		let output = Box::new(output.take());
+
+		let events = runtime.block_on(
+			Recipe::<std::collections::hash_map::DefaultHasher, ()>::stream(
+				output,
+				Box::pin(stream::empty()),
+			)
+			.collect::<Vec<_>>(),
+		);
+
+		let (_, last) = events.last().unwrap();
+		assert!(matches!(last, EnvironmentEvent::Exited));
+		assert_eq!(events.len(), 2);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn stalled_sends_time_out() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let _guard = runtime.enter();
+
+		// never reads its input
+		let mut plugin: Plugin = serde_json::from_value(serde_json::json!({
+			"name": "test",
+			"program": "sleep",
+			"args": ["10"],
+			"capabilities": {},
+		}))
+		.unwrap();
+		let settings = EvalSettings {
+			send_timeout: Duration::from_millis(100),
+			..EvalSettings::default()
+		};
+
+		let (mut env, _) = plugin.open(&settings).unwrap();
+
+		// far more than fits in the pipe's buffer
+		let code = "x".repeat(16 * 1024 * 1024);
+		let error = runtime.block_on(env.eval_string(&code)).unwrap_err();
+		assert!(error.to_string().contains("didn't accept input"));
+
+		// the input was closed, in case part of the message was sent
+		let error = runtime.block_on(env.eval_string("1")).unwrap_err();
+		assert!(error.to_string().contains("stopped accepting input"));
+
+		runtime.block_on(env.kill()).unwrap();
+	}
+
+	#[test]
+	fn numeric_ids_resolve_to_sent_calls() {
+		let env_id: Arc<str> = "test/0".into();
+		let call_ids = CallIds::default();
+		let first = call_ids.next_id();
+		let second = call_ids.next_id();
+		assert_ne!(first, second);
+		call_ids.record(first, &env_id, 0);
+		// a seq needn't match the ID its call was sent with
+		call_ids.record(second, &env_id, 3);
+		assert_eq!(call_ids.id_of(3), Some(second));
+
+		let mut id = StringOrNumber::Number(second);
+		call_ids.resolve(&mut id, false);
+		assert_eq!(id, StringOrNumber::from("test/0/3"));
+
+		// the call is forgotten after its last response
+		let mut id = StringOrNumber::Number(second);
+		call_ids.resolve(&mut id, true);
+		assert_eq!(id, StringOrNumber::from("test/0/3"));
+		let mut id = StringOrNumber::Number(second);
+		call_ids.resolve(&mut id, true);
+		assert_eq!(id, StringOrNumber::Number(second));
+		assert_eq!(call_ids.id_of(3), None);
+
+		// so are calls answered with their string ID
+		let mut id = StringOrNumber::from("test/0/0");
+		call_ids.resolve(&mut id, true);
+		assert_eq!(id, StringOrNumber::from("test/0/0"));
+		assert_eq!(call_ids.id_of(0), None);
+	}
+}
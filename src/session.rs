@@ -0,0 +1,141 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::model::Tab;
+
+/// The tabs that were open when Evalvana last exited, which are opened again
+/// when it starts.
+///
+/// Only the input of each cell is kept; results are lost with the
+/// environments that produced them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Session {
+	pub(crate) tabs: Vec<SessionTab>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SessionTab {
+	/// The name of the tab's plugin, or `None` for a scratch tab.
+	#[serde(default)]
+	pub(crate) plugin: Option<String>,
+	/// The name of the language of a scratch tab.
+	#[serde(default)]
+	pub(crate) language: Option<String>,
+	/// The input of each cell, in order.
+	pub(crate) cells: Vec<String>,
+}
+
+impl Session {
+	/// Returns the path of the session file in Evalvana's data directory.
+	pub(crate) fn path(data_dir: &Path) -> PathBuf {
+		data_dir.join("session.json")
+	}
+
+	pub(crate) fn from_tabs(tabs: &[Tab]) -> Self {
+		let tabs = tabs
+			.iter()
+			.map(|tab| SessionTab {
+				plugin: tab.plugin_name().map(|name| name.to_string()),
+				// tabs of plugins get their language from the plugin
+				language: match tab.plugin_name() {
+					Some(_) => None,
+					None => {
+						tab.language().map(|language| language.name.to_owned())
+					}
+				},
+				cells: tab.cells.contents(),
+			})
+			.collect();
+
+		Self { tabs }
+	}
+
+	/// Reads the session saved at `path`, or returns `None` if there isn't
+	/// one.
+	pub(crate) async fn load(path: &Path) -> Result<Option<Self>> {
+		let text = match tokio::fs::read_to_string(path).await {
+			Ok(text) => text,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => {
+				return Err(e).with_context(|| {
+					format!("Failed to read session from {:?}", path)
+				})
+			}
+		};
+
+		serde_json::from_str(&text)
+			.map(Some)
+			.with_context(|| format!("Failed to parse session at {:?}", path))
+	}
+
+	/// Writes this session to `path`, replacing the previous one only once
+	/// it's completely written.
+	///
+	/// This blocks, since it's done as Evalvana exits.
+	pub(crate) fn save(&self, path: &Path) -> Result<()> {
+		let text = serde_json::to_string_pretty(self)?;
+
+		let temp = path.with_extension("json.tmp");
+		std::fs::write(&temp, text).with_context(|| {
+			format!("Failed to write session to {:?}", temp)
+		})?;
+		std::fs::rename(&temp, path)
+			.with_context(|| format!("Failed to write session to {:?}", path))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sessions_round_trip() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let dir = std::env::temp_dir()
+			.join(format!("evalvana-session-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = Session::path(&dir);
+
+		let missing = runtime.block_on(Session::load(&path));
+		assert_eq!(missing.unwrap(), None);
+
+		let session = Session {
+			tabs: vec![
+				SessionTab {
+					plugin: Some("cargo".to_owned()),
+					language: None,
+					cells: vec!["let x = 1;".to_owned(), "x + 1".to_owned()],
+				},
+				SessionTab {
+					plugin: None,
+					language: Some("rust".to_owned()),
+					cells: vec!["fn main() {}\n".to_owned()],
+				},
+			],
+		};
+		session.save(&path).unwrap();
+
+		let loaded = runtime.block_on(Session::load(&path));
+		assert_eq!(loaded.unwrap(), Some(session));
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn plugin_and_language_are_optional() {
+		let tab: SessionTab =
+			serde_json::from_str(r#"{ "cells": ["1 + 1"] }"#).unwrap();
+
+		assert_eq!(tab.plugin, None);
+		assert_eq!(tab.language, None);
+		assert_eq!(tab.cells, ["1 + 1"]);
+	}
+}
@@ -0,0 +1,105 @@
+// Copyright 2022 ThatsNoMoon
+// Licensed under the Open Software License version 3.0
+
+use iced::Subscription;
+use iced_native::{
+	event,
+	keyboard::{self, KeyCode},
+	subscription, Event,
+};
+
+use crate::{message::Message, model::cell::Placement};
+
+pub(crate) fn subscription() -> Subscription<Message> {
+	subscription::events_with(handle_event)
+}
+
+/// Maps the app's keyboard shortcuts to their messages:
+///
+/// - Ctrl+Enter evaluates the focused cell, and Ctrl+Shift+Enter only its
+///   selected code.
+/// - Ctrl+Alt+Enter inserts a cell below the focused cell, and
+///   Ctrl+Alt+Shift+Enter one above it. These were Ctrl+Shift+Enter and
+///   Ctrl+Alt+Enter before evaluating the selection took Ctrl+Shift+Enter.
+/// - Ctrl+Space asks for completions at the caret.
+/// - Ctrl+Shift+N duplicates the active tab.
+/// - Ctrl+= and Ctrl+- change the font size, and Ctrl+0 resets it.
+// The editor captures every key press while it's focused, so the event status
+// is deliberately ignored here; these chords do nothing in the editor itself.
+fn handle_event(event: Event, _status: event::Status) -> Option<Message> {
+	let (key_code, modifiers) = match event {
+		Event::Keyboard(keyboard::Event::KeyPressed {
+			key_code,
+			modifiers,
+		}) => (key_code, modifiers),
+		_ => return None,
+	};
+
+	if !modifiers.command() {
+		return None;
+	}
+
+	match key_code {
+		KeyCode::Equals | KeyCode::Plus | KeyCode::NumpadAdd => {
+			Some(Message::AdjustFontSize(1))
+		}
+		KeyCode::Minus | KeyCode::NumpadSubtract => {
+			Some(Message::AdjustFontSize(-1))
+		}
+		KeyCode::Key0 | KeyCode::Numpad0 => Some(Message::ResetFontSize),
+		KeyCode::Enter | KeyCode::NumpadEnter
+			if modifiers.alt() && modifiers.shift() =>
+		{
+			Some(Message::NewCellAtFocused(Placement::Above))
+		}
+		KeyCode::Enter | KeyCode::NumpadEnter if modifiers.alt() => {
+			Some(Message::NewCellAtFocused(Placement::Below))
+		}
+		KeyCode::Enter | KeyCode::NumpadEnter if modifiers.shift() => {
+			Some(Message::EvalFocusedSelection)
+		}
+		KeyCode::Enter | KeyCode::NumpadEnter => Some(Message::EvalFocused),
+		KeyCode::N if modifiers.shift() => Some(Message::DuplicateActiveTab),
+		KeyCode::Space => Some(Message::CompleteFocused),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use iced_native::keyboard::Modifiers;
+
+	use super::*;
+
+	fn chord(key_code: KeyCode, modifiers: Modifiers) -> Option<Message> {
+		handle_event(
+			Event::Keyboard(keyboard::Event::KeyPressed {
+				key_code,
+				modifiers,
+			}),
+			event::Status::Ignored,
+		)
+	}
+
+	#[test]
+	fn enter_chords_eval_and_insert_cells() {
+		let ctrl = Modifiers::COMMAND;
+
+		assert!(matches!(
+			chord(KeyCode::Enter, ctrl),
+			Some(Message::EvalFocused)
+		));
+		assert!(matches!(
+			chord(KeyCode::Enter, ctrl | Modifiers::SHIFT),
+			Some(Message::EvalFocusedSelection)
+		));
+		assert!(matches!(
+			chord(KeyCode::Enter, ctrl | Modifiers::ALT),
+			Some(Message::NewCellAtFocused(Placement::Below))
+		));
+		assert!(matches!(
+			chord(KeyCode::Enter, ctrl | Modifiers::ALT | Modifiers::SHIFT),
+			Some(Message::NewCellAtFocused(Placement::Above))
+		));
+	}
+}
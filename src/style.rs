@@ -60,6 +60,120 @@ pub(crate) mod text_input {
 			self.active()
 		}
 	}
+
+	pub(crate) struct Filter {
+		bg: Color,
+		focused_bg: Color,
+		text: Color,
+		placeholder: Color,
+		selection: Color,
+		border: Color,
+	}
+
+	impl From<&'_ Config> for Filter {
+		fn from(config: &Config) -> Self {
+			Self {
+				bg: config.ui_colors.secondary_bg,
+				focused_bg: config.ui_colors.focused_bg,
+				text: config.ui_colors.text,
+				placeholder: config.ui_colors.unfocused_text,
+				selection: config.editor_colors.selection,
+				border: config.ui_colors.borders,
+			}
+		}
+	}
+
+	impl iced::text_input::StyleSheet for Filter {
+		fn active(&self) -> iced::text_input::Style {
+			iced::text_input::Style {
+				background: Background::Color(self.bg),
+				border_radius: 1.0,
+				border_width: 1.0,
+				border_color: self.border,
+			}
+		}
+
+		fn focused(&self) -> iced::text_input::Style {
+			iced::text_input::Style {
+				background: Background::Color(self.focused_bg),
+				..self.active()
+			}
+		}
+
+		fn placeholder_color(&self) -> Color {
+			self.placeholder
+		}
+
+		fn value_color(&self) -> Color {
+			self.text
+		}
+
+		fn selection_color(&self) -> Color {
+			self.selection
+		}
+	}
+}
+
+pub(crate) mod pick_list {
+	use iced::{
+		pick_list::{Style, StyleSheet},
+		Background, Color,
+	};
+	use iced_native::overlay::menu;
+
+	use crate::config::Config;
+
+	pub(crate) struct PickList {
+		bg: Color,
+		hovered_bg: Color,
+		text: Color,
+		placeholder: Color,
+		border: Color,
+	}
+
+	impl From<&'_ Config> for PickList {
+		fn from(config: &Config) -> Self {
+			Self {
+				bg: config.ui_colors.secondary_bg,
+				hovered_bg: config.ui_colors.hovered_bg,
+				text: config.ui_colors.text,
+				placeholder: config.ui_colors.unfocused_text,
+				border: config.ui_colors.borders,
+			}
+		}
+	}
+
+	impl StyleSheet for PickList {
+		fn menu(&self) -> menu::Style {
+			menu::Style {
+				text_color: self.text,
+				background: Background::Color(self.bg),
+				border_width: 1.0,
+				border_color: self.border,
+				selected_text_color: self.text,
+				selected_background: Background::Color(self.hovered_bg),
+			}
+		}
+
+		fn active(&self) -> Style {
+			Style {
+				text_color: self.text,
+				placeholder_color: self.placeholder,
+				background: Background::Color(self.bg),
+				border_radius: 1.0,
+				border_width: 1.0,
+				border_color: self.border,
+				..Style::default()
+			}
+		}
+
+		fn hovered(&self) -> Style {
+			Style {
+				background: Background::Color(self.hovered_bg),
+				..self.active()
+			}
+		}
+	}
 }
 
 pub(crate) mod button {
@@ -134,6 +248,24 @@ pub(crate) mod button {
 		}
 	}
 
+	pub(crate) fn text(config: &Config) -> StyleSheet {
+		StyleSheet {
+			bg: Color::TRANSPARENT,
+			..primary(config)
+		}
+	}
+
+	pub(crate) fn completion(config: &Config, is_selected: bool) -> StyleSheet {
+		StyleSheet {
+			bg: if is_selected {
+				config.ui_colors.hovered_bg
+			} else {
+				Color::TRANSPARENT
+			},
+			..primary(config)
+		}
+	}
+
 	pub(crate) fn tab_close(config: &Config, is_active: bool) -> StyleSheet {
 		StyleSheet {
 			bg: if is_active {